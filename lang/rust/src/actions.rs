@@ -0,0 +1,114 @@
+// `audit`: the latest changes recorded in the audit file, and taking back
+// the latest address or default route one of them added.
+use cw_netup::audit::{self, Entry};
+use cw_netup::config::Config;
+use cw_netup::error::WatchdogError;
+use cw_netup::exec::Executor;
+use cw_netup::history::rfc3339;
+use cw_netup::json::Json;
+use cw_netup::output::OutputSink;
+use cw_netup::{iface, log, probe};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+fn path(cfg: &Config) -> &PathBuf {
+    match &cfg.audit_file {
+        Some(p) => p,
+        None => {
+            log::error!("no audit_file configured");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `ok`, or the error; and how to take it back, or that it was.
+fn result(e: &Entry, entries: &[Entry]) -> String {
+    let mut s = match &e.error {
+        None => "ok".to_string(),
+        Some(err) => format!("failed: {}", err),
+    };
+    let undone = entries
+        .iter()
+        .any(|l| l.ok && l.undoes.as_deref() == Some(&e.id));
+    match &e.undo {
+        Some(_) if undone => s.push_str("; taken back"),
+        Some(u) => s.push_str(&format!("; undo: {}", u.command())),
+        None => {}
+    }
+    s
+}
+
+/// Puts the last `n` entries of the audit file through `out`, oldest
+/// first. Exits 1 when there are none.
+pub fn run(cfg: &Config, n: usize, out: &mut dyn OutputSink) -> ! {
+    let path = path(cfg);
+    let entries = audit::read(path);
+    if entries.is_empty() {
+        log::error!("no changes recorded in {}", path.display());
+        std::process::exit(1);
+    }
+    let last = &entries[entries.len().saturating_sub(n)..];
+    let mut t = String::new();
+    let _ = writeln!(
+        t,
+        "{:<20}  {:>10}  {:<7}  {:<40}  RESULT",
+        "TIME", "DURATION", "KIND", "ACTION"
+    );
+    for e in last {
+        let action = match &e.netns {
+            Some(ns) => format!("[{}] {}", ns, e.action),
+            None => e.action.clone(),
+        };
+        let _ = writeln!(
+            t,
+            "{:<20}  {:>7} ms  {:<7}  {:<40}  {}",
+            rfc3339(e.at),
+            probe::ms(e.duration),
+            e.kind,
+            action,
+            result(e, &entries)
+        );
+    }
+    let v: Vec<Json> = last.iter().map(Entry::to_json).collect();
+    out.document(&t, Json::obj().set("entries", v));
+    std::process::exit(0)
+}
+
+/// `--rollback-last`: takes back the latest added address or default
+/// route not taken back yet. Exit 0 when it was, 4 when privileges were
+/// lacking, else 1.
+pub fn rollback(cfg: &Config, ex: &dyn Executor, out: &mut dyn OutputSink) -> ! {
+    let path = path(cfg);
+    let entries = audit::read(path);
+    let Some(e) = audit::last_undoable(&entries) else {
+        log::error!("nothing to take back in {}", path.display());
+        std::process::exit(1);
+    };
+    let undo = e.undo.as_ref().map(|u| u.command()).unwrap_or_default();
+    let res = audit::rollback(ex, e);
+    // A failure is told by the log alone in text.
+    let text = match res {
+        Ok(()) => format!("took back `{}` ({}): {}\n", e.action, rfc3339(e.at), undo),
+        Err(_) => String::new(),
+    };
+    out.document(
+        &text,
+        Json::obj()
+            .set("undoes", e.to_json())
+            .set("action", undo.as_str())
+            .set("ok", res.is_ok())
+            .set("error", res.as_ref().err().map(String::as_str))
+            .set("dry_run", ex.actions()),
+    );
+    match res {
+        Ok(()) => std::process::exit(0),
+        Err(err) => {
+            log::error!("{}: {}", undo, err);
+            let code = match iface::denied(&err) {
+                true => WatchdogError::Permission.exit_code(),
+                false => 1,
+            };
+            std::process::exit(code)
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// ARP requests over an AF_PACKET socket: whether a neighbour answers at
+// layer 2, before anything is routed through it, and whether another host
+// holds an address we were given.
+use crate::{netlink, sys};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::raw::{c_int, c_short, c_ulong, c_void};
+use std::time::{Duration, Instant};
+
+const AF_PACKET: c_int = 17;
+const ETH_P_ARP: u16 = 0x0806;
+const ARPOP_REQUEST: u16 = 1;
+const ARPOP_REPLY: u16 = 2;
+const IFF_NOARP: u32 = 0x80;
+const POLLIN: c_short = 1;
+/// Between repeated requests within the timeout.
+const RESEND: Duration = Duration::from_millis(500);
+/// How long an address probe waits for another host to claim it.
+const PROBE_WAIT: Duration = Duration::from_secs(1);
+
+extern "C" {
+    fn bind(fd: c_int, addr: *const c_void, len: u32) -> c_int;
+    fn sendto(
+        fd: c_int,
+        buf: *const c_void,
+        len: usize,
+        flags: c_int,
+        addr: *const c_void,
+        alen: u32,
+    ) -> isize;
+    fn recv(fd: c_int, buf: *mut c_void, len: usize, flags: c_int) -> isize;
+    fn poll(fds: *mut PollFd, n: c_ulong, timeout: c_int) -> c_int;
+}
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+/// `struct sockaddr_ll`.
+#[repr(C)]
+struct SockaddrLl {
+    family: u16,
+    protocol: u16,
+    ifindex: c_int,
+    hatype: u16,
+    pkttype: u8,
+    halen: u8,
+    addr: [u8; 8],
+}
+
+impl SockaddrLl {
+    fn new(ifindex: u32, to: Option<[u8; 6]>) -> SockaddrLl {
+        let mut a = SockaddrLl {
+            family: AF_PACKET as u16,
+            protocol: ETH_P_ARP.to_be(),
+            ifindex: ifindex as c_int,
+            hatype: 0,
+            pkttype: 0,
+            halen: 0,
+            addr: [0; 8],
+        };
+        if let Some(mac) = to {
+            a.halen = 6;
+            a.addr[..6].copy_from_slice(&mac);
+        }
+        a
+    }
+}
+
+fn cvt(r: isize) -> io::Result<usize> {
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(r as usize)
+}
+
+/// From `/sys/class/net/<if>/address`; `None` for links without one.
+fn mac(iface: &str) -> Option<[u8; 6]> {
+    let s = fs::read_to_string(format!("/sys/class/net/{}/address", iface)).ok()?;
+    let b: Vec<u8> = s
+        .trim()
+        .split(':')
+        .map(|h| u8::from_str_radix(h, 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    <[u8; 6]>::try_from(b).ok().filter(|m| m != &[0; 6])
+}
+
+/// Whether ARP is spoken on `iface` at all (Ethernet-like, not IFF_NOARP).
+fn arp_link(iface: &str) -> bool {
+    let flags = fs::read_to_string(format!("/sys/class/net/{}/flags", iface))
+        .ok()
+        .and_then(|s| u32::from_str_radix(s.trim().trim_start_matches("0x"), 16).ok());
+    flags.is_some_and(|f| f & IFF_NOARP == 0) && mac(iface).is_some()
+}
+
+/// Asks who has `target`, as `spa`; only replies from another link-layer
+/// address count, so our own answers are not mistaken for a neighbour's.
+fn request(iface: &str, target: Ipv4Addr, spa: Ipv4Addr, timeout: Duration) -> io::Result<bool> {
+    let own = mac(iface).ok_or(io::ErrorKind::Unsupported)?;
+    let idx = netlink::index(iface)?;
+    let fd: OwnedFd = sys::socket(AF_PACKET, sys::SOCK_DGRAM, ETH_P_ARP.to_be() as c_int)?;
+    let me = SockaddrLl::new(idx, None);
+    let n = std::mem::size_of::<SockaddrLl>() as u32;
+    if unsafe { bind(fd.as_raw_fd(), (&me as *const SockaddrLl).cast(), n) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut pkt = Vec::with_capacity(28);
+    pkt.extend_from_slice(&1u16.to_be_bytes()); // Ethernet
+    pkt.extend_from_slice(&0x0800u16.to_be_bytes());
+    pkt.extend_from_slice(&[6, 4]);
+    pkt.extend_from_slice(&ARPOP_REQUEST.to_be_bytes());
+    pkt.extend_from_slice(&own);
+    pkt.extend_from_slice(&spa.octets());
+    pkt.extend_from_slice(&[0; 6]);
+    pkt.extend_from_slice(&target.octets());
+    let to = SockaddrLl::new(idx, Some([0xff; 6]));
+    let end = Instant::now() + timeout;
+    let mut buf = [0u8; 64];
+    loop {
+        cvt(unsafe {
+            sendto(
+                fd.as_raw_fd(),
+                pkt.as_ptr().cast(),
+                pkt.len(),
+                0,
+                (&to as *const SockaddrLl).cast(),
+                n,
+            )
+        })?;
+        let resend = (Instant::now() + RESEND).min(end);
+        loop {
+            let left = resend.saturating_duration_since(Instant::now());
+            let mut p = PollFd {
+                fd: fd.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            };
+            match unsafe { poll(&mut p, 1, left.as_millis() as c_int) } {
+                r if r < 0 => return Err(io::Error::last_os_error()),
+                0 => break,
+                _ => {}
+            }
+            let len = cvt(unsafe { recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) })?;
+            // op at 6, sender MAC at 8..14, sender IP at 14..18
+            if len >= 28
+                && u16::from_be_bytes([buf[6], buf[7]]) == ARPOP_REPLY
+                && buf[8..14] != own
+                && buf[14..18] == target.octets()
+            {
+                return Ok(true);
+            }
+        }
+        if Instant::now() >= end {
+            return Ok(false);
+        }
+    }
+}
+
+/// Asks who has `target` on `iface`, resending for up to `timeout`.
+/// `None` when that cannot be told: IPv6, a link without ARP, or no
+/// CAP_NET_RAW for the packet socket.
+pub fn arp_ping(iface: &str, target: IpAddr, timeout: Duration) -> Option<bool> {
+    let IpAddr::V4(t) = target else {
+        return None;
+    };
+    if !arp_link(iface) {
+        return None;
+    }
+    // Without an address yet the sender IP stays 0.0.0.0 (an RFC 5227 probe).
+    let spa = sys::if_ipv4(iface).unwrap_or(Ipv4Addr::UNSPECIFIED);
+    request(iface, t, spa, timeout).ok()
+}
+
+/// Whether another host answers for `ip`, an address of our own, on
+/// `iface`: an RFC 5227 probe, sent from 0.0.0.0 so no neighbour's cache
+/// learns from it. `false` also when that cannot be told.
+pub fn check_mac_conflict(iface: &str, ip: IpAddr) -> bool {
+    let IpAddr::V4(t) = ip else {
+        return false;
+    };
+    arp_link(iface) && request(iface, t, Ipv4Addr::UNSPECIFIED, PROBE_WAIT).unwrap_or(false)
+}
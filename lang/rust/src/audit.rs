@@ -0,0 +1,322 @@
+// The audit file: a JSON line per change made to the system (a link set
+// up, a DHCP client run, an address or route added, a hook), apart from
+// the log. Those that added an address or a default route say how to take
+// it off again, for `audit --rollback-last`.
+use crate::config::Config;
+use crate::exec::Executor;
+use crate::history::{rfc3339, unix_now};
+use crate::json::Json;
+#[cfg(target_os = "linux")]
+use crate::{iface, netlink};
+use crate::{log, probe, timeline};
+use std::cell::{Cell, RefCell};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+struct Trail {
+    path: PathBuf,
+    max_size: u64,
+}
+
+static TRAIL: OnceLock<Option<Trail>> = OnceLock::new();
+/// Held while a line is written; namespace threads share the file.
+static WRITING: Mutex<()> = Mutex::new(());
+static SEQ: AtomicU64 = AtomicU64::new(0);
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Within an action already: the `ip` fallback of a netlink change is
+    /// part of it, not one of its own.
+    static INSIDE: Cell<bool> = const { Cell::new(false) };
+    /// The entry the actions on this thread take back.
+    static UNDOING: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Takes `audit_file` from `cfg`; the first call wins.
+pub fn configure(cfg: &Config) {
+    let _ = TRAIL.set(cfg.audit_file.as_ref().map(|p| Trail {
+        path: p.clone(),
+        max_size: cfg.history_max_size,
+    }));
+}
+
+/// What taking an added address or default route off again needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Undo {
+    Address {
+        iface: String,
+        address: Ipv4Addr,
+        prefix: u8,
+    },
+    Route {
+        iface: String,
+        gateway: Ipv4Addr,
+    },
+}
+
+impl Undo {
+    /// From the `ip` command a change is described by: `addr add|replace
+    /// A/P dev I` or `route add|replace default via G dev I`.
+    pub fn of(action: &str) -> Option<Undo> {
+        let w: Vec<&str> = action.split_whitespace().collect();
+        match w[..] {
+            ["ip", "addr", "add" | "replace", a, "dev", i] => {
+                let (address, prefix) = a.split_once('/')?;
+                Some(Undo::Address {
+                    iface: i.into(),
+                    address: address.parse().ok()?,
+                    prefix: prefix.parse().ok().filter(|p| *p <= 32)?,
+                })
+            }
+            ["ip", "route", "add" | "replace", "default", "via", g, "dev", i] => {
+                Some(Undo::Route {
+                    iface: i.into(),
+                    gateway: g.parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The `ip` command that does it.
+    pub fn command(&self) -> String {
+        match self {
+            Undo::Address {
+                iface,
+                address,
+                prefix,
+            } => format!("ip addr del {}/{} dev {}", address, prefix, iface),
+            Undo::Route { iface, gateway } => {
+                format!("ip route del default via {} dev {}", gateway, iface)
+            }
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let j = match self {
+            Undo::Address {
+                iface,
+                address,
+                prefix,
+            } => Json::obj()
+                .set("op", "del_address")
+                .set("iface", iface.as_str())
+                .set("address", format!("{}/{}", address, prefix)),
+            Undo::Route { iface, gateway } => Json::obj()
+                .set("op", "del_route")
+                .set("iface", iface.as_str())
+                .set("gateway", gateway.to_string()),
+        };
+        j.set("command", self.command())
+    }
+
+    fn from_json(j: &Json) -> Option<Undo> {
+        let s = |k: &str| j.get(k).and_then(Json::as_str);
+        match s("op")? {
+            "del_address" => {
+                let (a, p) = s("address")?.split_once('/')?;
+                Some(Undo::Address {
+                    iface: s("iface")?.into(),
+                    address: a.parse().ok()?,
+                    prefix: p.parse().ok()?,
+                })
+            }
+            "del_route" => Some(Undo::Route {
+                iface: s("iface")?.into(),
+                gateway: s("gateway")?.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// One change.
+pub struct Entry {
+    /// `<unix secs>-<pid>-<n>`.
+    pub id: String,
+    pub at: u64,
+    /// `command`, `change` (netlink or a file) or `hook`.
+    pub kind: String,
+    /// The command run, or the equivalent one.
+    pub action: String,
+    pub ok: bool,
+    pub error: Option<String>,
+    pub duration: Duration,
+    /// The `[[namespace]]` it was made in.
+    pub netns: Option<String>,
+    /// How to take it back, when it added an address or a route.
+    pub undo: Option<Undo>,
+    /// The entry this one took back.
+    pub undoes: Option<String>,
+}
+
+impl Entry {
+    pub fn to_json(&self) -> Json {
+        Json::obj()
+            .set("id", self.id.as_str())
+            .set("ts", rfc3339(self.at))
+            .set("at", self.at)
+            .set("kind", self.kind.as_str())
+            .set("action", self.action.as_str())
+            .set("ok", self.ok)
+            .set("error", self.error.as_deref())
+            .set("duration_ms", probe::ms(self.duration))
+            .set("netns", self.netns.as_deref())
+            .set("undo", self.undo.as_ref().map(Undo::to_json))
+            .set("undoes", self.undoes.as_deref())
+    }
+
+    fn from_json(j: &Json) -> Option<Entry> {
+        let s = |k: &str| j.get(k).and_then(Json::as_str).map(String::from);
+        Some(Entry {
+            id: s("id")?,
+            at: j.get("at")?.as_u64()?,
+            kind: s("kind")?,
+            action: s("action")?,
+            ok: matches!(j.get("ok"), Some(Json::Bool(true))),
+            error: s("error"),
+            duration: Duration::from_secs_f64(
+                j.get("duration_ms")
+                    .and_then(Json::as_f64)
+                    .unwrap_or(0.0)
+                    .max(0.0)
+                    / 1e3,
+            ),
+            netns: s("netns"),
+            undo: j.get("undo").and_then(Undo::from_json),
+            undoes: s("undoes"),
+        })
+    }
+}
+
+/// Runs `f`, the change `action` of `kind`, and records how it went; a
+/// change within another is the outer one's.
+pub fn action(
+    kind: &str,
+    action: &str,
+    f: impl FnOnce() -> Result<(), String>,
+) -> Result<(), String> {
+    let Some(Some(trail)) = TRAIL.get() else {
+        return f();
+    };
+    if INSIDE.with(|i| i.replace(true)) {
+        return f();
+    }
+    let start = Instant::now();
+    let res = f();
+    INSIDE.with(|i| i.set(false));
+    let at = unix_now();
+    let e = Entry {
+        id: format!(
+            "{}-{}-{}",
+            at,
+            std::process::id(),
+            SEQ.fetch_add(1, Ordering::Relaxed)
+        ),
+        at,
+        kind: kind.into(),
+        action: action.into(),
+        ok: res.is_ok(),
+        error: res.as_ref().err().cloned(),
+        duration: start.elapsed(),
+        netns: log::scope(),
+        undo: res.is_ok().then(|| Undo::of(action)).flatten(),
+        undoes: UNDOING.with(|u| u.borrow().clone()),
+    };
+    if let Err(err) = append(trail, &e) {
+        if !WARNED.swap(true, Ordering::Relaxed) {
+            log::warn!("audit file {}: {}", trail.path.display(), err);
+        }
+    }
+    res
+}
+
+/// Appends `e`, first moving the file to `<file>.1` once it has grown past
+/// `max_size` bytes.
+fn append(t: &Trail, e: &Entry) -> io::Result<()> {
+    let _w = WRITING.lock().unwrap_or_else(|p| p.into_inner());
+    if let Some(dir) = t.path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::metadata(&t.path).is_ok_and(|m| m.len() >= t.max_size) {
+        fs::rename(&t.path, timeline::rotated(&t.path))?;
+    }
+    let mut f = OpenOptions::new().create(true).append(true).open(&t.path)?;
+    writeln!(f, "{}", e.to_json())
+}
+
+/// The entries of `<path>.1`, then of `path`, oldest first; lines that do
+/// not parse are skipped.
+pub fn read(path: &Path) -> Vec<Entry> {
+    [timeline::rotated(path), path.to_path_buf()]
+        .iter()
+        .filter_map(|p| File::open(p).ok())
+        .flat_map(|f| BufReader::new(f).lines().map_while(Result::ok))
+        .filter_map(|l| Json::parse(&l).ok().as_ref().and_then(Entry::from_json))
+        .collect()
+}
+
+/// The latest entry of `entries` that added something no later one took
+/// back.
+pub fn last_undoable(entries: &[Entry]) -> Option<&Entry> {
+    entries.iter().rev().find(|e| {
+        e.undo.is_some()
+            && !entries
+                .iter()
+                .any(|l| l.ok && l.undoes.as_deref() == Some(&e.id))
+    })
+}
+
+/// Takes back what `e` added, through `ex`; what that does is recorded as
+/// undoing `e`.
+#[cfg(target_os = "linux")]
+pub fn rollback(ex: &dyn Executor, e: &Entry) -> Result<(), String> {
+    let Some(u) = &e.undo else {
+        return Err(format!("{}: nothing to take back", e.action));
+    };
+    UNDOING.with(|x| *x.borrow_mut() = Some(e.id.clone()));
+    let res = match u {
+        Undo::Address {
+            iface,
+            address,
+            prefix,
+        } => {
+            let a = format!("{}/{}", address, prefix);
+            iface::link_op(
+                ex,
+                iface,
+                "removing address",
+                |i| netlink::delete_address(i, *address, *prefix),
+                &["addr", "del", &a, "dev", iface],
+            )
+        }
+        Undo::Route { iface, gateway } => iface::link_op(
+            ex,
+            iface,
+            "removing default route",
+            |i| netlink::delete_default_route(i, *gateway),
+            &[
+                "route",
+                "del",
+                "default",
+                "via",
+                &gateway.to_string(),
+                "dev",
+                iface,
+            ],
+        ),
+    };
+    UNDOING.with(|x| *x.borrow_mut() = None);
+    res
+}
+
+/// Nothing this far from Linux changed the system to take back.
+#[cfg(not(target_os = "linux"))]
+pub fn rollback(_ex: &dyn Executor, e: &Entry) -> Result<(), String> {
+    Err(format!("{}: taking it back needs Linux", e.action))
+}
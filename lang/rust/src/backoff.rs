@@ -0,0 +1,103 @@
+use crate::config::Config;
+use crate::sys;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A failed attempt that will be retried.
+pub struct Attempt {
+    /// 1-based number of the attempt that failed.
+    pub n: u32,
+    pub of: u32,
+    pub delay: Duration,
+    pub error: String,
+}
+
+/// Exponential back-off with jitter: `base`, `2*base`, `4*base`, ... up to
+/// `max`, each scaled by a random `1 ± jitter/2`, never past `deadline`.
+pub struct Backoff {
+    attempts: u32,
+    base: Duration,
+    max: Duration,
+    jitter: f64,
+    deadline: Option<Instant>,
+}
+
+/// When `max_wait` runs out, counted from now.
+pub fn deadline(cfg: &Config) -> Option<Instant> {
+    (cfg.max_wait > 0).then(|| Instant::now() + Duration::from_secs(cfg.max_wait))
+}
+
+/// `d`, shortened so it ends by `deadline`.
+pub fn cap(d: Duration, deadline: Option<Instant>) -> Duration {
+    deadline.map_or(d, |t| d.min(t.saturating_duration_since(Instant::now())))
+}
+
+/// Uniform in [0, 1).
+fn random() -> f64 {
+    // xorshift over the clock; only has to decorrelate concurrent retries.
+    let mut x = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(1, |d| d.as_nanos() as u64)
+        ^ (std::process::id() as u64) << 32
+        | 1;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    (x % 1_000_000) as f64 / 1e6
+}
+
+impl Backoff {
+    pub fn new(retries: u32, cfg: &Config, deadline: Option<Instant>) -> Backoff {
+        Backoff {
+            attempts: retries + 1,
+            base: Duration::from_millis(cfg.backoff_base_ms),
+            max: Duration::from_millis(cfg.backoff_max_ms),
+            jitter: cfg.jitter,
+            deadline,
+        }
+    }
+
+    /// How long to wait after `failed` attempts, or `None` when out of
+    /// attempts, stopping, or the wait would overrun the deadline.
+    pub fn delay(&self, failed: u32) -> Option<Duration> {
+        if failed >= self.attempts || sys::stop_requested() {
+            return None;
+        }
+        let exp = self
+            .base
+            .saturating_mul(1 << (failed - 1).min(16))
+            .min(self.max);
+        let d = exp.mul_f64(1.0 + self.jitter * (random() - 0.5));
+        match self.deadline {
+            Some(t) if Instant::now() + d >= t => None,
+            _ => Some(d),
+        }
+    }
+
+    /// Runs `f` until it succeeds or retries run out, sleeping in between
+    /// and recording each failed attempt in `log`.
+    pub fn retry<T>(
+        &self,
+        log: &mut Vec<Attempt>,
+        mut f: impl FnMut() -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut n = 0;
+        loop {
+            let res = f();
+            n += 1;
+            let (Err(e), Some(d)) = (&res, self.delay(n)) else {
+                return res;
+            };
+            log.push(Attempt {
+                n,
+                of: self.attempts,
+                delay: d,
+                error: e.clone(),
+            });
+            sys::sleep(d);
+        }
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
@@ -0,0 +1,679 @@
+use cw_netup::config::{Config, Family, ProbeMethod, ProbeMode, TargetOpts};
+use cw_netup::dhcp::DhcpBackend;
+use cw_netup::error::WatchdogError;
+use cw_netup::history;
+use cw_netup::http::Url;
+use cw_netup::log::{Backend, Level};
+use cw_netup::manager::ManagerMode;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const USAGE: &str = "\
+usage: cw-netup [options]
+       cw-netup status [options]
+       cw-netup doctor [options]
+       cw-netup history [--since <time>] [options]
+       cw-netup audit [--last <n>] [--rollback-last] [options]
+       cw-netup repair [--iface <name>] [options]
+  --config <path>        config file (default /etc/cyber-watchdog/config.toml,
+                         then /etc/cyber-watchdog.toml)
+  --print-config         print the effective configuration and exit
+  --list-ifaces          print the interfaces that would be managed and exit
+  --status, status       print the daemon's recorded state, uptime and flap
+                         count from its state file, and each interface's
+                         address, carrier, operstate, last probe verdict,
+                         carrier changes within flap_window and time since
+                         its link last changed, without probing
+                         (only what the kernel says when no daemon runs)
+  --history, history     print the outages recorded in history_file: start,
+                         end, duration and cause, then the down time,
+                         availability and longest outage over the window
+  --since <time>         ... that window, back from now (90m, 24h, 7d;
+                         default 24h)
+  --audit, audit         print the latest changes recorded in audit_file: when,
+                         how long, the result and the command, and how to take
+                         back an address or default route that was added
+  --last <n>             ... that many of them (default 20)
+  --rollback-last        take back the latest added address or default route
+                         not taken back yet, and record that; exit 1 if there
+                         is none or it fails
+  --doctor, doctor       check, without changing anything, that the programs
+                         the configured backends run are installed, the
+                         capabilities, /sys/class/net and /proc/net/route,
+                         that there are interfaces, and the config and its
+                         targets; exit 1 if anything required fails
+  --repair, repair       work up from the link to DNS and fix each layer once
+                         the one below it works: set a link up, request a
+                         lease for one without an address, add a missing
+                         default route, renew the lease when the gateway does
+                         not answer; then ping the targets and resolve
+                         dns_name. What works is left alone; exit 1 if
+                         anything is still broken
+  --iface <name>         ... only on this interface, repeatable (default the
+                         managed ones)
+  --release              release the DHCP leases of the managed interfaces
+                         and set them down (unless teardown_link_down is
+                         false), then exit
+  --once                 report the interfaces as they are and probe, without
+                         bringing anything up; the exit status is that of
+                         the state reported (for monitoring checks; the host
+                         first, then each [[namespace]])
+  --wait                 ... probing again until the host is ONLINE, then
+                         reporting as --once does, or as it stands after
+                         --wait-timeout, with that state's exit status: 1
+                         when still OFFLINE, not 2, which is NO_INTERFACES
+                         (implies --once)
+  --wait-timeout <time>  how long to wait (30s, 5m; default 60s); so is a
+                         --timeout after --wait
+  --wait-interval <secs> pause between probes while waiting (default 2)
+  --netns <name>         work inside network namespace /run/netns/<name>
+                         (its interfaces, links and probes; /etc/netns/<name>
+                         files replace those in /etc); needs CAP_SYS_ADMIN
+  --netns-path <path>    ... the namespace at <path>, e.g. /proc/<pid>/ns/net
+  --daemon               keep running and re-check periodically
+  --interval <secs>      seconds between checks (implies --daemon)
+  --fail-threshold <k>   checks in a row that must find the host down before
+                         the daemon goes OFFLINE (default 2)
+  --adaptive             while the link stays ONLINE, check less often with
+                         one target, and at once with all of them again on a
+                         failure (implies --daemon)
+  --max-interval <secs>  the longest pause between such checks (default 300;
+                         implies --adaptive)
+  --pid-file <path>      daemon PID file and single-instance lock
+                         (default /run/cyber-watchdog.pid, '' for none)
+  --reboot-after <time>  in daemon mode, run the recovery command once OFFLINE
+                         this long without a break (90s, 30m, 2h; 0 = never),
+                         counting across restarts
+  --recovery-command <cmd>
+                         what to run then (default systemctl reboot)
+  --include <glob>       manage only matching interfaces, repeatable
+  --exclude <glob>       never manage matching interfaces, repeatable
+                         (either replaces the configured list)
+  --prefer <iface>       uplink in failover order, repeatable (replaces the
+                         configured priority): each is brought up in turn
+                         until one is ONLINE, the rest stay on standby
+  --network-manager <name>
+                         NetworkManager or networkd: leave the interfaces to
+                         it and only watch them, with its view of each in the
+                         report; none: always manage them; auto (default):
+                         defer to whichever is running
+  --failback-hold <secs> in daemon mode, how long a preferred uplink must stay
+                         healthy before failing back to it (default 60)
+  --target <ip|host:port>[@iface]
+                         probe target, repeatable (replaces configured
+                         targets); host:port is a TCP connect; @iface only
+                         probes it through that interface
+  --tcp <host:port>[@iface]
+                         TCP connect target, repeatable (likewise)
+  --resolve <host[@ip]>  name to look up via the system resolver or the given
+                         server, repeatable (likewise; DNS_BROKEN if it fails)
+  --quorum <k>           targets that must answer for ONLINE (replaces
+                         quorum_threshold)
+  --quorum-threshold <r> ... or their share of the total [[probes]] weight
+                         that must be exceeded, 0 <= r < 1 (replaces --quorum)
+  --count <n>            echoes (or connects, lookups) per target
+  --timeout <secs>       probe reply timeout (after --wait, how long to wait)
+  --max-loss <pct>       a target losing more is DEGRADED (default 20)
+  --max-rtt <ms>         ... as is one slower than this on average
+  --degraded-fail        DEGRADED targets do not count towards the quorum
+                         (by default they do, and make the verdict DEGRADED)
+  --retries <n>          extra probe rounds before declaring OFFLINE, and
+                         extra DHCP runs after a failed one
+  --backoff-base <ms>    first retry delay, doubled with jitter each time
+  --max-wait <secs>      give up retrying once this much time has passed
+  --probe icmp|tcp|http|both
+                         which probes decide connectivity; tcp connects to
+                         port 443 of IP targets instead of pinging
+  --per-interface        probe through each interface separately; ONLINE
+                         (exit 0) when any interface is
+  --require-all          ... only when all of them are (implies --per-interface)
+  --no-gateway-check     probe external targets even when the link or the
+                         default gateway is down
+  --flush-neigh          flush the gateway's neighbour entry when it failed
+                         (GATEWAY_ARP_FAILED), and ping it again
+  --tunnel               when OFFLINE through a VPN tunnel, probe again outside
+                         it, bound to the uplink: TUNNEL_DOWN or UPLINK_DOWN
+  --pmtu                 measure each interface's path MTU to the first IP
+                         target after bring-up (a warning below pmtu_min), and
+                         the route's after each ONLINE check
+  --min-mtu <bytes>      ... DEGRADED when that is below this (implies --pmtu)
+  --url <url>            HTTP probe URL (expects 204 unless configured)
+  --throughput-url <url> download this after an ONLINE check and report the
+                         throughput
+  --min-throughput <kbit/s>
+                         ... DEGRADED when slower than this
+  --dns-name <host>      resolve this name too (DNS_BROKEN, exit 6, if it fails)
+  --dns-server <ip>      ask this server instead of resolv.conf, repeatable
+  --jobs <n>             interfaces brought up in parallel (0 = all)
+  --carrier-wait <secs>  wait this long for carrier before skipping DHCP
+                         on a link as NO-CARRIER (default 2)
+  --dhcp-backend <name>  dhclient, dhcpcd, udhcpc, none (wait for an address
+                         from NetworkManager/networkd) or auto
+  --dhcp-command <cmd>   DHCP client command, interface name appended
+  --force-dhcp           renew leases even on already-configured interfaces
+  --repair-routes        re-add a default route missing after bring-up, via
+                         the gateway the lease names
+  --ipv6                 wait for SLAAC/DHCPv6 and probe IPv6 targets too
+  --family 4|6|any|both  families that must be up for ONLINE (any by default;
+                         anything but 4 implies --ipv6)
+  --output table|json|ndjson
+                         output format (human is another name for table):
+                         json prints the report as one document (the daemon,
+                         a line per state change); ndjson one object per line
+                         for every event too, each numbered (seq)
+  -v, --verbose          log debug messages too (commands run, and why they
+                         failed)
+  -q, --quiet            log errors only (to stderr), and in a one-shot run
+                         print nothing: the exit status tells; without -v or
+                         -q, $CW_LOG (error|warn|info|debug) or info
+  --state-only           print just the status word of the exit status:
+                         ONLINE, OFFLINE, NO_INTERFACES, DHCP_FAILED,
+                         PERMISSION_DENIED, CONFIG_ERROR, DNS_BROKEN,
+                         DEGRADED, CAPTIVE_PORTAL or INTERNAL_ERROR; the
+                         report goes to stderr (not in daemon mode)
+  --log-backend <name>   stderr, syslog, journald, or auto (stderr, but the
+                         journal or syslog in daemon mode)
+  --metrics-addr <ip:port>
+                         serve Prometheus /metrics there in daemon mode
+                         (default 127.0.0.1:9101)
+  --metrics-port <port>  ... on this port of the configured address
+  --health-addr <ip:port>
+                         answer HTTP health checks there in daemon mode: 200
+                         while ONLINE, else 503, the state in the body
+                         (default 127.0.0.1:9102)
+  --health-port <port>   ... on this port of the configured address
+  --on-online <cmd>      run a shell command when the daemon goes ONLINE
+  --on-offline <cmd>     ... goes OFFLINE
+  --on-change <cmd>      ... on any state change (CW_STATE, CW_PREV_STATE,
+                         CW_IFACE and CW_TIMESTAMP describe it; behind a
+                         captive portal, CW_PORTAL_URL is its login page;
+                         in a [[namespace]], CW_NETNS names it)
+  --dry-run              print link, address, DHCP and Wi-Fi commands, and log
+                         webhooks and hooks, instead of running them; the
+                         JSON report lists the commands under dry_run
+  --benchmark            time each interface's link up, DHCP and probes, and
+                         the whole run: after the report, or under benchmark
+                         in it with --output json (and in interface_up and
+                         dhcp events with ndjson)
+
+signals to the daemon, acted on between checks: SIGHUP reads the config file
+again and checks with it at once, or keeps the old one when it does not load
+(listeners and [[namespace]]s stay as they started); SIGUSR1 checks at once;
+SIGUSR2 prints its whole state as JSON
+
+exit status: 0 ONLINE; 1 OFFLINE or NO_DEFAULT_ROUTE; 2 no interfaces to
+manage; 3 DHCP failed on every interface; 4 links could not be set up for lack
+of privileges; 5 bad command line or configuration, or the daemon is already
+running; 6 DNS_BROKEN; 7 DEGRADED; 8 CAPTIVE_PORTAL; 64 internal error";
+
+pub fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(WatchdogError::Usage.exit_code());
+}
+
+fn bad(flag: &str, v: &str) -> ! {
+    eprintln!("cw-netup: {}: invalid value `{}`", flag, v);
+    std::process::exit(WatchdogError::Usage.exit_code());
+}
+
+/// Command-line options. Anything that also exists in the config file is
+/// kept as an override and applied on top of it by [`Cli::apply`].
+#[derive(Default)]
+pub struct Cli {
+    pub config: Option<PathBuf>,
+    pub print_config: bool,
+    pub list_ifaces: bool,
+    pub status: bool,
+    pub doctor: bool,
+    pub history: bool,
+    /// Seconds back `history` looks.
+    pub since: Option<u64>,
+    pub audit: bool,
+    /// Entries `audit` prints.
+    pub last: Option<usize>,
+    pub rollback_last: bool,
+    pub release: bool,
+    pub repair: bool,
+    /// `repair --iface`.
+    pub ifaces: Vec<String>,
+    pub daemon: bool,
+    pub json: bool,
+    /// `--output ndjson`: every event printed too, not just the report.
+    pub ndjson: bool,
+    /// `--quiet`: nothing on stdout.
+    pub quiet: bool,
+    pub state_only: bool,
+    pub dry_run: bool,
+    pub benchmark: bool,
+    pub once: bool,
+    /// `--wait`: the timeout and the pause between probes, in seconds.
+    pub wait: Option<(u64, u64)>,
+    pub log_level: Option<Level>,
+    pub netns: Option<String>,
+    pub netns_path: Option<PathBuf>,
+    ipv6: bool,
+    family: Option<Family>,
+    force_dhcp: bool,
+    repair_routes: bool,
+    per_interface: bool,
+    require_all: bool,
+    no_gateway_check: bool,
+    tunnel: bool,
+    flush_neigh: bool,
+    pmtu: bool,
+    min_mtu: Option<u16>,
+    interval: Option<u64>,
+    fail_threshold: Option<u32>,
+    adaptive: bool,
+    max_interval: Option<u64>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    prefer: Vec<String>,
+    failback_hold: Option<u64>,
+    network_manager: Option<ManagerMode>,
+    targets: Vec<ProbeMethod>,
+    /// Interfaces given as `--target <t>@iface`.
+    bound: Vec<(ProbeMethod, String)>,
+    quorum: Option<usize>,
+    quorum_threshold: Option<f64>,
+    count: Option<u8>,
+    max_loss: Option<u8>,
+    max_rtt: Option<u64>,
+    degraded_fail: bool,
+    timeout: Option<u64>,
+    retries: Option<u32>,
+    backoff_base: Option<u64>,
+    max_wait: Option<u64>,
+    probe: Option<ProbeMode>,
+    url: Option<Url>,
+    throughput_url: Option<Url>,
+    min_throughput: Option<u64>,
+    dns_name: Option<String>,
+    dns_servers: Vec<IpAddr>,
+    jobs: Option<usize>,
+    carrier_wait: Option<u64>,
+    dhcp_backend: Option<Option<DhcpBackend>>,
+    dhcp_command: Option<String>,
+    metrics_addr: Option<SocketAddr>,
+    metrics_port: Option<u16>,
+    health_addr: Option<SocketAddr>,
+    health_port: Option<u16>,
+    pid_file: Option<PathBuf>,
+    reboot_after: Option<Option<u64>>,
+    recovery_command: Option<String>,
+    log_backend: Option<Backend>,
+    on_online: Option<String>,
+    on_offline: Option<String>,
+    on_change: Option<String>,
+    wait_timeout: Option<u64>,
+    wait_interval: Option<u64>,
+}
+
+struct Args(std::iter::Skip<std::env::Args>);
+
+impl Args {
+    fn raw(&mut self, flag: &str) -> String {
+        self.0.next().unwrap_or_else(|| {
+            eprintln!("cw-netup: {} needs a value", flag);
+            std::process::exit(WatchdogError::Usage.exit_code());
+        })
+    }
+
+    fn value<T: FromStr>(&mut self, flag: &str) -> T {
+        let v = self.raw(flag);
+        v.parse().unwrap_or_else(|_| bad(flag, &v))
+    }
+
+    fn positive<T: FromStr + Default + PartialOrd>(&mut self, flag: &str) -> T {
+        let v = self.raw(flag);
+        v.parse()
+            .ok()
+            .filter(|n| *n > T::default())
+            .unwrap_or_else(|| bad(flag, &v))
+    }
+
+    /// A positive time span such as `90`, `30s` or `5m`, in seconds.
+    fn span(&mut self, flag: &str) -> u64 {
+        let v = self.raw(flag);
+        history::parse_span(&v)
+            .filter(|s| *s > 0)
+            .unwrap_or_else(|| bad(flag, &v))
+    }
+}
+
+impl Cli {
+    pub fn parse() -> Cli {
+        let mut c = Cli::default();
+        let mut a = Args(std::env::args().skip(1));
+        while let Some(f) = a.0.next() {
+            match f.as_str() {
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                "--config" => c.config = Some(PathBuf::from(a.raw(&f))),
+                "--print-config" => c.print_config = true,
+                "--list-ifaces" => c.list_ifaces = true,
+                "--release" => c.release = true,
+                "--repair" | "repair" => c.repair = true,
+                "--iface" => c.ifaces.push(a.raw(&f)),
+                "--status" | "status" => c.status = true,
+                "--doctor" | "doctor" => c.doctor = true,
+                "--history" | "history" => c.history = true,
+                "--audit" | "audit" => c.audit = true,
+                "--last" => c.last = Some(a.positive(&f)),
+                "--rollback-last" => {
+                    c.rollback_last = true;
+                    c.audit = true;
+                }
+                "--since" => c.since = Some(a.span(&f)),
+                "--daemon" => c.daemon = true,
+                "--dry-run" => c.dry_run = true,
+                "--benchmark" => c.benchmark = true,
+                "--once" => c.once = true,
+                "--wait" => {
+                    c.wait = Some((60, 2));
+                    c.once = true;
+                }
+                "--wait-timeout" => c.wait_timeout = Some(a.span(&f)),
+                "--wait-interval" => c.wait_interval = Some(a.positive(&f)),
+                "--netns" => c.netns = Some(a.raw(&f)),
+                "--netns-path" => c.netns_path = Some(PathBuf::from(a.raw(&f))),
+                "-v" | "--verbose" => c.log_level = Some(Level::Debug),
+                "-q" | "--quiet" => {
+                    c.log_level = Some(Level::Error);
+                    c.quiet = true;
+                }
+                "--state-only" => c.state_only = true,
+                "--on-online" => c.on_online = Some(a.raw(&f)),
+                "--on-offline" => c.on_offline = Some(a.raw(&f)),
+                "--on-change" => c.on_change = Some(a.raw(&f)),
+                "--interval" => {
+                    c.interval = Some(a.positive(&f));
+                    c.daemon = true;
+                }
+                "--fail-threshold" => c.fail_threshold = Some(a.positive(&f)),
+                "--adaptive" => {
+                    c.adaptive = true;
+                    c.daemon = true;
+                }
+                "--max-interval" => {
+                    c.max_interval = Some(a.positive(&f));
+                    c.adaptive = true;
+                    c.daemon = true;
+                }
+                "--include" => c.include.push(a.raw(&f)),
+                "--exclude" => c.exclude.push(a.raw(&f)),
+                "--prefer" => c.prefer.push(a.raw(&f)),
+                "--network-manager" => c.network_manager = Some(a.value(&f)),
+                "--failback-hold" => c.failback_hold = Some(a.value(&f)),
+                "--target" | "--tcp" => {
+                    let v = a.raw(&f);
+                    let parse = match f.as_str() {
+                        "--tcp" => ProbeMethod::tcp,
+                        _ => ProbeMethod::target,
+                    };
+                    let (t, i) = ProbeMethod::bound(&v, parse).unwrap_or_else(|| bad(&f, &v));
+                    if let Some(i) = i {
+                        c.bound.push((t.clone(), i));
+                    }
+                    c.targets.push(t);
+                }
+                "--resolve" => {
+                    let v = a.raw(&f);
+                    c.targets
+                        .push(ProbeMethod::dns(&v).unwrap_or_else(|| bad(&f, &v)));
+                }
+                "--quorum" => c.quorum = Some(a.positive(&f)),
+                "--quorum-threshold" => {
+                    let v = a.raw(&f);
+                    let t = v.parse().ok().filter(|t| (0.0..1.0).contains(t));
+                    c.quorum_threshold = Some(t.unwrap_or_else(|| bad(&f, &v)));
+                }
+                "--count" => c.count = Some(a.positive(&f)),
+                "--max-loss" => {
+                    let v = a.raw(&f);
+                    c.max_loss = Some(
+                        v.parse()
+                            .ok()
+                            .filter(|&p| p <= 100)
+                            .unwrap_or_else(|| bad(&f, &v)),
+                    );
+                }
+                "--max-rtt" => c.max_rtt = Some(a.value(&f)),
+                "--degraded-fail" => c.degraded_fail = true,
+                "--timeout" if c.wait.is_some() => c.wait_timeout = Some(a.span(&f)),
+                "--timeout" => c.timeout = Some(a.positive(&f)),
+                "--retries" => c.retries = Some(a.value(&f)),
+                "--backoff-base" => c.backoff_base = Some(a.value(&f)),
+                "--max-wait" => c.max_wait = Some(a.positive(&f)),
+                "--probe" => c.probe = Some(a.value(&f)),
+                "--url" => c.url = Some(a.value(&f)),
+                "--throughput-url" => c.throughput_url = Some(a.value(&f)),
+                "--min-throughput" => c.min_throughput = Some(a.value(&f)),
+                "--dns-name" => c.dns_name = Some(a.raw(&f)),
+                "--dns-server" => c.dns_servers.push(a.value(&f)),
+                "--jobs" => c.jobs = Some(a.value(&f)),
+                "--carrier-wait" => c.carrier_wait = Some(a.value(&f)),
+                "--ipv6" => c.ipv6 = true,
+                "--family" => c.family = Some(a.value(&f)),
+                "--force-dhcp" => c.force_dhcp = true,
+                "--repair-routes" => c.repair_routes = true,
+                "--per-interface" => c.per_interface = true,
+                "--require-all" => c.require_all = true,
+                "--no-gateway-check" => c.no_gateway_check = true,
+                "--flush-neigh" => c.flush_neigh = true,
+                "--tunnel" => c.tunnel = true,
+                "--pmtu" => c.pmtu = true,
+                "--min-mtu" => {
+                    c.min_mtu = Some(a.positive(&f));
+                    c.pmtu = true;
+                }
+                "--dhcp-backend" => {
+                    let v = a.raw(&f);
+                    c.dhcp_backend = Some(match v.as_str() {
+                        "auto" => None,
+                        b => Some(b.parse().unwrap_or_else(|_| bad(&f, b))),
+                    });
+                }
+                "--dhcp-command" => c.dhcp_command = Some(a.raw(&f)),
+                "--log-backend" => c.log_backend = Some(a.value(&f)),
+                "--metrics-addr" => c.metrics_addr = Some(a.value(&f)),
+                "--metrics-port" => c.metrics_port = Some(a.value(&f)),
+                "--health-addr" => c.health_addr = Some(a.value(&f)),
+                "--health-port" => c.health_port = Some(a.value(&f)),
+                "--pid-file" => c.pid_file = Some(PathBuf::from(a.raw(&f))),
+                "--reboot-after" => {
+                    let v = a.raw(&f);
+                    let secs = history::parse_span(&v).unwrap_or_else(|| bad(&f, &v));
+                    c.reboot_after = Some((secs > 0).then_some(secs));
+                }
+                "--recovery-command" => c.recovery_command = Some(a.raw(&f)),
+                "--output" => match a.raw(&f).as_str() {
+                    "human" | "table" => (c.json, c.ndjson) = (false, false),
+                    "json" => (c.json, c.ndjson) = (true, false),
+                    "ndjson" => (c.json, c.ndjson) = (true, true),
+                    v => bad(&f, v),
+                },
+                _ => usage(),
+            }
+        }
+        if let Some((t, i)) = &mut c.wait {
+            *t = c.wait_timeout.unwrap_or(*t);
+            *i = c.wait_interval.unwrap_or(*i);
+        }
+        c
+    }
+
+    /// CLI flags win over the config file.
+    pub fn apply(&self, cfg: &mut Config) {
+        cfg.daemon |= self.daemon;
+        cfg.ipv6 |= self.ipv6;
+        if let Some(f) = self.family {
+            cfg.set_family(f);
+            cfg.ipv6 |= f == Family::Any;
+        }
+        cfg.force_dhcp |= self.force_dhcp;
+        cfg.repair_routes |= self.repair_routes;
+        cfg.require_all |= self.require_all;
+        cfg.per_interface |= self.per_interface || self.require_all;
+        cfg.gateway_check &= !self.no_gateway_check;
+        cfg.tunnel |= self.tunnel;
+        // A dry run changes nothing, the neighbour table included.
+        cfg.flush_neigh = (cfg.flush_neigh || self.flush_neigh) && !self.dry_run;
+        cfg.pmtu |= self.pmtu;
+        if let Some(v) = self.min_mtu {
+            cfg.pmtu_min = v;
+        }
+        if let Some(v) = self.interval {
+            cfg.interval = v;
+        }
+        if let Some(v) = self.fail_threshold {
+            cfg.offline_after = v;
+        }
+        cfg.adaptive |= self.adaptive;
+        if let Some(v) = self.max_interval {
+            cfg.max_interval = v;
+        }
+        if !self.include.is_empty() {
+            cfg.include = self.include.clone();
+        }
+        if !self.exclude.is_empty() {
+            cfg.exclude = self.exclude.clone();
+        }
+        if !self.prefer.is_empty() {
+            cfg.priority = self.prefer.clone();
+        }
+        if let Some(v) = self.failback_hold {
+            cfg.failback_hold = v;
+        }
+        if let Some(m) = self.network_manager {
+            cfg.network_manager = m;
+        }
+        if !self.targets.is_empty() {
+            cfg.targets = self.targets.clone();
+        }
+        for (t, i) in &self.bound {
+            match cfg.target_opts.iter_mut().find(|(m, _)| m == t) {
+                Some((_, o)) => o.interface = Some(i.clone()),
+                None => cfg.target_opts.push((
+                    t.clone(),
+                    TargetOpts {
+                        interface: Some(i.clone()),
+                        ..TargetOpts::default()
+                    },
+                )),
+            }
+        }
+        if let Some(v) = self.quorum {
+            cfg.quorum = v;
+            cfg.quorum_threshold = None;
+        }
+        if let Some(v) = self.quorum_threshold {
+            cfg.quorum_threshold = Some(v);
+        }
+        if let Some(v) = self.count {
+            cfg.ping_count = v;
+        }
+        if let Some(v) = self.max_loss {
+            cfg.max_loss = v;
+        }
+        if let Some(v) = self.max_rtt {
+            cfg.max_rtt_ms = v;
+        }
+        if self.degraded_fail {
+            cfg.degraded_fail = true;
+        }
+        if let Some(v) = self.timeout {
+            cfg.ping_timeout = v;
+        }
+        if let Some(v) = self.retries {
+            cfg.retries = v;
+            cfg.dhcp_retries = v;
+        }
+        if let Some(v) = self.backoff_base {
+            cfg.backoff_base_ms = v;
+        }
+        if let Some(v) = self.max_wait {
+            cfg.max_wait = v;
+        }
+        if let Some(v) = self.probe {
+            cfg.probe_mode = v;
+        }
+        if let Some(v) = &self.url {
+            cfg.http_url = v.clone();
+        }
+        if let Some(v) = &self.throughput_url {
+            cfg.throughput_url = Some(v.clone());
+        }
+        if let Some(v) = self.min_throughput {
+            cfg.min_throughput = v;
+        }
+        if let Some(v) = &self.dns_name {
+            cfg.dns = true;
+            cfg.dns_name = v.clone();
+        }
+        if !self.dns_servers.is_empty() {
+            cfg.dns = true;
+            cfg.dns_servers = self.dns_servers.clone();
+        }
+        if let Some(v) = self.jobs {
+            cfg.jobs = v;
+        }
+        if let Some(v) = self.carrier_wait {
+            cfg.carrier_wait = v;
+        }
+        if let Some(v) = self.metrics_addr {
+            cfg.metrics = true;
+            cfg.metrics_addr = v;
+        }
+        if let Some(v) = self.metrics_port {
+            cfg.metrics = true;
+            cfg.metrics_addr.set_port(v);
+        }
+        if let Some(v) = self.health_addr {
+            cfg.health = true;
+            cfg.health_addr = v;
+        }
+        if let Some(v) = self.health_port {
+            cfg.health = true;
+            cfg.health_addr.set_port(v);
+        }
+        if let Some(p) = &self.pid_file {
+            cfg.pid_file = (!p.as_os_str().is_empty()).then(|| p.clone());
+        }
+        if let Some(v) = self.reboot_after {
+            cfg.reboot_after = v;
+        }
+        if let Some(v) = &self.recovery_command {
+            let cmd: Vec<String> = v.split_whitespace().map(String::from).collect();
+            if cmd.is_empty() {
+                bad("--recovery-command", v);
+            }
+            cfg.recovery_command = cmd;
+        }
+        if self.on_online.is_some() {
+            cfg.on_online = self.on_online.clone();
+        }
+        if self.on_offline.is_some() {
+            cfg.on_offline = self.on_offline.clone();
+        }
+        if self.on_change.is_some() {
+            cfg.on_change = self.on_change.clone();
+        }
+        if let Some(b) = self.dhcp_backend {
+            cfg.dhcp_backend = b;
+        }
+        if let Some(b) = self.log_backend {
+            cfg.log_backend = b;
+        }
+        if let Some(v) = &self.dhcp_command {
+            let cmd: Vec<String> = v.split_whitespace().map(String::from).collect();
+            if cmd.is_empty() {
+                bad("--dhcp-command", v);
+            }
+            cfg.dhcp_command = Some(cmd);
+        }
+    }
+}
@@ -0,0 +1,2216 @@
+use crate::dhcp::DhcpBackend;
+use crate::http::Url;
+use crate::log::Backend;
+use crate::manager::ManagerMode;
+use crate::net::Cidr;
+use crate::toml::{self, Table, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Tried in order when no `--config` is given.
+#[cfg(unix)]
+pub const DEFAULT_PATHS: [&str; 2] = [
+    "/etc/cyber-watchdog/config.toml",
+    "/etc/cyber-watchdog.toml",
+];
+#[cfg(windows)]
+pub const DEFAULT_PATHS: [&str; 2] = [
+    r"C:\ProgramData\cyber-watchdog\config.toml",
+    r"C:\ProgramData\cyber-watchdog.toml",
+];
+
+/// Where `*.toml` fragments with more `[[probes]]` are read from.
+#[cfg(unix)]
+pub const PROBES_DIR: &str = "/etc/cyber-watchdog/probes.d";
+#[cfg(windows)]
+pub const PROBES_DIR: &str = r"C:\ProgramData\cyber-watchdog\probes.d";
+
+/// Where the PID file and the state file's directory go by default, and
+/// the files kept across boots.
+#[cfg(target_os = "linux")]
+const RUN_DIR: &str = "/run";
+#[cfg(target_os = "macos")]
+const RUN_DIR: &str = "/var/run";
+#[cfg(windows)]
+const RUN_DIR: &str = r"C:\ProgramData";
+#[cfg(unix)]
+const LIB_DIR: &str = "/var/lib";
+#[cfg(windows)]
+const LIB_DIR: &str = r"C:\ProgramData";
+
+/// How `recovery_command` reboots by default.
+#[cfg(target_os = "linux")]
+const REBOOT: &[&str] = &["systemctl", "reboot"];
+#[cfg(target_os = "macos")]
+const REBOOT: &[&str] = &["shutdown", "-r", "now"];
+#[cfg(windows)]
+const REBOOT: &[&str] = &["shutdown", "/r", "/t", "0"];
+
+/// Which probes decide connectivity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeMode {
+    Icmp,
+    /// Like `icmp`, but IP targets get a TCP handshake on port 443 instead
+    /// of an echo, for networks that drop ICMP.
+    Tcp,
+    Http,
+    /// Both; ping without HTTP is DEGRADED.
+    Both,
+}
+
+impl ProbeMode {
+    /// Whether the configured targets are probed (ICMP or TCP).
+    pub fn targets(self) -> bool {
+        self != ProbeMode::Http
+    }
+
+    pub fn http(self) -> bool {
+        matches!(self, ProbeMode::Http | ProbeMode::Both)
+    }
+}
+
+impl FromStr for ProbeMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ProbeMode, ()> {
+        match s {
+            "icmp" => Ok(ProbeMode::Icmp),
+            "tcp" => Ok(ProbeMode::Tcp),
+            "http" => Ok(ProbeMode::Http),
+            "both" => Ok(ProbeMode::Both),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for ProbeMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ProbeMode::Icmp => "icmp",
+            ProbeMode::Tcp => "tcp",
+            ProbeMode::Http => "http",
+            ProbeMode::Both => "both",
+        })
+    }
+}
+
+/// Which address families must be up for ONLINE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    V4,
+    V6,
+    /// Either family; without IPv6 enabled, the quorum spans all targets.
+    Any,
+    Both,
+}
+
+impl FromStr for Family {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Family, ()> {
+        match s {
+            "4" => Ok(Family::V4),
+            "6" => Ok(Family::V6),
+            "any" => Ok(Family::Any),
+            "both" => Ok(Family::Both),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Family::V4 => "4",
+            Family::V6 => "6",
+            Family::Any => "any",
+            Family::Both => "both",
+        })
+    }
+}
+
+/// How one probe target is checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeMethod {
+    Icmp(IpAddr),
+    /// A TCP handshake, for networks that drop ICMP.
+    Tcp {
+        host: String,
+        port: u16,
+    },
+    /// Resolution of `hostname`, through `server` or else the system
+    /// resolver. Decides DNS_BROKEN rather than reachability.
+    Dns {
+        hostname: String,
+        server: Option<IpAddr>,
+    },
+}
+
+impl ProbeMethod {
+    pub fn method(&self) -> &'static str {
+        match self {
+            ProbeMethod::Icmp(_) => "icmp",
+            ProbeMethod::Tcp { .. } => "tcp",
+            ProbeMethod::Dns { .. } => "dns",
+        }
+    }
+
+    pub fn is_dns(&self) -> bool {
+        matches!(self, ProbeMethod::Dns { .. })
+    }
+
+    /// `hostname` or `hostname@server`.
+    pub fn dns(s: &str) -> Option<ProbeMethod> {
+        let (h, server) = match s.split_once('@') {
+            Some((h, a)) => (h, Some(a.parse().ok()?)),
+            None => (s, None),
+        };
+        (!h.is_empty()).then(|| ProbeMethod::Dns {
+            hostname: h.into(),
+            server,
+        })
+    }
+
+    /// An IP address for ICMP, or `host:port` for TCP.
+    pub fn target(s: &str) -> Option<ProbeMethod> {
+        match s.parse() {
+            Ok(a) => Some(ProbeMethod::Icmp(a)),
+            Err(_) => ProbeMethod::tcp(s),
+        }
+    }
+
+    /// `host:port`, with IPv6 literals in brackets.
+    pub fn tcp(s: &str) -> Option<ProbeMethod> {
+        let (h, p) = s.rsplit_once(':')?;
+        let host = h
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(h);
+        if host.is_empty() || (host.contains(':') && !h.starts_with('[')) {
+            return None;
+        }
+        Some(ProbeMethod::Tcp {
+            host: host.into(),
+            port: p.parse().ok().filter(|&p| p > 0)?,
+        })
+    }
+
+    /// `target@iface`, a target only probed through `iface`, as `--target`
+    /// and `--tcp` take it; `parse` reads the target.
+    pub fn bound(
+        s: &str,
+        parse: fn(&str) -> Option<ProbeMethod>,
+    ) -> Option<(ProbeMethod, Option<String>)> {
+        match s.rsplit_once('@') {
+            Some((t, i)) if valid_iface(i) => Some((parse(t)?, Some(i.into()))),
+            Some(_) => None,
+            None => Some((parse(s)?, None)),
+        }
+    }
+}
+
+/// A `[[probes]]` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeConfig {
+    pub target: ProbeMethod,
+    pub opts: TargetOpts,
+}
+
+/// Settings a `[[probes]]` entry gives its target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetOpts {
+    /// Its share of a weighted quorum.
+    pub weight: u32,
+    /// Instead of `[probe] count`, `failure_threshold` and `timeout`.
+    pub count: Option<u8>,
+    pub failure_threshold: Option<u8>,
+    pub timeout: Option<u64>,
+    /// Where its probes leave from: only through `interface`, from the
+    /// local address `source`, with firewall mark `fwmark` for policy
+    /// routing.
+    pub interface: Option<String>,
+    pub source: Option<IpAddr>,
+    pub fwmark: Option<u32>,
+}
+
+const DEFAULT_OPTS: TargetOpts = TargetOpts {
+    weight: 1,
+    count: None,
+    failure_threshold: None,
+    timeout: None,
+    interface: None,
+    source: None,
+    fwmark: None,
+};
+
+impl Default for TargetOpts {
+    fn default() -> TargetOpts {
+        DEFAULT_OPTS
+    }
+}
+
+/// A name SO_BINDTODEVICE can take.
+fn valid_iface(s: &str) -> bool {
+    !s.is_empty() && s.len() < 16 && !s.contains(['/', ' ', ':', '@'])
+}
+
+impl fmt::Display for ProbeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProbeMethod::Icmp(a) => write!(f, "{}", a),
+            ProbeMethod::Tcp { host, port } if host.contains(':') => {
+                write!(f, "[{}]:{}", host, port)
+            }
+            ProbeMethod::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            ProbeMethod::Dns { hostname, server } => match server {
+                Some(a) => write!(f, "{}@{}", hostname, a),
+                None => f.write_str(hostname),
+            },
+        }
+    }
+}
+
+/// Address, default route and resolvers for an interface DHCP failed on.
+#[derive(Debug, Clone)]
+pub struct StaticConfig {
+    pub address: Ipv4Addr,
+    pub prefix_len: u8,
+    pub gateway: Option<Ipv4Addr>,
+    /// Written to `/etc/resolv.conf` when not empty.
+    pub dns: Vec<IpAddr>,
+}
+
+/// A `[[vlans]]` entry: the tagged sub-interface `<parent>.<id>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VlanConfig {
+    pub parent: String,
+    pub id: u16,
+    /// Deleted again by the exit teardown and `--release`.
+    pub delete_on_exit: bool,
+}
+
+/// A `[[namespace]]` entry: another network namespace checked alongside
+/// the host's, as its own run with these overrides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceConfig {
+    pub name: String,
+    /// `None` is `/run/netns/<name>`, where `ip netns add` puts it.
+    pub path: Option<PathBuf>,
+    /// In place of `[interfaces] manage`.
+    pub interfaces: Option<Vec<String>>,
+    /// In place of `[probe] targets`.
+    pub targets: Option<Vec<ProbeMethod>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Interfaces to manage; empty means every non-loopback interface.
+    pub interfaces: Vec<String>,
+    /// Glob patterns applied to discovered interfaces (not to `interfaces`).
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Only manage Ethernet-type links (wired and wireless).
+    pub ether_only: bool,
+    /// Leave interfaces enslaved to a bridge alone.
+    pub skip_bridge_ports: bool,
+    /// Interfaces brought up in parallel; 0 means all at once.
+    pub jobs: usize,
+    /// Seconds to wait for carrier after setting a link up; DHCP is skipped
+    /// on links that still have none.
+    pub carrier_wait: u64,
+    /// Uplinks brought up in this order until one gets ONLINE; the rest
+    /// stay on standby. Managed even when discovery would leave them out.
+    pub priority: Vec<String>,
+    /// Standby uplinks are set down, not only left without a lease.
+    pub standby_down: bool,
+    /// Mbps a link with carrier is warned about below, as it is at half
+    /// duplex; 0 = never.
+    pub min_speed: u32,
+    /// Under NetworkManager or networkd the interfaces are only watched.
+    pub network_manager: ManagerMode,
+    pub targets: Vec<ProbeMethod>,
+    /// Targets whose `[[probes]]` entry sets something.
+    pub target_opts: Vec<(ProbeMethod, TargetOpts)>,
+    /// `*.toml` files here add their `[[probes]]` to the targets; `None`
+    /// reads none.
+    pub probes_dir: Option<PathBuf>,
+    /// Minimum number of reachable targets to report ONLINE.
+    pub quorum: usize,
+    /// ONLINE when the reachable targets hold more than this share of the
+    /// total weight; replaces `quorum` when set. 0.5 by default with
+    /// `[[probes]]` targets and no `quorum`.
+    pub quorum_threshold: Option<f64>,
+    pub ping_count: u8,
+    /// Lost replies out of `ping_count` that make a target unreachable;
+    /// `None` is all of them.
+    pub failure_threshold: Option<u8>,
+    pub ping_timeout: u64,
+    /// Extra full probe rounds before giving up.
+    pub retries: u32,
+    pub probe_mode: ProbeMode,
+    /// Probe through each managed interface separately (SO_BINDTODEVICE);
+    /// ONLINE when any of them is, or all with `require_all`.
+    pub per_interface: bool,
+    pub require_all: bool,
+    /// Check the link and default gateway before external targets, and skip
+    /// those when either is down. Not with `per_interface` or IPv6.
+    pub gateway_check: bool,
+    /// A gateway that does not answer and whose neighbour entry failed has
+    /// the entry flushed, and is pinged again.
+    pub flush_neigh: bool,
+    /// A refused TCP connection (RST) proves the host reachable too.
+    pub tcp_refused_ok: bool,
+    /// Measure each interface's path MTU to the first IP target after
+    /// bring-up, and warn when it is below `pmtu_min`; an ONLINE check
+    /// whose path MTU is below it is DEGRADED.
+    pub pmtu: bool,
+    pub pmtu_min: u16,
+    /// ICMP payload of the largest echo tried.
+    pub pmtu_payload: u16,
+    /// On OFFLINE, trace the path to the first IP target over up to this
+    /// many hops; 0 turns it off.
+    pub traceroute_hops: u8,
+    /// Echo outcomes kept for mean/p95 latency and loss.
+    pub latency_window: usize,
+    /// Loss is judged over this many most recent echoes.
+    pub loss_window: usize,
+    /// Percent loss above which ONLINE becomes DEGRADED, over the window
+    /// and for each target's own echoes alike.
+    pub max_loss: u8,
+    /// p95 ceiling in milliseconds; 0 disables it.
+    pub max_p95_ms: u64,
+    /// Mean RTT ceiling per target in milliseconds; 0 disables it.
+    pub max_rtt_ms: u64,
+    /// Targets past `max_loss` or `max_rtt_ms` do not count towards the
+    /// quorum, rather than counting and making it DEGRADED.
+    pub degraded_fail: bool,
+    /// Fetched by the HTTP probe; anything but `http_status` is a failure,
+    /// and a page or redirect in place of an expected 204 a captive portal.
+    pub http_url: Url,
+    pub http_status: u16,
+    pub http_timeout: u64,
+    /// Where the HTTP probe's host may resolve to; an answer from outside
+    /// them is a captive portal's. Empty takes any public address, so only
+    /// a hostname resolving to a private one is a portal's.
+    pub http_expected_ranges: Vec<Cidr>,
+    /// Resolve `dns_name`; failure with the network up is DNS_BROKEN.
+    pub dns: bool,
+    pub dns_name: String,
+    /// Empty means the `/etc/resolv.conf` nameservers.
+    pub dns_servers: Vec<IpAddr>,
+    pub dns_timeout: u64,
+    /// Downloaded after an ONLINE verdict to measure throughput.
+    pub throughput_url: Option<Url>,
+    /// kbit/s below which that verdict becomes DEGRADED; 0 only reports.
+    pub min_throughput: u64,
+    pub throughput_timeout: u64,
+    /// `None` detects one on `$PATH`; `auto` in the config file.
+    pub dhcp_backend: Option<DhcpBackend>,
+    /// Custom client command line instead of the backend's; the interface
+    /// name is appended.
+    pub dhcp_command: Option<Vec<String>>,
+    pub dhcp_timeout: u64,
+    /// Extra DHCP client runs after a failure.
+    pub dhcp_retries: u32,
+    /// Seconds between DHCP retries in daemon mode while an interface is on
+    /// its `[fallback_static]` address; 0 retries only when a check fails.
+    pub fallback_retry: u64,
+    /// Renew once less than this fraction of a lease is left, 0.0-1.0
+    /// exclusive; 0.5 renews half-way through.
+    pub renew_remaining: f64,
+    /// A lease only counts once its gateway answers ARP.
+    pub verify_gateway: bool,
+    /// Re-add a default route that is missing after bring-up, via the
+    /// gateway the lease or `[fallback_static]` names.
+    pub repair_routes: bool,
+    /// ARP-probe a fresh lease's address (RFC 5227) for another host on it.
+    pub conflict_check: bool,
+    /// With `conflict_check`, release a lease whose address is taken and
+    /// count it as failed.
+    pub decline_conflict: bool,
+    /// First retry delay; doubled (with jitter) for each further one.
+    pub backoff_base_ms: u64,
+    /// Ceiling for a single retry delay.
+    pub backoff_max_ms: u64,
+    /// Spread of the random factor applied to each delay, 0.0-1.0: a delay
+    /// `d` becomes anything in `d * (1 ± jitter/2)`.
+    pub jitter: f64,
+    /// Bound on a one-shot run (or one daemon cycle) including retries;
+    /// 0 means none.
+    pub max_wait: u64,
+    /// `[commands]`: seconds `ip` gets before it is killed.
+    pub ip_timeout: u64,
+    /// For the `ping` fallback; 0 derives it from the probe count and timeout.
+    pub ping_cmd_timeout: u64,
+    /// For `iw` and `wpa_cli` queries.
+    pub wireless_timeout: u64,
+    /// Between SIGTERM and SIGKILL for a command past its time.
+    pub kill_grace: u64,
+    pub skip_dhcp: bool,
+    /// Run wpa_supplicant with this file on wireless interfaces and wait
+    /// `assoc_timeout` seconds for association before DHCP.
+    pub wpa_config: Option<PathBuf>,
+    pub assoc_timeout: u64,
+    /// When a wireless interface is up but not associated, `wpa_cli
+    /// reconnect`, then restart wpa_supplicant if `wpa_config` is set.
+    pub reconnect: bool,
+    /// `[fallback_static.<iface>]`: configured by hand when DHCP fails.
+    pub fallback_static: BTreeMap<String, StaticConfig>,
+    /// Created on startup where missing, then managed like other links.
+    pub vlans: Vec<VlanConfig>,
+    /// Checked too, each from a thread of its own inside it.
+    pub namespaces: Vec<NamespaceConfig>,
+    /// Renew even on interfaces that already have an address and route.
+    pub force_dhcp: bool,
+    /// `[ipv6] enabled`: wait for SLAAC, optionally run DHCPv6, probe v6 targets.
+    pub ipv6: bool,
+    pub ipv6_targets: Vec<IpAddr>,
+    pub dhcp6: bool,
+    pub dhcp6_command: Option<Vec<String>>,
+    /// Seconds to wait for a SLAAC global address before DHCPv6.
+    pub slaac_wait: u64,
+    pub family: Family,
+    /// Tells a tunnel that is down from the uplink beneath it: an OFFLINE
+    /// check probes `tunnel_targets` again, bound to the uplink.
+    pub tunnel: bool,
+    /// Globs; empty for the WireGuard, tun/tap and IP tunnel links found.
+    pub tunnel_interfaces: Vec<String>,
+    /// Empty for the probe targets.
+    pub tunnel_targets: Vec<ProbeMethod>,
+    /// Run through `sh -c` when the tunnel is down and the uplink works.
+    pub tunnel_restart: Option<String>,
+    /// `mode = "daemon"`: keep running instead of checking once.
+    pub daemon: bool,
+    /// Seconds between checks in daemon mode.
+    pub interval: u64,
+    /// Daemon state and transition history; `None` keeps it in memory.
+    pub state_file: Option<PathBuf>,
+    /// Holds the daemon's PID and keeps a second instance from starting.
+    pub pid_file: Option<PathBuf>,
+    /// A JSON line per state change, for `history`; `None` keeps none.
+    pub history_file: Option<PathBuf>,
+    /// Bytes, and days since its first record, after which the history
+    /// file is rotated to `<file>.1`.
+    pub history_max_size: u64,
+    pub history_max_days: u64,
+    /// A JSON line per change made to the system, for `audit`; `None`
+    /// keeps none. Rotated by `history_max_size` as well.
+    pub audit_file: Option<PathBuf>,
+    pub log_backend: Backend,
+    /// More than `flap_threshold` transitions within `flap_window` minutes
+    /// is FLAPPING: remediation hooks are held back. An interface whose
+    /// carrier changes as often is FLAPPING too.
+    pub flap_window: u64,
+    pub flap_threshold: usize,
+    /// Seconds a flapping interface's carrier must hold before it is
+    /// brought up again; 0 does not wait.
+    pub flap_hold: u64,
+    /// Run `recovery_command` after this many seconds OFFLINE without a
+    /// break, and again each time that much more has passed.
+    pub reboot_after: Option<u64>,
+    pub recovery_command: Vec<String>,
+    /// On SIGTERM/SIGINT the daemon releases its leases, and with
+    /// `teardown_link_down` sets the links down, before exiting.
+    pub teardown_on_exit: bool,
+    pub teardown_link_down: bool,
+    /// Seconds a higher-priority uplink must stay healthy before the
+    /// daemon fails back to it.
+    pub failback_hold: u64,
+    /// Probe cycles in a row that must fail before the daemon goes
+    /// OFFLINE; those before it leave the state as it was.
+    pub offline_after: u32,
+    /// Seconds of checks the daemon keeps for its rolling success rate,
+    /// p95 round-trip time and streaks.
+    pub availability_window: u64,
+    /// After `adaptive_after` checks in a row ONLINE, the daemon stretches
+    /// the time between checks by `adaptive_factor` a check, up to
+    /// `max_interval` seconds, and probes only one target; the first that
+    /// fails brings back every target and `interval`.
+    pub adaptive: bool,
+    pub max_interval: u64,
+    pub adaptive_after: u64,
+    pub adaptive_factor: u64,
+    /// Serve Prometheus metrics on `metrics_addr` in daemon mode.
+    pub metrics: bool,
+    pub metrics_addr: SocketAddr,
+    /// Answer health checks on `health_addr` in daemon mode.
+    pub health: bool,
+    pub health_addr: SocketAddr,
+    /// POSTed a JSON body on every state change in daemon mode.
+    pub webhooks: Vec<Url>,
+    pub webhook_timeout: u64,
+    /// A Telegram bot token and the chat it writes to.
+    pub telegram: Option<(String, String)>,
+    /// Seconds an outage must last before it is reported, to webhooks and
+    /// Telegram; shorter ones are not, nor the recovery.
+    pub notify_min_outage: u64,
+    /// Seconds at least between two such reports.
+    pub notify_min_interval: u64,
+    /// Shell commands run on daemon state transitions.
+    pub on_online: Option<String>,
+    pub on_offline: Option<String>,
+    pub on_change: Option<String>,
+    pub hook_timeout: u64,
+    /// MQTT broker host and port for state publishing; `None` is off.
+    pub mqtt_broker: Option<(String, Option<u16>)>,
+    pub mqtt_topic: String,
+    pub mqtt_client_id: String,
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    pub mqtt_tls: bool,
+    pub mqtt_ca_file: Option<PathBuf>,
+    /// Publish after every check, not just on transitions.
+    pub mqtt_every_check: bool,
+    /// Home Assistant discovery configs, published on startup.
+    pub mqtt_discovery: bool,
+    pub mqtt_discovery_prefix: String,
+    pub mqtt_timeout: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            interfaces: Vec::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            ether_only: true,
+            skip_bridge_ports: true,
+            jobs: 0,
+            carrier_wait: 2,
+            priority: Vec::new(),
+            standby_down: false,
+            min_speed: 100,
+            network_manager: ManagerMode::Auto,
+            targets: vec![
+                ProbeMethod::Icmp(IpAddr::from([8, 8, 8, 8])),
+                ProbeMethod::Dns {
+                    hostname: "one.one.one.one".into(),
+                    server: None,
+                },
+            ],
+            target_opts: Vec::new(),
+            quorum: 1,
+            quorum_threshold: None,
+            ping_count: 1,
+            failure_threshold: None,
+            ping_timeout: 3,
+            retries: 0,
+            probe_mode: ProbeMode::Icmp,
+            per_interface: false,
+            require_all: false,
+            gateway_check: true,
+            flush_neigh: false,
+            tcp_refused_ok: false,
+            pmtu: false,
+            pmtu_min: 1280,
+            pmtu_payload: 1472,
+            traceroute_hops: 30,
+            probes_dir: Some(PathBuf::from(PROBES_DIR)),
+            latency_window: 60,
+            loss_window: 10,
+            max_loss: 20,
+            max_p95_ms: 0,
+            max_rtt_ms: 0,
+            degraded_fail: false,
+            http_url: "http://connectivitycheck.gstatic.com/generate_204"
+                .parse()
+                .unwrap(),
+            http_status: 204,
+            http_timeout: 5,
+            http_expected_ranges: Vec::new(),
+            dns: false,
+            dns_name: "dns.google".into(),
+            dns_servers: Vec::new(),
+            dns_timeout: 3,
+            throughput_url: None,
+            min_throughput: 0,
+            throughput_timeout: 20,
+            dhcp_backend: None,
+            dhcp_command: None,
+            dhcp_timeout: 30,
+            verify_gateway: true,
+            repair_routes: false,
+            conflict_check: true,
+            decline_conflict: false,
+            dhcp_retries: 0,
+            renew_remaining: 0.5,
+            fallback_retry: 300,
+            backoff_base_ms: 1000,
+            backoff_max_ms: 60_000,
+            jitter: 0.5,
+            max_wait: 0,
+            ip_timeout: 10,
+            ping_cmd_timeout: 0,
+            wireless_timeout: 5,
+            kill_grace: 2,
+            skip_dhcp: false,
+            fallback_static: BTreeMap::new(),
+            vlans: Vec::new(),
+            namespaces: Vec::new(),
+            wpa_config: None,
+            assoc_timeout: 30,
+            reconnect: true,
+            force_dhcp: false,
+            ipv6: false,
+            ipv6_targets: vec!["2001:4860:4860::8888".parse().unwrap()],
+            dhcp6: false,
+            dhcp6_command: None,
+            slaac_wait: 5,
+            tunnel: false,
+            tunnel_interfaces: Vec::new(),
+            tunnel_targets: Vec::new(),
+            tunnel_restart: None,
+            family: Family::Any,
+            daemon: false,
+            interval: 30,
+            state_file: Some(Path::new(RUN_DIR).join("cyber-watchdog").join("state.json")),
+            pid_file: Some(Path::new(RUN_DIR).join("cyber-watchdog.pid")),
+            history_file: Some(
+                Path::new(LIB_DIR)
+                    .join("cyber-watchdog")
+                    .join("history.jsonl"),
+            ),
+            history_max_size: 1 << 20,
+            history_max_days: 30,
+            audit_file: Some(
+                Path::new(LIB_DIR)
+                    .join("cyber-watchdog")
+                    .join("audit.jsonl"),
+            ),
+            log_backend: Backend::Auto,
+            flap_window: 10,
+            flap_threshold: 5,
+            flap_hold: 0,
+            reboot_after: None,
+            recovery_command: REBOOT.iter().map(|s| s.to_string()).collect(),
+            teardown_on_exit: false,
+            teardown_link_down: true,
+            failback_hold: 60,
+            offline_after: 2,
+            availability_window: 3600,
+            adaptive: false,
+            max_interval: 300,
+            adaptive_after: 5,
+            adaptive_factor: 2,
+            metrics: false,
+            metrics_addr: SocketAddr::from(([127, 0, 0, 1], 9101)),
+            health: false,
+            health_addr: SocketAddr::from(([127, 0, 0, 1], 9102)),
+            webhooks: Vec::new(),
+            webhook_timeout: 10,
+            telegram: None,
+            notify_min_outage: 0,
+            notify_min_interval: 0,
+            on_online: None,
+            on_offline: None,
+            on_change: None,
+            hook_timeout: 30,
+            mqtt_broker: None,
+            mqtt_topic: "cyber-watchdog/state".into(),
+            mqtt_client_id: "cw-netup".into(),
+            mqtt_username: None,
+            mqtt_password: None,
+            mqtt_tls: false,
+            mqtt_ca_file: None,
+            mqtt_every_check: false,
+            mqtt_discovery: true,
+            mqtt_discovery_prefix: "homeassistant".into(),
+            mqtt_timeout: 10,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::ParseError),
+    Invalid(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(p, e) => write!(f, "{}: {}", p.display(), e),
+            ConfigError::Parse(p, e) => write!(f, "{}: {}", p.display(), e),
+            ConfigError::Invalid(k, m) => write!(f, "`{}`: {}", k, m),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// What the `[[probes]]` entry for `t` set, if there is one.
+    pub fn opts(&self, t: &ProbeMethod) -> &TargetOpts {
+        self.target_opts
+            .iter()
+            .find(|(m, _)| m == t)
+            .map_or(&DEFAULT_OPTS, |(_, o)| o)
+    }
+
+    /// Probe targets including the IPv6 ones when IPv6 is enabled, minus
+    /// literal addresses of a family `family` leaves out.
+    pub fn probe_targets(&self) -> Vec<ProbeMethod> {
+        let mut v = self.targets.clone();
+        if self.ipv6 {
+            for &a in &self.ipv6_targets {
+                if !v.contains(&ProbeMethod::Icmp(a)) {
+                    v.push(ProbeMethod::Icmp(a));
+                }
+            }
+        }
+        v.retain(|t| match (t, self.family) {
+            (ProbeMethod::Icmp(a), Family::V4) => a.is_ipv4(),
+            (ProbeMethod::Icmp(a), Family::V6) => a.is_ipv6(),
+            _ => true,
+        });
+        v
+    }
+
+    fn add_probe(&mut self, p: ProbeConfig) {
+        if p.opts != TargetOpts::default() {
+            self.target_opts.push((p.target.clone(), p.opts));
+        }
+        self.targets.push(p.target);
+    }
+
+    /// Sets `family`; IPv6 is switched on for 6 and both, off for 4.
+    pub fn set_family(&mut self, f: Family) {
+        self.family = f;
+        match f {
+            Family::V4 => self.ipv6 = false,
+            Family::V6 | Family::Both => self.ipv6 = true,
+            Family::Any => {}
+        }
+    }
+
+    /// What `[[namespace]]` entry `ns` runs with: this, with its overrides,
+    /// less what there is one of per host (the PID and state files, the
+    /// metrics and health listeners, the recovery command, VLANs, other
+    /// namespaces). No host network manager owns a namespace's links, so
+    /// `auto` does not go looking for one.
+    pub fn for_namespace(&self, ns: &NamespaceConfig) -> Config {
+        let mut c = self.clone();
+        if let Some(i) = &ns.interfaces {
+            c.interfaces = i.clone();
+        }
+        if let Some(t) = &ns.targets {
+            c.targets = t.clone();
+        }
+        if c.network_manager == ManagerMode::Auto {
+            c.network_manager = ManagerMode::None;
+        }
+        c.state_file = None;
+        c.pid_file = None;
+        c.history_file = None;
+        c.metrics = false;
+        c.health = false;
+        c.reboot_after = None;
+        c.vlans.clear();
+        c.namespaces.clear();
+        c
+    }
+
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let src = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+        let t = toml::parse(&src).map_err(|e| ConfigError::Parse(path.into(), e))?;
+        Config::from_table(t)
+    }
+
+    /// Loads `path` if given, else the first default location that exists,
+    /// else built-in defaults.
+    pub fn resolve(path: Option<&Path>) -> Result<Config, ConfigError> {
+        if let Some(p) = path {
+            return Config::load(p);
+        }
+        match DEFAULT_PATHS.iter().map(Path::new).find(|p| p.exists()) {
+            Some(p) => Config::load(p),
+            None => Config::from_table(Table::new()),
+        }
+    }
+
+    fn from_table(mut root: Table) -> Result<Config, ConfigError> {
+        let mut c = Config::default();
+        match root.remove("mode") {
+            None => {}
+            Some(Value::Str(m)) if m == "oneshot" => c.daemon = false,
+            Some(Value::Str(m)) if m == "daemon" => c.daemon = true,
+            Some(_) => {
+                return Err(ConfigError::Invalid(
+                    "mode".into(),
+                    "expected \"oneshot\" or \"daemon\"".into(),
+                ))
+            }
+        }
+        let mut s = Section::take(&mut root, "interfaces")?;
+        if let Some(v) = s.str_list("manage")? {
+            c.interfaces = v;
+        }
+        // allow_interfaces/deny_interfaces are other names for the same lists.
+        for (k, alias, list) in [
+            ("include", "allow_interfaces", &mut c.include),
+            ("exclude", "deny_interfaces", &mut c.exclude),
+        ] {
+            match (s.str_list(k)?, s.str_list(alias)?) {
+                (Some(_), Some(_)) => {
+                    return Err(s.invalid(alias, &format!("conflicts with `{}`", k)))
+                }
+                (Some(v), None) | (None, Some(v)) => *list = v,
+                (None, None) => {}
+            }
+        }
+        if let Some(v) = s.bool("ether_only")? {
+            c.ether_only = v;
+        }
+        if let Some(v) = s.bool("skip_bridge_ports")? {
+            c.skip_bridge_ports = v;
+        }
+        if let Some(v) = s.uint("jobs")? {
+            c.jobs = v as usize;
+        }
+        if let Some(v) = s.uint("carrier_wait")? {
+            c.carrier_wait = v;
+        }
+        if let Some(v) = s.str_list("priority")? {
+            c.priority = v;
+        }
+        if let Some(v) = s.bool("standby_down")? {
+            c.standby_down = v;
+        }
+        if let Some(v) = s.uint("min_speed")? {
+            c.min_speed = u32::try_from(v).map_err(|_| s.invalid("min_speed", "out of range"))?;
+        }
+        if let Some(v) = s.str("network_manager")? {
+            c.network_manager = v.parse().map_err(|_| {
+                s.invalid(
+                    "network_manager",
+                    "expected \"auto\", \"none\", \"NetworkManager\" or \"networkd\"",
+                )
+            })?;
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "probe")?;
+        let mut explicit = false;
+        if let Some(v) = s.str("target")? {
+            c.targets = vec![ProbeMethod::Icmp(parse_ip(&s, "target", &v)?)];
+            explicit = true;
+        }
+        if let Some(v) = s.str_list("targets")? {
+            c.targets = v
+                .iter()
+                .map(|a| {
+                    ProbeMethod::target(a).ok_or_else(|| {
+                        s.invalid(
+                            "targets",
+                            &format!("`{}` is neither an IP address nor host:port", a),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            explicit = true;
+        }
+        let quorum = s.uint("quorum")?;
+        if let Some(v) = quorum {
+            c.quorum = v.max(1) as usize;
+        }
+        if let Some(v) = s.float("quorum_threshold")? {
+            if !(0.0..1.0).contains(&v) {
+                return Err(s.invalid("quorum_threshold", "expected 0 <= ratio < 1"));
+            }
+            c.quorum_threshold = Some(v);
+        }
+        if let Some(v) = s.uint("count")? {
+            c.ping_count = v.clamp(1, 255) as u8;
+        }
+        if let Some(v) = s.uint("failure_threshold")? {
+            c.failure_threshold = Some(v.clamp(1, 255) as u8);
+        }
+        if let Some(v) = s.uint("timeout")? {
+            c.ping_timeout = v;
+        }
+        if let Some(v) = s.uint("retries")? {
+            c.retries = v as u32;
+        }
+        if let Some(v) = s.str("mode")? {
+            c.probe_mode = v.parse().map_err(|_| {
+                s.invalid("mode", "expected \"icmp\", \"tcp\", \"http\" or \"both\"")
+            })?;
+        }
+        if let Some(v) = s.bool("per_interface")? {
+            c.per_interface = v;
+        }
+        if let Some(v) = s.bool("require_all")? {
+            c.require_all = v;
+        }
+        if let Some(v) = s.bool("gateway_check")? {
+            c.gateway_check = v;
+        }
+        if let Some(v) = s.bool("flush_neigh")? {
+            c.flush_neigh = v;
+        }
+        if let Some(v) = s.bool("tcp_refused_ok")? {
+            c.tcp_refused_ok = v;
+        }
+        if let Some(v) = s.bool("pmtu")? {
+            c.pmtu = v;
+        }
+        if let Some(v) = s.uint("pmtu_min")? {
+            c.pmtu_min = u16::try_from(v).map_err(|_| s.invalid("pmtu_min", "out of range"))?;
+        }
+        if let Some(v) = s.uint("pmtu_payload")? {
+            c.pmtu_payload = u16::try_from(v)
+                .ok()
+                .filter(|&p| (40..=65507).contains(&p))
+                .ok_or_else(|| s.invalid("pmtu_payload", "must be 40..65507"))?;
+        }
+        if let Some(v) = s.uint("traceroute_hops")? {
+            c.traceroute_hops =
+                u8::try_from(v).map_err(|_| s.invalid("traceroute_hops", "expected 0-255"))?;
+        }
+        if let Some(v) = s.str("probes_dir")? {
+            c.probes_dir = (!v.is_empty()).then(|| PathBuf::from(v));
+        }
+        s.finish()?;
+        // [[probes]] entries add to `[probe] targets`, or replace the default.
+        let mut weighed = false;
+        if let Some(v) = root.remove("probes") {
+            if !explicit {
+                c.targets.clear();
+            }
+            for p in probe_entries("probes", v)? {
+                weighed |= !p.target.is_dns();
+                c.add_probe(p);
+            }
+        }
+        // Fragments only add, and not what is already there, so a config
+        // printed with them loads the same.
+        if let Some(dir) = &c.probes_dir {
+            for p in load_probe_fragments(dir)? {
+                if !c.targets.contains(&p.target) {
+                    weighed |= !p.target.is_dns();
+                    c.add_probe(p);
+                }
+            }
+        }
+        // [[probes]] targets are weighed: more than half, unless `quorum`
+        // or `quorum_threshold` says otherwise.
+        if weighed && quorum.is_none() && c.quorum_threshold.is_none() {
+            c.quorum_threshold = Some(0.5);
+        }
+        let mut s = Section::take(&mut root, "latency")?;
+        if let Some(v) = s.uint("window")? {
+            c.latency_window = v.max(1) as usize;
+        }
+        if let Some(v) = s.uint("loss_window")? {
+            c.loss_window = v.max(1) as usize;
+        }
+        if let Some(v) = s.uint("max_loss")? {
+            c.max_loss = v.min(100) as u8;
+        }
+        if let Some(v) = s.uint("max_p95_ms")? {
+            c.max_p95_ms = v;
+        }
+        if let Some(v) = s.uint("max_rtt_ms")? {
+            c.max_rtt_ms = v;
+        }
+        if let Some(v) = s.bool("degraded_fail")? {
+            c.degraded_fail = v;
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "http")?;
+        if let Some(v) = s.str("url")? {
+            c.http_url = v.parse().map_err(|e: String| s.invalid("url", &e))?;
+        }
+        if let Some(v) = s.uint("status")? {
+            c.http_status = u16::try_from(v).map_err(|_| s.invalid("status", "out of range"))?;
+        }
+        if let Some(v) = s.uint("timeout")? {
+            c.http_timeout = v.max(1);
+        }
+        if let Some(v) = s.str_list("expected_ranges")? {
+            c.http_expected_ranges = v
+                .iter()
+                .map(|r| r.parse())
+                .collect::<Result<_, String>>()
+                .map_err(|e| s.invalid("expected_ranges", &e))?;
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "dns")?;
+        if let Some(v) = s.bool("enabled")? {
+            c.dns = v;
+        }
+        if let Some(v) = s.str("name")? {
+            c.dns_name = v;
+        }
+        if let Some(v) = s.str_list("servers")? {
+            c.dns_servers = v
+                .iter()
+                .map(|a| parse_ip(&s, "servers", a))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(v) = s.uint("timeout")? {
+            c.dns_timeout = v.max(1);
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "throughput")?;
+        if let Some(v) = s.str("url")? {
+            c.throughput_url = match v.as_str() {
+                "" => None,
+                _ => Some(v.parse().map_err(|e: String| s.invalid("url", &e))?),
+            };
+        }
+        if let Some(v) = s.uint("min_kbps")? {
+            c.min_throughput = v;
+        }
+        if let Some(v) = s.uint("timeout")? {
+            c.throughput_timeout = v.max(1);
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "dhcp")?;
+        if let Some(v) = s.str("backend")? {
+            c.dhcp_backend = match v.as_str() {
+                "auto" => None,
+                b => Some(b.parse().map_err(|_| {
+                    s.invalid(
+                        "backend",
+                        "expected \"auto\", \"dhclient\", \"dhcpcd\", \"udhcpc\" or \"none\"",
+                    )
+                })?),
+            };
+        }
+        if let Some(v) = s.str("command")? {
+            let cmd: Vec<String> = v.split_whitespace().map(String::from).collect();
+            if cmd.is_empty() {
+                return Err(s.invalid("command", "must not be empty"));
+            }
+            c.dhcp_command = Some(cmd);
+        }
+        if let Some(v) = s.uint("timeout")? {
+            c.dhcp_timeout = v;
+        }
+        if let Some(v) = s.uint("retries")? {
+            c.dhcp_retries = v as u32;
+        }
+        if let Some(v) = s.uint("fallback_retry")? {
+            c.fallback_retry = v;
+        }
+        if let Some(v) = s.float("renew_remaining")? {
+            if !(v > 0.0 && v < 1.0) {
+                return Err(s.invalid("renew_remaining", "expected between 0.0 and 1.0"));
+            }
+            c.renew_remaining = v;
+        }
+        if let Some(v) = s.bool("verify_gateway")? {
+            c.verify_gateway = v;
+        }
+        if let Some(v) = s.bool("repair_routes")? {
+            c.repair_routes = v;
+        }
+        if let Some(v) = s.bool("conflict_check")? {
+            c.conflict_check = v;
+        }
+        if let Some(v) = s.bool("decline_conflict")? {
+            c.decline_conflict = v;
+        }
+        if let Some(v) = s.bool("skip")? {
+            c.skip_dhcp = v;
+        }
+        if let Some(v) = s.bool("force")? {
+            c.force_dhcp = v;
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "wireless")?;
+        if let Some(v) = s.str("wpa_config")? {
+            c.wpa_config = (!v.is_empty()).then(|| PathBuf::from(v));
+        }
+        if let Some(v) = s.uint("assoc_timeout")? {
+            c.assoc_timeout = v.max(1);
+        }
+        if let Some(v) = s.bool("reconnect")? {
+            c.reconnect = v;
+        }
+        s.finish()?;
+        match root.remove("fallback_static") {
+            None => {}
+            Some(Value::Table(t)) => {
+                for (iface, v) in t {
+                    let Value::Table(t) = v else {
+                        return Err(ConfigError::Invalid(
+                            format!("fallback_static.{}", iface),
+                            format!("expected a table, found {}", v.type_name()),
+                        ));
+                    };
+                    let mut s = Section {
+                        name: format!("fallback_static.{}", iface),
+                        t,
+                    };
+                    c.fallback_static.insert(iface, static_config(&mut s)?);
+                    s.finish()?;
+                }
+            }
+            Some(v) => {
+                return Err(ConfigError::Invalid(
+                    "fallback_static".into(),
+                    format!("expected a table, found {}", v.type_name()),
+                ))
+            }
+        }
+        match root.remove("vlans") {
+            None => {}
+            Some(Value::Array(a)) => {
+                for (i, v) in a.into_iter().enumerate() {
+                    let Value::Table(t) = v else {
+                        return Err(ConfigError::Invalid(
+                            "vlans".into(),
+                            "expected an array of tables".into(),
+                        ));
+                    };
+                    let mut s = Section {
+                        name: format!("vlans[{}]", i),
+                        t,
+                    };
+                    let parent = s
+                        .str("parent")?
+                        .ok_or_else(|| s.invalid("parent", "required"))?;
+                    let id = s
+                        .uint("vlan_id")?
+                        .ok_or_else(|| s.invalid("vlan_id", "required"))?;
+                    let id = u16::try_from(id)
+                        .ok()
+                        .filter(|i| (1..4095).contains(i))
+                        .ok_or_else(|| s.invalid("vlan_id", "expected 1-4094"))?;
+                    c.vlans.push(VlanConfig {
+                        parent,
+                        id,
+                        delete_on_exit: s.bool("delete_on_exit")?.unwrap_or(false),
+                    });
+                    s.finish()?;
+                }
+            }
+            Some(v) => {
+                return Err(ConfigError::Invalid(
+                    "vlans".into(),
+                    format!("expected an array of tables, found {}", v.type_name()),
+                ))
+            }
+        }
+        match root.remove("namespace") {
+            None => {}
+            Some(Value::Array(a)) => {
+                for (i, v) in a.into_iter().enumerate() {
+                    let Value::Table(t) = v else {
+                        return Err(ConfigError::Invalid(
+                            "namespace".into(),
+                            "expected an array of tables".into(),
+                        ));
+                    };
+                    let mut s = Section {
+                        name: format!("namespace[{}]", i),
+                        t,
+                    };
+                    let name = s
+                        .str("name")?
+                        .filter(|n| !n.is_empty() && !n.contains('/'))
+                        .ok_or_else(|| s.invalid("name", "required, without '/'"))?;
+                    if c.namespaces.iter().any(|n| n.name == name) {
+                        return Err(s.invalid("name", &format!("`{}` is already listed", name)));
+                    }
+                    let path = s.str("path")?.filter(|p| !p.is_empty()).map(PathBuf::from);
+                    let interfaces = s.str_list("manage")?;
+                    let targets = match s.str_list("targets")? {
+                        None => None,
+                        Some(v) => Some(
+                            v.iter()
+                                .map(|a| {
+                                    ProbeMethod::target(a).ok_or_else(|| {
+                                        s.invalid(
+                                            "targets",
+                                            &format!(
+                                                "`{}` is neither an IP address nor host:port",
+                                                a
+                                            ),
+                                        )
+                                    })
+                                })
+                                .collect::<Result<_, _>>()?,
+                        ),
+                    };
+                    c.namespaces.push(NamespaceConfig {
+                        name,
+                        path,
+                        interfaces,
+                        targets,
+                    });
+                    s.finish()?;
+                }
+            }
+            Some(v) => {
+                return Err(ConfigError::Invalid(
+                    "namespace".into(),
+                    format!("expected an array of tables, found {}", v.type_name()),
+                ))
+            }
+        }
+        let mut s = Section::take(&mut root, "retry")?;
+        if let Some(v) = s.uint("backoff_base_ms")? {
+            c.backoff_base_ms = v;
+        }
+        if let Some(v) = s.uint("backoff_max_ms")? {
+            c.backoff_max_ms = v;
+        }
+        if let Some(v) = s.float("jitter")? {
+            if !(0.0..=1.0).contains(&v) {
+                return Err(s.invalid("jitter", "expected 0.0-1.0"));
+            }
+            c.jitter = v;
+        }
+        if let Some(v) = s.uint("max_wait")? {
+            c.max_wait = v;
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "commands")?;
+        if let Some(v) = s.uint("ip_timeout")? {
+            c.ip_timeout = v.max(1);
+        }
+        if let Some(v) = s.uint("ping_timeout")? {
+            c.ping_cmd_timeout = v;
+        }
+        if let Some(v) = s.uint("wireless_timeout")? {
+            c.wireless_timeout = v.max(1);
+        }
+        if let Some(v) = s.uint("kill_grace")? {
+            c.kill_grace = v;
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "ipv6")?;
+        if let Some(v) = s.bool("enabled")? {
+            c.ipv6 = v;
+        }
+        if let Some(v) = s.str_list("targets")? {
+            c.ipv6_targets = v
+                .iter()
+                .map(|a| parse_ip(&s, "targets", a))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(v) = s.bool("dhcp")? {
+            c.dhcp6 = v;
+        }
+        if let Some(v) = s.str("dhcp_command")? {
+            let cmd: Vec<String> = v.split_whitespace().map(String::from).collect();
+            if cmd.is_empty() {
+                return Err(s.invalid("dhcp_command", "must not be empty"));
+            }
+            c.dhcp6_command = Some(cmd);
+        }
+        if let Some(v) = s.uint("slaac_wait")? {
+            c.slaac_wait = v;
+        }
+        if let Some(v) = s.str("family")? {
+            let f = v
+                .parse()
+                .map_err(|_| s.invalid("family", "expected \"4\", \"6\", \"any\" or \"both\""))?;
+            c.set_family(f);
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "tunnel")?;
+        if let Some(v) = s.bool("enabled")? {
+            c.tunnel = v;
+        }
+        if let Some(v) = s.str_list("interfaces")? {
+            c.tunnel_interfaces = v;
+        }
+        if let Some(v) = s.str_list("outside_targets")? {
+            c.tunnel_targets = v
+                .iter()
+                .map(|a| {
+                    ProbeMethod::target(a).ok_or_else(|| {
+                        s.invalid(
+                            "outside_targets",
+                            &format!("`{}` is neither an IP address nor host:port", a),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(v) = s.str("restart_command")? {
+            c.tunnel_restart = Some(v).filter(|c| !c.trim().is_empty());
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "daemon")?;
+        if let Some(v) = s.uint("interval")? {
+            c.interval = v.max(1);
+        }
+        if let Some(v) = s.str("state_file")? {
+            c.state_file = (!v.is_empty()).then(|| PathBuf::from(v));
+        }
+        if let Some(v) = s.str("pid_file")? {
+            c.pid_file = (!v.is_empty()).then(|| PathBuf::from(v));
+        }
+        if let Some(v) = s.str("history_file")? {
+            c.history_file = (!v.is_empty()).then(|| PathBuf::from(v));
+        }
+        if let Some(v) = s.uint("history_max_size")? {
+            c.history_max_size = v.max(1);
+        }
+        if let Some(v) = s.uint("history_max_days")? {
+            c.history_max_days = v.max(1);
+        }
+        if let Some(v) = s.str("audit_file")? {
+            c.audit_file = (!v.is_empty()).then(|| PathBuf::from(v));
+        }
+        if let Some(v) = s.str("log_backend")? {
+            c.log_backend = v.parse().map_err(|_| {
+                s.invalid(
+                    "log_backend",
+                    "expected \"auto\", \"stderr\", \"syslog\" or \"journald\"",
+                )
+            })?;
+        }
+        if let Some(v) = s.uint("flap_window")? {
+            c.flap_window = v.max(1);
+        }
+        if let Some(v) = s.uint("flap_threshold")? {
+            c.flap_threshold = v as usize;
+        }
+        if let Some(v) = s.uint("flap_hold")? {
+            c.flap_hold = v;
+        }
+        if let Some(v) = s.uint("reboot_after")? {
+            c.reboot_after = (v > 0).then_some(v);
+        }
+        if let Some(v) = s.str("recovery_command")? {
+            let cmd: Vec<String> = v.split_whitespace().map(String::from).collect();
+            if cmd.is_empty() {
+                return Err(s.invalid("recovery_command", "must not be empty"));
+            }
+            c.recovery_command = cmd;
+        }
+        if let Some(v) = s.bool("teardown_on_exit")? {
+            c.teardown_on_exit = v;
+        }
+        if let Some(v) = s.bool("teardown_link_down")? {
+            c.teardown_link_down = v;
+        }
+        if let Some(v) = s.uint("failback_hold")? {
+            c.failback_hold = v;
+        }
+        if let Some(v) = s.uint("consecutive_failures_before_offline")? {
+            c.offline_after = v.clamp(1, u32::MAX as u64) as u32;
+        }
+        if let Some(v) = s.uint("availability_window")? {
+            c.availability_window = v.max(1);
+        }
+        if let Some(v) = s.bool("adaptive")? {
+            c.adaptive = v;
+        }
+        if let Some(v) = s.uint("max_interval")? {
+            c.max_interval = v.max(1);
+        }
+        if let Some(v) = s.uint("adaptive_after")? {
+            c.adaptive_after = v.max(1);
+        }
+        if let Some(v) = s.uint("adaptive_factor")? {
+            if v < 2 {
+                return Err(s.invalid("adaptive_factor", "expected 2 or more"));
+            }
+            c.adaptive_factor = v;
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "metrics")?;
+        if let Some(v) = s.bool("enabled")? {
+            c.metrics = v;
+        }
+        if let Some(v) = s.str("address")? {
+            c.metrics_addr = v
+                .parse()
+                .map_err(|_| s.invalid("address", "expected ip:port"))?;
+        }
+        // Older configs give just the port.
+        if let Some(v) = s.uint("port")? {
+            let p = u16::try_from(v).map_err(|_| s.invalid("port", "out of range"))?;
+            c.metrics_addr.set_port(p);
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "health")?;
+        if let Some(v) = s.bool("enabled")? {
+            c.health = v;
+        }
+        if let Some(v) = s.str("address")? {
+            c.health_addr = v
+                .parse()
+                .map_err(|_| s.invalid("address", "expected ip:port"))?;
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "notifications")?;
+        if let Some(v) = s.str_list("webhooks")? {
+            c.webhooks = v
+                .iter()
+                .map(|u| {
+                    u.parse()
+                        .map_err(|e: String| s.invalid("webhooks", &format!("`{}`: {}", u, e)))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(v) = s.uint("timeout")? {
+            c.webhook_timeout = v.max(1);
+        }
+        if let Some(v) = s.uint("min_outage")? {
+            c.notify_min_outage = v;
+        }
+        if let Some(v) = s.uint("min_interval")? {
+            c.notify_min_interval = v;
+        }
+        let token = s.str("telegram_token")?.filter(|t| !t.is_empty());
+        let chat = s.str("telegram_chat_id")?.filter(|t| !t.is_empty());
+        c.telegram = match (token, chat) {
+            (Some(t), Some(c)) => Some((t, c)),
+            (None, None) => None,
+            (Some(_), None) => return Err(s.invalid("telegram_token", "needs telegram_chat_id")),
+            (None, Some(_)) => return Err(s.invalid("telegram_chat_id", "needs telegram_token")),
+        };
+        s.finish()?;
+        let mut s = Section::take(&mut root, "hooks")?;
+        let nonempty = |v: Option<String>| v.filter(|c| !c.trim().is_empty());
+        if let Some(v) = s.str("on_online")? {
+            c.on_online = nonempty(Some(v));
+        }
+        if let Some(v) = s.str("on_offline")? {
+            c.on_offline = nonempty(Some(v));
+        }
+        if let Some(v) = s.str("on_change")? {
+            c.on_change = nonempty(Some(v));
+        }
+        if let Some(v) = s.uint("timeout")? {
+            c.hook_timeout = v.max(1);
+        }
+        s.finish()?;
+        let mut s = Section::take(&mut root, "mqtt")?;
+        if let Some(v) = s.str("broker")? {
+            c.mqtt_broker = match v.as_str() {
+                "" => None,
+                _ => {
+                    Some(host_port(&v).ok_or_else(|| s.invalid("broker", "expected host[:port]"))?)
+                }
+            };
+        }
+        if let Some(v) = s.str("topic")? {
+            if v.is_empty() || v.contains(['+', '#']) {
+                return Err(s.invalid("topic", "expected a topic without wildcards"));
+            }
+            c.mqtt_topic = v;
+        }
+        if let Some(v) = s.str("client_id")? {
+            if v.is_empty() {
+                return Err(s.invalid("client_id", "must not be empty"));
+            }
+            c.mqtt_client_id = v;
+        }
+        if let Some(v) = s.str("username")? {
+            c.mqtt_username = (!v.is_empty()).then_some(v);
+        }
+        if let Some(v) = s.str("password")? {
+            c.mqtt_password = (!v.is_empty()).then_some(v);
+        }
+        if let Some(v) = s.bool("tls")? {
+            c.mqtt_tls = v;
+        }
+        if let Some(v) = s.str("ca_file")? {
+            c.mqtt_ca_file = (!v.is_empty()).then(|| PathBuf::from(v));
+        }
+        if let Some(v) = s.bool("every_check")? {
+            c.mqtt_every_check = v;
+        }
+        if let Some(v) = s.bool("discovery")? {
+            c.mqtt_discovery = v;
+        }
+        if let Some(v) = s.str("discovery_prefix")? {
+            c.mqtt_discovery_prefix = v;
+        }
+        if let Some(v) = s.uint("timeout")? {
+            c.mqtt_timeout = v.max(1);
+        }
+        s.finish()?;
+        if let Some(k) = root.keys().next() {
+            return Err(ConfigError::Invalid(k.clone(), "unknown key".into()));
+        }
+        Ok(c)
+    }
+}
+
+/// `host`, or `host:port` as for TCP probes.
+fn host_port(s: &str) -> Option<(String, Option<u16>)> {
+    if let Some(ProbeMethod::Tcp { host, port }) = ProbeMethod::tcp(s) {
+        return Some((host, Some(port)));
+    }
+    let host = s
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(s);
+    (host.parse::<std::net::Ipv6Addr>().is_ok() || !host.contains([':', '[', ']', '/']))
+        .then(|| host.to_string())
+        .map(|h| (h, None))
+}
+
+/// The `[[probes]]` array `v`, its entries named `<name>[i]` in errors.
+fn probe_entries(name: &str, v: Value) -> Result<Vec<ProbeConfig>, ConfigError> {
+    let Value::Array(a) = v else {
+        return Err(ConfigError::Invalid(
+            name.into(),
+            format!("expected an array of tables, found {}", v.type_name()),
+        ));
+    };
+    let mut v = Vec::new();
+    for (i, e) in a.into_iter().enumerate() {
+        let Value::Table(t) = e else {
+            return Err(ConfigError::Invalid(
+                name.into(),
+                "expected an array of tables".into(),
+            ));
+        };
+        let mut s = Section {
+            name: format!("{}[{}]", name, i),
+            t,
+        };
+        let target = probe_method(&mut s)?;
+        let opts = target_opts(&mut s, &target)?;
+        s.finish()?;
+        v.push(ProbeConfig { target, opts });
+    }
+    Ok(v)
+}
+
+/// The `[[probes]]` of every `*.toml` file in `dir`, by file name; a file
+/// may hold nothing else. A missing `dir` has none.
+pub fn load_probe_fragments(dir: &Path) -> Result<Vec<ProbeConfig>, ConfigError> {
+    let mut files: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(d) => d
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|x| x == "toml") && p.is_file())
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(ConfigError::Io(dir.into(), e)),
+    };
+    files.sort();
+    let mut v = Vec::new();
+    for p in files {
+        let src = std::fs::read_to_string(&p).map_err(|e| ConfigError::Io(p.clone(), e))?;
+        let mut t = toml::parse(&src).map_err(|e| ConfigError::Parse(p.clone(), e))?;
+        let name = format!("{}: probes", p.display());
+        if let Some(e) = t.remove("probes") {
+            v.extend(probe_entries(&name, e)?);
+        }
+        if let Some(k) = t.keys().next() {
+            return Err(ConfigError::Invalid(
+                format!("{}: {}", p.display(), k),
+                "a probe fragment holds only [[probes]]".into(),
+            ));
+        }
+    }
+    Ok(v)
+}
+
+fn probe_method(s: &mut Section) -> Result<ProbeMethod, ConfigError> {
+    match s.str("method")?.as_deref() {
+        None => {
+            let a = s
+                .str("target")?
+                .ok_or_else(|| s.invalid("target", "required without a method"))?;
+            ProbeMethod::target(&a).ok_or_else(|| {
+                s.invalid(
+                    "target",
+                    &format!("`{}` is neither an IP address nor host:port", a),
+                )
+            })
+        }
+        Some("icmp") => {
+            let a = s
+                .str("target")?
+                .ok_or_else(|| s.invalid("target", "required for icmp"))?;
+            Ok(ProbeMethod::Icmp(parse_ip(s, "target", &a)?))
+        }
+        Some("tcp") => {
+            let host = s
+                .str("host")?
+                .ok_or_else(|| s.invalid("host", "required for tcp"))?;
+            let port = s
+                .uint("port")?
+                .and_then(|p| u16::try_from(p).ok())
+                .filter(|&p| p > 0)
+                .ok_or_else(|| s.invalid("port", "required for tcp, 1-65535"))?;
+            let host = host.trim_start_matches('[').trim_end_matches(']').into();
+            Ok(ProbeMethod::Tcp { host, port })
+        }
+        Some("dns") => {
+            let hostname = s
+                .str("host")?
+                .ok_or_else(|| s.invalid("host", "required for dns"))?;
+            let server = match s.str("server")? {
+                Some(a) => Some(parse_ip(s, "server", &a)?),
+                None => None,
+            };
+            Ok(ProbeMethod::Dns { hostname, server })
+        }
+        Some(_) => Err(s.invalid("method", "expected \"icmp\", \"tcp\" or \"dns\"")),
+    }
+}
+
+fn target_opts(s: &mut Section, t: &ProbeMethod) -> Result<TargetOpts, ConfigError> {
+    let mut o = TargetOpts::default();
+    if let Some(v) = s.uint("weight")? {
+        o.weight = u32::try_from(v)
+            .ok()
+            .filter(|&w| w > 0)
+            .ok_or_else(|| s.invalid("weight", "expected a positive integer"))?;
+    }
+    if let Some(v) = s.uint("count")? {
+        o.count = Some(v.clamp(1, 255) as u8);
+    }
+    if let Some(v) = s.uint("failure_threshold")? {
+        o.failure_threshold = Some(v.clamp(1, 255) as u8);
+    }
+    if let Some(v) = s.uint("timeout")? {
+        o.timeout = Some(v.max(1));
+    }
+    // Lookups go through the resolver, which cannot be bound.
+    for k in ["interface", "source", "fwmark"] {
+        if t.is_dns() && s.t.contains_key(k) {
+            return Err(s.invalid(k, "not for dns probes"));
+        }
+    }
+    if let Some(v) = s.str("interface")? {
+        if !valid_iface(&v) {
+            return Err(s.invalid("interface", "expected an interface name"));
+        }
+        o.interface = Some(v);
+    }
+    if let Some(v) = s.str("source")? {
+        o.source = Some(parse_ip(s, "source", &v)?);
+    }
+    if let Some(v) = s.uint("fwmark")? {
+        o.fwmark = Some(
+            u32::try_from(v)
+                .ok()
+                .filter(|&m| m > 0)
+                .ok_or_else(|| s.invalid("fwmark", "expected 1-4294967295"))?,
+        );
+    }
+    Ok(o)
+}
+
+fn static_config(s: &mut Section) -> Result<StaticConfig, ConfigError> {
+    let v4 = |s: &Section, k: &str, v: &str| match parse_ip(s, k, v)? {
+        IpAddr::V4(a) => Ok(a),
+        IpAddr::V6(_) => Err(s.invalid(k, "expected an IPv4 address")),
+    };
+    let a = s
+        .str("address")?
+        .ok_or_else(|| s.invalid("address", "required"))?;
+    let address = v4(s, "address", &a)?;
+    let prefix_len = s
+        .uint("prefix_len")?
+        .filter(|&p| (1..=32).contains(&p))
+        .ok_or_else(|| s.invalid("prefix_len", "required, 1-32"))? as u8;
+    let gateway = match s.str("gateway")? {
+        Some(g) => Some(v4(s, "gateway", &g)?),
+        None => None,
+    };
+    let dns = s
+        .str_list("dns")?
+        .unwrap_or_default()
+        .iter()
+        .map(|a| parse_ip(s, "dns", a))
+        .collect::<Result<_, _>>()?;
+    Ok(StaticConfig {
+        address,
+        prefix_len,
+        gateway,
+        dns,
+    })
+}
+
+fn parse_ip(s: &Section, k: &str, v: &str) -> Result<IpAddr, ConfigError> {
+    v.parse()
+        .map_err(|_| s.invalid(k, &format!("`{}` is not an IP address", v)))
+}
+
+/// Renders the effective configuration as TOML (`--print-config`).
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "mode = {}",
+            toml::quote(if self.daemon { "daemon" } else { "oneshot" })
+        )?;
+        writeln!(f, "\n[interfaces]")?;
+        writeln!(f, "manage = {}", toml::quote_list(&self.interfaces))?;
+        writeln!(f, "include = {}", toml::quote_list(&self.include))?;
+        writeln!(f, "exclude = {}", toml::quote_list(&self.exclude))?;
+        writeln!(f, "ether_only = {}", self.ether_only)?;
+        writeln!(f, "skip_bridge_ports = {}", self.skip_bridge_ports)?;
+        writeln!(f, "jobs = {}", self.jobs)?;
+        writeln!(f, "carrier_wait = {}", self.carrier_wait)?;
+        writeln!(f, "priority = {}", toml::quote_list(&self.priority))?;
+        writeln!(f, "standby_down = {}", self.standby_down)?;
+        writeln!(f, "min_speed = {}", self.min_speed)?;
+        writeln!(
+            f,
+            "network_manager = {}",
+            toml::quote(&self.network_manager.to_string())
+        )?;
+        writeln!(f, "\n[probe]")?;
+        let icmp: Vec<&ProbeMethod> = self
+            .targets
+            .iter()
+            .filter(|t| matches!(t, ProbeMethod::Icmp(_)) && *self.opts(t) == DEFAULT_OPTS)
+            .collect();
+        writeln!(f, "targets = {}", toml::quote_list(&icmp))?;
+        writeln!(f, "quorum = {}", self.quorum)?;
+        if let Some(t) = self.quorum_threshold {
+            writeln!(f, "quorum_threshold = {:?}", t)?;
+        }
+        writeln!(f, "count = {}", self.ping_count)?;
+        if let Some(n) = self.failure_threshold {
+            writeln!(f, "failure_threshold = {}", n)?;
+        }
+        writeln!(f, "timeout = {}", self.ping_timeout)?;
+        writeln!(f, "retries = {}", self.retries)?;
+        writeln!(f, "mode = {}", toml::quote(&self.probe_mode.to_string()))?;
+        writeln!(f, "per_interface = {}", self.per_interface)?;
+        writeln!(f, "require_all = {}", self.require_all)?;
+        writeln!(f, "gateway_check = {}", self.gateway_check)?;
+        writeln!(f, "flush_neigh = {}", self.flush_neigh)?;
+        writeln!(f, "tcp_refused_ok = {}", self.tcp_refused_ok)?;
+        writeln!(f, "pmtu = {}", self.pmtu)?;
+        writeln!(f, "pmtu_min = {}", self.pmtu_min)?;
+        writeln!(f, "pmtu_payload = {}", self.pmtu_payload)?;
+        writeln!(f, "traceroute_hops = {}", self.traceroute_hops)?;
+        let pd = self.probes_dir.as_ref().map(|p| p.to_string_lossy());
+        writeln!(
+            f,
+            "probes_dir = {}",
+            toml::quote(pd.as_deref().unwrap_or(""))
+        )?;
+        writeln!(f, "\n[latency]")?;
+        writeln!(f, "window = {}", self.latency_window)?;
+        writeln!(f, "loss_window = {}", self.loss_window)?;
+        writeln!(f, "max_loss = {}", self.max_loss)?;
+        writeln!(f, "max_p95_ms = {}", self.max_p95_ms)?;
+        writeln!(f, "max_rtt_ms = {}", self.max_rtt_ms)?;
+        writeln!(f, "degraded_fail = {}", self.degraded_fail)?;
+        writeln!(f, "\n[http]")?;
+        writeln!(f, "url = {}", toml::quote(&self.http_url.to_string()))?;
+        writeln!(f, "status = {}", self.http_status)?;
+        writeln!(f, "timeout = {}", self.http_timeout)?;
+        writeln!(
+            f,
+            "expected_ranges = {}",
+            toml::quote_list(&self.http_expected_ranges)
+        )?;
+        writeln!(f, "\n[dns]")?;
+        writeln!(f, "enabled = {}", self.dns)?;
+        writeln!(f, "name = {}", toml::quote(&self.dns_name))?;
+        writeln!(f, "servers = {}", toml::quote_list(&self.dns_servers))?;
+        writeln!(f, "timeout = {}", self.dns_timeout)?;
+        writeln!(f, "\n[throughput]")?;
+        let url = self.throughput_url.as_ref().map(|u| u.to_string());
+        writeln!(f, "url = {}", toml::quote(url.as_deref().unwrap_or("")))?;
+        writeln!(f, "min_kbps = {}", self.min_throughput)?;
+        writeln!(f, "timeout = {}", self.throughput_timeout)?;
+        writeln!(f, "\n[dhcp]")?;
+        let backend = self.dhcp_backend.map_or("auto".into(), |b| b.to_string());
+        writeln!(f, "backend = {}", toml::quote(&backend))?;
+        if let Some(c) = &self.dhcp_command {
+            writeln!(f, "command = {}", toml::quote(&c.join(" ")))?;
+        }
+        writeln!(f, "timeout = {}", self.dhcp_timeout)?;
+        writeln!(f, "retries = {}", self.dhcp_retries)?;
+        writeln!(f, "fallback_retry = {}", self.fallback_retry)?;
+        writeln!(f, "renew_remaining = {:?}", self.renew_remaining)?;
+        writeln!(f, "verify_gateway = {}", self.verify_gateway)?;
+        writeln!(f, "repair_routes = {}", self.repair_routes)?;
+        writeln!(f, "conflict_check = {}", self.conflict_check)?;
+        writeln!(f, "decline_conflict = {}", self.decline_conflict)?;
+        writeln!(f, "skip = {}", self.skip_dhcp)?;
+        writeln!(f, "force = {}", self.force_dhcp)?;
+        writeln!(f, "\n[wireless]")?;
+        let wpa = self.wpa_config.as_ref().map(|p| p.to_string_lossy());
+        writeln!(
+            f,
+            "wpa_config = {}",
+            toml::quote(wpa.as_deref().unwrap_or(""))
+        )?;
+        writeln!(f, "assoc_timeout = {}", self.assoc_timeout)?;
+        writeln!(f, "reconnect = {}", self.reconnect)?;
+        for (iface, st) in &self.fallback_static {
+            writeln!(f, "\n[fallback_static.{}]", toml::quote(iface))?;
+            writeln!(f, "address = {}", toml::quote(&st.address.to_string()))?;
+            writeln!(f, "prefix_len = {}", st.prefix_len)?;
+            if let Some(g) = st.gateway {
+                writeln!(f, "gateway = {}", toml::quote(&g.to_string()))?;
+            }
+            writeln!(f, "dns = {}", toml::quote_list(&st.dns))?;
+        }
+        for v in &self.vlans {
+            writeln!(f, "\n[[vlans]]")?;
+            writeln!(f, "parent = {}", toml::quote(&v.parent))?;
+            writeln!(f, "vlan_id = {}", v.id)?;
+            writeln!(f, "delete_on_exit = {}", v.delete_on_exit)?;
+        }
+        for n in &self.namespaces {
+            writeln!(f, "\n[[namespace]]")?;
+            writeln!(f, "name = {}", toml::quote(&n.name))?;
+            if let Some(p) = &n.path {
+                writeln!(f, "path = {}", toml::quote(&p.to_string_lossy()))?;
+            }
+            if let Some(m) = &n.interfaces {
+                writeln!(f, "manage = {}", toml::quote_list(m))?;
+            }
+            if let Some(t) = &n.targets {
+                writeln!(f, "targets = {}", toml::quote_list(t))?;
+            }
+        }
+        writeln!(f, "\n[retry]")?;
+        writeln!(f, "backoff_base_ms = {}", self.backoff_base_ms)?;
+        writeln!(f, "backoff_max_ms = {}", self.backoff_max_ms)?;
+        writeln!(f, "jitter = {:?}", self.jitter)?;
+        writeln!(f, "max_wait = {}", self.max_wait)?;
+        writeln!(f, "\n[commands]")?;
+        writeln!(f, "ip_timeout = {}", self.ip_timeout)?;
+        writeln!(f, "ping_timeout = {}", self.ping_cmd_timeout)?;
+        writeln!(f, "wireless_timeout = {}", self.wireless_timeout)?;
+        writeln!(f, "kill_grace = {}", self.kill_grace)?;
+        writeln!(f, "\n[ipv6]")?;
+        writeln!(f, "enabled = {}", self.ipv6)?;
+        writeln!(f, "targets = {}", toml::quote_list(&self.ipv6_targets))?;
+        writeln!(f, "dhcp = {}", self.dhcp6)?;
+        if let Some(c) = &self.dhcp6_command {
+            writeln!(f, "dhcp_command = {}", toml::quote(&c.join(" ")))?;
+        }
+        writeln!(f, "slaac_wait = {}", self.slaac_wait)?;
+        writeln!(f, "family = {}", toml::quote(&self.family.to_string()))?;
+        writeln!(f, "\n[tunnel]")?;
+        writeln!(f, "enabled = {}", self.tunnel)?;
+        writeln!(
+            f,
+            "interfaces = {}",
+            toml::quote_list(&self.tunnel_interfaces)
+        )?;
+        writeln!(
+            f,
+            "outside_targets = {}",
+            toml::quote_list(&self.tunnel_targets)
+        )?;
+        writeln!(
+            f,
+            "restart_command = {}",
+            toml::quote(self.tunnel_restart.as_deref().unwrap_or(""))
+        )?;
+        writeln!(f, "\n[daemon]")?;
+        writeln!(f, "interval = {}", self.interval)?;
+        let sf = self.state_file.as_ref().map(|p| p.to_string_lossy());
+        writeln!(
+            f,
+            "state_file = {}",
+            toml::quote(sf.as_deref().unwrap_or(""))
+        )?;
+        let pf = self.pid_file.as_ref().map(|p| p.to_string_lossy());
+        writeln!(f, "pid_file = {}", toml::quote(pf.as_deref().unwrap_or("")))?;
+        let hf = self.history_file.as_ref().map(|p| p.to_string_lossy());
+        writeln!(
+            f,
+            "history_file = {}",
+            toml::quote(hf.as_deref().unwrap_or(""))
+        )?;
+        writeln!(f, "history_max_size = {}", self.history_max_size)?;
+        writeln!(f, "history_max_days = {}", self.history_max_days)?;
+        let af = self.audit_file.as_ref().map(|p| p.to_string_lossy());
+        writeln!(
+            f,
+            "audit_file = {}",
+            toml::quote(af.as_deref().unwrap_or(""))
+        )?;
+        writeln!(
+            f,
+            "log_backend = {}",
+            toml::quote(&self.log_backend.to_string())
+        )?;
+        writeln!(f, "flap_window = {}", self.flap_window)?;
+        writeln!(f, "flap_threshold = {}", self.flap_threshold)?;
+        writeln!(f, "flap_hold = {}", self.flap_hold)?;
+        writeln!(f, "reboot_after = {}", self.reboot_after.unwrap_or(0))?;
+        writeln!(
+            f,
+            "recovery_command = {}",
+            toml::quote(&self.recovery_command.join(" "))
+        )?;
+        writeln!(f, "teardown_on_exit = {}", self.teardown_on_exit)?;
+        writeln!(f, "teardown_link_down = {}", self.teardown_link_down)?;
+        writeln!(f, "failback_hold = {}", self.failback_hold)?;
+        writeln!(
+            f,
+            "consecutive_failures_before_offline = {}",
+            self.offline_after
+        )?;
+        writeln!(f, "availability_window = {}", self.availability_window)?;
+        writeln!(f, "adaptive = {}", self.adaptive)?;
+        writeln!(f, "max_interval = {}", self.max_interval)?;
+        writeln!(f, "adaptive_after = {}", self.adaptive_after)?;
+        writeln!(f, "adaptive_factor = {}", self.adaptive_factor)?;
+        writeln!(f, "\n[metrics]")?;
+        writeln!(f, "enabled = {}", self.metrics)?;
+        writeln!(
+            f,
+            "address = {}",
+            toml::quote(&self.metrics_addr.to_string())
+        )?;
+        writeln!(f, "\n[health]")?;
+        writeln!(f, "enabled = {}", self.health)?;
+        writeln!(
+            f,
+            "address = {}",
+            toml::quote(&self.health_addr.to_string())
+        )?;
+        writeln!(f, "\n[notifications]")?;
+        writeln!(f, "webhooks = {}", toml::quote_list(&self.webhooks))?;
+        writeln!(f, "timeout = {}", self.webhook_timeout)?;
+        writeln!(f, "min_outage = {}", self.notify_min_outage)?;
+        writeln!(f, "min_interval = {}", self.notify_min_interval)?;
+        let (token, chat) = self
+            .telegram
+            .as_ref()
+            .map_or(("", ""), |(t, c)| (t.as_str(), c.as_str()));
+        writeln!(f, "telegram_token = {}", toml::quote(token))?;
+        writeln!(f, "telegram_chat_id = {}", toml::quote(chat))?;
+        writeln!(f, "\n[hooks]")?;
+        let hook = |h: &Option<String>| toml::quote(h.as_deref().unwrap_or(""));
+        writeln!(f, "on_online = {}", hook(&self.on_online))?;
+        writeln!(f, "on_offline = {}", hook(&self.on_offline))?;
+        writeln!(f, "on_change = {}", hook(&self.on_change))?;
+        writeln!(f, "timeout = {}", self.hook_timeout)?;
+        writeln!(f, "\n[mqtt]")?;
+        let broker = match &self.mqtt_broker {
+            None => String::new(),
+            Some((h, p)) => {
+                let h = if h.contains(':') {
+                    format!("[{}]", h)
+                } else {
+                    h.clone()
+                };
+                match p {
+                    Some(p) => format!("{}:{}", h, p),
+                    None => h,
+                }
+            }
+        };
+        writeln!(f, "broker = {}", toml::quote(&broker))?;
+        writeln!(f, "topic = {}", toml::quote(&self.mqtt_topic))?;
+        writeln!(f, "client_id = {}", toml::quote(&self.mqtt_client_id))?;
+        let opt = |v: &Option<String>| toml::quote(v.as_deref().unwrap_or(""));
+        writeln!(f, "username = {}", opt(&self.mqtt_username))?;
+        writeln!(f, "password = {}", opt(&self.mqtt_password))?;
+        writeln!(f, "tls = {}", self.mqtt_tls)?;
+        let ca = self.mqtt_ca_file.as_ref().map(|p| p.to_string_lossy());
+        writeln!(f, "ca_file = {}", toml::quote(ca.as_deref().unwrap_or("")))?;
+        writeln!(f, "every_check = {}", self.mqtt_every_check)?;
+        writeln!(f, "discovery = {}", self.mqtt_discovery)?;
+        writeln!(
+            f,
+            "discovery_prefix = {}",
+            toml::quote(&self.mqtt_discovery_prefix)
+        )?;
+        writeln!(f, "timeout = {}", self.mqtt_timeout)?;
+        for t in &self.targets {
+            let o = self.opts(t);
+            match t {
+                ProbeMethod::Icmp(_) if *o == DEFAULT_OPTS => continue,
+                ProbeMethod::Icmp(a) => {
+                    writeln!(f, "\n[[probes]]")?;
+                    writeln!(f, "method = \"icmp\"")?;
+                    writeln!(f, "target = {}", toml::quote(&a.to_string()))?;
+                }
+                ProbeMethod::Tcp { host, port } => {
+                    writeln!(f, "\n[[probes]]")?;
+                    writeln!(f, "method = \"tcp\"")?;
+                    writeln!(f, "host = {}", toml::quote(host))?;
+                    writeln!(f, "port = {}", port)?;
+                }
+                ProbeMethod::Dns { hostname, server } => {
+                    writeln!(f, "\n[[probes]]")?;
+                    writeln!(f, "method = \"dns\"")?;
+                    writeln!(f, "host = {}", toml::quote(hostname))?;
+                    if let Some(a) = server {
+                        writeln!(f, "server = {}", toml::quote(&a.to_string()))?;
+                    }
+                }
+            }
+            if o.weight != 1 {
+                writeln!(f, "weight = {}", o.weight)?;
+            }
+            if let Some(n) = o.count {
+                writeln!(f, "count = {}", n)?;
+            }
+            if let Some(n) = o.failure_threshold {
+                writeln!(f, "failure_threshold = {}", n)?;
+            }
+            if let Some(t) = o.timeout {
+                writeln!(f, "timeout = {}", t)?;
+            }
+            if let Some(i) = &o.interface {
+                writeln!(f, "interface = {}", toml::quote(i))?;
+            }
+            if let Some(a) = o.source {
+                writeln!(f, "source = {}", toml::quote(&a.to_string()))?;
+            }
+            if let Some(m) = o.fwmark {
+                writeln!(f, "fwmark = {:#x}", m)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A config table whose keys are consumed as they are read, so leftovers can
+/// be reported as unknown.
+struct Section {
+    name: String,
+    t: Table,
+}
+
+impl Section {
+    fn take(root: &mut Table, name: &str) -> Result<Section, ConfigError> {
+        let t = match root.remove(name) {
+            None => Table::new(),
+            Some(Value::Table(t)) => t,
+            Some(v) => {
+                return Err(ConfigError::Invalid(
+                    name.into(),
+                    format!("expected a table, found {}", v.type_name()),
+                ))
+            }
+        };
+        Ok(Section {
+            name: name.into(),
+            t,
+        })
+    }
+
+    fn invalid(&self, k: &str, m: &str) -> ConfigError {
+        ConfigError::Invalid(format!("{}.{}", self.name, k), m.into())
+    }
+
+    fn mismatch(&self, k: &str, want: &str, v: &Value) -> ConfigError {
+        self.invalid(k, &format!("expected {}, found {}", want, v.type_name()))
+    }
+
+    fn str(&mut self, k: &str) -> Result<Option<String>, ConfigError> {
+        match self.t.remove(k) {
+            None => Ok(None),
+            Some(Value::Str(s)) => Ok(Some(s)),
+            Some(v) => Err(self.mismatch(k, "a string", &v)),
+        }
+    }
+
+    fn uint(&mut self, k: &str) -> Result<Option<u64>, ConfigError> {
+        match self.t.remove(k) {
+            None => Ok(None),
+            Some(Value::Int(i)) if i >= 0 => Ok(Some(i as u64)),
+            Some(v) => Err(self.mismatch(k, "a non-negative integer", &v)),
+        }
+    }
+
+    /// Integers are accepted too.
+    fn float(&mut self, k: &str) -> Result<Option<f64>, ConfigError> {
+        match self.t.remove(k) {
+            None => Ok(None),
+            Some(Value::Float(f)) => Ok(Some(f)),
+            Some(Value::Int(i)) => Ok(Some(i as f64)),
+            Some(v) => Err(self.mismatch(k, "a number", &v)),
+        }
+    }
+
+    fn bool(&mut self, k: &str) -> Result<Option<bool>, ConfigError> {
+        match self.t.remove(k) {
+            None => Ok(None),
+            Some(Value::Bool(b)) => Ok(Some(b)),
+            Some(v) => Err(self.mismatch(k, "a boolean", &v)),
+        }
+    }
+
+    fn str_list(&mut self, k: &str) -> Result<Option<Vec<String>>, ConfigError> {
+        match self.t.remove(k) {
+            None => Ok(None),
+            Some(Value::Array(a)) => a
+                .into_iter()
+                .map(|v| match v {
+                    Value::Str(s) => Ok(s),
+                    v => Err(self.mismatch(k, "an array of strings", &v)),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(Some),
+            Some(v) => Err(self.mismatch(k, "an array", &v)),
+        }
+    }
+
+    fn finish(self) -> Result<(), ConfigError> {
+        match self.t.keys().next() {
+            Some(k) => Err(self.invalid(k, "unknown key")),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `src` loaded as a config file; without the host's probes.d unless
+    /// it names one.
+    fn load(src: &str) -> Result<Config, ConfigError> {
+        let mut t = toml::parse(src).map_err(|e| ConfigError::Parse("test.toml".into(), e))?;
+        let probe = t
+            .entry("probe".into())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if let Value::Table(p) = probe {
+            p.entry("probes_dir".into())
+                .or_insert_with(|| Value::Str(String::new()));
+        }
+        Config::from_table(t)
+    }
+
+    #[test]
+    fn probes_are_weighed_over_half_by_default() {
+        let probes = "[[probes]]\ntarget = \"192.0.2.1\"\nweight = 2\n";
+        assert_eq!(load(probes).unwrap().quorum_threshold, Some(0.5));
+        let set = format!("[probe]\nquorum_threshold = 0.7\n\n{}", probes);
+        assert_eq!(load(&set).unwrap().quorum_threshold, Some(0.7));
+        let quorum = format!("[probe]\nquorum = 2\n\n{}", probes);
+        assert_eq!(load(&quorum).unwrap().quorum_threshold, None);
+        // Lookups are not weighed.
+        let dns = "[[probes]]\nmethod = \"dns\"\nhost = \"example.com\"\n";
+        assert_eq!(load(dns).unwrap().quorum_threshold, None);
+        assert_eq!(load("").unwrap().quorum_threshold, None);
+    }
+
+    #[test]
+    fn unknown_keys_are_refused() {
+        for src in [
+            "nosuch = 1\n",
+            "[nosuch]\n",
+            "[probe]\nnosuch = 1\n",
+            "[[probes]]\ntarget = \"192.0.2.1\"\nnosuch = 1\n",
+        ] {
+            let e = load(src).map(|_| ()).unwrap_err().to_string();
+            assert!(
+                e.contains("nosuch") && e.ends_with("unknown key"),
+                "{}: {}",
+                src,
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,1279 @@
+use crate::availability::Availability;
+use crate::backoff;
+use crate::config::Config;
+use crate::exec::{self, CommandExecutor, Executor};
+use crate::health;
+use crate::history::{self, History};
+use crate::iface::{self, Dhcp, IfaceReport, IfaceRole, LinkStatus};
+use crate::json::Json;
+use crate::latency::LatencyWindow;
+use crate::manager::{self, NetworkBackend};
+use crate::metrics::{self, Metrics};
+use crate::net::{self, IfaceStats};
+use crate::notify::{Notification, Notifier};
+use crate::output::{Event, OutputSink};
+use crate::probe::State;
+#[cfg(target_os = "linux")]
+use crate::systemd::Systemd;
+use crate::timeline::{self, Record};
+use crate::{clock, log, ping, probe, sys, uplink};
+#[cfg(target_os = "linux")]
+use crate::{netlink, vlan};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::os::fd::AsRawFd;
+use std::os::raw::c_int;
+use std::time::{Duration, Instant};
+
+const RECOVERY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The interfaces to look after that exist now; a configured one that is
+/// unplugged is taken in when it appears.
+fn present(cfg: &Config) -> Vec<String> {
+    let mut v = iface::discover(cfg);
+    v.retain(|i| net::exists(i));
+    v
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    Up {
+        v4_ok: bool,
+        v6_ok: bool,
+    },
+    /// Ping works, HTTP does not (filtering proxy), or the link is poor.
+    Degraded,
+    DnsBroken,
+    /// HTTP lands on a login page; renewing the lease will not help.
+    CaptivePortal,
+    Down,
+    Recovering,
+}
+
+impl fmt::Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LinkState::Up { .. } => "ONLINE",
+            LinkState::Degraded => "DEGRADED",
+            LinkState::DnsBroken => "DNS_BROKEN",
+            LinkState::CaptivePortal => "CAPTIVE_PORTAL",
+            LinkState::Down => "OFFLINE",
+            LinkState::Recovering => "RECOVERING",
+        })
+    }
+}
+
+impl LinkState {
+    /// State name, plus per-family detail when IPv6 is being checked.
+    pub fn describe(self, ipv6: bool) -> String {
+        match self {
+            LinkState::Up { v4_ok, v6_ok } if ipv6 => {
+                let st = |ok| if ok { "ok" } else { "down" };
+                format!("{} (IPv4 {}, IPv6 {})", self, st(v4_ok), st(v6_ok))
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// What SIGHUP reads again: the config, with CLI flags applied as at
+/// start, and the notifiers made from it; or why it did not load.
+pub type Reload<'a> = Box<dyn Fn() -> Result<(Config, Vec<Box<dyn Notifier>>), String> + 'a>;
+
+/// SIGHUP reloads the config, SIGUSR1 checks at once, SIGUSR2 dumps the
+/// state.
+const CONTROLS: [c_int; 3] = [sys::SIGHUP, sys::SIGUSR1, sys::SIGUSR2];
+
+/// Takes SIGHUP, SIGUSR1 and SIGUSR2 for the loops; before any thread
+/// starts.
+pub fn trap_controls() {
+    sys::trap_aside(&CONTROLS);
+}
+
+/// Owns the link state and drives the check-and-repair cycle.
+pub struct WatchdogLoop<'a> {
+    /// Replaced by SIGHUP's reload.
+    cfg: Cow<'a, Config>,
+    out: Box<dyn OutputSink>,
+    ifaces: Vec<String>,
+    /// `priority` uplinks that exist, in order, and the one in use.
+    uplinks: Vec<String>,
+    active: Option<String>,
+    /// A preferred uplink found healthy again, and since when.
+    failback: Option<(String, Instant)>,
+    state: Option<LinkState>,
+    metrics: Option<metrics::Shared>,
+    health: Option<health::Shared>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    ex: Box<dyn Executor>,
+    /// Last seen link state per interface, to notice flips.
+    links: BTreeMap<String, bool>,
+    /// Skipped for want of carrier; watched between cycles.
+    no_carrier: BTreeSet<String>,
+    /// When each recorded lease is due for renewal, or DHCP is
+    /// tried again on a static fallback.
+    renew_at: BTreeMap<String, Instant>,
+    /// On their `[fallback_static]` address.
+    on_static: BTreeSet<String>,
+    window: LatencyWindow,
+    /// Previous counter reading per interface, with its index, for
+    /// throughput.
+    counters: BTreeMap<String, (Instant, Option<u32>, IfaceStats)>,
+    /// Up with carrier but receiving nothing; said once.
+    stalled: BTreeSet<String>,
+    /// Last `carrier_changes` reading per interface.
+    carriers: BTreeMap<String, u64>,
+    /// Interfaces whose carrier keeps changing.
+    flapping_ifaces: BTreeSet<String>,
+    /// Hears of links coming and going; `None` where netlink would not
+    /// say, and the interfaces are looked for each cycle instead.
+    #[cfg(target_os = "linux")]
+    hotplug: Option<netlink::Monitor>,
+    /// NetworkManager or networkd owns the interfaces: probe, never repair.
+    manager: Option<NetworkBackend>,
+    /// What it last said of each.
+    manager_states: BTreeMap<String, String>,
+    #[cfg(target_os = "linux")]
+    systemd: Option<Systemd>,
+    /// The `[[namespace]]` this loop watches; `None` for the host.
+    netns: Option<String>,
+    ready: bool,
+    history: History,
+    flapping: bool,
+    /// Where the captive portal the last probe ran into wants a login.
+    portal: Option<String>,
+    /// Where the last OFFLINE probe found the path broken.
+    diagnosis: Option<probe::Diagnosis>,
+    /// TUNNEL_DOWN since the last change; its restart is behind.
+    tunnel_down: bool,
+    /// The checks of the last `availability_window`.
+    availability: Availability,
+    /// Seconds between checks now; stretched past `interval` while
+    /// `adaptive` and healthy, when one target is probed.
+    interval: u64,
+    reload: Option<Reload<'a>>,
+    /// [`CONTROLS`] yet to act on.
+    signals: sys::Signals,
+    /// The state file could not be written; said once.
+    save_failed: bool,
+    /// Nor the history file.
+    record_failed: bool,
+}
+
+impl<'a> WatchdogLoop<'a> {
+    pub fn new(cfg: &'a Config, out: Box<dyn OutputSink>) -> Self {
+        let (uplinks, rest) = uplink::split(cfg, &present(cfg));
+        WatchdogLoop {
+            cfg: Cow::Borrowed(cfg),
+            out,
+            ifaces: [&rest[..], &uplinks[..]].concat(),
+            uplinks,
+            active: None,
+            failback: None,
+            state: None,
+            metrics: None,
+            health: None,
+            notifiers: Vec::new(),
+            ex: Box::new(CommandExecutor),
+            links: BTreeMap::new(),
+            no_carrier: BTreeSet::new(),
+            renew_at: BTreeMap::new(),
+            on_static: BTreeSet::new(),
+            window: LatencyWindow::new(cfg.latency_window),
+            counters: BTreeMap::new(),
+            stalled: BTreeSet::new(),
+            carriers: BTreeMap::new(),
+            flapping_ifaces: BTreeSet::new(),
+            #[cfg(target_os = "linux")]
+            hotplug: None,
+            manager: manager::passive(cfg),
+            manager_states: BTreeMap::new(),
+            #[cfg(target_os = "linux")]
+            systemd: Systemd::from_env(),
+            ready: false,
+            history: cfg
+                .state_file
+                .as_deref()
+                .map(|p| History::load(p).resume())
+                .unwrap_or_default(),
+            flapping: false,
+            portal: None,
+            diagnosis: None,
+            tunnel_down: false,
+            availability: Availability::new(cfg.availability_window),
+            interval: cfg.interval,
+            reload: None,
+            signals: sys::Signals::new(&CONTROLS),
+            save_failed: false,
+            record_failed: false,
+            netns: None,
+        }
+    }
+
+    pub fn notifiers(mut self, n: Vec<Box<dyn Notifier>>) -> Self {
+        self.notifiers = n;
+        self
+    }
+
+    pub fn executor(mut self, ex: Box<dyn Executor>) -> Self {
+        self.ex = ex;
+        self
+    }
+
+    pub fn reloader(mut self, f: Reload<'a>) -> Self {
+        self.reload = Some(f);
+        self
+    }
+
+    /// Runs as `[[namespace]]` `name`'s loop, on a thread already inside
+    /// it: messages and notifications say so, and systemd hears only from
+    /// the host's.
+    pub fn namespace(mut self, name: &str) -> Self {
+        self.netns = Some(name.to_string());
+        #[cfg(target_os = "linux")]
+        {
+            self.systemd = None;
+        }
+        self
+    }
+
+    /// Runs until SIGTERM/SIGINT, checking every `interval` seconds, and
+    /// between checks acts on the control signals.
+    pub fn run(&mut self) {
+        sys::trap(&[sys::SIGTERM, sys::SIGINT]);
+        if let Some(n) = &self.netns {
+            log::set_scope(n);
+        }
+        if self.cfg.metrics {
+            let m = metrics::Shared::default();
+            let addr = self.cfg.metrics_addr;
+            match metrics::serve(addr, m.clone()) {
+                Ok(()) => self.metrics = Some(m),
+                Err(e) => log::error!("metrics: cannot listen on {}: {}", addr, e),
+            }
+        }
+        if self.cfg.health {
+            let h = health::Shared::default();
+            let addr = self.cfg.health_addr;
+            match health::serve(addr, h.clone()) {
+                Ok(()) => self.health = Some(h),
+                Err(e) => log::error!("health: cannot listen on {}: {}", addr, e),
+            }
+        }
+        #[cfg(target_os = "linux")]
+        {
+            self.hotplug = netlink::Monitor::open()
+                .inspect_err(|e| log::debug!("link notifications: {}", e))
+                .ok();
+        }
+        for i in iface::discover(&self.cfg) {
+            if !self.ifaces.contains(&i) {
+                log::warn!(iface = &i; "{}: not present, taken in when it appears", i);
+            }
+        }
+        match self.manager {
+            Some(b) => log::info!("{} manages the interfaces; only watching them", b),
+            None => self.start(),
+        }
+        while !sys::stop_requested() {
+            let began = Instant::now();
+            self.tick();
+            self.adapt();
+            self.recover();
+            self.traffic();
+            self.carrier_flaps();
+            self.sd_cycle();
+            self.save();
+            // Checks start every `interval`, however long one takes.
+            let rest = Duration::from_secs(self.interval).saturating_sub(began.elapsed());
+            self.pause(self.until_renewal(rest));
+            self.rescan();
+            self.carrier_back();
+            self.renew_due();
+        }
+        self.save();
+        self.record(timeline::STOPPED);
+        self.sd("STOPPING=1");
+        if self.cfg.teardown_on_exit && self.manager.is_none() {
+            for (i, e) in iface::tear_down(
+                &self.cfg,
+                &self.ifaces,
+                self.cfg.teardown_link_down,
+                &*self.ex,
+            ) {
+                log::warn!(iface = &i; "{}: {}", i, e);
+            }
+            #[cfg(target_os = "linux")]
+            for (i, e) in vlan::remove(&self.cfg, &*self.ex) {
+                log::warn!(iface = &i; "{}: {}", i, e);
+            }
+        }
+        self.out.event(Event::Stopping {
+            state: self.state,
+            ipv6: self.cfg.ipv6,
+        });
+    }
+
+    /// One probe; on failure renew DHCP and probe again, unless the
+    /// interfaces are another manager's.
+    fn tick(&mut self) {
+        self.out.event(Event::Check);
+        let s = self.probe();
+        if self.premature(s) {
+            return;
+        }
+        self.restart_tunnel();
+        if let Some(b) = self.manager {
+            self.set(s);
+            return self.watch(b);
+        }
+        if s != LinkState::Down {
+            self.set(s);
+            return self.fail_back();
+        }
+        // The uplink works; a lease on it is not what the tunnel lacks.
+        if self.tunnel_down {
+            return self.set(s);
+        }
+        if self.state != Some(LinkState::Down) {
+            self.set(LinkState::Recovering);
+        }
+        if sys::stop_requested() {
+            return;
+        }
+        // One unplugged since is not worth a renewal.
+        self.rescan();
+        self.bring_up_all(Dhcp::renew(&self.cfg), &[]);
+        let s = self.probe();
+        self.set(s);
+    }
+
+    /// Whether an OFFLINE verdict comes after a working state with fewer
+    /// than `consecutive_failures_before_offline` checks in a row that
+    /// found the host down, to be waited out rather than acted on.
+    fn premature(&self, s: LinkState) -> bool {
+        if s != LinkState::Down {
+            return false;
+        }
+        let failed = self.availability.failures();
+        let working = !matches!(self.state, None | Some(LinkState::Down));
+        if working && failed < self.cfg.offline_after as u64 {
+            log::info!(
+                "check failed ({} of {} before OFFLINE)",
+                failed,
+                self.cfg.offline_after
+            );
+            return true;
+        }
+        false
+    }
+
+    /// The first bring-up, after creating the `[[vlans]]`. Interfaces still
+    /// holding the address of an unexpired lease from before a restart (per
+    /// the state file) are set up without DHCP; uplinks are selected afresh.
+    fn start(&mut self) {
+        #[cfg(target_os = "linux")]
+        if vlan::setup(&self.cfg, &*self.ex) {
+            let (uplinks, rest) = uplink::split(&self.cfg, &present(&self.cfg));
+            self.ifaces = [&rest[..], &uplinks[..]].concat();
+            self.uplinks = uplinks;
+        }
+        let now = history::unix_now();
+        let held: Vec<String> = self
+            .ifaces
+            .iter()
+            .filter(|i| !self.uplinks.contains(i))
+            .filter(|i| {
+                self.history
+                    .ifaces
+                    .get(*i)
+                    .is_some_and(|s| s.lease_valid(net::ipv4_addr(i), now))
+            })
+            .cloned()
+            .collect();
+        for i in &held {
+            log::info!(iface = i; "{}: lease from before the restart still valid, DHCP skipped", i);
+        }
+        if !held.is_empty() {
+            self.bring_up(held.clone(), Dhcp::Off);
+        }
+        self.bring_up_all(Dhcp::from_cfg(&self.cfg), &held);
+    }
+
+    /// Every interface but `skip`, or with uplinks the others, then the
+    /// uplinks in priority order until one is ONLINE.
+    fn bring_up_all(&mut self, dhcp: Dhcp, skip: &[String]) {
+        let rest: Vec<String> = self
+            .ifaces
+            .iter()
+            .filter(|i| !self.uplinks.contains(i) && !skip.contains(i))
+            .filter(|i| {
+                let held = self.held(i);
+                if held {
+                    log::info!(iface = i; "{}: flapping, held down until its carrier settles", i);
+                }
+                !held
+            })
+            .cloned()
+            .collect();
+        if !rest.is_empty() {
+            self.bring_up(rest, dhcp);
+        }
+        if self.uplinks.is_empty() {
+            return;
+        }
+        let sel = uplink::select(
+            &self.cfg,
+            &self.uplinks,
+            dhcp,
+            backoff::deadline(&self.cfg),
+            &*self.ex,
+            &mut self.window,
+        );
+        for (rs, s) in &sel.tried {
+            self.track(rs);
+            self.out.probes(s);
+        }
+        for i in &sel.standby {
+            self.renew_at.remove(i);
+        }
+        match (&self.active, &sel.active) {
+            (a, b) if a == b => {}
+            (Some(a), Some(b)) => log::warn!(iface = b; "failing over from {} to {}", a, b),
+            (None, Some(b)) => log::info!(iface = b; "uplink {}", b),
+            (_, None) => log::warn!("no uplink ONLINE"),
+        }
+        self.active = sel.active;
+        self.failback = None;
+    }
+
+    /// Checks the uplinks ahead of the active one, bringing them up if
+    /// need be, and goes back to the first that has stayed healthy for
+    /// `failback_hold`.
+    fn fail_back(&mut self) {
+        let Some(active) = self.active.clone() else {
+            return;
+        };
+        let n = self.uplinks.iter().position(|u| *u == active).unwrap_or(0);
+        if n == 0 || sys::stop_requested() {
+            return;
+        }
+        let deadline = backoff::deadline(&self.cfg);
+        let mut healthy = None;
+        let ahead = self.uplinks[..n].to_vec();
+        for u in ahead {
+            if net::ipv4_addr(&u).is_none() {
+                self.bring_up(vec![u.clone()], Dhcp::from_cfg(&self.cfg));
+            }
+            let mut win = LatencyWindow::new(self.cfg.latency_window);
+            if uplink::check(&self.cfg, &u, &mut win, deadline).online() {
+                healthy = Some(u);
+                break;
+            }
+        }
+        let Some(u) = healthy else {
+            self.failback = None;
+            return;
+        };
+        let since = match &self.failback {
+            Some((p, t)) if *p == u => *t,
+            _ => Instant::now(),
+        };
+        self.failback = Some((u.clone(), since));
+        let held = since.elapsed().as_secs();
+        if held < self.cfg.failback_hold {
+            log::debug!(iface = &u; "{} healthy for {}s", u, held);
+            return;
+        }
+        log::warn!(iface = &u; "{} healthy for {}s, failing back from {}", u, held, active);
+        let m = self.uplinks.iter().position(|x| *x == u).unwrap_or(0);
+        let standby = self.uplinks[m + 1..].to_vec();
+        uplink::standby(&self.cfg, &standby, &*self.ex);
+        for i in &standby {
+            self.renew_at.remove(i);
+        }
+        self.active = Some(u);
+        self.failback = None;
+    }
+
+    /// Takes in the interfaces that appeared, bringing them up unless
+    /// another manager owns them or they are uplinks, which wait for
+    /// selection or fail-back, and forgets those that vanished.
+    fn rescan(&mut self) {
+        let (uplinks, rest) = uplink::split(&self.cfg, &present(&self.cfg));
+        let now = [&rest[..], &uplinks[..]].concat();
+        let gone: Vec<String> = self
+            .ifaces
+            .iter()
+            .filter(|i| !now.contains(i))
+            .cloned()
+            .collect();
+        let added: Vec<String> = now
+            .iter()
+            .filter(|i| !self.ifaces.contains(i))
+            .cloned()
+            .collect();
+        if gone.is_empty() && added.is_empty() {
+            return;
+        }
+        for i in &gone {
+            self.out.event(Event::Hotplug {
+                iface: i,
+                added: false,
+            });
+            self.forget(i);
+        }
+        for i in &added {
+            self.out.event(Event::Hotplug {
+                iface: i,
+                added: true,
+            });
+        }
+        self.ifaces = now;
+        self.uplinks = uplinks;
+        // Probed as a whole again until the next selection.
+        if self.active.as_ref().is_some_and(|a| gone.contains(a)) {
+            self.active = None;
+        }
+        if self
+            .failback
+            .as_ref()
+            .is_some_and(|(u, _)| gone.contains(u))
+        {
+            self.failback = None;
+        }
+        let new: Vec<String> = added
+            .into_iter()
+            .filter(|i| !self.uplinks.contains(i) && !self.held(i))
+            .collect();
+        if self.manager.is_none() && !new.is_empty() && !sys::stop_requested() {
+            self.bring_up(new, Dhcp::from_cfg(&self.cfg));
+        }
+    }
+
+    /// Drops what is kept about interface `i`, which vanished; its history
+    /// stays, for when it comes back.
+    fn forget(&mut self, i: &str) {
+        self.links.remove(i);
+        self.no_carrier.remove(i);
+        self.renew_at.remove(i);
+        self.on_static.remove(i);
+        self.counters.remove(i);
+        self.stalled.remove(i);
+        self.carriers.remove(i);
+        self.flapping_ifaces.remove(i);
+        self.manager_states.remove(i);
+        self.with_metrics(|m| m.forget_iface(i));
+    }
+
+    /// Whether the interfaces present differ from those looked after.
+    #[cfg(target_os = "linux")]
+    fn plugged(&self) -> bool {
+        let now = present(&self.cfg);
+        now.len() != self.ifaces.len() || now.iter().any(|i| !self.ifaces.contains(i))
+    }
+
+    /// Sleeps `d`, less once a control signal comes in; `true` when cut
+    /// short by an interface appearing or vanishing.
+    fn wait(&self, d: Duration) -> bool {
+        let end = Instant::now() + d;
+        while !sys::stop_requested() && !self.signals.pending() {
+            let left = end.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return false;
+            }
+            let step = left.min(Duration::from_millis(200));
+            if self.hotplugged(step) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Waits up to `step` to hear of a link coming or going; `true` when
+    /// one did and the interfaces present changed.
+    #[cfg(target_os = "linux")]
+    fn hotplugged(&self, step: Duration) -> bool {
+        let Some(m) = &self.hotplug else {
+            std::thread::sleep(step);
+            return false;
+        };
+        match sys::poll_in(&[m.as_raw_fd()], step) {
+            Ok(r) => r[0] && m.drain() && self.plugged(),
+            Err(_) => {
+                std::thread::sleep(step);
+                false
+            }
+        }
+    }
+
+    /// Elsewhere nothing tells: sleeps `step`, and the interfaces are
+    /// looked for each cycle instead.
+    #[cfg(not(target_os = "linux"))]
+    fn hotplugged(&self, step: Duration) -> bool {
+        std::thread::sleep(step);
+        false
+    }
+
+    /// Logs what the manager now says of the interfaces, where it changed.
+    fn watch(&mut self, b: NetworkBackend) {
+        let now = b.states();
+        for i in &self.ifaces {
+            let (was, is) = (self.manager_states.get(i), now.get(i));
+            if was == is {
+                continue;
+            }
+            let st = |s: Option<&String>| s.map_or("unknown", |s| s.as_str()).to_string();
+            match was {
+                Some(_) => log::info!(iface = i; "{}: {} {} -> {}", i, b, st(was), st(is)),
+                None => log::debug!(iface = i; "{}: {} {}", i, b, st(is)),
+            }
+        }
+        self.manager_states = now;
+    }
+
+    /// Runs `[tunnel] restart_command` as the check finds the tunnel down
+    /// with the uplink working; as hooks, once a change, and not while the
+    /// link is flapping.
+    fn restart_tunnel(&mut self) {
+        let down = self.diagnosis == Some(probe::Diagnosis::TunnelDown);
+        let new = down && !self.tunnel_down;
+        self.tunnel_down = down;
+        let Some(cmd) = self.cfg.tunnel_restart.clone().filter(|_| new) else {
+            return;
+        };
+        if self.flapping {
+            log::info!("tunnel down, uplink working; restart held back while FLAPPING");
+            return;
+        }
+        log::warn!("tunnel down, uplink working: running `{}`", cmd);
+        let argv = exec::argv(&["sh", "-c", &cmd]);
+        let t = Duration::from_secs(self.cfg.hook_timeout);
+        if let Err(e) = self.ex.run(&argv, t) {
+            log::error!("tunnel restart: {}", e);
+        }
+    }
+
+    /// Runs the recovery command once OFFLINE has lasted `reboot_after`,
+    /// then waits as long again before the next attempt.
+    fn recover(&mut self) {
+        let (Some(after), Some(since)) = (self.cfg.reboot_after, self.history.offline_since) else {
+            return;
+        };
+        let now = history::unix_now();
+        let down = now.saturating_sub(since);
+        if down < after || sys::stop_requested() {
+            return;
+        }
+        let cmd = self.cfg.recovery_command.clone();
+        log::warn!(
+            "OFFLINE for {}, running `{}`",
+            history::span(down),
+            cmd.join(" ")
+        );
+        self.history.offline_since = Some(now);
+        // Written first: a reboot may not come back to this loop.
+        self.save();
+        if let Err(e) = self.ex.run(&cmd, RECOVERY_TIMEOUT) {
+            log::error!("recovery command: {}", e);
+        }
+    }
+
+    /// `d`, or less when a lease renewal comes first.
+    fn until_renewal(&self, d: Duration) -> Duration {
+        let now = Instant::now();
+        self.renew_at
+            .values()
+            .map(|t| t.saturating_duration_since(now))
+            .fold(d, Duration::min)
+    }
+
+    /// Renews leases once `renew_remaining` of them is left rather than
+    /// letting them run out between checks.
+    fn renew_due(&mut self) {
+        let now = Instant::now();
+        let due: Vec<String> = self
+            .renew_at
+            .iter()
+            .filter(|(_, t)| **t <= now)
+            .map(|(i, _)| i.clone())
+            .collect();
+        if due.is_empty() || sys::stop_requested() {
+            return;
+        }
+        for i in &due {
+            self.renew_at.remove(i);
+            if self.on_static.contains(i) {
+                log::info!(iface = i; "{}: on static fallback, trying DHCP again", i);
+            } else {
+                log::info!(iface = i; "{}: lease running out, renewing", i);
+            }
+        }
+        self.bring_up(due, Dhcp::renew(&self.cfg));
+    }
+
+    /// Brings up links whose carrier has appeared since they were skipped,
+    /// instead of waiting for a check to fail.
+    fn carrier_back(&mut self) {
+        let back: Vec<String> = self
+            .no_carrier
+            .iter()
+            .filter(|i| net::has_carrier(i) && !self.held(i))
+            .cloned()
+            .collect();
+        if back.is_empty() || sys::stop_requested() {
+            return;
+        }
+        for i in &back {
+            log::info!(iface = i; "carrier on {}", i);
+        }
+        self.bring_up(back, Dhcp::renew(&self.cfg));
+    }
+
+    /// Reads interface counters and reports throughput since the last
+    /// cycle, so "link up but no traffic" shows even while pings pass.
+    /// An interface that went away, or came back as a new one, starts over.
+    fn traffic(&mut self) {
+        for i in &self.ifaces {
+            let now = Instant::now();
+            let Some(stats) = net::read_iface_stats(i) else {
+                self.counters.remove(i);
+                continue;
+            };
+            let index = net::index(i);
+            let rates = self
+                .counters
+                .get(i)
+                .filter(|(_, x, _)| *x == index)
+                .and_then(|(t, _, prev)| {
+                    let secs = now.duration_since(*t).as_secs_f64().max(1e-3);
+                    let rx = net::counter_delta(prev.rx_bytes, stats.rx_bytes)?;
+                    let tx = net::counter_delta(prev.tx_bytes, stats.tx_bytes)?;
+                    Some((rx as f64 / secs, tx as f64 / secs))
+                });
+            self.counters.insert(i.clone(), (now, index, stats));
+            let stalled = rates.is_some_and(|r| r.0 == 0.0) && net::has_carrier(i);
+            if stalled && self.stalled.insert(i.clone()) {
+                log::warn!(iface = i; "{}: link up but nothing received since the last check", i);
+            } else if !stalled && self.stalled.remove(i) {
+                log::info!(iface = i; "{}: receiving again", i);
+            }
+            self.out.event(Event::Traffic {
+                iface: i,
+                stats: &stats,
+                rates,
+            });
+            if let Some(m) = &self.metrics {
+                m.lock().unwrap().record_traffic(i, stats, rates);
+            }
+        }
+    }
+
+    /// Counts carrier changes from the kernel's counter, which catches
+    /// those between checks too, and says when an interface starts or
+    /// stops flapping.
+    fn carrier_flaps(&mut self) {
+        let now = history::unix_now();
+        let window = self.cfg.flap_window * 60;
+        for i in &self.ifaces {
+            let Some(n) = net::carrier_changes(i) else {
+                self.carriers.remove(i);
+                continue;
+            };
+            // A recreated interface counts from zero again.
+            match self.carriers.insert(i.clone(), n) {
+                Some(p) if n > p => self.history.carrier_changed(i, n - p, now),
+                _ => {}
+            }
+            let count = self
+                .history
+                .ifaces
+                .get(i)
+                .map_or(0, |s| s.flaps.count(now, window));
+            if count > self.cfg.flap_threshold {
+                if self.flapping_ifaces.insert(i.clone()) {
+                    self.out.event(Event::Flapping {
+                        iface: Some(i),
+                        transitions: count,
+                        window_min: self.cfg.flap_window,
+                        hold: self.cfg.flap_hold,
+                    });
+                }
+            } else if self.flapping_ifaces.remove(i) {
+                log::info!(iface = i; "{}: no longer flapping", i);
+            }
+        }
+    }
+
+    /// Flapping, and its carrier changed within `flap_hold`.
+    fn held(&self, i: &str) -> bool {
+        let now = history::unix_now();
+        self.cfg.flap_hold > 0
+            && self.flapping_ifaces.contains(i)
+            && self
+                .history
+                .ifaces
+                .get(i)
+                .and_then(|s| s.flaps.last())
+                .is_some_and(|t| now.saturating_sub(t) < self.cfg.flap_hold)
+    }
+
+    fn save(&mut self) {
+        self.history.tick(history::unix_now());
+        let Some(path) = &self.cfg.state_file else {
+            return;
+        };
+        match self.history.save(path) {
+            Ok(()) => self.save_failed = false,
+            Err(e) if !self.save_failed => {
+                log::warn!("state file {}: {}", path.display(), e);
+                self.save_failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Appends the change to `to` to the history file.
+    fn record(&mut self, to: &str) {
+        let cause = match to {
+            "OFFLINE" | "RECOVERING" => self.diagnosis.map(|d| d.as_str()),
+            _ => None,
+        };
+        let r = Record::new(self.state.map(|s| s.to_string()), to, cause);
+        match timeline::append(&self.cfg, &r) {
+            Ok(()) => self.record_failed = false,
+            Err(e) if !self.record_failed => {
+                let path = self.cfg.history_file.as_deref().unwrap_or("".as_ref());
+                log::warn!("history file {}: {}", path.display(), e);
+                self.record_failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn sd(&self, msg: &str) {
+        if let Some(sd) = &self.systemd {
+            sd.notify(msg);
+        }
+    }
+
+    /// No systemd to tell.
+    #[cfg(not(target_os = "linux"))]
+    fn sd(&self, _msg: &str) {}
+
+    /// When to pet the systemd watchdog, if it is watching.
+    #[cfg(target_os = "linux")]
+    fn keepalive(&self) -> Option<Duration> {
+        self.systemd.as_ref().and_then(Systemd::keepalive)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn keepalive(&self) -> Option<Duration> {
+        None
+    }
+
+    /// READY=1 once the first check has come back ONLINE; after that every
+    /// completed cycle counts as a watchdog keep-alive.
+    fn sd_cycle(&mut self) {
+        let online = matches!(self.state, Some(LinkState::Up { .. }));
+        if let Some(s) = self.state {
+            self.sd(&format!("STATUS={}", s.describe(self.cfg.ipv6)));
+        }
+        if !self.ready && online {
+            self.ready = true;
+            self.sd("READY=1");
+        }
+        if self.ready {
+            self.sd("WATCHDOG=1");
+        }
+    }
+
+    /// Sleeps `d`, waking up to pet the systemd watchdog at half its timeout.
+    /// Cut short, polling every second, once carrier comes back on a link
+    /// that had none, when an interface appears or vanishes, and by SIGHUP
+    /// and SIGUSR1.
+    fn pause(&mut self, d: Duration) {
+        let end = Instant::now() + d;
+        let mut step = self.keepalive().unwrap_or(d);
+        if !self.no_carrier.is_empty() {
+            step = step.min(Duration::from_secs(1));
+        }
+        loop {
+            let d = end.saturating_duration_since(Instant::now());
+            if d.is_zero() || sys::stop_requested() {
+                return;
+            }
+            if self.signals.pending() && self.controls() {
+                return;
+            }
+            if self
+                .no_carrier
+                .iter()
+                .any(|i| net::has_carrier(i) && !self.held(i))
+            {
+                return;
+            }
+            if self.wait(step.min(d)) {
+                return;
+            }
+            if self.ready && Instant::now() < end {
+                self.sd("WATCHDOG=1");
+            }
+        }
+    }
+
+    /// Acts on the [`CONTROLS`] that came in; whether to check at once.
+    /// Only ever between checks, so none is cut into.
+    fn controls(&mut self) -> bool {
+        if self.signals.take(sys::SIGUSR2) {
+            let j = self.dump();
+            self.out.event(Event::Dump { state: &j });
+        }
+        let reloaded = self.signals.take(sys::SIGHUP) && self.reload();
+        let now = self.signals.take(sys::SIGUSR1);
+        if now {
+            log::info!("check requested");
+        }
+        reloaded || now
+    }
+
+    /// Swaps in the config as it reads now, unless it does not load; the
+    /// interfaces it names are taken in by the rescan that follows. The
+    /// metrics and health listeners, and the `[[namespace]]` loops, stay
+    /// as they started.
+    fn reload(&mut self) -> bool {
+        let Some(f) = &self.reload else {
+            return false;
+        };
+        let (cfg, notifiers) = match f() {
+            Ok(r) => r,
+            Err(e) => {
+                self.out.event(Event::Reload { error: Some(&e) });
+                return false;
+            }
+        };
+        if cfg.latency_window != self.cfg.latency_window {
+            self.window = LatencyWindow::new(cfg.latency_window);
+        }
+        self.availability.resize(cfg.availability_window);
+        self.interval = cfg.interval;
+        self.manager = manager::passive(&cfg);
+        self.notifiers = notifiers;
+        self.cfg = Cow::Owned(cfg);
+        self.out.event(Event::Reload { error: None });
+        true
+    }
+
+    /// What SIGUSR2 prints: the state file's content, and what is only
+    /// kept in memory.
+    fn dump(&self) -> Json {
+        let set = |s: &BTreeSet<String>| s.iter().cloned().collect::<Vec<_>>();
+        self.history
+            .to_json()
+            .set("ifaces", self.history.ifaces_json())
+            .set("netns", self.netns.as_deref())
+            .set("managed", self.ifaces.clone())
+            .set("uplink", self.active.as_deref())
+            .set("no_carrier", set(&self.no_carrier))
+            .set("on_static", set(&self.on_static))
+            .set("flapping", self.flapping)
+            .set("interval_secs", self.interval)
+            .set("relaxed", self.relaxed())
+            .set("diagnosis", self.diagnosis.map(|d| d.as_str()))
+            .set("portal_url", self.portal.as_deref())
+    }
+
+    fn with_metrics(&self, f: impl FnOnce(&mut Metrics)) {
+        if let Some(m) = &self.metrics {
+            f(&mut m.lock().unwrap());
+        }
+    }
+
+    fn bring_up(&mut self, ifaces: Vec<String>, dhcp: Dhcp) {
+        let rs = iface::bring_up(
+            &self.cfg,
+            &ifaces,
+            dhcp,
+            backoff::deadline(&self.cfg),
+            &*self.ex,
+        );
+        self.track(&rs);
+    }
+
+    /// Reports bring-up and keeps the lease, carrier and link bookkeeping.
+    fn track(&mut self, rs: &[IfaceReport]) {
+        self.out.interfaces(rs);
+        self.with_metrics(|m| m.record_ifaces(rs));
+        let updown = |up| if up { "UP" } else { "DOWN" };
+        let now = history::unix_now();
+        for r in rs {
+            let expires = r.lease.as_ref().and_then(|l| l.expire);
+            self.history.link(&r.name, r.link_up, r.ipv4, expires, now);
+            match r.renew_at.filter(|_| !self.cfg.skip_dhcp) {
+                Some(t) => {
+                    // An overdue one waits a cycle, lest a lease file that
+                    // never changes make us renew in a loop.
+                    let wait = match t.checked_sub(now) {
+                        Some(w) if w > 0 => Duration::from_secs(w),
+                        _ => Duration::from_secs(self.cfg.interval),
+                    };
+                    self.renew_at.insert(r.name.clone(), Instant::now() + wait);
+                }
+                None => {
+                    self.renew_at.remove(&r.name);
+                }
+            }
+            if r.fallback == Some(true) {
+                self.on_static.insert(r.name.clone());
+                if self.cfg.fallback_retry > 0 && !self.cfg.skip_dhcp {
+                    let t = Instant::now() + Duration::from_secs(self.cfg.fallback_retry);
+                    self.renew_at.insert(r.name.clone(), t);
+                }
+            } else {
+                self.on_static.remove(&r.name);
+            }
+            if r.link == LinkStatus::NoCarrier && r.kind.role() == IfaceRole::Managed {
+                self.no_carrier.insert(r.name.clone());
+            } else {
+                self.no_carrier.remove(&r.name);
+            }
+            match self.links.insert(r.name.clone(), r.link_up) {
+                Some(was) if was != r.link_up => {
+                    self.notify(updown(r.link_up), Some(&r.name), Some(updown(was).into()))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn notification(
+        &self,
+        status: &str,
+        iface: Option<&str>,
+        previous_status: Option<String>,
+    ) -> Notification {
+        Notification {
+            status: status.into(),
+            iface: iface.map(String::from),
+            timestamp: clock::now(),
+            previous_status,
+            portal_url: self.portal.clone().filter(|_| iface.is_none()),
+            outage_secs: None,
+            netns: self.netns.clone(),
+        }
+    }
+
+    fn notify(&self, status: &str, iface: Option<&str>, previous_status: Option<String>) {
+        self.send(&self.notification(status, iface, previous_status));
+    }
+
+    fn send(&self, n: &Notification) {
+        for x in &self.notifiers {
+            if !(self.flapping && x.remediates()) {
+                x.notify(n);
+            }
+        }
+    }
+
+    /// With `adaptive`, stretches the time to the next check after
+    /// `adaptive_after` checks in a row ONLINE, up to `max_interval`, and
+    /// brings it back once one is not.
+    fn adapt(&mut self) {
+        let c = &self.cfg;
+        let next = match self.state {
+            Some(LinkState::Up { .. })
+                if c.adaptive && self.availability.successes() >= c.adaptive_after =>
+            {
+                let n = self.interval.saturating_mul(c.adaptive_factor);
+                n.min(c.max_interval).max(c.interval)
+            }
+            _ => c.interval,
+        };
+        let relaxed = next > c.interval;
+        if relaxed && !self.relaxed() {
+            log::info!("healthy: probing one target, less often");
+        }
+        log::debug!("next check in {}s", next);
+        self.interval = next;
+        self.with_metrics(|m| m.record_pace(next, relaxed));
+    }
+
+    /// Probing one target, less often than `interval`.
+    fn relaxed(&self) -> bool {
+        self.interval > self.cfg.interval
+    }
+
+    /// Through the active uplink only, when there is one. A relaxed check
+    /// that does not find the host ONLINE is made again with every target.
+    fn probe(&mut self) -> LinkState {
+        let relaxed = self.relaxed();
+        let mut sum = self.check(relaxed);
+        if relaxed && !sum.online() {
+            log::info!("{} with one target; probing them all", sum.state.as_str());
+            self.interval = self.cfg.interval;
+            let first = sum.elapsed;
+            sum = self.check(false);
+            sum.elapsed += first;
+        }
+        self.out.probes(&sum);
+        self.portal = sum.portal_url().map(String::from);
+        self.diagnosis = sum.diagnosis;
+        self.with_metrics(|m| m.record_probe(&sum));
+        self.availability.push(&sum);
+        let w = self.availability.stats();
+        self.with_metrics(|m| m.record_window(&w));
+        self.history.window = Some(w);
+        // Without per-interface verdicts, the managed one the default route
+        // leaves by gets the overall one.
+        let now = history::unix_now();
+        let label = |i: &str, st: &'static str| match st {
+            "ONLINE" if self.on_static.contains(i) => "STATIC_FALLBACK",
+            _ => st,
+        };
+        match &sum.gateway {
+            Some(g) if sum.per_iface.is_empty() && self.ifaces.contains(&g.iface) => {
+                let st = label(&g.iface, sum.state.as_str());
+                self.history.probed(&g.iface, st, now)
+            }
+            _ => {}
+        }
+        for v in &sum.per_iface {
+            let st = label(&v.iface, if v.online { "ONLINE" } else { "OFFLINE" });
+            self.history.probed(&v.iface, st, now);
+        }
+        verdict(&sum)
+    }
+
+    /// `relaxed`: with one target, the others recorded as skipped.
+    fn check(&mut self, relaxed: bool) -> probe::Summary {
+        let relaxed = relaxed.then(|| probe::relaxed(&self.cfg));
+        let cfg = relaxed.as_ref().map_or(&*self.cfg, |r| &r.0);
+        let deadline = backoff::deadline(cfg);
+        let mut sum = match &self.active {
+            Some(u) => uplink::check(cfg, u, &mut self.window, deadline),
+            None => probe::check(cfg, &mut self.window, deadline),
+        };
+        sum.skipped = relaxed.map(|r| r.1).unwrap_or_default();
+        sum
+    }
+
+    /// A transition is a change of the state's name: IPv4 or IPv6 coming
+    /// or going within ONLINE is logged, and is none.
+    fn set(&mut self, s: LinkState) {
+        if self.state.map(|p| p.to_string()) == Some(s.to_string()) {
+            if self.state != Some(s) && self.cfg.ipv6 {
+                log::info!("still {}", s.describe(true));
+            }
+            self.state = Some(s);
+            let n = self.notification(&s.to_string(), None, Some(s.to_string()));
+            for x in &self.notifiers {
+                x.checked(&n);
+            }
+            return;
+        }
+        self.out.event(Event::State {
+            from: self.state,
+            to: s,
+            ipv6: self.cfg.ipv6,
+        });
+        self.record(&s.to_string());
+        let now = history::unix_now();
+        let down_since = self.history.offline_since;
+        self.history.record(&s.to_string(), now);
+        let n = self.history.flaps(now, self.cfg.flap_window * 60);
+        self.flapping = n > self.cfg.flap_threshold;
+        if self.flapping {
+            self.out.event(Event::Flapping {
+                iface: None,
+                transitions: n,
+                window_min: self.cfg.flap_window,
+                hold: 0,
+            });
+        }
+        let prev = self.state.map(|p| p.to_string());
+        let mut n = self.notification(&s.to_string(), None, prev);
+        n.outage_secs = down_since
+            .filter(|_| self.history.offline_since.is_none())
+            .map(|t| now.saturating_sub(t));
+        self.send(&n);
+        if s == LinkState::Down && self.cfg.traceroute_hops > 0 {
+            // Hops may take seconds each; the loop does not wait for them.
+            let cfg = self.cfg.clone().into_owned();
+            std::thread::spawn(move || {
+                if let Some(t) = ping::Trace::run(&cfg) {
+                    log::warn!("{}", t);
+                }
+            });
+        }
+        if let Some(h) = &self.health {
+            *h.lock().unwrap_or_else(|p| p.into_inner()) = Some(s);
+        }
+        self.state = Some(s);
+    }
+}
+
+fn verdict(sum: &probe::Summary) -> LinkState {
+    match sum.state {
+        State::Online => LinkState::Up {
+            v4_ok: sum.v4 == Some(true),
+            v6_ok: sum.v6 == Some(true),
+        },
+        State::Degraded => LinkState::Degraded,
+        State::DnsBroken => LinkState::DnsBroken,
+        State::CaptivePortal => LinkState::CaptivePortal,
+        State::Offline => LinkState::Down,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProbeMethod;
+    use crate::output::Quiet;
+    use std::fs;
+    use std::path::Path;
+
+    fn write(path: &Path, targets: &str, interval: u64) {
+        let daemon = "state_file = \"\"\npid_file = \"\"\nhistory_file = \"\"\naudit_file = \"\"";
+        let src = format!(
+            "[probe]\ntargets = [{}]\n\n[daemon]\ninterval = {}\n{}\n",
+            targets, interval, daemon
+        );
+        fs::write(path, src).unwrap();
+    }
+
+    #[test]
+    fn reload_swaps_in_a_config_that_loads() {
+        let path = std::env::temp_dir().join(format!("cw-reload-{}.toml", std::process::id()));
+        write(&path, "\"192.0.2.1\"", 30);
+        let cfg = Config::load(&path).unwrap();
+        let mut w = WatchdogLoop::new(&cfg, Box::new(Quiet)).reloader(Box::new(|| {
+            Config::load(&path)
+                .map(|c| (c, Vec::new()))
+                .map_err(|e| e.to_string())
+        }));
+        let first = vec![ProbeMethod::Icmp("192.0.2.1".parse().unwrap())];
+
+        // One that does not load keeps the old one.
+        fs::write(&path, "[probe]\ntargets = [\"192.0.2.1\"\n").unwrap();
+        assert!(!w.reload());
+        fs::write(&path, "[probe]\nnosuch = 1\n").unwrap();
+        assert!(!w.reload());
+        assert_eq!(w.cfg.targets, first);
+        assert_eq!(w.interval, 30);
+
+        write(&path, "\"192.0.2.2\", \"192.0.2.3\"", 5);
+        assert!(w.reload());
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            w.cfg.targets,
+            ["192.0.2.2", "192.0.2.3"]
+                .map(|a| ProbeMethod::Icmp(a.parse().unwrap()))
+                .to_vec()
+        );
+        assert_eq!((w.cfg.interval, w.interval), (5, 5));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::exec::{self, Executor};
+use crate::iface::global_v6;
+use crate::net;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// A DHCP client we know how to drive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpBackend {
+    Dhclient,
+    Dhcpcd,
+    /// BusyBox; `udhcpc6` for IPv6.
+    Udhcpc,
+    /// `none`: NetworkManager or systemd-networkd runs DHCP; just wait for
+    /// an address to appear.
+    External,
+}
+
+/// What the interface has once the client is done.
+pub struct Lease {
+    pub ipv4: Option<Ipv4Addr>,
+    pub gateway: Option<Ipv4Addr>,
+    pub v6_addrs: Vec<Ipv6Addr>,
+}
+
+impl Lease {
+    fn read(iface: &str) -> Lease {
+        Lease {
+            ipv4: net::ipv4_addr(iface),
+            gateway: net::gateway(iface),
+            v6_addrs: global_v6(iface),
+        }
+    }
+}
+
+const ALL: [DhcpBackend; 3] = [
+    DhcpBackend::Dhclient,
+    DhcpBackend::Dhcpcd,
+    DhcpBackend::Udhcpc,
+];
+
+/// The first of dhclient, dhcpcd, udhcpc found on `$PATH`; dhclient if none
+/// is, so the error names something familiar. Never `none`.
+pub fn detect_dhcp_backend() -> DhcpBackend {
+    first_installed(exec::in_path)
+}
+
+fn first_installed(installed: impl Fn(&str) -> bool) -> DhcpBackend {
+    ALL.into_iter()
+        .find(|b| installed(b.binary(4)))
+        .unwrap_or(DhcpBackend::Dhclient)
+}
+
+impl DhcpBackend {
+    /// The client it runs; `none` for [`DhcpBackend::External`].
+    pub fn binary(self, family: u8) -> &'static str {
+        match (self, family) {
+            (DhcpBackend::Dhclient, _) => "dhclient",
+            (DhcpBackend::Dhcpcd, _) => "dhcpcd",
+            (DhcpBackend::Udhcpc, 6) => "udhcpc6",
+            (DhcpBackend::Udhcpc, _) => "udhcpc",
+            (DhcpBackend::External, _) => "none",
+        }
+    }
+
+    /// Full command line: get one lease in the foreground within `timeout`,
+    /// then exit, non-zero on failure. Empty for `none`.
+    pub fn argv(self, family: u8, iface: &str, timeout: Duration) -> Vec<String> {
+        let secs = timeout.as_secs().max(1);
+        let t = secs.to_string();
+        // udhcpc sends -t discovers -T seconds apart.
+        let tries = (secs / 3).max(1).to_string();
+        let fam = if family == 6 { "-6" } else { "-4" };
+        let mut v: Vec<&str> = vec![self.binary(family)];
+        match self {
+            DhcpBackend::Dhclient if family == 6 => v.extend(["-6", "-1", "-q", iface]),
+            DhcpBackend::Dhclient => v.extend(["-1", "-q", iface]),
+            DhcpBackend::Dhcpcd => v.extend(["-1", fam, "-q", "-t", &t, iface]),
+            DhcpBackend::Udhcpc => v.extend(["-i", iface, "-n", "-q", "-T", "3", "-t", &tries]),
+            DhcpBackend::External => return Vec::new(),
+        }
+        v.into_iter().map(String::from).collect()
+    }
+
+    /// Gives the lease on `iface` back to the server. Empty where the
+    /// client cannot: udhcpc was not left running, and `none` has no lease
+    /// of its own.
+    pub fn release_argv(self, iface: &str) -> Vec<String> {
+        let v: &[&str] = match self {
+            DhcpBackend::Dhclient => &["dhclient", "-r", iface],
+            DhcpBackend::Dhcpcd => &["dhcpcd", "-k", iface],
+            DhcpBackend::Udhcpc | DhcpBackend::External => &[],
+        };
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    pub fn acquire(
+        self,
+        ex: &dyn Executor,
+        iface: &str,
+        timeout: Duration,
+    ) -> Result<Lease, String> {
+        self.acquire_family(ex, 4, iface, timeout)
+    }
+
+    pub fn acquire6(
+        self,
+        ex: &dyn Executor,
+        iface: &str,
+        timeout: Duration,
+    ) -> Result<Lease, String> {
+        self.acquire_family(ex, 6, iface, timeout)
+    }
+
+    fn acquire_family(
+        self,
+        ex: &dyn Executor,
+        family: u8,
+        iface: &str,
+        timeout: Duration,
+    ) -> Result<Lease, String> {
+        match self {
+            DhcpBackend::External if !ex.live() => Ok(Lease::read(iface)),
+            DhcpBackend::External => wait_for_address(family, iface, timeout),
+            _ => acquire_with(
+                ex,
+                &self.argv(family, iface, timeout),
+                family,
+                iface,
+                timeout,
+            ),
+        }
+    }
+}
+
+/// Whether `l` has what a client of `family` is for.
+fn has_address(l: &Lease, family: u8) -> bool {
+    match family {
+        6 => !l.v6_addrs.is_empty(),
+        _ => l.ipv4.is_some(),
+    }
+}
+
+/// Runs a complete client command line (`family` 4 or 6). Clients are
+/// given `timeout` themselves and killed a little later, so one that gives
+/// up on its own says why. An exit status of 0 without an address is still
+/// a failure (unless nothing really ran).
+pub fn acquire_with(
+    ex: &dyn Executor,
+    argv: &[String],
+    family: u8,
+    iface: &str,
+    timeout: Duration,
+) -> Result<Lease, String> {
+    ex.run(argv, timeout + Duration::from_secs(2))?;
+    let l = Lease::read(iface);
+    if ex.live() && !has_address(&l, family) {
+        return Err(format!(
+            "{} exited without an IPv{} address on {}",
+            argv[0], family, iface
+        ));
+    }
+    Ok(l)
+}
+
+/// The `none` backend: polls until the system's own DHCP client has put an
+/// address on `iface`.
+fn wait_for_address(family: u8, iface: &str, timeout: Duration) -> Result<Lease, String> {
+    let end = Instant::now() + timeout;
+    loop {
+        let l = Lease::read(iface);
+        if has_address(&l, family) {
+            return Ok(l);
+        }
+        if Instant::now() >= end {
+            return Err(format!(
+                "no IPv{} address appeared on {} within {}s",
+                family,
+                iface,
+                timeout.as_secs()
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+}
+
+impl FromStr for DhcpBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<DhcpBackend, ()> {
+        ALL.into_iter()
+            .chain([DhcpBackend::External])
+            .find(|b| b.to_string() == s)
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for DhcpBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.binary(4))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detection_prefers_dhclient_then_dhcpcd_then_udhcpc() {
+        let with = |bins: &'static [&str]| first_installed(|b| bins.contains(&b));
+        assert_eq!(
+            with(&["udhcpc", "dhcpcd", "dhclient"]),
+            DhcpBackend::Dhclient
+        );
+        assert_eq!(with(&["udhcpc", "dhcpcd"]), DhcpBackend::Dhcpcd);
+        assert_eq!(with(&["udhcpc"]), DhcpBackend::Udhcpc);
+        assert_eq!(with(&[]), DhcpBackend::Dhclient);
+    }
+}
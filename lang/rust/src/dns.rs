@@ -0,0 +1,237 @@
+// A stub resolver: one A query over UDP per server, no caching, no TCP
+// fallback. Enough to tell "DNS is broken" from "the network is down".
+use crate::ping::ProbeResult;
+use crate::probe::Probe;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+static ID: AtomicU16 = AtomicU16::new(0x5c3a);
+
+pub struct Answer {
+    pub server: IpAddr,
+    pub addrs: Vec<IpAddr>,
+    pub rtt: Duration,
+}
+
+/// `nameserver` lines of `/etc/resolv.conf`, or on Windows the adapters'
+/// DNS servers; the local host if there are none.
+pub fn system_servers() -> Vec<IpAddr> {
+    #[cfg(unix)]
+    let v: Vec<IpAddr> = fs::read_to_string("/etc/resolv.conf")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("nameserver"))
+        .filter_map(|a| a.trim().split('%').next()?.parse().ok())
+        .collect();
+    #[cfg(windows)]
+    let v = crate::ifaddrs::dns_servers();
+    if v.is_empty() {
+        vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]
+    } else {
+        v
+    }
+}
+
+/// `name`'s addresses: itself when it is one, those /etc/hosts gives it,
+/// or else the A records of the first system server that answers within
+/// `timeout`, all of them asked in turn within it.
+pub fn lookup(name: &str, timeout: Duration) -> Result<Vec<IpAddr>, String> {
+    if let Ok(a) = name.parse() {
+        return Ok(vec![a]);
+    }
+    let hosts = hosts(name);
+    if !hosts.is_empty() {
+        return Ok(hosts);
+    }
+    let end = Instant::now() + timeout;
+    let mut err = String::from("no DNS servers");
+    for s in system_servers() {
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        match query(name, s, left) {
+            Ok(a) => return Ok(a.addrs),
+            Err(e) => err = format!("{}: {}", s, e),
+        }
+    }
+    Err(format!("resolving {}: {}", name, err))
+}
+
+/// Where the hosts file is.
+#[cfg(unix)]
+fn hosts_file() -> std::path::PathBuf {
+    "/etc/hosts".into()
+}
+
+#[cfg(windows)]
+fn hosts_file() -> std::path::PathBuf {
+    let root = std::env::var_os("SystemRoot").unwrap_or_else(|| r"C:\Windows".into());
+    std::path::Path::new(&root).join(r"System32\drivers\etc\hosts")
+}
+
+/// The addresses the hosts file gives `name`.
+fn hosts(name: &str) -> Vec<IpAddr> {
+    fs::read_to_string(hosts_file())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| {
+            let mut w = l.split('#').next()?.split_whitespace();
+            let a = w.next()?.parse().ok()?;
+            w.any(|n| n.eq_ignore_ascii_case(name)).then_some(a)
+        })
+        .collect()
+}
+
+/// Asks each server in turn, returning the first that resolves `name`.
+pub fn resolve(name: &str, servers: &[IpAddr], timeout: Duration) -> Result<Answer, String> {
+    let mut err = String::from("no DNS servers");
+    for &s in servers {
+        match query(name, s, timeout) {
+            Ok(a) => return Ok(a),
+            Err(e) => err = format!("{}: {}", s, e),
+        }
+    }
+    Err(err)
+}
+
+/// `count` lookups of `name`, asking `server` or else the system resolver
+/// (getaddrinfo, so /etc/hosts and nsswitch apply and `timeout` does not).
+/// The RTT is the mean lookup time of the successful ones.
+pub fn probe(name: &str, server: Option<IpAddr>, timeout: Duration, count: u8) -> ProbeResult {
+    let mut found = None;
+    let mut rtts = Vec::new();
+    for _ in 0..count {
+        let t0 = Instant::now();
+        let addr = match server {
+            Some(s) => query(name, s, timeout).ok().map(|a| a.addrs[0]),
+            None => (name, 0)
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut a| a.next())
+                .map(|a| a.ip()),
+        };
+        if let Some(a) = addr {
+            rtts.push(t0.elapsed());
+            found = Some(a);
+        }
+    }
+    ProbeResult::from_rtts(found, count, &rtts)
+}
+
+fn query(name: &str, server: IpAddr, timeout: Duration) -> Result<Answer, String> {
+    let id = ID.fetch_add(1, Ordering::Relaxed);
+    let mut q = Vec::with_capacity(32 + name.len());
+    q.extend_from_slice(&id.to_be_bytes());
+    q.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for l in name.trim_end_matches('.').split('.') {
+        if l.is_empty() || l.len() > 63 {
+            return Err(format!("invalid name `{}`", name));
+        }
+        q.push(l.len() as u8);
+        q.extend_from_slice(l.as_bytes());
+    }
+    q.extend_from_slice(&[0, 0, 1, 0, 1]);
+    let local: SocketAddr = match server {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let io = |e: std::io::Error| e.to_string();
+    let sock = UdpSocket::bind(local).map_err(io)?;
+    sock.connect((server, 53)).map_err(io)?;
+    let t0 = Instant::now();
+    sock.send(&q).map_err(io)?;
+    let mut buf = [0u8; 1500];
+    loop {
+        let left = timeout.saturating_sub(t0.elapsed());
+        if left.is_zero() {
+            return Err("timed out".into());
+        }
+        sock.set_read_timeout(Some(left)).map_err(io)?;
+        let n = match sock.recv(&mut buf) {
+            Ok(n) => n,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err("timed out".into())
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        let r = &buf[..n];
+        // Stray datagrams (late answers to earlier queries) are skipped.
+        if n < 12 || r[..2] != id.to_be_bytes() || r[2] & 0x80 == 0 {
+            continue;
+        }
+        let rcode = r[3] & 0x0f;
+        if rcode != 0 {
+            let what = match rcode {
+                2 => "server failure",
+                3 => "no such name",
+                5 => "refused",
+                _ => "error",
+            };
+            return Err(format!("{} (rcode {})", what, rcode));
+        }
+        let addrs = parse_answers(r).ok_or("malformed response")?;
+        if addrs.is_empty() {
+            return Err("no addresses in answer".into());
+        }
+        return Ok(Answer {
+            server,
+            addrs,
+            rtt: t0.elapsed(),
+        });
+    }
+}
+
+fn skip_name(r: &[u8], mut i: usize) -> Option<usize> {
+    loop {
+        let l = *r.get(i)?;
+        match l {
+            0 => return Some(i + 1),
+            l if l & 0xc0 == 0xc0 => return Some(i + 2),
+            l => i += 1 + l as usize,
+        }
+    }
+}
+
+fn parse_answers(r: &[u8]) -> Option<Vec<IpAddr>> {
+    let qd = u16::from_be_bytes([r[4], r[5]]);
+    let an = u16::from_be_bytes([r[6], r[7]]);
+    let mut i = 12;
+    for _ in 0..qd {
+        i = skip_name(r, i)? + 4;
+    }
+    let mut v = Vec::new();
+    for _ in 0..an {
+        i = skip_name(r, i)?;
+        let h = r.get(i..i + 10)?;
+        let ty = u16::from_be_bytes([h[0], h[1]]);
+        let len = u16::from_be_bytes([h[8], h[9]]) as usize;
+        let data = r.get(i + 10..i + 10 + len)?;
+        match (ty, len) {
+            (1, 4) => v.push(IpAddr::from(<[u8; 4]>::try_from(data).ok()?)),
+            (28, 16) => v.push(IpAddr::from(<[u8; 16]>::try_from(data).ok()?)),
+            _ => {}
+        }
+        i += 10 + len;
+    }
+    Some(v)
+}
+
+pub struct Lookup<'a> {
+    pub name: &'a str,
+    pub server: Option<IpAddr>,
+    pub count: u8,
+}
+
+impl Probe for Lookup<'_> {
+    fn check(&self, timeout: Duration) -> ProbeResult {
+        probe(self.name, self.server, timeout, self.count)
+    }
+}
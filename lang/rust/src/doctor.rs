@@ -0,0 +1,303 @@
+// `doctor`: whether this host can run cw-netup as configured, checked
+// without touching anything: the programs the selected backends start,
+// the capabilities it needs, what it reads from /sys and /proc, and the
+// config itself. Off Linux, where nothing is set up, only what probing
+// needs.
+use cw_netup::config::{Config, ProbeMethod};
+use cw_netup::dhcp::{self, DhcpBackend};
+#[cfg(not(target_os = "linux"))]
+use cw_netup::ifaddrs;
+use cw_netup::json::Json;
+use cw_netup::output::OutputSink;
+use cw_netup::{exec, iface, manager, sys};
+use std::fmt::Write as _;
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::net::ToSocketAddrs;
+
+#[cfg(target_os = "linux")]
+const CAP_NET_ADMIN: u32 = 12;
+#[cfg(target_os = "linux")]
+const CAP_NET_RAW: u32 = 13;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Level {
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Pass => "pass",
+            Level::Warn => "warn",
+            Level::Fail => "fail",
+        }
+    }
+}
+
+struct Check {
+    name: String,
+    level: Level,
+    detail: String,
+}
+
+#[derive(Default)]
+struct Checks(Vec<Check>);
+
+impl Checks {
+    fn add(&mut self, name: impl Into<String>, level: Level, detail: impl Into<String>) {
+        self.0.push(Check {
+            name: name.into(),
+            level,
+            detail: detail.into(),
+        });
+    }
+
+    /// `bin` on `$PATH`, or `missing` when not, with `why` it is wanted.
+    fn binary(&mut self, bin: &str, missing: Level, why: &str) {
+        if exec::in_path(bin) {
+            self.add(bin, Level::Pass, why);
+        } else {
+            self.add(bin, missing, format!("not found; {}", why));
+        }
+    }
+}
+
+/// The effective capability set from /proc/self/status.
+#[cfg(target_os = "linux")]
+fn cap_eff() -> Option<u64> {
+    let s = fs::read_to_string("/proc/self/status").ok()?;
+    let hex = s.lines().find_map(|l| l.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(hex.trim(), 16).ok()
+}
+
+#[cfg(target_os = "linux")]
+fn capabilities(c: &mut Checks, cfg: &Config) {
+    let Some(caps) = cap_eff() else {
+        c.add("capabilities", Level::Warn, "/proc/self/status unreadable");
+        return;
+    };
+    let has = |bit: u32| caps & (1 << bit) != 0;
+    let managing = manager::passive(cfg).is_none();
+    match (has(CAP_NET_ADMIN), managing) {
+        (true, _) => c.add("CAP_NET_ADMIN", Level::Pass, "links, addresses and routes"),
+        (false, true) => c.add(
+            "CAP_NET_ADMIN",
+            Level::Fail,
+            "missing; links cannot be set up (run as root)",
+        ),
+        (false, false) => c.add(
+            "CAP_NET_ADMIN",
+            Level::Warn,
+            "missing; only monitoring, the network manager owns the links",
+        ),
+    }
+    if has(CAP_NET_RAW) {
+        c.add("CAP_NET_RAW", Level::Pass, "ICMP probes");
+    } else {
+        c.add(
+            "CAP_NET_RAW",
+            Level::Warn,
+            "missing; ICMP needs ping_group_range or the ping binary",
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn readable(c: &mut Checks) {
+    match fs::read_dir("/sys/class/net") {
+        Ok(_) => c.add("/sys/class/net", Level::Pass, "readable"),
+        Err(e) => c.add("/sys/class/net", Level::Fail, e.to_string()),
+    }
+    match fs::read_to_string("/proc/thread-self/net/route") {
+        Ok(_) => c.add("/proc/net/route", Level::Pass, "readable"),
+        Err(e) => c.add("/proc/net/route", Level::Fail, e.to_string()),
+    }
+}
+
+/// Bring-up and DHCP are Linux's; elsewhere the links are only looked at.
+#[cfg(not(target_os = "linux"))]
+fn platform(c: &mut Checks) {
+    c.add(
+        "platform",
+        Level::Warn,
+        format!(
+            "{}: probing only; bring-up and DHCP need Linux",
+            std::env::consts::OS
+        ),
+    );
+}
+
+/// The interfaces there are, besides loopback.
+#[cfg(target_os = "linux")]
+fn links() -> Vec<String> {
+    fs::read_dir("/sys/class/net")
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|n| n != "lo")
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn links() -> Vec<String> {
+    ifaddrs::links()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|l| !l.loopback)
+        .map(|l| l.name)
+        .collect()
+}
+
+fn interfaces(c: &mut Checks, cfg: &Config) {
+    let mut links = links();
+    links.sort();
+    if links.is_empty() {
+        c.add("interfaces", Level::Fail, "no interface besides lo");
+        return;
+    }
+    c.add("interfaces", Level::Pass, links.join(" "));
+    let managed = iface::discover(cfg);
+    if managed.is_empty() {
+        c.add("managed", Level::Fail, "include/exclude leave none");
+    } else {
+        c.add("managed", Level::Pass, managed.join(" "));
+    }
+}
+
+fn targets(c: &mut Checks, cfg: &Config) {
+    if cfg.targets.is_empty() {
+        c.add("targets", Level::Fail, "no probe targets");
+    }
+    for t in &cfg.targets {
+        let name = format!("target {}", t);
+        match t {
+            // A name that does not resolve now may later; the probe says so.
+            ProbeMethod::Tcp { host, port } if host.parse::<std::net::IpAddr>().is_err() => {
+                match (host.as_str(), *port).to_socket_addrs() {
+                    Ok(mut a) => match a.next() {
+                        Some(a) => c.add(&name, Level::Pass, format!("tcp, {}", a.ip())),
+                        None => c.add(&name, Level::Warn, "tcp, no address"),
+                    },
+                    Err(e) => c.add(&name, Level::Warn, format!("tcp, {}", e)),
+                }
+            }
+            _ => c.add(&name, Level::Pass, t.method()),
+        }
+        // An interface that is not there yet may be by the next check.
+        let o = cfg.opts(t);
+        let bind = sys::Bind {
+            dev: o.interface.as_deref(),
+            source: o.source,
+            mark: o.fwmark,
+        };
+        if bind != sys::Bind::default() {
+            if let Err(e) = bind.check() {
+                c.add(name, Level::Warn, format!("cannot bind: {}", e));
+            }
+        }
+    }
+}
+
+fn binaries(c: &mut Checks, cfg: &Config) {
+    let managing = manager::passive(cfg).is_none() && cfg!(target_os = "linux");
+    if managing {
+        match &cfg.dhcp_command {
+            Some(argv) => c.binary(&argv[0], Level::Fail, "dhcp_command"),
+            None => match cfg.dhcp_backend.unwrap_or_else(dhcp::detect_dhcp_backend) {
+                DhcpBackend::External => {}
+                b => {
+                    c.binary(b.binary(4), Level::Fail, "DHCP client");
+                    if cfg.ipv6 && b.binary(6) != b.binary(4) {
+                        c.binary(b.binary(6), Level::Warn, "DHCPv6 client");
+                    }
+                }
+            },
+        }
+        if cfg!(feature = "iproute2") {
+            c.binary("ip", Level::Warn, "fallback where netlink fails");
+        }
+        if cfg.wpa_config.is_some() {
+            c.binary("wpa_supplicant", Level::Fail, "wpa_config is set");
+            c.binary("wpa_cli", Level::Fail, "wpa_config is set");
+        }
+    }
+    if let Some(b) = manager::passive(cfg) {
+        let prog = match b {
+            manager::NetworkBackend::NetworkManager => "nmcli",
+            manager::NetworkBackend::Networkd => "networkctl",
+        };
+        c.binary(prog, Level::Warn, &format!("{} device states", b));
+    }
+    #[cfg(target_os = "linux")]
+    let raw = cap_eff().is_some_and(|caps| caps & (1 << CAP_NET_RAW) != 0);
+    // Elsewhere ICMP needs no privileges.
+    #[cfg(not(target_os = "linux"))]
+    let raw = true;
+    let icmp = cfg
+        .targets
+        .iter()
+        .any(|t| matches!(t, ProbeMethod::Icmp(_)));
+    if icmp && !raw {
+        c.binary("ping", Level::Warn, "ICMP probes without CAP_NET_RAW");
+    }
+    if cfg.http_url.https || cfg.webhooks.iter().any(|u| u.https) || cfg.telegram.is_some() {
+        c.binary("curl", Level::Fail, "https requests");
+    }
+    if cfg.mqtt_broker.is_some() && cfg.mqtt_tls {
+        c.binary("openssl", Level::Fail, "MQTT over TLS");
+    }
+}
+
+fn table(c: &Checks) -> String {
+    let w = c.0.iter().map(|c| c.name.len()).max().unwrap_or(0).max(5);
+    let mut t = format!("{:<w$}  {:<6}  DETAIL\n", "CHECK", "RESULT");
+    for c in &c.0 {
+        let _ = writeln!(t, "{:<w$}  {:<6}  {}", c.name, c.level.as_str(), c.detail);
+    }
+    t
+}
+
+/// Runs every check and puts the table through `out`, or with `--output
+/// json` an object. Exits 0 unless a check failed, else 1. A config that
+/// does not load is a failure of its own; the rest is checked with the
+/// defaults.
+pub fn run(cfg: Result<&Config, String>, out: &mut dyn OutputSink) -> ! {
+    let mut c = Checks::default();
+    let default;
+    let cfg = match cfg {
+        Ok(cfg) => {
+            c.add("config", Level::Pass, "loaded");
+            cfg
+        }
+        Err(e) => {
+            c.add("config", Level::Fail, e);
+            default = Config::default();
+            &default
+        }
+    };
+    targets(&mut c, cfg);
+    #[cfg(target_os = "linux")]
+    capabilities(&mut c, cfg);
+    #[cfg(target_os = "linux")]
+    readable(&mut c);
+    #[cfg(not(target_os = "linux"))]
+    platform(&mut c);
+    interfaces(&mut c, cfg);
+    binaries(&mut c, cfg);
+    let ok = c.0.iter().all(|c| c.level != Level::Fail);
+    let checks: Vec<Json> =
+        c.0.iter()
+            .map(|c| {
+                Json::obj()
+                    .set("check", c.name.as_str())
+                    .set("result", c.level.as_str())
+                    .set("detail", c.detail.as_str())
+            })
+            .collect();
+    out.document(&table(&c), Json::obj().set("ok", ok).set("checks", checks));
+    std::process::exit(if ok { 0 } else { 1 })
+}
@@ -0,0 +1,215 @@
+// Why a run did not end ONLINE, and the exit status scripts see for it.
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+pub enum WatchdogError {
+    /// Probes failed: OFFLINE.
+    Offline,
+    /// Reachable, but lossy, slow or failing the HTTP probe.
+    Degraded,
+    /// Targets answer but a name does not resolve.
+    DnsBroken,
+    /// HTTP goes to a portal's login page.
+    CaptivePortal,
+    /// Discovery and the include/exclude globs left nothing to manage.
+    NoInterfaces,
+    /// No interface got a lease or a static fallback.
+    DhcpFailed,
+    /// No link could be set up, at least one for lack of privileges.
+    Permission,
+    /// The command line was not understood.
+    Usage,
+    /// A bad config file or option, or the daemon already holds the PID
+    /// file.
+    Config(String),
+    Internal(String),
+}
+
+impl WatchdogError {
+    pub fn exit_code(&self) -> i32 {
+        Status::from(self).exit_code()
+    }
+}
+
+/// How a run ended, in one word per exit status: what `--state-only`
+/// prints, and what scripts may match on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Online,
+    /// Also NO_DEFAULT_ROUTE and the other diagnoses of an OFFLINE verdict.
+    Offline,
+    NoInterfaces,
+    DhcpFailed,
+    PermissionDenied,
+    /// The command line or config was not understood, or the daemon runs.
+    ConfigError,
+    DnsBroken,
+    Degraded,
+    CaptivePortal,
+    InternalError,
+}
+
+impl Status {
+    pub const ALL: [Status; 10] = [
+        Status::Online,
+        Status::Offline,
+        Status::NoInterfaces,
+        Status::DhcpFailed,
+        Status::PermissionDenied,
+        Status::ConfigError,
+        Status::DnsBroken,
+        Status::Degraded,
+        Status::CaptivePortal,
+        Status::InternalError,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Online => "ONLINE",
+            Status::Offline => "OFFLINE",
+            Status::NoInterfaces => "NO_INTERFACES",
+            Status::DhcpFailed => "DHCP_FAILED",
+            Status::PermissionDenied => "PERMISSION_DENIED",
+            Status::ConfigError => "CONFIG_ERROR",
+            Status::DnsBroken => "DNS_BROKEN",
+            Status::Degraded => "DEGRADED",
+            Status::CaptivePortal => "CAPTIVE_PORTAL",
+            Status::InternalError => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Online => 0,
+            Status::Offline => 1,
+            Status::NoInterfaces => 2,
+            Status::DhcpFailed => 3,
+            Status::PermissionDenied => 4,
+            Status::ConfigError => 5,
+            Status::DnsBroken => 6,
+            Status::Degraded => 7,
+            Status::CaptivePortal => 8,
+            Status::InternalError => 64,
+        }
+    }
+}
+
+impl From<&WatchdogError> for Status {
+    fn from(e: &WatchdogError) -> Status {
+        match e {
+            WatchdogError::Offline => Status::Offline,
+            WatchdogError::Degraded => Status::Degraded,
+            WatchdogError::DnsBroken => Status::DnsBroken,
+            WatchdogError::CaptivePortal => Status::CaptivePortal,
+            WatchdogError::NoInterfaces => Status::NoInterfaces,
+            WatchdogError::DhcpFailed => Status::DhcpFailed,
+            WatchdogError::Permission => Status::PermissionDenied,
+            WatchdogError::Usage | WatchdogError::Config(_) => Status::ConfigError,
+            WatchdogError::Internal(_) => Status::InternalError,
+        }
+    }
+}
+
+impl From<Result<(), WatchdogError>> for Status {
+    fn from(r: Result<(), WatchdogError>) -> Status {
+        r.as_ref().map_or_else(Status::from, |_| Status::Online)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Status {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Status, ()> {
+        Status::ALL.into_iter().find(|t| t.as_str() == s).ok_or(())
+    }
+}
+
+impl fmt::Display for WatchdogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchdogError::Offline => f.write_str("offline"),
+            WatchdogError::Degraded => f.write_str("degraded"),
+            WatchdogError::DnsBroken => f.write_str("DNS broken"),
+            WatchdogError::CaptivePortal => f.write_str("captive portal"),
+            WatchdogError::NoInterfaces => f.write_str("no interfaces to manage"),
+            WatchdogError::DhcpFailed => f.write_str("no lease and no static fallback"),
+            WatchdogError::Permission => f.write_str("permission denied"),
+            WatchdogError::Usage => f.write_str("command line not understood"),
+            WatchdogError::Config(m) | WatchdogError::Internal(m) => f.write_str(m),
+        }
+    }
+}
+
+impl std::error::Error for WatchdogError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_the_documented_ones() {
+        let codes: Vec<(&str, i32)> = Status::ALL
+            .iter()
+            .map(|s| (s.as_str(), s.exit_code()))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                ("ONLINE", 0),
+                ("OFFLINE", 1),
+                ("NO_INTERFACES", 2),
+                ("DHCP_FAILED", 3),
+                ("PERMISSION_DENIED", 4),
+                ("CONFIG_ERROR", 5),
+                ("DNS_BROKEN", 6),
+                ("DEGRADED", 7),
+                ("CAPTIVE_PORTAL", 8),
+                ("INTERNAL_ERROR", 64),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_exit_with_their_status() {
+        for (e, code) in [
+            (WatchdogError::Offline, 1),
+            (WatchdogError::NoInterfaces, 2),
+            (WatchdogError::DhcpFailed, 3),
+            (WatchdogError::Permission, 4),
+            (WatchdogError::Usage, 5),
+            (WatchdogError::Config("bad".into()), 5),
+            (WatchdogError::DnsBroken, 6),
+            (WatchdogError::Degraded, 7),
+            (WatchdogError::CaptivePortal, 8),
+            (WatchdogError::Internal("bug".into()), 64),
+        ] {
+            assert_eq!(e.exit_code(), code, "{:?}", e);
+            assert_eq!(Status::from(&e).exit_code(), code, "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn status_words_are_one_per_exit_status() {
+        let mut codes = Vec::new();
+        for s in Status::ALL {
+            assert_eq!(s.to_string().parse::<Status>(), Ok(s));
+            codes.push(s.exit_code());
+        }
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), Status::ALL.len());
+        assert_eq!("online".parse::<Status>(), Err(()));
+        assert_eq!(
+            Status::from(Err(WatchdogError::DhcpFailed)),
+            Status::DhcpFailed
+        );
+        assert_eq!(Status::from(Ok(())).to_string(), "ONLINE");
+    }
+}
@@ -0,0 +1,475 @@
+//! The lines `--output ndjson` prints, as types: each is one JSON object
+//! with its kind under `event`, then [`Stamp`]'s fields. The sink builds
+//! these events from them, and a consumer can read them back.
+use crate::config::ProbeMethod;
+use crate::json::Json;
+use crate::probe::{self, TargetResult};
+
+/// What every line ends with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stamp {
+    /// 1 for the first line and one more for each after it, across the
+    /// process.
+    pub seq: u64,
+    /// RFC 3339, UTC.
+    pub ts: String,
+    /// The `[[namespace]]` the line is about, if not the host.
+    pub netns: Option<String>,
+}
+
+impl Stamp {
+    /// `j` with the stamp's fields appended.
+    pub fn on(&self, j: Json) -> Json {
+        let j = j.set("seq", self.seq).set("ts", self.ts.as_str());
+        match &self.netns {
+            Some(n) => j.set("netns", n.as_str()),
+            None => j,
+        }
+    }
+
+    pub fn from_json(j: &Json) -> Option<Stamp> {
+        Some(Stamp {
+            seq: j.get("seq")?.as_u64()?,
+            ts: j.get("ts")?.as_str()?.into(),
+            netns: str_field(j, "netns"),
+        })
+    }
+}
+
+/// How one target answered, or that it was skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub target: String,
+    /// `icmp`, `tcp` or `dns`.
+    pub method: String,
+    pub addr: Option<String>,
+    /// `ipv4` or `ipv6`, after `addr`.
+    pub family: Option<String>,
+    /// `None` when skipped.
+    pub reachable: Option<bool>,
+    pub rtt_ms: Option<f64>,
+    pub rtt_min_ms: Option<f64>,
+    pub rtt_max_ms: Option<f64>,
+    pub sent: u8,
+    pub received: u8,
+    pub loss_pct: f64,
+    pub degraded: bool,
+    pub weight: u32,
+    pub iface: Option<String>,
+    pub source: Option<String>,
+    pub error: Option<String>,
+    /// Left out by a relaxed check: only `target` and `method` are set.
+    pub skipped: bool,
+}
+
+impl From<&TargetResult> for Probe {
+    fn from(r: &TargetResult) -> Probe {
+        Probe {
+            target: r.target.to_string(),
+            method: r.target.method().into(),
+            addr: r.addr.map(|a| a.to_string()),
+            family: r
+                .addr
+                .map(|a| if a.is_ipv6() { "ipv6" } else { "ipv4" }.into()),
+            reachable: Some(r.rtt.is_some()),
+            rtt_ms: r.rtt.map(probe::ms),
+            rtt_min_ms: r.min.map(probe::ms),
+            rtt_max_ms: r.max.map(probe::ms),
+            sent: r.sent,
+            received: r.received,
+            loss_pct: r.loss(),
+            degraded: r.degraded,
+            weight: r.weight,
+            iface: r.iface.clone(),
+            source: r.source.map(|a| a.to_string()),
+            error: r.error.clone(),
+            skipped: false,
+        }
+    }
+}
+
+impl Probe {
+    pub fn skipped(t: &ProbeMethod) -> Probe {
+        Probe::left_out(t.to_string(), t.method().into())
+    }
+
+    fn left_out(target: String, method: String) -> Probe {
+        Probe {
+            target,
+            method,
+            addr: None,
+            family: None,
+            reachable: None,
+            rtt_ms: None,
+            rtt_min_ms: None,
+            rtt_max_ms: None,
+            sent: 0,
+            received: 0,
+            loss_pct: 0.0,
+            degraded: false,
+            weight: 0,
+            iface: None,
+            source: None,
+            error: None,
+            skipped: true,
+        }
+    }
+
+    /// `j` with the probe's fields appended; the report's `targets` have
+    /// the same.
+    pub fn on(&self, j: Json) -> Json {
+        let j = j
+            .set("target", self.target.as_str())
+            .set("method", self.method.as_str());
+        if self.skipped {
+            return j.set("reachable", None::<bool>).set("skipped", true);
+        }
+        j.set("addr", self.addr.as_deref())
+            .set("family", self.family.as_deref())
+            .set("reachable", self.reachable)
+            .set("rtt_ms", self.rtt_ms)
+            .set("rtt_min_ms", self.rtt_min_ms)
+            .set("rtt_max_ms", self.rtt_max_ms)
+            .set("sent", self.sent)
+            .set("received", self.received)
+            .set("loss_pct", self.loss_pct)
+            .set("degraded", self.degraded)
+            .set("weight", self.weight as u64)
+            .set("iface", self.iface.as_deref())
+            .set("source", self.source.as_deref())
+            .set("error", self.error.as_deref())
+            .set("skipped", false)
+    }
+
+    pub fn from_json(j: &Json) -> Option<Probe> {
+        let target = str_field(j, "target")?;
+        let method = str_field(j, "method")?;
+        if bool_field(j, "skipped")? {
+            return Some(Probe::left_out(target, method));
+        }
+        let small = |k: &str| j.get(k)?.as_u64()?.try_into().ok();
+        Some(Probe {
+            target,
+            method,
+            addr: str_field(j, "addr"),
+            family: str_field(j, "family"),
+            reachable: bool_field(j, "reachable"),
+            rtt_ms: j.get("rtt_ms").and_then(Json::as_f64),
+            rtt_min_ms: j.get("rtt_min_ms").and_then(Json::as_f64),
+            rtt_max_ms: j.get("rtt_max_ms").and_then(Json::as_f64),
+            sent: small("sent")?,
+            received: small("received")?,
+            loss_pct: j.get("loss_pct")?.as_f64()?,
+            degraded: bool_field(j, "degraded")?,
+            weight: j.get("weight")?.as_u64()?.try_into().ok()?,
+            iface: str_field(j, "iface"),
+            source: str_field(j, "source"),
+            error: str_field(j, "error"),
+            skipped: false,
+        })
+    }
+}
+
+/// One event, by its `event` field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    /// `check`: a daemon cycle's connectivity check begins.
+    Check,
+    /// `interface_up`; `link_up_ms` with `--benchmark`.
+    InterfaceUp {
+        iface: String,
+        link_up_ms: Option<f64>,
+    },
+    /// `dhcp`: a DHCP run ended, for `family` 4 or 6; `dhcp_ms` with
+    /// `--benchmark`.
+    Dhcp {
+        iface: String,
+        family: u8,
+        ok: bool,
+        dhcp_ms: Option<f64>,
+    },
+    /// `probe`.
+    Probe(Probe),
+    /// `state`: the daemon's verdict changed; `previous_status` is `None`
+    /// for the first. `v4_ok` and `v6_ok` are set when ONLINE.
+    State {
+        status: String,
+        previous_status: Option<String>,
+        v4_ok: Option<bool>,
+        v6_ok: Option<bool>,
+    },
+    /// `hook`: a hook run for `state`. `ok` and `error` are `None` on a dry
+    /// run, which only names it.
+    Hook {
+        command: String,
+        state: String,
+        dry_run: bool,
+        ok: Option<bool>,
+        error: Option<String>,
+    },
+}
+
+impl Record {
+    pub fn to_json(&self) -> Json {
+        match self {
+            Record::Check => Json::obj().set("event", "check"),
+            Record::InterfaceUp { iface, link_up_ms } => {
+                let j = Json::obj()
+                    .set("event", "interface_up")
+                    .set("iface", iface.as_str());
+                match link_up_ms {
+                    Some(ms) => j.set("link_up_ms", *ms),
+                    None => j,
+                }
+            }
+            Record::Dhcp {
+                iface,
+                family,
+                ok,
+                dhcp_ms,
+            } => {
+                let j = Json::obj()
+                    .set("event", "dhcp")
+                    .set("iface", iface.as_str())
+                    .set("family", if *family == 6 { "ipv6" } else { "ipv4" })
+                    .set("ok", *ok);
+                match dhcp_ms {
+                    Some(ms) => j.set("dhcp_ms", *ms),
+                    None => j,
+                }
+            }
+            Record::Probe(p) => p.on(Json::obj().set("event", "probe")),
+            Record::State {
+                status,
+                previous_status,
+                v4_ok,
+                v6_ok,
+            } => {
+                let j = Json::obj()
+                    .set("event", "state")
+                    .set("status", status.as_str());
+                let j = match (v4_ok, v6_ok) {
+                    (Some(v4), Some(v6)) => j.set("v4_ok", *v4).set("v6_ok", *v6),
+                    _ => j,
+                };
+                j.set("previous_status", previous_status.as_deref())
+            }
+            Record::Hook {
+                command,
+                state,
+                dry_run,
+                ok,
+                error,
+            } => {
+                let j = Json::obj()
+                    .set("event", "hook")
+                    .set("command", command.as_str())
+                    .set("state", state.as_str())
+                    .set("dry_run", *dry_run);
+                match ok {
+                    Some(ok) => j.set("ok", *ok).set("error", error.as_deref()),
+                    None => j,
+                }
+            }
+        }
+    }
+
+    /// `None` for another `event`, or one missing a field.
+    pub fn from_json(j: &Json) -> Option<Record> {
+        Some(match j.get("event")?.as_str()? {
+            "check" => Record::Check,
+            "interface_up" => Record::InterfaceUp {
+                iface: str_field(j, "iface")?,
+                link_up_ms: j.get("link_up_ms").and_then(Json::as_f64),
+            },
+            "dhcp" => Record::Dhcp {
+                iface: str_field(j, "iface")?,
+                family: match j.get("family")?.as_str()? {
+                    "ipv4" => 4,
+                    "ipv6" => 6,
+                    _ => return None,
+                },
+                ok: bool_field(j, "ok")?,
+                dhcp_ms: j.get("dhcp_ms").and_then(Json::as_f64),
+            },
+            "probe" => Record::Probe(Probe::from_json(j)?),
+            "state" => Record::State {
+                status: str_field(j, "status")?,
+                previous_status: str_field(j, "previous_status"),
+                v4_ok: bool_field(j, "v4_ok"),
+                v6_ok: bool_field(j, "v6_ok"),
+            },
+            "hook" => Record::Hook {
+                command: str_field(j, "command")?,
+                state: str_field(j, "state")?,
+                dry_run: bool_field(j, "dry_run")?,
+                ok: bool_field(j, "ok"),
+                error: str_field(j, "error"),
+            },
+            _ => return None,
+        })
+    }
+}
+
+fn str_field(j: &Json, k: &str) -> Option<String> {
+    j.get(k).and_then(Json::as_str).map(String::from)
+}
+
+fn bool_field(j: &Json, k: &str) -> Option<bool> {
+    match j.get(k)? {
+        Json::Bool(b) => Some(*b),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `r` printed, parsed again, and read back.
+    fn round_trip(r: &Record, s: &Stamp) -> (Record, Stamp) {
+        let line = s.on(r.to_json()).to_string();
+        assert!(!line.contains('\n'), "{}", line);
+        let j = Json::parse(&line).unwrap_or_else(|e| panic!("{}: {}", line, e));
+        let back = Record::from_json(&j).unwrap_or_else(|| panic!("unread: {}", line));
+        (back, Stamp::from_json(&j).unwrap())
+    }
+
+    fn stamp() -> Stamp {
+        Stamp {
+            seq: 7,
+            ts: "2026-10-14T08:00:00Z".into(),
+            netns: None,
+        }
+    }
+
+    fn result() -> TargetResult {
+        TargetResult {
+            target: ProbeMethod::Tcp {
+                host: "example.net".into(),
+                port: 443,
+            },
+            addr: Some("192.0.2.10".parse().unwrap()),
+            rtt: Some(std::time::Duration::from_micros(12_345)),
+            min: Some(std::time::Duration::from_micros(11_002)),
+            max: Some(std::time::Duration::from_micros(14_870)),
+            sent: 3,
+            received: 2,
+            degraded: true,
+            weight: 2,
+            iface: Some("eth0".into()),
+            source: Some("192.0.2.2".parse().unwrap()),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn stamps_round_trip() {
+        let s = Stamp {
+            netns: Some("blue".into()),
+            ..stamp()
+        };
+        assert_eq!(round_trip(&Record::Check, &s), (Record::Check, s));
+    }
+
+    #[test]
+    fn checks_round_trip() {
+        assert_eq!(round_trip(&Record::Check, &stamp()).0, Record::Check);
+    }
+
+    #[test]
+    fn interface_ups_round_trip() {
+        for link_up_ms in [None, Some(41.5)] {
+            let r = Record::InterfaceUp {
+                iface: "eth0".into(),
+                link_up_ms,
+            };
+            assert_eq!(round_trip(&r, &stamp()).0, r);
+        }
+    }
+
+    #[test]
+    fn dhcp_results_round_trip() {
+        for (family, ok, dhcp_ms) in [(4, true, Some(812.25)), (6, false, None)] {
+            let r = Record::Dhcp {
+                iface: "wlan0".into(),
+                family,
+                ok,
+                dhcp_ms,
+            };
+            assert_eq!(round_trip(&r, &stamp()).0, r);
+        }
+    }
+
+    #[test]
+    fn probes_round_trip() {
+        let answered = Probe::from(&result());
+        assert_eq!(answered.rtt_ms, Some(12.345));
+        assert_eq!(answered.family.as_deref(), Some("ipv4"));
+        let unanswered = Probe {
+            addr: None,
+            family: None,
+            reachable: Some(false),
+            rtt_ms: None,
+            rtt_min_ms: None,
+            rtt_max_ms: None,
+            received: 0,
+            loss_pct: 100.0,
+            error: Some("interface eth1 is gone".into()),
+            ..answered.clone()
+        };
+        let skipped = Probe::skipped(&ProbeMethod::Icmp("1.1.1.1".parse().unwrap()));
+        for p in [answered, unanswered, skipped] {
+            let r = Record::Probe(p);
+            assert_eq!(round_trip(&r, &stamp()).0, r);
+        }
+    }
+
+    #[test]
+    fn state_changes_round_trip() {
+        let first = Record::State {
+            status: "ONLINE".into(),
+            previous_status: None,
+            v4_ok: Some(true),
+            v6_ok: Some(false),
+        };
+        let down = Record::State {
+            status: "OFFLINE".into(),
+            previous_status: Some("ONLINE".into()),
+            v4_ok: None,
+            v6_ok: None,
+        };
+        for r in [first, down] {
+            assert_eq!(round_trip(&r, &stamp()).0, r);
+        }
+    }
+
+    #[test]
+    fn hooks_round_trip() {
+        let ran = Record::Hook {
+            command: "systemctl restart vpn".into(),
+            state: "ONLINE".into(),
+            dry_run: false,
+            ok: Some(false),
+            error: Some("exited 1".into()),
+        };
+        let named = Record::Hook {
+            command: "logger \"link \\\"down\\\"\"".into(),
+            state: "OFFLINE".into(),
+            dry_run: true,
+            ok: None,
+            error: None,
+        };
+        for r in [ran, named] {
+            assert_eq!(round_trip(&r, &stamp()).0, r);
+        }
+    }
+
+    #[test]
+    fn other_events_are_not_misread() {
+        let j = Json::obj().set("event", "hotplug").set("iface", "eth0");
+        assert_eq!(Record::from_json(&j), None);
+        let j = Json::obj().set("event", "dhcp").set("iface", "eth0");
+        assert_eq!(Record::from_json(&j), None);
+    }
+}
@@ -0,0 +1,586 @@
+// How bring-up changes the system: commands it runs and kernel or file
+// changes it makes go through an Executor, so `--dry-run` can print them
+// and record them instead. Below that, every process the tool starts goes
+// through a SysExec, which integration tests can script.
+use crate::config::Config;
+#[cfg(unix)]
+use crate::sys;
+use crate::{audit, log};
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use std::{
+    fs::File,
+    os::fd::{AsRawFd, OwnedFd},
+    os::raw::c_int,
+    os::unix::fs::PermissionsExt,
+};
+
+/// What a finished command left behind.
+pub struct Captured {
+    /// `None` when killed, by a signal or for running past its time.
+    pub code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl Captured {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Starts processes: `ip`, the DHCP clients, `ping`, `iw`, `wpa_cli` and
+/// hooks all run through [`sys`].
+pub trait SysExec: Sync + Send {
+    /// Runs `cmd` with its output captured, killing it after `t`. Fails only
+    /// when it cannot be started.
+    fn exec(&self, cmd: &mut Command, t: Duration) -> io::Result<Captured>;
+
+    /// Whether runs of `program` are made up rather than real.
+    fn scripts(&self, _program: &str) -> bool {
+        false
+    }
+}
+
+pub struct RealExec;
+
+/// Time limits from `[commands]`.
+pub struct Limits {
+    /// Only `ip` fallbacks built with `iproute2` run it.
+    #[cfg(feature = "iproute2")]
+    pub ip: Duration,
+    /// `None`: derived from the probe count and timeout.
+    pub ping: Option<Duration>,
+    pub wireless: Duration,
+    pub kill_grace: Duration,
+}
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+/// Takes the limits from `cfg`; the first call wins.
+pub fn configure(cfg: &Config) {
+    let _ = LIMITS.set(Limits {
+        #[cfg(feature = "iproute2")]
+        ip: Duration::from_secs(cfg.ip_timeout),
+        ping: (cfg.ping_cmd_timeout > 0).then(|| Duration::from_secs(cfg.ping_cmd_timeout)),
+        wireless: Duration::from_secs(cfg.wireless_timeout),
+        kill_grace: Duration::from_secs(cfg.kill_grace),
+    });
+}
+
+pub fn limits() -> &'static Limits {
+    LIMITS.get_or_init(|| Limits {
+        #[cfg(feature = "iproute2")]
+        ip: Duration::from_secs(10),
+        ping: None,
+        wireless: Duration::from_secs(5),
+        kill_grace: Duration::from_secs(2),
+    })
+}
+
+/// How long output is still read once the command has exited: a client
+/// that daemonized may hold the pipes open.
+const DRAIN: Duration = Duration::from_millis(500);
+
+#[cfg(unix)]
+impl SysExec for RealExec {
+    /// Past `t` the command gets SIGTERM, and SIGKILL after the kill grace;
+    /// either way it is reaped before this returns.
+    fn exec(&self, cmd: &mut Command, t: Duration) -> io::Result<Captured> {
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let prog = cmd.get_program().to_string_lossy().into_owned();
+        let mut pipes = [
+            child.stdout.take().map(|p| File::from(OwnedFd::from(p))),
+            child.stderr.take().map(|p| File::from(OwnedFd::from(p))),
+        ];
+        let mut out = [Vec::new(), Vec::new()];
+        let mut buf = [0u8; 4096];
+        let end = Instant::now() + t;
+        let mut term: Option<Instant> = None;
+        let mut exited: Option<(Option<i32>, Instant)> = None;
+        while !exited
+            .is_some_and(|(_, at)| pipes.iter().all(Option::is_none) || at.elapsed() >= DRAIN)
+        {
+            let open: Vec<usize> = (0..2).filter(|&n| pipes[n].is_some()).collect();
+            let fds: Vec<c_int> = open
+                .iter()
+                .filter_map(|&n| pipes[n].as_ref().map(|p| p.as_raw_fd()))
+                .collect();
+            let ready = sys::poll_in(&fds, Duration::from_millis(100))?;
+            for (&n, _) in open.iter().zip(ready).filter(|(_, r)| *r) {
+                // Readable, so this does not block; 0 is EOF.
+                match pipes[n].as_mut().map(|p| p.read(&mut buf)) {
+                    Some(Ok(len)) if len > 0 => out[n].extend_from_slice(&buf[..len]),
+                    _ => pipes[n] = None,
+                }
+            }
+            if exited.is_some() {
+                continue;
+            }
+            if let Some(s) = child.try_wait()? {
+                exited = Some((s.code(), Instant::now()));
+                continue;
+            }
+            match term {
+                None if Instant::now() >= end => {
+                    log::warn!(
+                        "{}: out of time after {}s, sending SIGTERM",
+                        prog,
+                        t.as_secs()
+                    );
+                    let _ = sys::terminate(child.id());
+                    term = Some(Instant::now());
+                }
+                Some(at) if at.elapsed() >= limits().kill_grace => {
+                    log::warn!("{}: still running after SIGTERM, killing it", prog);
+                    let _ = child.kill();
+                    exited = Some((child.wait()?.code(), Instant::now()));
+                }
+                _ => {}
+            }
+        }
+        let [stdout, stderr] = out;
+        Ok(Captured {
+            code: exited.and_then(|(c, _)| c).filter(|_| term.is_none()),
+            timed_out: term.is_some(),
+            stdout,
+            stderr,
+        })
+    }
+}
+
+#[cfg(windows)]
+impl SysExec for RealExec {
+    /// Past `t` the command is killed, there being no SIGTERM to give it
+    /// first, and reaped before this returns. The pipes are read from a
+    /// thread apiece.
+    fn exec(&self, cmd: &mut Command, t: Duration) -> io::Result<Captured> {
+        use std::sync::mpsc::{self, RecvTimeoutError};
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let prog = cmd.get_program().to_string_lossy().into_owned();
+        let pipes: [Option<Box<dyn Read + Send>>; 2] = [
+            child.stdout.take().map(|p| Box::new(p) as _),
+            child.stderr.take().map(|p| Box::new(p) as _),
+        ];
+        let (tx, rx) = mpsc::channel();
+        let mut open = 0;
+        for (n, mut p) in pipes.into_iter().enumerate() {
+            let Some(mut p) = p.take() else {
+                continue;
+            };
+            let tx = tx.clone();
+            open += 1;
+            std::thread::spawn(move || {
+                let mut buf = [0u8; 4096];
+                loop {
+                    match p.read(&mut buf) {
+                        Ok(len) if len > 0 => {
+                            if tx.send((n, Some(buf[..len].to_vec()))).is_err() {
+                                return;
+                            }
+                        }
+                        _ => {
+                            let _ = tx.send((n, None));
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        drop(tx);
+        let mut out = [Vec::new(), Vec::new()];
+        let end = Instant::now() + t;
+        let mut killed = false;
+        let mut exited: Option<(Option<i32>, Instant)> = None;
+        while !exited.is_some_and(|(_, at)| open == 0 || at.elapsed() >= DRAIN) {
+            if open == 0 {
+                std::thread::sleep(Duration::from_millis(100));
+            } else {
+                match rx.recv_timeout(Duration::from_millis(100)) {
+                    Ok((n, Some(b))) => out[n].extend_from_slice(&b),
+                    Ok((_, None)) => open -= 1,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => open = 0,
+                }
+            }
+            if exited.is_some() {
+                continue;
+            }
+            if let Some(s) = child.try_wait()? {
+                exited = Some((s.code(), Instant::now()));
+            } else if Instant::now() >= end {
+                log::warn!("{}: out of time after {}s, killing it", prog, t.as_secs());
+                let _ = child.kill();
+                killed = true;
+                exited = Some((child.wait()?.code(), Instant::now()));
+            }
+        }
+        let [stdout, stderr] = out;
+        Ok(Captured {
+            code: exited.and_then(|(c, _)| c).filter(|_| !killed),
+            timed_out: killed,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+static SYS: OnceLock<Box<dyn SysExec>> = OnceLock::new();
+
+/// The process-wide [`SysExec`]: real, or with the `integration-tests`
+/// feature the script `$CW_EXEC_SCRIPT` names.
+pub fn sys() -> &'static dyn SysExec {
+    &**SYS.get_or_init(|| {
+        #[cfg(feature = "integration-tests")]
+        if let Some(p) = std::env::var_os("CW_EXEC_SCRIPT") {
+            return Box::new(script::Scripted::load(std::path::Path::new(&p)));
+        }
+        Box::new(RealExec)
+    })
+}
+
+/// Whether `err` came from [`run`] giving up on a command out of time.
+pub fn timed_out(err: &str) -> bool {
+    err.contains(" timed out after ")
+}
+
+/// Runs `cmd`, killing it once `t` has elapsed. The error says why it failed.
+pub fn run(cmd: &mut Command, t: Duration) -> Result<(), String> {
+    let prog = cmd.get_program().to_string_lossy().into_owned();
+    let line = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|a| a.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    log::debug!("running {}", line);
+    let c = sys().exec(cmd, t).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => format!("{} not found", prog),
+        _ => format!("{}: {}", prog, e),
+    })?;
+    if c.timed_out {
+        return Err(format!("{} timed out after {}s", prog, t.as_secs()));
+    }
+    if c.success() {
+        return Ok(());
+    }
+    let status = c
+        .code
+        .map_or("killed".into(), |n| format!("exit status: {}", n));
+    let err = String::from_utf8_lossy(&c.stderr);
+    log::debug!("{}: {}: {}", line, status, err.trim());
+    match err.trim() {
+        "" => Err(format!("{} failed ({})", prog, status)),
+        e => Err(format!("{} failed ({}): {}", prog, status, e)),
+    }
+}
+
+pub trait Executor: Sync {
+    /// Runs `argv[0]` with the rest as arguments, killing it after `t`.
+    fn run(&self, argv: &[String], t: Duration) -> Result<(), String>;
+
+    /// Makes a change that needs no command (netlink, a file write);
+    /// `desc` is the equivalent shell command.
+    fn apply(&self, desc: &str, f: &mut dyn FnMut() -> Result<(), String>) -> Result<(), String>;
+
+    /// Whether changes really happen; when not, their effects cannot be
+    /// waited for or checked.
+    fn live(&self) -> bool {
+        true
+    }
+
+    /// What a dry run has left undone so far; `None` when changes are made.
+    fn actions(&self) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// Makes the changes, and records them in the audit file.
+pub struct CommandExecutor;
+
+impl Executor for CommandExecutor {
+    fn run(&self, argv: &[String], t: Duration) -> Result<(), String> {
+        audit::action("command", &argv.join(" "), || {
+            run(Command::new(&argv[0]).args(&argv[1..]), t)
+        })
+    }
+
+    fn apply(&self, desc: &str, f: &mut dyn FnMut() -> Result<(), String>) -> Result<(), String> {
+        audit::action("change", desc, f)
+    }
+}
+
+/// Logs `[DRY-RUN] <command>`, keeps it for the report and reports
+/// success.
+#[derive(Default)]
+pub struct DryRunExecutor {
+    done: Mutex<Vec<String>>,
+}
+
+impl DryRunExecutor {
+    fn note(&self, cmd: String) -> Result<(), String> {
+        log::info!("[DRY-RUN] {}", cmd);
+        self.done.lock().unwrap().push(cmd);
+        Ok(())
+    }
+}
+
+impl Executor for DryRunExecutor {
+    fn run(&self, argv: &[String], _t: Duration) -> Result<(), String> {
+        self.note(argv.join(" "))
+    }
+
+    fn apply(&self, desc: &str, _f: &mut dyn FnMut() -> Result<(), String>) -> Result<(), String> {
+        self.note(desc.into())
+    }
+
+    fn live(&self) -> bool {
+        false
+    }
+
+    fn actions(&self) -> Option<Vec<String>> {
+        Some(self.done.lock().unwrap().clone())
+    }
+}
+
+/// Records what it is asked to do, for unit tests, and runs nothing;
+/// what it runs succeeds unless [`MockExecutor::fails`] says otherwise.
+#[cfg(test)]
+pub(crate) struct MockExecutor {
+    calls: Mutex<Vec<String>>,
+    fail: Vec<(&'static str, &'static str)>,
+    live: bool,
+}
+
+#[cfg(test)]
+impl MockExecutor {
+    /// `live`: whether effects are waited for and checked, as after real
+    /// changes.
+    pub fn new(live: bool) -> MockExecutor {
+        MockExecutor {
+            calls: Mutex::new(Vec::new()),
+            fail: Vec::new(),
+            live,
+        }
+    }
+
+    /// Command lines starting with `prefix` fail with `err`.
+    #[cfg(target_os = "linux")]
+    pub fn fails(mut self, prefix: &'static str, err: &'static str) -> MockExecutor {
+        self.fail.push((prefix, err));
+        self
+    }
+
+    /// Command lines and the `ip` equivalents of changes, in order.
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn record(&self, cmd: String) -> Result<(), String> {
+        let res = match self.fail.iter().find(|(p, _)| cmd.starts_with(p)) {
+            Some((_, e)) => Err(e.to_string()),
+            None => Ok(()),
+        };
+        self.calls.lock().unwrap().push(cmd);
+        res
+    }
+}
+
+#[cfg(test)]
+impl Executor for MockExecutor {
+    fn run(&self, argv: &[String], _t: Duration) -> Result<(), String> {
+        self.record(argv.join(" "))
+    }
+
+    fn apply(&self, desc: &str, _f: &mut dyn FnMut() -> Result<(), String>) -> Result<(), String> {
+        self.record(desc.into())
+    }
+
+    fn live(&self) -> bool {
+        self.live
+    }
+}
+
+pub fn executor(dry_run: bool) -> Box<dyn Executor> {
+    if dry_run {
+        Box::<DryRunExecutor>::default()
+    } else {
+        Box::new(CommandExecutor)
+    }
+}
+
+/// `argv` from string slices.
+pub fn argv(v: &[&str]) -> Vec<String> {
+    v.iter().map(|s| s.to_string()).collect()
+}
+
+/// Whether `bin` is an executable file on `$PATH` or in an sbin directory.
+pub fn in_path(bin: &str) -> bool {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    // sbin is often missing from PATH outside login shells.
+    let found = std::env::split_paths(&path)
+        .chain(["/sbin", "/usr/sbin", "/usr/local/sbin"].map(Into::into))
+        .filter(|d| !d.as_os_str().is_empty())
+        .any(|d| executable(&d.join(bin)));
+    found
+}
+
+#[cfg(unix)]
+fn executable(p: &Path) -> bool {
+    p.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// Windows goes by the extension.
+#[cfg(windows)]
+fn executable(p: &Path) -> bool {
+    p.with_extension("exe").is_file()
+}
+
+/// Made-up command runs for the integration tests. `$CW_EXEC_SCRIPT` names
+/// a TOML file of `[[exec]]` entries; the first whose `program` and `args`
+/// (a glob over the arguments joined by spaces, `*` when left out) match
+/// answers in place of running anything:
+///
+/// ```toml
+/// [[exec]]
+/// program = "ping"
+/// exit = 1
+/// stdout = "3 packets transmitted, 1 received, 66% packet loss"
+///
+/// [[exec]]
+/// program = "dhclient"
+/// timeout = true      # as if it ran out of time
+///
+/// [[exec]]
+/// program = "ip"
+/// missing = true      # as if not on PATH
+/// ```
+///
+/// Commands no entry matches run for real.
+#[cfg(feature = "integration-tests")]
+mod script {
+    use super::{Captured, RealExec, SysExec};
+    use crate::glob;
+    use crate::toml::{self, Value};
+    use std::io::{self, ErrorKind};
+    use std::path::Path;
+    use std::process::Command;
+    use std::time::Duration;
+
+    struct Entry {
+        program: String,
+        args: String,
+        exit: i32,
+        stdout: String,
+        stderr: String,
+        timeout: bool,
+        missing: bool,
+    }
+
+    pub struct Scripted(Vec<Entry>);
+
+    fn entry(v: &Value) -> Result<Entry, String> {
+        let Value::Table(t) = v else {
+            return Err("exec: expected a table".into());
+        };
+        let str = |k: &str| match t.get(k) {
+            None => Ok(None),
+            Some(Value::Str(s)) => Ok(Some(s.clone())),
+            Some(v) => Err(format!(
+                "exec.{}: expected a string, not {}",
+                k,
+                v.type_name()
+            )),
+        };
+        let bool = |k: &str| match t.get(k) {
+            None => Ok(false),
+            Some(Value::Bool(b)) => Ok(*b),
+            Some(v) => Err(format!(
+                "exec.{}: expected a boolean, not {}",
+                k,
+                v.type_name()
+            )),
+        };
+        let exit = match t.get("exit") {
+            None => 0,
+            Some(Value::Int(n)) => i32::try_from(*n).map_err(|_| "exec.exit: out of range")?,
+            Some(v) => {
+                return Err(format!(
+                    "exec.exit: expected an integer, not {}",
+                    v.type_name()
+                ))
+            }
+        };
+        Ok(Entry {
+            program: str("program")?.ok_or("exec: program missing")?,
+            args: str("args")?.unwrap_or_else(|| "*".into()),
+            exit,
+            stdout: str("stdout")?.unwrap_or_default(),
+            stderr: str("stderr")?.unwrap_or_default(),
+            timeout: bool("timeout")?,
+            missing: bool("missing")?,
+        })
+    }
+
+    impl Scripted {
+        /// Panics when `path` is no usable script.
+        pub fn load(path: &Path) -> Scripted {
+            let res = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|s| toml::parse(&s).map_err(|e| e.to_string()))
+                .and_then(|t| match t.get("exec") {
+                    None => Ok(Vec::new()),
+                    Some(Value::Array(a)) => a.iter().map(entry).collect(),
+                    Some(_) => Err("exec: expected an array of tables".into()),
+                });
+            match res {
+                Ok(es) => Scripted(es),
+                Err(e) => panic!("CW_EXEC_SCRIPT {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    impl SysExec for Scripted {
+        fn exec(&self, cmd: &mut Command, t: Duration) -> io::Result<Captured> {
+            let prog = cmd.get_program().to_string_lossy().into_owned();
+            let args = cmd
+                .get_args()
+                .map(|a| a.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let Some(e) = self
+                .0
+                .iter()
+                .find(|e| e.program == prog && glob::matches(&e.args, &args))
+            else {
+                return RealExec.exec(cmd, t);
+            };
+            if e.missing {
+                return Err(ErrorKind::NotFound.into());
+            }
+            Ok(Captured {
+                code: (!e.timeout).then_some(e.exit),
+                timed_out: e.timeout,
+                stdout: e.stdout.clone().into_bytes(),
+                stderr: e.stderr.clone().into_bytes(),
+            })
+        }
+
+        fn scripts(&self, program: &str) -> bool {
+            self.0.iter().any(|e| e.program == program)
+        }
+    }
+}
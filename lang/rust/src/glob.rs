@@ -0,0 +1,52 @@
+// Shell-style wildcards for interface names: `*`, `?`, `[abc]`, `[a-z]`, `[!x]`.
+
+pub fn matches(pat: &str, s: &str) -> bool {
+    let p: Vec<char> = pat.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    m(&p, &s)
+}
+
+pub fn any(pats: &[String], s: &str) -> bool {
+    pats.iter().any(|p| matches(p, s))
+}
+
+fn m(p: &[char], s: &[char]) -> bool {
+    match p.first() {
+        None => s.is_empty(),
+        Some('*') => (0..=s.len()).any(|i| m(&p[1..], &s[i..])),
+        Some('?') => !s.is_empty() && m(&p[1..], &s[1..]),
+        Some('[') => match (class(&p[1..], s.first().copied()), s.first()) {
+            (Some((true, rest)), Some(_)) => m(rest, &s[1..]),
+            (Some(_), _) => false,
+            // No closing `]`: treat `[` literally.
+            (None, Some('[')) => m(&p[1..], &s[1..]),
+            (None, _) => false,
+        },
+        Some(c) => s.first() == Some(c) && m(&p[1..], &s[1..]),
+    }
+}
+
+/// Matches `c` against a bracket class; returns the verdict and the pattern
+/// after the closing `]`.
+fn class(p: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (neg, mut i) = match p.first() {
+        Some('!' | '^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut hit = false;
+    let mut first = true;
+    while i < p.len() {
+        if p[i] == ']' && !first {
+            return Some((hit != neg, &p[i + 1..]));
+        }
+        first = false;
+        if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+            hit |= c.is_some_and(|c| p[i] <= c && c <= p[i + 2]);
+            i += 3;
+        } else {
+            hit |= c == Some(p[i]);
+            i += 1;
+        }
+    }
+    None
+}
@@ -0,0 +1,39 @@
+// A health check for load balancers and liveness probes in daemon mode:
+// 200 while the daemon last found the host ONLINE, 503 otherwise, with the
+// state in the body, from a tiny HTTP/1.1 responder like the metrics one.
+use crate::daemon::LinkState;
+use crate::json::Json;
+use crate::metrics;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+/// The daemon's latest verdict; `None` until the first check.
+pub type Shared = Arc<Mutex<Option<LinkState>>>;
+
+fn handle(c: &mut TcpStream, state: &Shared) -> io::Result<()> {
+    let line = metrics::read_request(c)?;
+    let head = line.starts_with("HEAD ");
+    let s = *state.lock().unwrap_or_else(|p| p.into_inner());
+    let status = match s {
+        Some(LinkState::Up { .. }) => "200 OK",
+        _ => "503 Service Unavailable",
+    };
+    let body = Json::obj()
+        .set("status", s.map(|s| s.to_string()))
+        .to_string();
+    write!(
+        c,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        if head { "" } else { &body }
+    )
+}
+
+/// Binds `addr` and answers every path from a background thread.
+pub fn serve(addr: SocketAddr, state: Shared) -> io::Result<()> {
+    let l = TcpListener::bind(addr)?;
+    metrics::accept(l, move |c| handle(c, &state));
+    Ok(())
+}
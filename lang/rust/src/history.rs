@@ -0,0 +1,460 @@
+// Daemon state persisted across restarts: the current state, recent
+// transitions for flap detection, cumulative up/down time, and what was
+// last seen of each interface.
+use crate::availability::WindowStats;
+use crate::clock;
+use crate::json::Json;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Transitions kept in the state file.
+const RING: usize = 64;
+
+pub struct Transition {
+    /// Unix seconds.
+    pub at: u64,
+    pub from: String,
+    pub to: String,
+}
+
+/// Carrier changes of one interface, for flap detection over a rolling
+/// window.
+#[derive(Default)]
+pub struct FlappingDetector {
+    at: VecDeque<u64>,
+}
+
+impl FlappingDetector {
+    /// Notes `n` changes at `now`.
+    pub fn record(&mut self, n: u64, now: u64) {
+        for _ in 0..n.min(RING as u64) {
+            if self.at.len() == RING {
+                self.at.pop_front();
+            }
+            self.at.push_back(now);
+        }
+    }
+
+    /// Changes within the last `window` seconds.
+    pub fn count(&self, now: u64, window: u64) -> usize {
+        let from = now.saturating_sub(window);
+        self.at.iter().filter(|&&t| t >= from).count()
+    }
+
+    /// When the carrier last changed.
+    pub fn last(&self) -> Option<u64> {
+        self.at.back().copied()
+    }
+}
+
+/// What the daemon last saw of one interface.
+pub struct IfaceState {
+    pub up: bool,
+    /// When `up` last changed.
+    pub since: u64,
+    /// Verdict of the last probe that went through it.
+    pub probe: Option<String>,
+    /// Probes in a row that did not find it ONLINE.
+    pub failures: u32,
+    pub ipv4: Option<Ipv4Addr>,
+    /// Unix seconds the address's lease runs out, per its lease file.
+    pub lease_expires: Option<u64>,
+    pub flaps: FlappingDetector,
+}
+
+impl IfaceState {
+    fn new(up: bool, now: u64) -> IfaceState {
+        IfaceState {
+            up,
+            since: now,
+            probe: None,
+            failures: 0,
+            ipv4: None,
+            lease_expires: None,
+            flaps: FlappingDetector::default(),
+        }
+    }
+
+    /// Still holds the address of a lease that has not run out.
+    pub fn lease_valid(&self, ipv4: Option<Ipv4Addr>, now: u64) -> bool {
+        ipv4.is_some() && self.ipv4 == ipv4 && self.lease_expires.is_some_and(|e| e > now)
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    pub state: Option<String>,
+    /// When `state` was entered.
+    pub since: u64,
+    pub transitions: VecDeque<Transition>,
+    /// Seconds spent ONLINE and otherwise, up to `updated`.
+    pub uptime: u64,
+    pub downtime: u64,
+    pub updated: u64,
+    /// Since when the daemon has been OFFLINE or RECOVERING without a break,
+    /// restarts included.
+    pub offline_since: Option<u64>,
+    pub ifaces: BTreeMap<String, IfaceState>,
+    /// The running daemon's checks over its `availability_window`.
+    pub window: Option<WindowStats>,
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Unix seconds as RFC 3339, UTC.
+pub fn rfc3339(secs: u64) -> String {
+    clock::rfc3339(UNIX_EPOCH + std::time::Duration::from_secs(secs))
+}
+
+impl History {
+    /// Whatever `path` holds; empty when it is missing or unreadable.
+    pub fn load(path: &Path) -> History {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|s| Json::parse(&s).ok())
+            .map(|j| History::from_json(&j))
+            .unwrap_or_default()
+    }
+
+    /// Written to a temporary file first so readers never see half of it.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        let j = self.to_json().set("ifaces", self.ifaces_json());
+        fs::write(&tmp, format!("{}\n", j))?;
+        fs::rename(tmp, path)
+    }
+
+    /// Picks up after a restart; time while no daemon ran is not counted.
+    pub fn resume(mut self) -> History {
+        self.updated = unix_now();
+        self.window = None;
+        self
+    }
+
+    /// Adds the time since the last update to uptime or downtime.
+    pub fn tick(&mut self, now: u64) {
+        let d = now.saturating_sub(self.updated);
+        if self.updated > 0 {
+            match self.state.as_deref() {
+                Some("ONLINE") => self.uptime += d,
+                Some(_) => self.downtime += d,
+                None => {}
+            }
+        }
+        self.updated = now;
+    }
+
+    /// Enters `to`; returns whether that was a change.
+    pub fn record(&mut self, to: &str, now: u64) -> bool {
+        self.tick(now);
+        if self.state.as_deref() == Some(to) {
+            return false;
+        }
+        if let Some(from) = self.state.take() {
+            if self.transitions.len() == RING {
+                self.transitions.pop_front();
+            }
+            self.transitions.push_back(Transition {
+                at: now,
+                from,
+                to: to.into(),
+            });
+        }
+        match to {
+            "OFFLINE" | "RECOVERING" => {
+                self.offline_since.get_or_insert(now);
+            }
+            _ => self.offline_since = None,
+        }
+        self.state = Some(to.into());
+        self.since = now;
+        true
+    }
+
+    /// Notes `iface`'s link state, and when it changed, its address and
+    /// when that address's lease expires.
+    pub fn link(
+        &mut self,
+        iface: &str,
+        up: bool,
+        ipv4: Option<Ipv4Addr>,
+        lease_expires: Option<u64>,
+        now: u64,
+    ) {
+        let e = self
+            .ifaces
+            .entry(iface.into())
+            .or_insert(IfaceState::new(up, now));
+        if e.up != up {
+            e.up = up;
+            e.since = now;
+        }
+        e.ipv4 = ipv4;
+        e.lease_expires = lease_expires;
+    }
+
+    /// Notes the verdict of a probe through `iface`.
+    pub fn probed(&mut self, iface: &str, state: &str, now: u64) {
+        let e = self
+            .ifaces
+            .entry(iface.into())
+            .or_insert(IfaceState::new(true, now));
+        e.failures = if state == "ONLINE" { 0 } else { e.failures + 1 };
+        e.probe = Some(state.into());
+    }
+
+    /// Notes `n` carrier changes of `iface`.
+    pub fn carrier_changed(&mut self, iface: &str, n: u64, now: u64) {
+        self.ifaces
+            .entry(iface.into())
+            .or_insert(IfaceState::new(true, now))
+            .flaps
+            .record(n, now);
+    }
+
+    /// Changes of state within the last `window` seconds. RECOVERING is
+    /// the way to a verdict rather than one: ONLINE, RECOVERING, ONLINE is
+    /// no change, and ONLINE, RECOVERING, OFFLINE one.
+    pub fn flaps(&self, now: u64, window: u64) -> usize {
+        let from = now.saturating_sub(window);
+        let mut settled: Option<&str> = None;
+        let mut n = 0;
+        for t in &self.transitions {
+            if t.to == "RECOVERING" {
+                if t.from != "RECOVERING" {
+                    settled = Some(&t.from);
+                }
+                continue;
+            }
+            let before = match t.from.as_str() {
+                "RECOVERING" => settled,
+                f => Some(f),
+            };
+            if t.at >= from && before != Some(t.to.as_str()) {
+                n += 1;
+            }
+            settled = Some(&t.to);
+        }
+        n
+    }
+
+    pub fn to_json(&self) -> Json {
+        let ts: Vec<Json> = self
+            .transitions
+            .iter()
+            .map(|t| {
+                Json::obj()
+                    .set("at", t.at)
+                    .set("from", t.from.as_str())
+                    .set("to", t.to.as_str())
+            })
+            .collect();
+        Json::obj()
+            .set("state", self.state.as_deref())
+            .set("since", self.since)
+            .set("since_ts", rfc3339(self.since))
+            .set("uptime_secs", self.uptime)
+            .set("downtime_secs", self.downtime)
+            .set("updated", self.updated)
+            .set("offline_since", self.offline_since)
+            .set("transitions", ts)
+            .set("window", self.window.as_ref().map(WindowStats::to_json))
+    }
+
+    pub fn ifaces_json(&self) -> Vec<Json> {
+        self.ifaces
+            .iter()
+            .map(|(name, i)| {
+                Json::obj()
+                    .set("name", name.as_str())
+                    .set("up", i.up)
+                    .set("since", i.since)
+                    .set("probe", i.probe.as_deref())
+                    .set("failures", i.failures as u64)
+                    .set("ipv4", i.ipv4.map(|a| a.to_string()))
+                    .set("lease_expires", i.lease_expires)
+                    .set(
+                        "carrier_changes",
+                        i.flaps.at.iter().copied().collect::<Vec<u64>>(),
+                    )
+            })
+            .collect()
+    }
+
+    fn from_json(j: &Json) -> History {
+        let num = |k: &str| j.get(k).and_then(Json::as_u64).unwrap_or(0);
+        let transitions = j
+            .get("transitions")
+            .and_then(Json::as_arr)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|t| {
+                Some(Transition {
+                    at: t.get("at")?.as_u64()?,
+                    from: t.get("from")?.as_str()?.into(),
+                    to: t.get("to")?.as_str()?.into(),
+                })
+            })
+            .collect();
+        let ifaces = j
+            .get("ifaces")
+            .and_then(Json::as_arr)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|i| {
+                let st = IfaceState {
+                    up: i.get("up")? == &Json::Bool(true),
+                    since: i.get("since")?.as_u64()?,
+                    probe: i.get("probe").and_then(Json::as_str).map(String::from),
+                    failures: i.get("failures").and_then(Json::as_u64).unwrap_or(0) as u32,
+                    ipv4: i
+                        .get("ipv4")
+                        .and_then(Json::as_str)
+                        .and_then(|a| a.parse().ok()),
+                    lease_expires: i.get("lease_expires").and_then(Json::as_u64),
+                    flaps: FlappingDetector {
+                        at: i
+                            .get("carrier_changes")
+                            .and_then(Json::as_arr)
+                            .unwrap_or_default()
+                            .iter()
+                            .filter_map(Json::as_u64)
+                            .collect(),
+                    },
+                };
+                Some((i.get("name")?.as_str()?.to_string(), st))
+            })
+            .collect();
+        History {
+            state: j.get("state").and_then(Json::as_str).map(String::from),
+            since: num("since"),
+            transitions,
+            uptime: num("uptime_secs"),
+            downtime: num("downtime_secs"),
+            updated: num("updated"),
+            offline_since: j.get("offline_since").and_then(Json::as_u64),
+            ifaces,
+            window: j.get("window").and_then(WindowStats::from_json),
+        }
+    }
+
+    /// What `--status` prints.
+    pub fn describe(&self, now: u64, flap_window: u64) -> String {
+        let Some(state) = &self.state else {
+            return "no state recorded yet".into();
+        };
+        let total = (self.uptime + self.downtime).max(1);
+        let window = self
+            .window
+            .as_ref()
+            .map_or(String::new(), |w| format!("\n{}", w));
+        format!(
+            "{} since {} ({})\nuptime {}, downtime {} ({:.1}% up)\n{} transitions recorded, {} in the last {}{}\nlast update {}",
+            state,
+            rfc3339(self.since),
+            span(now.saturating_sub(self.since)),
+            span(self.uptime),
+            span(self.downtime),
+            self.uptime as f64 * 100.0 / total as f64,
+            self.transitions.len(),
+            self.flaps(now, flap_window),
+            span(flap_window),
+            window,
+            rfc3339(self.updated),
+        )
+    }
+}
+
+/// Seconds from `90`, `90s`, `30m`, `2h` or `1d`.
+pub fn parse_span(s: &str) -> Option<u64> {
+    let (n, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let mult = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    n.parse::<u64>().ok()?.checked_mul(mult)
+}
+
+/// `1d 2h 3m 4s`, leaving out zero units.
+pub fn span(secs: u64) -> String {
+    let parts: Vec<String> = [
+        (secs / 86400, "d"),
+        (secs / 3600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+        (secs % 60, "s"),
+    ]
+    .iter()
+    .filter(|p| p.0 > 0)
+    .map(|(n, u)| format!("{}{}", n, u))
+    .collect();
+    if parts.is_empty() {
+        return "0s".into();
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(states: &[(&str, u64)]) -> History {
+        let mut h = History::default();
+        for &(s, at) in states {
+            h.record(s, at);
+        }
+        h
+    }
+
+    #[test]
+    fn recovering_is_no_flap() {
+        // A renewal that fixes it at once.
+        let h = history(&[("ONLINE", 0), ("RECOVERING", 60), ("ONLINE", 70)]);
+        assert_eq!(h.flaps(70, 600), 0);
+        // Two outages, down and up each.
+        let h = history(&[
+            ("ONLINE", 0),
+            ("RECOVERING", 60),
+            ("OFFLINE", 70),
+            ("ONLINE", 120),
+            ("RECOVERING", 300),
+            ("ONLINE", 310),
+            ("RECOVERING", 400),
+            ("OFFLINE", 410),
+            ("ONLINE", 500),
+        ]);
+        assert_eq!(h.transitions.len(), 8);
+        assert_eq!(h.flaps(500, 600), 4);
+        // What settled before the window still tells whether it changed.
+        assert_eq!(h.flaps(500, 195), 2);
+        assert_eq!(h.flaps(500, 5), 1);
+    }
+
+    #[test]
+    fn flaps_count_every_other_change() {
+        let h = history(&[
+            ("ONLINE", 0),
+            ("DEGRADED", 10),
+            ("OFFLINE", 20),
+            ("ONLINE", 30),
+        ]);
+        assert_eq!(h.flaps(30, 600), 3);
+        assert_eq!(h.flaps(30, 15), 2);
+    }
+}
@@ -0,0 +1,287 @@
+// Just enough HTTP/1.1 for connectivity probes and webhooks. Plain HTTP is
+// spoken directly; HTTPS is delegated to curl so we carry no TLS stack.
+use crate::{dns, log};
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    pub https: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl FromStr for Url {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Url, String> {
+        let (https, rest) = if let Some(r) = s.strip_prefix("http://") {
+            (false, r)
+        } else if let Some(r) = s.strip_prefix("https://") {
+            (true, r)
+        } else {
+            return Err("expected an http:// or https:// URL".into());
+        };
+        let (auth, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match auth.strip_prefix('[') {
+            Some(a) => a.split_once(']').ok_or("unterminated `[`")?,
+            None => match auth.rsplit_once(':') {
+                Some((h, p)) => (h, p),
+                None => (auth, ""),
+            },
+        };
+        let port = match port.trim_start_matches(':') {
+            "" => {
+                if https {
+                    443
+                } else {
+                    80
+                }
+            }
+            p => p.parse().map_err(|_| format!("bad port `{}`", p))?,
+        };
+        if host.is_empty() {
+            return Err("missing host".into());
+        }
+        Ok(Url {
+            https,
+            host: host.into(),
+            port,
+            path: path.into(),
+        })
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        let (scheme, default) = if self.https {
+            ("https", 443)
+        } else {
+            ("http", 80)
+        };
+        write!(f, "{}://{}", scheme, host)?;
+        if self.port != default {
+            write!(f, ":{}", self.port)?;
+        }
+        f.write_str(&self.path)
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    /// The `Location` header of a redirect.
+    pub location: Option<String>,
+    pub body: Vec<u8>,
+    pub elapsed: Duration,
+    /// The address that answered, when known.
+    pub peer: Option<IpAddr>,
+}
+
+/// Bytes of a response read at most, head and body; a longer one is cut
+/// off there, which is plenty to tell a captive portal's page by.
+const MAX_RESPONSE: u64 = 1 << 20;
+
+/// Sends one request without following redirects, all of it within
+/// `timeout`. `body` is
+/// `(content type, bytes)`.
+pub fn request(
+    method: &str,
+    url: &Url,
+    body: Option<(&str, &[u8])>,
+    timeout: Duration,
+) -> Result<Response, String> {
+    let t0 = Instant::now();
+    let (status, location, body, peer) = if url.https {
+        curl(method, url, body, timeout)?
+    } else {
+        plain(method, url, body, timeout).map_err(|e| e.to_string())?
+    };
+    Ok(Response {
+        status,
+        location,
+        body,
+        elapsed: t0.elapsed(),
+        peer,
+    })
+}
+
+/// Status, `Location`, body and the answering address.
+type Reply = (u16, Option<String>, Vec<u8>, Option<IpAddr>);
+
+fn plain(
+    method: &str,
+    url: &Url,
+    body: Option<(&str, &[u8])>,
+    timeout: Duration,
+) -> io::Result<Reply> {
+    let bad = |m: &str| io::Error::new(ErrorKind::InvalidData, m.to_string());
+    let end = Instant::now() + timeout;
+    let left = || {
+        let t = end.saturating_duration_since(Instant::now());
+        match t.is_zero() {
+            true => Err(io::Error::new(ErrorKind::TimedOut, "timed out")),
+            false => Ok(t),
+        }
+    };
+    let addrs = dns::lookup(&url.host, timeout).map_err(io::Error::other)?;
+    let mut last = None;
+    let mut conn = None;
+    for a in addrs {
+        match TcpStream::connect_timeout(&SocketAddr::new(a, url.port), left()?) {
+            Ok(c) => {
+                conn = Some(c);
+                break;
+            }
+            Err(e) => last = Some(e),
+        }
+    }
+    let mut c = match conn {
+        Some(c) => c,
+        None => return Err(last.unwrap_or_else(|| bad("no address for host"))),
+    };
+    c.set_write_timeout(Some(left()?))?;
+    let mut req = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: cw-netup/{}\r\nConnection: close\r\n",
+        method,
+        url.path,
+        url.host,
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some((ty, b)) = body {
+        req += &format!("Content-Type: {}\r\nContent-Length: {}\r\n", ty, b.len());
+    }
+    req += "\r\n";
+    c.write_all(req.as_bytes())?;
+    if let Some((_, b)) = body {
+        c.write_all(b)?;
+    }
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 4096];
+    let mut r = Read::take(&c, MAX_RESPONSE);
+    loop {
+        c.set_read_timeout(Some(left()?))?;
+        match r.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => raw.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                return Err(io::Error::new(ErrorKind::TimedOut, "timed out"))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let split = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| bad("truncated response"))?;
+    let head = String::from_utf8_lossy(&raw[..split]).into_owned();
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| bad("malformed status line"))?;
+    let location = head.lines().find_map(|l| {
+        let (k, v) = l.split_once(':')?;
+        k.trim()
+            .eq_ignore_ascii_case("location")
+            .then(|| v.trim().to_string())
+    });
+    let body = raw[split + 4..].to_vec();
+    let chunked = head.lines().any(|l| {
+        l.to_ascii_lowercase()
+            .replace(' ', "")
+            .starts_with("transfer-encoding:chunked")
+    });
+    let body = if chunked { dechunk(&body) } else { body };
+    let peer = c.peer_addr().ok().map(|a| a.ip());
+    Ok((status, location, body, peer))
+}
+
+fn dechunk(mut b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    while let Some(i) = b.windows(2).position(|w| w == b"\r\n") {
+        let len = String::from_utf8_lossy(&b[..i]);
+        let n = usize::from_str_radix(len.split(';').next().unwrap_or("").trim(), 16).unwrap_or(0);
+        b = &b[i + 2..];
+        if n == 0 || n > b.len() {
+            break;
+        }
+        out.extend_from_slice(&b[..n]);
+        b = b.get(n + 2..).unwrap_or_default();
+    }
+    out
+}
+
+fn curl(
+    method: &str,
+    url: &Url,
+    body: Option<(&str, &[u8])>,
+    timeout: Duration,
+) -> Result<Reply, String> {
+    let mut cmd = Command::new("curl");
+    cmd.args([
+        "-sS",
+        "-X",
+        method,
+        "-o",
+        "-",
+        "-w",
+        "\n%{remote_ip}\n%{redirect_url}\n%{http_code}",
+    ])
+    .arg("--max-time")
+    .arg(timeout.as_secs().max(1).to_string());
+    if let Some((ty, _)) = body {
+        cmd.args([
+            "-H",
+            &format!("Content-Type: {}", ty),
+            "--data-binary",
+            "@-",
+        ]);
+    }
+    let mut child = cmd
+        .arg(url.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => "https needs curl, which was not found".to_string(),
+            _ => format!("curl: {}", e),
+        })?;
+    if let (Some(mut i), Some((_, b))) = (child.stdin.take(), body) {
+        if let Err(e) = i.write_all(b) {
+            log::debug!("curl: writing body: {}", e);
+        }
+    }
+    let o = child
+        .wait_with_output()
+        .map_err(|e| format!("curl: {}", e))?;
+    if !o.status.success() {
+        let err = String::from_utf8_lossy(&o.stderr).trim().to_string();
+        log::debug!("curl {}: {}: {}", url, o.status, err);
+        return Err(err);
+    }
+    let mut out = o.stdout;
+    let tail = |out: &mut Vec<u8>| {
+        let nl = out.iter().rposition(|&c| c == b'\n').unwrap_or(0);
+        let s = String::from_utf8_lossy(&out[nl..]).trim().to_string();
+        out.truncate(nl);
+        s
+    };
+    let status = tail(&mut out).parse().unwrap_or(0);
+    let location = Some(tail(&mut out)).filter(|l| !l.is_empty());
+    let peer = tail(&mut out).parse().ok();
+    Ok((status, location, out, peer))
+}
@@ -0,0 +1,1429 @@
+use crate::backoff::Attempt;
+#[cfg(target_os = "linux")]
+use crate::backoff::{self, Backoff};
+use crate::config::{Config, Family, ProbeMethod, StaticConfig};
+#[cfg(target_os = "linux")]
+use crate::dhcp::{self, Lease};
+use crate::exec::{self, Executor};
+use crate::leases::{self, Lease as LeaseFile};
+use crate::net::{Duplex, IfaceStats};
+#[cfg(target_os = "linux")]
+use crate::netlink::{self, Link};
+#[cfg(target_os = "linux")]
+use crate::{arp, pool, wireless};
+use crate::{glob, log, net, ping};
+use std::fmt;
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::io::{self, ErrorKind};
+#[cfg(target_os = "linux")]
+use std::net::IpAddr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IfaceKind {
+    Wired,
+    /// Needs association before DHCP makes sense.
+    Wireless,
+    /// No backing device: bridges, veth, dummy, ...
+    Virtual,
+    /// tun/tap, WireGuard or PPP, owned by a VPN or PPP daemon.
+    Vpn,
+}
+
+impl IfaceKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IfaceKind::Wired => "wired",
+            IfaceKind::Wireless => "wireless",
+            IfaceKind::Virtual => "virtual",
+            IfaceKind::Vpn => "vpn",
+        }
+    }
+
+    pub fn role(self) -> IfaceRole {
+        match self {
+            IfaceKind::Vpn => IfaceRole::ProbeOnly,
+            _ => IfaceRole::Managed,
+        }
+    }
+}
+
+/// What cw-netup does with an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IfaceRole {
+    /// Set up, given a lease and torn down as configured.
+    Managed,
+    /// Left to the daemon that owns it; only looked at and probed through,
+    /// since routes may go over it.
+    ProbeOnly,
+}
+
+impl IfaceRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IfaceRole::Managed => "managed",
+            IfaceRole::ProbeOnly => "probe_only",
+        }
+    }
+}
+
+/// ARPHRD_PPP, ARPHRD_NONE (tun, WireGuard) and ARPHRD_VOID.
+const VPN_ARP_TYPES: [u32; 3] = [512, 65534, 65535];
+
+/// A VPN or PPP link, by name (`tun*`, `tap*`, `wg*`, `ppp*`), by link
+/// type, or by being a tun/tap device at all.
+pub fn classify_virtual(name: &str) -> bool {
+    let base = format!("/sys/class/net/{}", name);
+    let arp_type = fs::read_to_string(format!("{}/type", base))
+        .ok()
+        .and_then(|t| t.trim().parse::<u32>().ok());
+    ["tun", "tap", "wg", "ppp"]
+        .iter()
+        .any(|p| name.starts_with(p))
+        || arp_type.is_some_and(|t| VPN_ARP_TYPES.contains(&t))
+        || fs::metadata(format!("{}/tun_flags", base)).is_ok()
+}
+
+#[cfg(target_os = "linux")]
+pub fn classify_iface(name: &str) -> IfaceKind {
+    let base = format!("/sys/class/net/{}", name);
+    let has = |f: &str| fs::metadata(format!("{}/{}", base, f)).is_ok();
+    if classify_virtual(name) {
+        IfaceKind::Vpn
+    } else if has("wireless") || has("phy80211") {
+        IfaceKind::Wireless
+    } else if has("device") {
+        IfaceKind::Wired
+    } else {
+        IfaceKind::Virtual
+    }
+}
+
+/// As getifaddrs(3) or GetAdaptersAddresses has it.
+#[cfg(not(target_os = "linux"))]
+pub fn classify_iface(name: &str) -> IfaceKind {
+    crate::ifaddrs::links()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|l| l.name == name)
+        .map_or(IfaceKind::Virtual, |l| l.kind)
+}
+
+/// Why an interface can or cannot carry traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStatus {
+    Down,
+    NoCarrier,
+    /// Wireless, up, but not associated to an access point.
+    NotAssociated,
+    Up,
+    /// Unplugged while it was being set up or probed.
+    Gone,
+}
+
+impl LinkStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkStatus::Down => "DOWN",
+            LinkStatus::NoCarrier => "NO-CARRIER",
+            LinkStatus::NotAssociated => "NOT-ASSOCIATED",
+            LinkStatus::Up => "UP",
+            LinkStatus::Gone => "GONE",
+        }
+    }
+}
+
+/// A bond's or team's member link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub link: LinkStatus,
+}
+
+impl Member {
+    fn observe(name: &str) -> Member {
+        let link = match (net::admin_up(name), net::carrier(name)) {
+            (false, _) => LinkStatus::Down,
+            (_, Some(false)) => LinkStatus::NoCarrier,
+            _ => LinkStatus::Up,
+        };
+        Member {
+            name: name.into(),
+            link,
+        }
+    }
+}
+
+/// The links `iface` aggregates: a bond's from `bonding/slaves`, a team's
+/// those enslaved to it (teamd keeps no list in sysfs). Empty for anything
+/// else.
+#[cfg(target_os = "linux")]
+pub fn detect_bond_members(iface: &str) -> Vec<String> {
+    let base = Path::new("/sys/class/net").join(iface);
+    if let Ok(s) = fs::read_to_string(base.join("bonding/slaves")) {
+        return s.split_whitespace().map(String::from).collect();
+    }
+    let Ok(links) = netlink::list_links() else {
+        return Vec::new();
+    };
+    let Some(team) = links
+        .iter()
+        .find(|l| l.name == iface && l.kind.as_deref() == Some("team"))
+    else {
+        return Vec::new();
+    };
+    let mut v: Vec<String> = links
+        .iter()
+        .filter(|l| l.master == Some(team.index))
+        .map(|l| l.name.clone())
+        .collect();
+    v.sort();
+    v
+}
+
+/// Bonds and teams are not looked into here.
+#[cfg(not(target_os = "linux"))]
+pub fn detect_bond_members(_iface: &str) -> Vec<String> {
+    Vec::new()
+}
+
+/// The bond or team `iface` is a member of.
+pub fn aggregator(iface: &str) -> Option<String> {
+    let master = fs::read_link(Path::new("/sys/class/net").join(iface).join("master")).ok()?;
+    let m = master.file_name()?.to_string_lossy().into_owned();
+    detect_bond_members(&m)
+        .iter()
+        .any(|x| x == iface)
+        .then_some(m)
+}
+
+/// What is wrong with the default route once an interface is up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteProblem {
+    /// No IPv4 default route at all, though the lease or `[fallback_static]`
+    /// names a gateway; whether it was put back (`repair_routes`).
+    Missing { expected: Ipv4Addr, repaired: bool },
+    /// Through a gateway no connected route of the interface covers.
+    OffLink(Ipv4Addr),
+    /// Through a gateway that does not answer ARP.
+    DeadGateway(Ipv4Addr),
+    /// No IPv6 default route, though the interface has global addresses.
+    MissingV6,
+}
+
+impl RouteProblem {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RouteProblem::Missing { .. } => "missing",
+            RouteProblem::OffLink(_) => "off_link",
+            RouteProblem::DeadGateway(_) => "gateway_unreachable",
+            RouteProblem::MissingV6 => "missing_ipv6",
+        }
+    }
+
+    pub fn gateway(self) -> Option<Ipv4Addr> {
+        match self {
+            RouteProblem::Missing { expected: g, .. }
+            | RouteProblem::OffLink(g)
+            | RouteProblem::DeadGateway(g) => Some(g),
+            RouteProblem::MissingV6 => None,
+        }
+    }
+}
+
+impl fmt::Display for RouteProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RouteProblem::Missing {
+                expected,
+                repaired: true,
+            } => write!(f, "default route via {} was missing, added", expected),
+            RouteProblem::Missing { expected, .. } => {
+                write!(f, "no default route, expected one via {}", expected)
+            }
+            RouteProblem::OffLink(g) => write!(f, "default route via {}, which is not on-link", g),
+            RouteProblem::DeadGateway(g) => {
+                write!(f, "default route via {}, which does not answer ARP", g)
+            }
+            RouteProblem::MissingV6 => f.write_str("no IPv6 default route"),
+        }
+    }
+}
+
+/// How long bring-up spent on an interface, for `--benchmark`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timing {
+    /// Setting the link and its members up, association and the carrier
+    /// wait.
+    pub link_up: Duration,
+    /// DHCP runs, retries included.
+    pub dhcp: Duration,
+    pub dhcp6: Duration,
+    pub total: Duration,
+}
+
+/// What bring-up did to one interface.
+pub struct IfaceReport {
+    pub name: String,
+    pub kind: IfaceKind,
+    pub link_up: bool,
+    pub link: LinkStatus,
+    /// Hardware address, from sysfs.
+    pub mac: Option<String>,
+    /// Associated to an access point; `None` when not wireless or neither
+    /// `iw` nor wpa_supplicant could tell.
+    pub associated: Option<bool>,
+    pub ssid: Option<String>,
+    pub signal_dbm: Option<i32>,
+    /// Had an IPv4 address while the host had a default route, before DHCP
+    /// was considered; renewing would only disrupt it.
+    pub configured: bool,
+    pub ipv4: Option<Ipv4Addr>,
+    /// The address DHCP replaced with another lease's.
+    pub previous: Option<Ipv4Addr>,
+    /// Gateway of a default route through this interface.
+    pub gateway: Option<Ipv4Addr>,
+    /// `None` when DHCP was not attempted.
+    pub dhcp: Option<bool>,
+    /// Global IPv6 addresses after bring-up (only looked up with IPv6 enabled).
+    pub v6_addrs: Vec<Ipv6Addr>,
+    pub dhcp6: Option<bool>,
+    /// Whether `[fallback_static]` was applied after DHCP failed, or the
+    /// interface was found on it; `None` when it was not needed or not
+    /// configured.
+    pub fallback: Option<bool>,
+    /// What dhclient recorded for the address the interface has, or its
+    /// kernel lifetime.
+    pub lease: Option<LeaseFile>,
+    /// When the lease is due for renewal under `renew_remaining`, unix
+    /// seconds.
+    pub renew_at: Option<u64>,
+    /// Counters after bring-up.
+    pub stats: IfaceStats,
+    /// Path MTU to the first IP target, with `pmtu`.
+    pub pmtu: Option<u16>,
+    /// Negotiated link speed in Mbps and duplex, from sysfs; `None` while
+    /// down or where the driver does not say.
+    pub speed_mbps: Option<u32>,
+    pub duplex: Option<Duplex>,
+    /// DHCP attempts that failed and were retried.
+    pub retries: Vec<Attempt>,
+    pub errors: Vec<String>,
+    /// What NetworkManager or networkd says of it, when they own it.
+    pub manager_state: Option<String>,
+    /// A bond's or team's members, brought up ahead of it.
+    pub members: Vec<Member>,
+    pub routes: Vec<RouteProblem>,
+    pub timing: Timing,
+}
+
+impl IfaceReport {
+    fn new(name: &str) -> IfaceReport {
+        IfaceReport {
+            name: name.into(),
+            kind: classify_iface(name),
+            link_up: false,
+            link: LinkStatus::Down,
+            mac: None,
+            associated: None,
+            ssid: None,
+            signal_dbm: None,
+            configured: false,
+            ipv4: None,
+            previous: None,
+            gateway: None,
+            dhcp: None,
+            v6_addrs: Vec::new(),
+            dhcp6: None,
+            fallback: None,
+            lease: None,
+            renew_at: None,
+            stats: IfaceStats::default(),
+            pmtu: None,
+            speed_mbps: None,
+            duplex: None,
+            retries: Vec::new(),
+            errors: Vec::new(),
+            manager_state: None,
+            members: Vec::new(),
+            routes: Vec::new(),
+            timing: Timing::default(),
+        }
+    }
+
+    /// Whether a command for this interface ran out of time.
+    pub fn timed_out(&self) -> bool {
+        self.errors.iter().any(|e| exec::timed_out(e))
+    }
+
+    fn status(&self, carrier: Option<bool>) -> LinkStatus {
+        match (self.link_up, self.associated, carrier) {
+            (false, _, _) => LinkStatus::Down,
+            (_, Some(false), _) => LinkStatus::NotAssociated,
+            (_, _, Some(false)) => LinkStatus::NoCarrier,
+            _ => LinkStatus::Up,
+        }
+    }
+}
+
+/// Global-scope IPv6 addresses on `iface`, from `/proc/net/if_inet6`
+/// (SLAAC, DHCPv6 or static alike).
+#[cfg(target_os = "linux")]
+pub fn global_v6(iface: &str) -> Vec<Ipv6Addr> {
+    let Ok(s) = fs::read_to_string("/proc/thread-self/net/if_inet6") else {
+        return Vec::new();
+    };
+    s.lines()
+        .filter_map(|l| {
+            let f: Vec<&str> = l.split_whitespace().collect();
+            // addr ifindex prefixlen scope flags name; scope 00 = global
+            if f.len() < 6 || f[5] != iface || f[3] != "00" {
+                return None;
+            }
+            u128::from_str_radix(f[0], 16).ok().map(Ipv6Addr::from)
+        })
+        .collect()
+}
+
+/// IPv6 addresses on `iface` that are neither loopback nor link-local.
+#[cfg(not(target_os = "linux"))]
+pub fn global_v6(iface: &str) -> Vec<Ipv6Addr> {
+    crate::ifaddrs::links()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|l| l.name == iface)
+        .flat_map(|l| l.addrs)
+        .filter_map(|(a, _)| match a {
+            std::net::IpAddr::V6(a) => Some(a),
+            std::net::IpAddr::V4(_) => None,
+        })
+        .filter(|a| !a.is_loopback() && a.segments()[0] & 0xffc0 != 0xfe80)
+        .collect()
+}
+
+/// Waits up to `secs` for router advertisements to configure an address.
+#[cfg(target_os = "linux")]
+fn wait_v6(iface: &str, secs: u64) -> Vec<Ipv6Addr> {
+    let end = Instant::now() + Duration::from_secs(secs);
+    loop {
+        let a = global_v6(iface);
+        if !a.is_empty() || Instant::now() >= end {
+            return a;
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+}
+
+/// A discovered interface with the sysfs attributes filtering looks at.
+pub struct Candidate {
+    pub name: String,
+    /// ARPHRD_* from `/sys/class/net/<if>/type`.
+    pub arp_type: Option<u32>,
+    /// Enslaved to a bridge (`brport/` exists).
+    pub bridge_port: bool,
+    /// A member of a bond or team, which is managed in its stead.
+    pub member: bool,
+}
+
+/// ARPHRD_ETHER; wireless interfaces report it too.
+const ARPHRD_ETHER: u32 = 1;
+/// ARPHRD_NONE, for what has no link layer address.
+#[cfg(not(target_os = "linux"))]
+const ARPHRD_NONE: u32 = 65534;
+
+impl Candidate {
+    pub fn probe(name: &str) -> Candidate {
+        let base = format!("/sys/class/net/{}", name);
+        Candidate {
+            name: name.to_string(),
+            arp_type: fs::read_to_string(format!("{}/type", base))
+                .ok()
+                .and_then(|t| t.trim().parse().ok()),
+            bridge_port: fs::metadata(format!("{}/brport", base)).is_ok(),
+            member: aggregator(name).is_some(),
+        }
+    }
+
+    /// Bonds have slaves too, so `master` alone does not make a bridge port.
+    #[cfg(target_os = "linux")]
+    pub fn from_link(l: &Link) -> Candidate {
+        Candidate {
+            name: l.name.clone(),
+            arp_type: Some(u32::from(l.arp_type)),
+            bridge_port: l.master.is_some()
+                && fs::metadata(format!("/sys/class/net/{}/brport", l.name)).is_ok(),
+            member: l.master.is_some() && aggregator(&l.name).is_some(),
+        }
+    }
+}
+
+/// Drops loopback, non-Ethernet links (when `ether_only`), bridge ports
+/// (when `skip_bridge_ports`), bond and team members, and names failing the
+/// include/exclude globs.
+pub fn filter(cands: Vec<Candidate>, cfg: &Config) -> Vec<String> {
+    let mut v: Vec<String> = cands
+        .into_iter()
+        .filter(|c| c.name != "lo")
+        .filter(|c| !cfg.ether_only || c.arp_type.is_none_or(|t| t == ARPHRD_ETHER))
+        .filter(|c| !cfg.skip_bridge_ports || !c.bridge_port)
+        .filter(|c| !c.member)
+        .filter(|c| cfg.include.is_empty() || glob::any(&cfg.include, &c.name))
+        .filter(|c| !glob::any(&cfg.exclude, &c.name))
+        .map(|c| c.name)
+        .collect();
+    v.sort();
+    v
+}
+
+/// The configured interface list, or every discovered interface that passes
+/// [`filter`]. Links come from netlink, or `/sys/class/net` without it.
+#[cfg(target_os = "linux")]
+pub fn discover(cfg: &Config) -> Vec<String> {
+    if !cfg.interfaces.is_empty() {
+        return cfg.interfaces.clone();
+    }
+    if let Ok(links) = netlink::list_links() {
+        return filter(links.iter().map(Candidate::from_link).collect(), cfg);
+    }
+    let Ok(dir) = fs::read_dir("/sys/class/net") else {
+        return vec!["eth0".into()];
+    };
+    let cands = dir
+        .filter_map(|e| e.ok())
+        .map(|e| Candidate::probe(&e.file_name().to_string_lossy()))
+        .collect();
+    filter(cands, cfg)
+}
+
+/// The configured interface list, or every link getifaddrs(3) or
+/// GetAdaptersAddresses lists but loopback that passes [`filter`].
+#[cfg(not(target_os = "linux"))]
+pub fn discover(cfg: &Config) -> Vec<String> {
+    if !cfg.interfaces.is_empty() {
+        return cfg.interfaces.clone();
+    }
+    let cands = crate::ifaddrs::links()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|l| !l.loopback)
+        .map(|l| Candidate {
+            arp_type: Some(match l.kind {
+                IfaceKind::Wired | IfaceKind::Wireless => ARPHRD_ETHER,
+                IfaceKind::Virtual | IfaceKind::Vpn => ARPHRD_NONE,
+            }),
+            name: l.name,
+            bridge_port: false,
+            member: false,
+        })
+        .collect();
+    filter(cands, cfg)
+}
+
+/// Does `what` to `iface` over netlink; `ip <args>` is the equivalent
+/// command. Built with the `iproute2` feature, that is run instead when
+/// netlink itself is what failed.
+const DENIED: &str = "permission denied (run as root or with CAP_NET_ADMIN)";
+
+/// Whether `err` came from [`link_op`] lacking privileges.
+pub fn denied(err: &str) -> bool {
+    err.ends_with(DENIED)
+}
+
+#[cfg(target_os = "linux")]
+pub fn link_op(
+    ex: &dyn Executor,
+    iface: &str,
+    what: &str,
+    op: impl Fn(u32) -> io::Result<()>,
+    ip: &[&str],
+) -> Result<(), String> {
+    let argv = exec::argv(&[&["ip"], ip].concat());
+    ex.apply(&argv.join(" "), &mut || {
+        let e = match netlink::index(iface).and_then(&op) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        if e.kind() == ErrorKind::PermissionDenied {
+            return Err(format!("{}: {}", what, DENIED));
+        }
+        #[cfg(feature = "iproute2")]
+        if e.raw_os_error() != Some(19) {
+            return ex.run(&argv, exec::limits().ip);
+        }
+        Err(format!("{}: {}", what, e))
+    })
+}
+
+/// Configures `iface` by hand: address, then default route, then resolvers.
+/// Both are replaced rather than added so a daemon re-applying them does not
+/// fail.
+#[cfg(target_os = "linux")]
+pub fn apply_static(ex: &dyn Executor, iface: &str, st: &StaticConfig) -> Result<(), String> {
+    let addr = format!("{}/{}", st.address, st.prefix_len);
+    link_op(
+        ex,
+        iface,
+        "adding address",
+        |i| netlink::replace_address(i, st.address, st.prefix_len),
+        &["addr", "replace", &addr, "dev", iface],
+    )?;
+    if let Some(gw) = st.gateway {
+        link_op(
+            ex,
+            iface,
+            "adding default route",
+            |i| netlink::replace_default_route(i, gw),
+            &[
+                "route",
+                "replace",
+                "default",
+                "via",
+                &gw.to_string(),
+                "dev",
+                iface,
+            ],
+        )?;
+    }
+    if !st.dns.is_empty() {
+        let conf: String = st
+            .dns
+            .iter()
+            .map(|a| format!("nameserver {}\n", a))
+            .collect();
+        let desc = format!("printf '{}' > /etc/resolv.conf", conf.replace('\n', "\\n"));
+        ex.apply(&desc, &mut || {
+            fs::write("/etc/resolv.conf", &conf).map_err(|e| format!("/etc/resolv.conf: {}", e))
+        })?;
+    }
+    Ok(())
+}
+
+/// Checks the default routes `rs` leave: one should exist for each family
+/// in use, through an on-link gateway that answers ARP. With `repair`, a
+/// missing IPv4 one is added via the first gateway a lease or
+/// `[fallback_static]` names.
+fn check_routes(cfg: &Config, rs: &mut [IfaceReport], ex: &dyn Executor, repair: bool) {
+    let routes = net::default_routes();
+    let v4 = cfg.family != Family::V6;
+    if v4 && routes.is_empty() {
+        let mut added = false;
+        // With uplinks, the default route is theirs to carry.
+        let candidates = rs.iter_mut().filter(|r| {
+            r.ipv4.is_some()
+                && r.kind.role() == IfaceRole::Managed
+                && (cfg.priority.is_empty() || cfg.priority.contains(&r.name))
+        });
+        for r in candidates {
+            let expected = r
+                .gateway
+                .or_else(|| r.lease.as_ref()?.gateway)
+                .or_else(|| on_fallback(cfg, &r.name, r.ipv4)?.gateway);
+            let Some(gw) = expected else {
+                continue;
+            };
+            let mut repaired = false;
+            if repair && !added {
+                match add_default_route(ex, &r.name, gw) {
+                    Ok(()) => {
+                        r.gateway = Some(gw);
+                        repaired = true;
+                        added = true;
+                    }
+                    Err(e) => r.errors.push(e),
+                }
+            }
+            r.routes.push(RouteProblem::Missing {
+                expected: gw,
+                repaired,
+            });
+        }
+    }
+    for rt in routes.iter().filter(|_| v4) {
+        let Some(r) = rs.iter_mut().find(|r| r.name == rt.iface && r.link_up) else {
+            continue;
+        };
+        let gw = rt.gateway;
+        if gw.is_unspecified() {
+            continue;
+        }
+        if !net::on_link(&r.name, gw) {
+            r.routes.push(RouteProblem::OffLink(gw));
+        } else if ex.live() && !net::arp_resolved(gw, &r.name) && gateway_silent(&r.name, gw) {
+            r.routes.push(RouteProblem::DeadGateway(gw));
+        }
+    }
+    if cfg.ipv6 && cfg.family != Family::V4 && !net::has_default_v6() {
+        for r in rs.iter_mut().filter(|r| !r.v6_addrs.is_empty()) {
+            r.routes.push(RouteProblem::MissingV6);
+        }
+    }
+}
+
+/// Whether `gw` does not answer ARP on `iface`.
+#[cfg(target_os = "linux")]
+fn gateway_silent(iface: &str, gw: Ipv4Addr) -> bool {
+    arp::arp_ping(iface, IpAddr::V4(gw), GATEWAY_ARP) == Some(false)
+}
+
+/// There is no ARP socket to ask off Linux.
+#[cfg(not(target_os = "linux"))]
+fn gateway_silent(_iface: &str, _gw: Ipv4Addr) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn add_default_route(ex: &dyn Executor, iface: &str, gw: Ipv4Addr) -> Result<(), String> {
+    link_op(
+        ex,
+        iface,
+        "adding default route",
+        |i| netlink::replace_default_route(i, gw),
+        &[
+            "route",
+            "replace",
+            "default",
+            "via",
+            &gw.to_string(),
+            "dev",
+            iface,
+        ],
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn add_default_route(_ex: &dyn Executor, iface: &str, gw: Ipv4Addr) -> Result<(), String> {
+    Err(format!(
+        "adding default route via {} dev {}: needs Linux",
+        gw, iface
+    ))
+}
+
+/// Takes the `[fallback_static]` address and its default route off `iface`
+/// again, so DHCP can be retried without a duplicate address; resolv.conf is
+/// left to the DHCP client.
+#[cfg(target_os = "linux")]
+fn remove_static(ex: &dyn Executor, iface: &str, st: &StaticConfig) -> Result<(), String> {
+    if let Some(gw) = st.gateway {
+        // Gone already when the address went first.
+        let _ = link_op(
+            ex,
+            iface,
+            "removing default route",
+            |i| netlink::delete_default_route(i, gw),
+            &[
+                "route",
+                "del",
+                "default",
+                "via",
+                &gw.to_string(),
+                "dev",
+                iface,
+            ],
+        );
+    }
+    let addr = format!("{}/{}", st.address, st.prefix_len);
+    link_op(
+        ex,
+        iface,
+        "removing address",
+        |i| netlink::delete_address(i, st.address, st.prefix_len),
+        &["addr", "del", &addr, "dev", iface],
+    )
+}
+
+/// The `[fallback_static]` entry of `iface` when it holds that address.
+fn on_fallback<'a>(
+    cfg: &'a Config,
+    iface: &str,
+    ipv4: Option<Ipv4Addr>,
+) -> Option<&'a StaticConfig> {
+    cfg.fallback_static
+        .get(iface)
+        .filter(|st| ipv4 == Some(st.address))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dhcp {
+    Off,
+    /// Only on interfaces without an address and default route.
+    IfNeeded,
+    Always,
+}
+
+/// Whether bring-up asks for a lease: not on a link without carrier or
+/// association, whose client could only time out, nor on a member of a
+/// bond or team, which gets one for them.
+#[cfg(target_os = "linux")]
+fn wants_dhcp(dhcp: Dhcp, link: LinkStatus, configured: bool, member: bool) -> bool {
+    match dhcp {
+        _ if member => false,
+        _ if matches!(link, LinkStatus::NoCarrier | LinkStatus::NotAssociated) => false,
+        Dhcp::Off => false,
+        Dhcp::IfNeeded => !configured,
+        Dhcp::Always => true,
+    }
+}
+
+impl Dhcp {
+    pub fn from_cfg(cfg: &Config) -> Dhcp {
+        match (cfg.skip_dhcp, cfg.force_dhcp) {
+            (true, _) => Dhcp::Off,
+            (false, true) => Dhcp::Always,
+            (false, false) => Dhcp::IfNeeded,
+        }
+    }
+
+    /// What to do after connectivity has been seen to fail.
+    pub fn renew(cfg: &Config) -> Dhcp {
+        if cfg.skip_dhcp {
+            Dhcp::Off
+        } else {
+            Dhcp::Always
+        }
+    }
+}
+
+/// Releases the DHCP lease on each managed one of `ifaces` and, with
+/// `link_down`, sets it down. Returns what failed, per interface.
+#[cfg(target_os = "linux")]
+pub fn tear_down(
+    cfg: &Config,
+    ifaces: &[String],
+    link_down: bool,
+    ex: &dyn Executor,
+) -> Vec<(String, String)> {
+    let backend = cfg.dhcp_backend.unwrap_or_else(dhcp::detect_dhcp_backend);
+    let mut failed = Vec::new();
+    for i in ifaces {
+        if classify_iface(i).role() == IfaceRole::ProbeOnly {
+            continue;
+        }
+        let argv = backend.release_argv(i);
+        if argv.is_empty() {
+            log::debug!(iface = i; "{}: {} has no lease to release", i, backend);
+        } else if net::ipv4_addr(i).is_some() || !ex.live() {
+            match ex.run(&argv, Duration::from_secs(10)) {
+                Ok(()) => log::info!(iface = i; "{}: lease released", i),
+                Err(e) => failed.push((i.clone(), e)),
+            }
+        }
+        if link_down {
+            let down = link_op(
+                ex,
+                i,
+                "setting link down",
+                netlink::set_link_down,
+                &["link", "set", i, "down"],
+            );
+            match down {
+                Ok(()) => log::info!(iface = i; "{} down", i),
+                Err(e) => failed.push((i.clone(), e)),
+            }
+        }
+    }
+    failed
+}
+
+/// Changes nothing off Linux, bar the warning.
+#[cfg(not(target_os = "linux"))]
+pub fn tear_down(
+    _cfg: &Config,
+    _ifaces: &[String],
+    _link_down: bool,
+    _ex: &dyn Executor,
+) -> Vec<(String, String)> {
+    only_looking();
+    Vec::new()
+}
+
+/// Warns, once, that links are left as they are: changing them needs
+/// Linux.
+#[cfg(not(target_os = "linux"))]
+fn only_looking() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        log::warn!("setting links up or down and DHCP need Linux; only looking at them");
+    }
+}
+
+/// Checks a wireless interface's association and, when it is missing,
+/// associates through wpa_supplicant as `[wireless]` allows.
+#[cfg(target_os = "linux")]
+fn associate(cfg: &Config, ex: &dyn Executor, r: &mut IfaceReport) {
+    let i = r.name.as_str();
+    let mut a = wireless::association(i);
+    let try_it = cfg.wpa_config.is_some() || (cfg.reconnect && a.is_some());
+    if try_it && !a.as_ref().is_some_and(|a| a.associated) {
+        let t = Duration::from_secs(cfg.assoc_timeout);
+        let res = wireless::associate(ex, i, cfg.wpa_config.as_deref(), cfg.reconnect, t);
+        let ok = res.is_ok();
+        r.errors.extend(res.err());
+        if ok {
+            // After a dry run, assume it would have worked.
+            a = ex.live().then(|| wireless::association(i)).flatten();
+        }
+        r.associated = Some(a.as_ref().map_or(ok, |a| a.associated));
+    } else if let Some(a) = &a {
+        r.associated = Some(a.associated);
+        if !a.associated {
+            r.errors
+                .push("up but not associated to an access point".into());
+        }
+    }
+    if let Some(a) = a.filter(|a| a.associated) {
+        r.ssid = a.ssid;
+        r.signal_dbm = a.signal_dbm;
+    }
+}
+
+/// The recorded lease, if it is for the address `iface` has now, else
+/// the kernel's lifetime for that address.
+fn lease_file(iface: &str, ipv4: Option<Ipv4Addr>) -> Option<LeaseFile> {
+    let addr = ipv4?;
+    leases::latest(iface)
+        .filter(|l| l.address == Some(addr))
+        .or_else(|| leases::from_kernel(iface, addr))
+}
+
+/// What the kernel says about each interface, changing nothing: no link is
+/// set up, no lease requested and nothing waited for.
+pub fn inspect(cfg: &Config, ifaces: &[String]) -> Vec<IfaceReport> {
+    let mut rs = ifaces.iter().map(|i| observe(cfg, i)).collect::<Vec<_>>();
+    rs.sort_by(|a, b| a.name.cmp(&b.name));
+    check_routes(cfg, &mut rs, &exec::CommandExecutor, false);
+    measure_pmtu(cfg, &mut rs);
+    rs
+}
+
+/// With `pmtu`, finds the path MTU of each interface, in parallel, to the
+/// first IP target of a family it has an address in.
+fn measure_pmtu(cfg: &Config, rs: &mut [IfaceReport]) {
+    if !cfg.pmtu {
+        return;
+    }
+    std::thread::scope(|s| {
+        for r in rs.iter_mut() {
+            let target = cfg.targets.iter().find_map(|t| match t {
+                ProbeMethod::Icmp(a) if a.is_ipv4() && r.ipv4.is_some() => Some(*a),
+                ProbeMethod::Icmp(a) if a.is_ipv6() && !r.v6_addrs.is_empty() => Some(*a),
+                _ => None,
+            });
+            let Some(t) = target else {
+                continue;
+            };
+            s.spawn(move || {
+                let i = r.name.as_str();
+                r.pmtu = ping::measure_pmtu(i, t, cfg.pmtu_payload);
+                match r.pmtu {
+                    Some(m) if m < cfg.pmtu_min => log::warn!(
+                        iface = i; "{}: path MTU to {} is {}, below {}", i, t, m, cfg.pmtu_min
+                    ),
+                    Some(m) => log::info!(iface = i; "{}: path MTU to {} is {}", i, t, m),
+                    None => log::debug!(iface = i; "{}: no path MTU, {} does not answer", i, t),
+                }
+            });
+        }
+    });
+}
+
+/// Reads the link's speed and duplex, warning about one below `min_speed`
+/// or at half duplex: what a failed auto-negotiation falls back to.
+fn link_speed(cfg: &Config, r: &mut IfaceReport) {
+    r.speed_mbps = net::read_link_speed(&r.name);
+    r.duplex = net::read_duplex(&r.name);
+    if cfg.min_speed == 0 {
+        return;
+    }
+    let slow = r.speed_mbps.is_some_and(|s| s < cfg.min_speed);
+    if slow || r.duplex == Some(Duplex::Half) {
+        let i = r.name.as_str();
+        let below = match slow {
+            true => format!(", below {} Mbps", cfg.min_speed),
+            false => String::new(),
+        };
+        log::warn!(
+            iface = i;
+            "{}: link at {} Mbps, {} duplex{} (auto-negotiation failed?)",
+            i,
+            r.speed_mbps.map_or("?".into(), |s| s.to_string()),
+            r.duplex.map_or("unknown", Duplex::as_str),
+            below
+        );
+    }
+}
+
+fn observe(cfg: &Config, i: &str) -> IfaceReport {
+    let mut r = IfaceReport::new(i);
+    r.link_up = net::admin_up(i);
+    #[cfg(target_os = "linux")]
+    if r.kind == IfaceKind::Wireless {
+        if let Some(a) = wireless::association(i) {
+            r.associated = Some(a.associated);
+            r.ssid = a.ssid;
+            r.signal_dbm = a.signal_dbm;
+        }
+    }
+    r.link = r.status(net::carrier(i));
+    r.ipv4 = net::ipv4_addr(i);
+    r.fallback = on_fallback(cfg, i, r.ipv4).map(|_| true);
+    r.configured = r.ipv4.is_some() && r.fallback.is_none() && !net::default_routes().is_empty();
+    r.gateway = net::gateway(i);
+    if cfg.ipv6 {
+        r.v6_addrs = global_v6(i);
+    }
+    r.lease = lease_file(i, r.ipv4);
+    r.renew_at = r
+        .lease
+        .as_ref()
+        .and_then(|l| l.renew_at(cfg.renew_remaining));
+    r.mac = net::mac_addr(i);
+    link_speed(cfg, &mut r);
+    r.stats = net::read_iface_stats(i).unwrap_or_default();
+    r.members = detect_bond_members(i)
+        .iter()
+        .map(|m| Member::observe(m))
+        .collect();
+    r
+}
+
+/// How long a fresh lease's gateway gets to answer ARP.
+#[cfg(target_os = "linux")]
+const GATEWAY_ARP: Duration = Duration::from_secs(2);
+
+/// Sets each link up and requests a lease as `dhcp` says, retrying failed
+/// DHCP runs with back-off until `deadline`; `ProbeOnly` ones are only
+/// looked at. Interfaces are worked on in
+/// parallel (`[interfaces] jobs`); reports come back sorted by name, and one
+/// whose worker panicked only carries the error.
+#[cfg(target_os = "linux")]
+pub fn bring_up(
+    cfg: &Config,
+    ifaces: &[String],
+    dhcp: Dhcp,
+    deadline: Option<Instant>,
+    ex: &dyn Executor,
+) -> Vec<IfaceReport> {
+    let b = Backoff::new(cfg.dhcp_retries, cfg, deadline);
+    let backend = cfg.dhcp_backend.unwrap_or_else(dhcp::detect_dhcp_backend);
+    // A configured command line wins over the backend; the interface is
+    // appended to it.
+    let lease = |family: u8, i: &str, log: &mut Vec<Attempt>| -> Result<Lease, String> {
+        let custom = if family == 6 {
+            &cfg.dhcp6_command
+        } else {
+            &cfg.dhcp_command
+        };
+        b.retry(log, || {
+            let t = backoff::cap(Duration::from_secs(cfg.dhcp_timeout), deadline);
+            match custom {
+                Some(c) => {
+                    dhcp::acquire_with(ex, &[&c[..], &[i.to_string()]].concat(), family, i, t)
+                }
+                None if family == 6 => backend.acquire6(ex, i, t),
+                None => backend.acquire(ex, i, t),
+            }
+            .and_then(|l| match l.gateway.or_else(|| net::gateway(i)) {
+                Some(gw) if family == 4 && cfg.verify_gateway && ex.live() => {
+                    match arp::arp_ping(i, IpAddr::V4(gw), GATEWAY_ARP) {
+                        Some(false) => Err(format!("gateway {} does not answer ARP", gw)),
+                        _ => Ok(l),
+                    }
+                }
+                _ => Ok(l),
+            })
+            .and_then(|l| match l.ipv4.or_else(|| net::ipv4_addr(i)) {
+                Some(a)
+                    if family == 4
+                        && cfg.conflict_check
+                        && ex.live()
+                        && arp::check_mac_conflict(i, IpAddr::V4(a)) =>
+                {
+                    log::error!(iface = i; "{}: address {} is already in use by another host", i, a);
+                    if !cfg.decline_conflict {
+                        return Ok(l);
+                    }
+                    let argv = backend.release_argv(i);
+                    if !argv.is_empty() {
+                        if let Err(e) = ex.run(&argv, Duration::from_secs(10)) {
+                            log::warn!(iface = i; "{}: declining the lease: {}", i, e);
+                        }
+                    }
+                    Err(format!("address {} is in use by another host", a))
+                }
+                _ => Ok(l),
+            })
+        })
+    };
+    let rs = pool::map(ifaces, cfg.jobs, |i| {
+        let mut r = IfaceReport::new(i);
+        if r.kind.role() == IfaceRole::ProbeOnly {
+            log::debug!(iface = i; "{}: {}, left to its daemon", i, r.kind.as_str());
+            return observe(cfg, i);
+        }
+        let start = Instant::now();
+        let existed = net::exists(i);
+        // A bond or team only gets carrier from its members.
+        let members = detect_bond_members(i);
+        for m in &members {
+            if net::already_up(m) {
+                continue;
+            }
+            if let Err(e) = link_op(
+                ex,
+                m,
+                "setting member link up",
+                netlink::set_link_up,
+                &["link", "set", m, "up"],
+            ) {
+                r.errors.push(format!("{}: {}", m, e));
+            }
+        }
+        let up = if net::already_up(i) {
+            log::debug!(iface = i; "{}: already up", i);
+            Ok(())
+        } else {
+            link_op(
+                ex,
+                i,
+                "setting link up",
+                netlink::set_link_up,
+                &["link", "set", i, "up"],
+            )
+        };
+        match up {
+            Ok(()) => r.link_up = true,
+            Err(e) => r.errors.push(e),
+        }
+        if r.kind == IfaceKind::Wireless && r.link_up {
+            associate(cfg, ex, &mut r);
+        }
+        let carrier = match r.kind {
+            IfaceKind::Wireless => net::carrier(i),
+            _ if r.link_up && ex.live() => {
+                net::wait_carrier(i, Duration::from_secs(cfg.carrier_wait))
+            }
+            _ => None,
+        };
+        r.link = r.status(carrier);
+        r.timing.link_up = start.elapsed();
+        r.ipv4 = net::ipv4_addr(i);
+        // A fallback address is not a configuration worth keeping.
+        let fallback = on_fallback(cfg, i, r.ipv4);
+        r.configured = r.ipv4.is_some() && fallback.is_none() && !net::default_routes().is_empty();
+        let master = aggregator(i);
+        if let Some(m) = &master {
+            log::debug!(iface = i; "{}: member of {}, DHCP left to it", i, m);
+        }
+        let mut dhcp = wants_dhcp(dhcp, r.link, r.configured, master.is_some());
+        // DHCP next to the fallback address could hand out a duplicate.
+        if let Some(st) = fallback.filter(|_| dhcp) {
+            match remove_static(ex, i, st) {
+                Ok(()) => {
+                    log::info!(iface = i; "{}: removed static address {}, retrying DHCP", i, st.address);
+                    r.ipv4 = None;
+                }
+                Err(e) => {
+                    r.errors.push(e);
+                    dhcp = false;
+                }
+            }
+        }
+        if fallback.is_some() && !dhcp {
+            r.fallback = Some(true);
+        }
+        if dhcp {
+            let before = r.ipv4;
+            let t = Instant::now();
+            let l = lease(4, i, &mut r.retries);
+            r.timing.dhcp = t.elapsed();
+            match l {
+                Ok(l) => {
+                    r.dhcp = Some(true);
+                    r.previous = before.filter(|&a| l.ipv4.is_some_and(|n| n != a));
+                    r.ipv4 = l.ipv4;
+                    r.gateway = l.gateway;
+                }
+                Err(e) => {
+                    r.dhcp = Some(false);
+                    r.errors.push(e);
+                    r.ipv4 = net::ipv4_addr(i);
+                    if let Some(st) = cfg.fallback_static.get(i) {
+                        match apply_static(ex, i, st) {
+                            Ok(()) => {
+                                r.fallback = Some(true);
+                                r.ipv4 = Some(st.address);
+                            }
+                            Err(e) => {
+                                r.fallback = Some(false);
+                                r.errors.push(e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if r.gateway.is_none() {
+            r.gateway = net::gateway(i);
+        }
+        if cfg.ipv6 {
+            r.v6_addrs = wait_v6(i, if r.link_up { cfg.slaac_wait } else { 0 });
+            if r.v6_addrs.is_empty() && dhcp && cfg.dhcp6 {
+                let t = Instant::now();
+                let l = lease(6, i, &mut r.retries);
+                r.timing.dhcp6 = t.elapsed();
+                match l {
+                    Ok(l) => {
+                        r.dhcp6 = Some(true);
+                        r.v6_addrs = l.v6_addrs;
+                    }
+                    Err(e) => {
+                        r.dhcp6 = Some(false);
+                        r.errors.push(e);
+                        r.v6_addrs = global_v6(i);
+                    }
+                }
+            }
+        }
+        r.lease = lease_file(i, r.ipv4);
+        r.renew_at = r
+            .lease
+            .as_ref()
+            .and_then(|l| l.renew_at(cfg.renew_remaining));
+        r.mac = net::mac_addr(i);
+        link_speed(cfg, &mut r);
+        r.stats = net::read_iface_stats(i).unwrap_or_default();
+        r.members = members.iter().map(|m| Member::observe(m)).collect();
+        // Unplugged on the way: what failed since says nothing more.
+        if existed && !net::exists(i) {
+            log::info!(iface = i; "{}: gone while being set up", i);
+            return IfaceReport {
+                link: LinkStatus::Gone,
+                ..IfaceReport::new(i)
+            };
+        }
+        r.timing.total = start.elapsed();
+        r
+    });
+    let mut rs: Vec<IfaceReport> = rs
+        .into_iter()
+        .zip(ifaces)
+        .map(|(r, i)| {
+            r.unwrap_or_else(|e| {
+                let mut r = IfaceReport::new(i);
+                r.errors.push(format!("bring-up failed: {}", e));
+                r
+            })
+        })
+        .collect();
+    rs.sort_by(|a, b| a.name.cmp(&b.name));
+    check_routes(cfg, &mut rs, ex, cfg.repair_routes);
+    measure_pmtu(cfg, &mut rs);
+    rs
+}
+
+/// Off Linux links are only looked at, as [`inspect`] does, after a
+/// warning: nothing is set up and no lease requested.
+#[cfg(not(target_os = "linux"))]
+pub fn bring_up(
+    cfg: &Config,
+    ifaces: &[String],
+    _dhcp: Dhcp,
+    _deadline: Option<Instant>,
+    _ex: &dyn Executor,
+) -> Vec<IfaceReport> {
+    only_looking();
+    inspect(cfg, ifaces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dhcp::DhcpBackend;
+    #[cfg(target_os = "linux")]
+    use crate::exec::DryRunExecutor;
+    use crate::exec::MockExecutor;
+
+    /// A link no host has: never up, no carrier to read.
+    const MISSING: &str = "cwmock0";
+
+    fn cfg() -> Config {
+        Config {
+            dhcp_backend: Some(DhcpBackend::Dhclient),
+            carrier_wait: 0,
+            ..Config::default()
+        }
+    }
+
+    fn up(cfg: &Config, i: &str, dhcp: Dhcp, ex: &dyn Executor) -> IfaceReport {
+        bring_up(cfg, &[i.to_string()], dhcp, None, ex).remove(0)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn links_already_up_are_left_alone() {
+        let ex = MockExecutor::new(true);
+        let r = up(&cfg(), "lo", Dhcp::Off, &ex);
+        assert!(r.link_up);
+        assert_eq!(ex.calls(), Vec::<String>::new());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn a_link_is_set_up_before_its_lease_is_requested() {
+        let ex = MockExecutor::new(false);
+        let r = up(&cfg(), MISSING, Dhcp::Always, &ex);
+        assert_eq!(
+            ex.calls(),
+            ["ip link set cwmock0 up", "dhclient -1 -q cwmock0"]
+        );
+        assert_eq!(r.dhcp, Some(true));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn links_without_carrier_or_association_get_no_lease() {
+        for link in [LinkStatus::NoCarrier, LinkStatus::NotAssociated] {
+            assert!(!wants_dhcp(Dhcp::Always, link, false, false));
+        }
+        assert!(!wants_dhcp(Dhcp::Always, LinkStatus::Up, false, true));
+        assert!(!wants_dhcp(Dhcp::IfNeeded, LinkStatus::Up, true, false));
+        assert!(wants_dhcp(Dhcp::IfNeeded, LinkStatus::Up, false, false));
+        assert!(!wants_dhcp(Dhcp::Off, LinkStatus::Up, false, false));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn a_configured_dhcp_command_wins_over_the_backend() {
+        let ex = MockExecutor::new(false);
+        let c = Config {
+            dhcp_command: Some(vec!["my-dhcp".into(), "-v".into()]),
+            ..cfg()
+        };
+        up(&c, MISSING, Dhcp::Always, &ex);
+        assert_eq!(ex.calls()[1], "my-dhcp -v cwmock0");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn a_failed_lease_falls_back_to_the_static_address() {
+        let ex = MockExecutor::new(true).fails("dhclient", "dhclient failed (exit status: 2)");
+        let mut c = cfg();
+        c.fallback_static.insert(
+            MISSING.into(),
+            StaticConfig {
+                address: "10.9.9.2".parse().unwrap(),
+                prefix_len: 24,
+                gateway: Some("10.9.9.1".parse().unwrap()),
+                dns: Vec::new(),
+            },
+        );
+        let r = up(&c, MISSING, Dhcp::Always, &ex);
+        assert_eq!(
+            ex.calls(),
+            [
+                "ip link set cwmock0 up",
+                "dhclient -1 -q cwmock0",
+                "ip addr replace 10.9.9.2/24 dev cwmock0",
+                "ip route replace default via 10.9.9.1 dev cwmock0",
+            ]
+        );
+        assert_eq!((r.dhcp, r.fallback), (Some(false), Some(true)));
+        assert_eq!(r.ipv4, Some("10.9.9.2".parse().unwrap()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn a_dry_run_records_what_it_would_do() {
+        let ex = DryRunExecutor::default();
+        let r = up(&cfg(), MISSING, Dhcp::Always, &ex);
+        assert_eq!(
+            ex.actions().unwrap(),
+            ["ip link set cwmock0 up", "dhclient -1 -q cwmock0"]
+        );
+        assert!(r.errors.is_empty(), "{:?}", r.errors);
+    }
+
+    fn candidate(name: &str, arp_type: u32) -> Candidate {
+        Candidate {
+            name: name.into(),
+            arp_type: Some(arp_type),
+            bridge_port: false,
+            member: false,
+        }
+    }
+
+    /// ARPHRD_PPP.
+    const PPP: u32 = 512;
+
+    fn links() -> Vec<Candidate> {
+        vec![
+            candidate("lo", 772),
+            candidate("wlan0", ARPHRD_ETHER),
+            candidate("eth1", ARPHRD_ETHER),
+            candidate("eth0", ARPHRD_ETHER),
+            candidate("ppp0", PPP),
+            Candidate {
+                bridge_port: true,
+                ..candidate("veth0", ARPHRD_ETHER)
+            },
+            Candidate {
+                member: true,
+                ..candidate("enp1s0", ARPHRD_ETHER)
+            },
+            Candidate {
+                arp_type: None,
+                ..candidate("usb0", 0)
+            },
+        ]
+    }
+
+    #[test]
+    fn filter_keeps_ethernet_links_in_order() {
+        let cfg = Config::default();
+        assert_eq!(filter(links(), &cfg), ["eth0", "eth1", "usb0", "wlan0"]);
+        // Loopback stays out whatever else is allowed.
+        let cfg = Config {
+            ether_only: false,
+            skip_bridge_ports: false,
+            ..cfg
+        };
+        assert_eq!(
+            filter(links(), &cfg),
+            ["eth0", "eth1", "ppp0", "usb0", "veth0", "wlan0"]
+        );
+    }
+
+    #[test]
+    fn filter_applies_the_globs() {
+        let cfg = Config {
+            include: vec!["eth*".into(), "wlan?".into()],
+            exclude: vec!["eth1".into()],
+            ..Config::default()
+        };
+        assert_eq!(filter(links(), &cfg), ["eth0", "wlan0"]);
+        // No glob lets a bond member in.
+        let cfg = Config {
+            include: vec!["e*".into()],
+            ..Config::default()
+        };
+        assert_eq!(filter(links(), &cfg), ["eth0", "eth1"]);
+        let cfg = Config {
+            exclude: vec!["w*".into(), "usb0".into()],
+            ..Config::default()
+        };
+        assert_eq!(filter(links(), &cfg), ["eth0", "eth1"]);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn bring_up_only_looks_off_linux() {
+        let ex = MockExecutor::new(true);
+        let r = up(&cfg(), MISSING, Dhcp::Always, &ex);
+        assert_eq!(ex.calls(), Vec::<String>::new());
+        assert_eq!((r.link_up, r.dhcp), (false, None));
+    }
+}
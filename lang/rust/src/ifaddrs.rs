@@ -0,0 +1,798 @@
+// Links, their addresses and counters, and the default routes, from the
+// portable interfaces: getifaddrs(3) and the routing sysctl on macOS,
+// GetAdaptersAddresses on Windows. What net reads from sysfs and procfs
+// on Linux comes from here elsewhere.
+use crate::iface::IfaceKind;
+use crate::net::{Duplex, IfaceStats, OperState, Route};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// One link as the system lists it.
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub name: String,
+    pub index: u32,
+    /// Set administratively up.
+    pub admin_up: bool,
+    pub oper: OperState,
+    pub loopback: bool,
+    pub mac: Option<String>,
+    /// Addresses with their prefix length.
+    pub addrs: Vec<(IpAddr, u8)>,
+    pub stats: Option<IfaceStats>,
+    /// Mbps.
+    pub speed: Option<u32>,
+    /// macOS reports Wi-Fi as Ethernet, so it comes out wired there.
+    pub kind: IfaceKind,
+    /// Default gateways; Windows lists them with the adapter.
+    pub gateways: Vec<IpAddr>,
+    /// DNS servers; Windows lists them with the adapter.
+    pub dns: Vec<IpAddr>,
+}
+
+impl Link {
+    fn new(name: String) -> Link {
+        Link {
+            name,
+            index: 0,
+            admin_up: false,
+            oper: OperState::Unknown,
+            loopback: false,
+            mac: None,
+            addrs: Vec::new(),
+            stats: None,
+            speed: None,
+            kind: IfaceKind::Virtual,
+            gateways: Vec::new(),
+            dns: Vec::new(),
+        }
+    }
+}
+
+/// `aa:bb:cc:dd:ee:ff`; `None` for no address or an all-zero one.
+fn mac(b: &[u8]) -> Option<String> {
+    (b.len() == 6 && b.iter().any(|&x| x != 0)).then(|| {
+        b.iter()
+            .map(|x| format!("{:02x}", x))
+            .collect::<Vec<_>>()
+            .join(":")
+    })
+}
+
+/// Length of the leading ones of a netmask.
+#[cfg(unix)]
+fn prefix(mask: &[u8]) -> u8 {
+    let mut n = 0;
+    for b in mask {
+        n += b.leading_ones();
+        if *b != 0xff {
+            break;
+        }
+    }
+    n as u8
+}
+
+/// A VPN or PPP link by name, as macOS and BSDs name them.
+fn vpn_name(name: &str) -> bool {
+    ["utun", "tun", "tap", "wg", "ppp", "ipsec"]
+        .iter()
+        .any(|p| name.starts_with(p))
+}
+
+#[cfg(unix)]
+mod unix {
+    #[cfg(target_os = "macos")]
+    use std::os::raw::c_void;
+    use std::os::raw::{c_char, c_int, c_uint};
+
+    pub const IFF_UP: c_uint = 0x1;
+    pub const IFF_LOOPBACK: c_uint = 0x8;
+    pub const IFF_RUNNING: c_uint = 0x40;
+    pub const AF_INET: u16 = 2;
+    #[cfg(target_os = "linux")]
+    pub const AF_INET6: u16 = 10;
+    #[cfg(target_os = "macos")]
+    pub const AF_INET6: u16 = 30;
+    #[cfg(target_os = "linux")]
+    pub const AF_PACKET: u16 = 17;
+    #[cfg(target_os = "macos")]
+    pub const AF_LINK: u16 = 18;
+
+    #[repr(C)]
+    pub struct IfAddrs {
+        pub next: *mut IfAddrs,
+        pub name: *const c_char,
+        pub flags: c_uint,
+        pub addr: *const u8,
+        pub netmask: *const u8,
+        pub dstaddr: *const u8,
+        pub data: *const u8,
+    }
+
+    extern "C" {
+        pub fn getifaddrs(ifap: *mut *mut IfAddrs) -> c_int;
+        pub fn freeifaddrs(ifa: *mut IfAddrs);
+        pub fn if_nametoindex(name: *const c_char) -> c_uint;
+        #[cfg(target_os = "macos")]
+        pub fn if_indextoname(index: c_uint, name: *mut c_char) -> *mut c_char;
+        #[cfg(target_os = "macos")]
+        pub fn sysctl(
+            name: *const c_int,
+            namelen: c_uint,
+            old: *mut c_void,
+            oldlen: *mut usize,
+            new: *const c_void,
+            newlen: usize,
+        ) -> c_int;
+    }
+
+    /// The family of the sockaddr at `p`, and its bytes. BSD ones start with
+    /// their length; Linux ones are as long as their family makes them.
+    ///
+    /// # Safety
+    /// `p` is null or points at a whole sockaddr.
+    pub unsafe fn sockaddr<'a>(p: *const u8) -> Option<(u16, &'a [u8])> {
+        if p.is_null() {
+            return None;
+        }
+        #[cfg(target_os = "macos")]
+        {
+            let len = (*p as usize).max(2);
+            Some((u16::from(*p.add(1)), std::slice::from_raw_parts(p, len)))
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let family = u16::from_ne_bytes([*p, *p.add(1)]);
+            let len = match family {
+                AF_INET => 16,
+                AF_INET6 => 28,
+                AF_PACKET => 20,
+                _ => 2,
+            };
+            Some((family, std::slice::from_raw_parts(p, len)))
+        }
+    }
+}
+
+/// Every link, with what getifaddrs(3) says of it.
+#[cfg(unix)]
+pub fn links() -> io::Result<Vec<Link>> {
+    use std::ffi::CStr;
+    use unix::*;
+
+    let mut head = std::ptr::null_mut();
+    if unsafe { getifaddrs(&mut head) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut v: Vec<Link> = Vec::new();
+    let mut p = head;
+    while let Some(a) = unsafe { p.as_ref() } {
+        p = a.next;
+        let name = unsafe { CStr::from_ptr(a.name) };
+        let l = match v.iter().position(|l| l.name.as_bytes() == name.to_bytes()) {
+            Some(i) => &mut v[i],
+            None => {
+                let mut l = Link::new(name.to_string_lossy().into_owned());
+                l.index = unsafe { if_nametoindex(a.name) };
+                l.admin_up = a.flags & IFF_UP != 0;
+                l.loopback = a.flags & IFF_LOOPBACK != 0;
+                l.oper = match (l.admin_up, a.flags & IFF_RUNNING != 0) {
+                    (true, true) => OperState::Up,
+                    _ => OperState::Down,
+                };
+                if vpn_name(&l.name) {
+                    l.kind = IfaceKind::Vpn;
+                }
+                v.push(l);
+                v.last_mut().expect("just pushed")
+            }
+        };
+        let Some((family, sa)) = (unsafe { sockaddr(a.addr) }) else {
+            continue;
+        };
+        let mask = unsafe { sockaddr(a.netmask) }.map_or(&[][..], |m| m.1);
+        // macOS cuts netmasks short after their last non-zero byte.
+        let bits = |from: usize, to: usize| prefix(&mask[from.min(mask.len())..to.min(mask.len())]);
+        match family {
+            AF_INET if sa.len() >= 8 => {
+                let ip = Ipv4Addr::new(sa[4], sa[5], sa[6], sa[7]);
+                l.addrs.push((ip.into(), bits(4, 8)));
+            }
+            AF_INET6 if sa.len() >= 24 => {
+                let o: [u8; 16] = sa[8..24].try_into().expect("16 bytes");
+                l.addrs.push((IpAddr::from(o), bits(8, 24)));
+            }
+            // sockaddr_ll: index, type, length and the address; the data
+            // is rtnl_link_stats, in 32-bit counters.
+            #[cfg(target_os = "linux")]
+            AF_PACKET => {
+                l.mac = mac(&sa[12..12 + usize::from(sa[11]).min(8)]);
+                if u16::from_ne_bytes([sa[8], sa[9]]) == 1 && l.kind != IfaceKind::Vpn {
+                    l.kind = IfaceKind::Wired;
+                }
+                if !a.data.is_null() {
+                    let c = |i: usize| unsafe { u64::from(*a.data.cast::<u32>().add(i)) };
+                    l.stats = Some(IfaceStats {
+                        rx_packets: c(0),
+                        tx_packets: c(1),
+                        rx_bytes: c(2),
+                        tx_bytes: c(3),
+                        rx_errors: c(4),
+                        tx_errors: c(5),
+                    });
+                }
+            }
+            // sockaddr_dl: the name, then the address; the data is
+            // if_data, the type first and 32-bit counters from 20 on.
+            #[cfg(target_os = "macos")]
+            AF_LINK if sa.len() >= 8 => {
+                let (nlen, alen) = (usize::from(sa[5]), usize::from(sa[6]));
+                l.mac = sa.get(8 + nlen..8 + nlen + alen).and_then(mac);
+                if !a.data.is_null() {
+                    let d = unsafe { std::slice::from_raw_parts(a.data, 48) };
+                    let c = |at: usize| {
+                        u64::from(u32::from_ne_bytes(
+                            d[at..at + 4].try_into().expect("4 bytes"),
+                        ))
+                    };
+                    const IFT_ETHER: u8 = 6;
+                    const IFT_IEEE80211: u8 = 71;
+                    l.kind = match d[0] {
+                        _ if l.kind == IfaceKind::Vpn => IfaceKind::Vpn,
+                        IFT_ETHER if !l.name.starts_with("bridge") => IfaceKind::Wired,
+                        IFT_IEEE80211 => IfaceKind::Wireless,
+                        _ => IfaceKind::Virtual,
+                    };
+                    l.speed = Some(c(16) / 1_000_000).filter(|&s| s > 0).map(|s| s as u32);
+                    l.stats = Some(IfaceStats {
+                        rx_packets: c(20),
+                        rx_errors: c(24),
+                        tx_packets: c(28),
+                        tx_errors: c(32),
+                        rx_bytes: c(40),
+                        tx_bytes: c(44),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    unsafe { freeifaddrs(head) };
+    Ok(v)
+}
+
+/// The default routes of `family` in a `NET_RT_FLAGS` dump `b`: per route
+/// message, the index of the link it leaves through and the gateway.
+#[cfg(any(target_os = "macos", test))]
+fn parse_routes(b: &[u8], family: u8) -> Vec<(u32, IpAddr)> {
+    // rt_msghdr: length, version, type, index at 4, flags at 8, which
+    // sockaddrs follow at 12; the sockaddrs start at 92.
+    const HDR: usize = 92;
+    const RTF_UP: u32 = 0x1;
+    let mut v = Vec::new();
+    let mut at = 0;
+    while at + HDR <= b.len() {
+        let len = usize::from(u16::from_ne_bytes([b[at], b[at + 1]]));
+        if len < HDR || at + len > b.len() {
+            break;
+        }
+        let m = &b[at..at + len];
+        at += len;
+        let word = |i: usize| u32::from_ne_bytes(m[i..i + 4].try_into().expect("4 bytes"));
+        if word(8) & RTF_UP == 0 {
+            continue;
+        }
+        let (mut rest, mut sas) = (&m[HDR..], [&[][..]; 3]);
+        for (bit, sa) in sas.iter_mut().enumerate() {
+            if word(12) & (1 << bit) == 0 {
+                continue;
+            }
+            // Each is padded to 4 bytes; an empty one still takes 4.
+            let l = usize::from(*rest.first().unwrap_or(&0));
+            let step = if l == 0 { 4 } else { (l + 3) & !3 };
+            *sa = &rest[..l.min(rest.len())];
+            rest = &rest[step.min(rest.len())..];
+        }
+        let [dst, gw, mask] = sas;
+        let zero = |s: &[u8]| s.iter().skip(2).all(|&x| x == 0);
+        if dst.get(1) != Some(&family) || !zero(dst) || !zero(mask) || gw.get(1) != Some(&family) {
+            continue;
+        }
+        let ip = match gw.len() {
+            16.. if family == 2 => IpAddr::from(<[u8; 4]>::try_from(&gw[4..8]).expect("4 bytes")),
+            24.. if family != 2 => {
+                IpAddr::from(<[u8; 16]>::try_from(&gw[8..24]).expect("16 bytes"))
+            }
+            _ => continue,
+        };
+        v.push((u32::from(u16::from_ne_bytes([m[4], m[5]])), ip));
+    }
+    v
+}
+
+/// Default routes through the routing sysctl, v4 or `v6`.
+#[cfg(target_os = "macos")]
+fn route_dump(v6: bool) -> Vec<(String, IpAddr)> {
+    use std::os::raw::c_int;
+    use unix::*;
+
+    let family = if v6 { AF_INET6 } else { AF_INET };
+    // CTL_NET, PF_ROUTE, 0, family, NET_RT_FLAGS, RTF_GATEWAY
+    let mib = [4, 17, 0, c_int::from(family), 2, 2];
+    let mut len = 0;
+    let null = std::ptr::null_mut();
+    if unsafe { sysctl(mib.as_ptr(), 6, null, &mut len, std::ptr::null(), 0) } < 0 {
+        return Vec::new();
+    }
+    let mut b = vec![0u8; len];
+    if unsafe {
+        sysctl(
+            mib.as_ptr(),
+            6,
+            b.as_mut_ptr().cast(),
+            &mut len,
+            std::ptr::null(),
+            0,
+        )
+    } < 0
+    {
+        return Vec::new();
+    }
+    b.truncate(len);
+    parse_routes(&b, family as u8)
+        .into_iter()
+        .filter_map(|(i, gw)| {
+            let mut n = [0 as std::os::raw::c_char; 16];
+            let p = unsafe { if_indextoname(i, n.as_mut_ptr()) };
+            (!p.is_null())
+                .then(|| unsafe { std::ffi::CStr::from_ptr(p) })
+                .map(|n| (n.to_string_lossy().into_owned(), gw))
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+mod win {
+    use std::os::raw::{c_char, c_int, c_void};
+
+    pub const AF_UNSPEC: u32 = 0;
+    pub const AF_INET: u16 = 2;
+    pub const AF_INET6: u16 = 23;
+    pub const GAA_FLAG_SKIP_ANYCAST: u32 = 0x2;
+    pub const GAA_FLAG_SKIP_MULTICAST: u32 = 0x4;
+    pub const GAA_FLAG_INCLUDE_GATEWAYS: u32 = 0x80;
+    pub const ERROR_BUFFER_OVERFLOW: u32 = 111;
+    pub const IF_TYPE_ETHERNET_CSMACD: u32 = 6;
+    pub const IF_TYPE_PPP: u32 = 23;
+    pub const IF_TYPE_SOFTWARE_LOOPBACK: u32 = 24;
+    pub const IF_TYPE_IEEE80211: u32 = 71;
+    pub const IF_TYPE_TUNNEL: u32 = 131;
+    /// `MIB_IF_ROW2`, read by offset.
+    pub const ROW2_SIZE: usize = 1352;
+
+    #[repr(C)]
+    pub struct SocketAddress {
+        pub sockaddr: *const u8,
+        pub len: c_int,
+    }
+
+    /// `IP_ADAPTER_UNICAST_ADDRESS_LH`, up to the prefix length.
+    #[repr(C)]
+    pub struct Unicast {
+        pub length: u32,
+        pub flags: u32,
+        pub next: *const Unicast,
+        pub address: SocketAddress,
+        pub prefix_origin: c_int,
+        pub suffix_origin: c_int,
+        pub dad_state: c_int,
+        pub valid_lifetime: u32,
+        pub preferred_lifetime: u32,
+        pub lease_lifetime: u32,
+        pub prefix_len: u8,
+    }
+
+    /// `IP_ADAPTER_DNS_SERVER_ADDRESS` and `IP_ADAPTER_GATEWAY_ADDRESS_LH`.
+    #[repr(C)]
+    pub struct Server {
+        pub length: u32,
+        pub reserved: u32,
+        pub next: *const Server,
+        pub address: SocketAddress,
+    }
+
+    /// `IP_ADAPTER_ADDRESSES_LH`, up to the gateways.
+    #[repr(C)]
+    pub struct Adapter {
+        pub length: u32,
+        pub if_index: u32,
+        pub next: *const Adapter,
+        pub adapter_name: *const c_char,
+        pub unicast: *const Unicast,
+        pub anycast: *const c_void,
+        pub multicast: *const c_void,
+        pub dns: *const Server,
+        pub dns_suffix: *const u16,
+        pub description: *const u16,
+        pub friendly_name: *const u16,
+        pub physical: [u8; 8],
+        pub physical_len: u32,
+        pub flags: u32,
+        pub mtu: u32,
+        pub if_type: u32,
+        pub oper_status: c_int,
+        pub ipv6_if_index: u32,
+        pub zone_indices: [u32; 16],
+        pub prefix: *const c_void,
+        pub transmit_speed: u64,
+        pub receive_speed: u64,
+        pub wins: *const c_void,
+        pub gateways: *const Server,
+    }
+
+    #[link(name = "iphlpapi")]
+    extern "system" {
+        pub fn GetAdaptersAddresses(
+            family: u32,
+            flags: u32,
+            reserved: *mut c_void,
+            addrs: *mut Adapter,
+            size: *mut u32,
+        ) -> u32;
+        pub fn GetIfEntry2(row: *mut u8) -> u32;
+    }
+
+    /// The address a `SOCKET_ADDRESS` points at.
+    ///
+    /// # Safety
+    /// `s.sockaddr` is null or points at `s.len` bytes.
+    pub unsafe fn ip(s: &SocketAddress) -> Option<std::net::IpAddr> {
+        if s.sockaddr.is_null() {
+            return None;
+        }
+        let b = std::slice::from_raw_parts(s.sockaddr, s.len.max(0) as usize);
+        match u16::from_ne_bytes([*b.first()?, *b.get(1)?]) {
+            AF_INET if b.len() >= 8 => Some(<[u8; 4]>::try_from(&b[4..8]).ok()?.into()),
+            AF_INET6 if b.len() >= 24 => Some(<[u8; 16]>::try_from(&b[8..24]).ok()?.into()),
+            _ => None,
+        }
+    }
+
+    /// The servers of a `next`-linked list.
+    ///
+    /// # Safety
+    /// `p` is null or the head of a list GetAdaptersAddresses filled in.
+    pub unsafe fn servers(mut p: *const Server) -> Vec<std::net::IpAddr> {
+        let mut v = Vec::new();
+        while let Some(s) = p.as_ref() {
+            v.extend(ip(&s.address));
+            p = s.next;
+        }
+        v
+    }
+}
+
+/// Every adapter, with what GetAdaptersAddresses and GetIfEntry2 say of
+/// it; the friendly name ("Ethernet", "Wi-Fi") is the link's.
+#[cfg(windows)]
+pub fn links() -> io::Result<Vec<Link>> {
+    use win::*;
+
+    let flags = GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_INCLUDE_GATEWAYS;
+    let mut size: u32 = 16 * 1024;
+    let mut buf: Vec<u64>;
+    loop {
+        buf = vec![0; size as usize / 8 + 1];
+        let r = unsafe {
+            GetAdaptersAddresses(
+                AF_UNSPEC,
+                flags,
+                std::ptr::null_mut(),
+                buf.as_mut_ptr().cast(),
+                &mut size,
+            )
+        };
+        match r {
+            0 => break,
+            ERROR_BUFFER_OVERFLOW => continue,
+            e => return Err(io::Error::from_raw_os_error(e as i32)),
+        }
+    }
+    let mut v = Vec::new();
+    let mut p: *const Adapter = buf.as_ptr().cast();
+    while let Some(a) = unsafe { p.as_ref() } {
+        p = a.next;
+        let name = unsafe { wide(a.friendly_name) };
+        let mut l = Link::new(name);
+        l.index = if a.if_index != 0 {
+            a.if_index
+        } else {
+            a.ipv6_if_index
+        };
+        l.loopback = a.if_type == IF_TYPE_SOFTWARE_LOOPBACK;
+        // IfOperStatus is RFC 2863's, 1 to 7.
+        l.oper = match a.oper_status {
+            1 => OperState::Up,
+            2 => OperState::Down,
+            3 => OperState::Testing,
+            5 => OperState::Dormant,
+            6 => OperState::NotPresent,
+            7 => OperState::LowerLayerDown,
+            _ => OperState::Unknown,
+        };
+        l.admin_up = l.oper != OperState::Down;
+        l.mac = mac(&a.physical[..(a.physical_len as usize).min(8)]);
+        l.kind = match a.if_type {
+            IF_TYPE_IEEE80211 => IfaceKind::Wireless,
+            IF_TYPE_TUNNEL | IF_TYPE_PPP => IfaceKind::Vpn,
+            _ if vpn_name(&l.name.to_ascii_lowercase()) => IfaceKind::Vpn,
+            IF_TYPE_ETHERNET_CSMACD => IfaceKind::Wired,
+            _ => IfaceKind::Virtual,
+        };
+        l.speed = Some(a.transmit_speed)
+            .filter(|&s| s != u64::MAX && s > 0)
+            .map(|s| (s / 1_000_000) as u32);
+        let mut u = a.unicast;
+        while let Some(x) = unsafe { u.as_ref() } {
+            if let Some(ip) = unsafe { ip(&x.address) } {
+                l.addrs.push((ip, x.prefix_len));
+            }
+            u = x.next;
+        }
+        l.gateways = unsafe { servers(a.gateways) };
+        l.dns = unsafe { servers(a.dns) };
+        if let Some(row) = if_row(l.index) {
+            let at = |i: usize| u64::from_ne_bytes(row[i..i + 8].try_into().expect("8 bytes"));
+            l.admin_up = u32::from_ne_bytes(row[1160..1164].try_into().expect("4 bytes")) == 1;
+            l.stats = Some(IfaceStats {
+                rx_bytes: at(1208),
+                rx_packets: at(1216) + at(1224),
+                rx_errors: at(1240),
+                tx_bytes: at(1280),
+                tx_packets: at(1288) + at(1296),
+                tx_errors: at(1312),
+            });
+        }
+        v.push(l);
+    }
+    Ok(v)
+}
+
+/// `MIB_IF_ROW2` of the link with `index`, as bytes.
+#[cfg(windows)]
+fn if_row(index: u32) -> Option<Vec<u8>> {
+    let mut row = vec![0u64; win::ROW2_SIZE / 8];
+    let b: *mut u8 = row.as_mut_ptr().cast();
+    // The index, with the LUID before it left 0.
+    unsafe { b.add(8).cast::<u32>().write(index) };
+    if unsafe { win::GetIfEntry2(b) } != 0 {
+        return None;
+    }
+    Some(row.iter().flat_map(|w| w.to_ne_bytes()).collect())
+}
+
+/// A NUL-terminated UTF-16 string.
+///
+/// # Safety
+/// `p` is null or points at one.
+#[cfg(windows)]
+unsafe fn wide(p: *const u16) -> String {
+    if p.is_null() {
+        return String::new();
+    }
+    let n = (0..).take_while(|&i| *p.add(i) != 0).count();
+    String::from_utf16_lossy(std::slice::from_raw_parts(p, n))
+}
+
+fn link(iface: &str) -> Option<Link> {
+    links().ok()?.into_iter().find(|l| l.name == iface)
+}
+
+/// Whether a link of that name exists now.
+pub fn exists(iface: &str) -> bool {
+    index(iface).is_some()
+}
+
+pub fn index(iface: &str) -> Option<u32> {
+    link(iface).map(|l| l.index)
+}
+
+pub fn ipv4_addr(iface: &str) -> Option<Ipv4Addr> {
+    link(iface)?.addrs.into_iter().find_map(|(a, _)| match a {
+        IpAddr::V4(a) => Some(a),
+        IpAddr::V6(_) => None,
+    })
+}
+
+pub fn mac_addr(iface: &str) -> Option<String> {
+    link(iface)?.mac
+}
+
+pub fn read_link_speed(iface: &str) -> Option<u32> {
+    link(iface)?.speed
+}
+
+/// Not reported here.
+pub fn read_duplex(_iface: &str) -> Option<Duplex> {
+    None
+}
+
+pub fn admin_up(iface: &str) -> bool {
+    link(iface).is_some_and(|l| l.admin_up)
+}
+
+/// `NotPresent` when the link is gone.
+pub fn read_oper_state(iface: &str) -> OperState {
+    link(iface).map_or(OperState::NotPresent, |l| l.oper)
+}
+
+pub fn carrier(iface: &str) -> Option<bool> {
+    match read_oper_state(iface) {
+        OperState::Up => Some(true),
+        OperState::Down | OperState::LowerLayerDown | OperState::NotPresent => Some(false),
+        _ => None,
+    }
+}
+
+/// Not counted here.
+pub fn carrier_changes(_iface: &str) -> Option<u64> {
+    None
+}
+
+/// `None` when the interface is gone.
+pub fn read_iface_stats(iface: &str) -> Option<IfaceStats> {
+    link(iface)?.stats
+}
+
+/// Default gateways of `v6` or IPv4, with the link each leaves through.
+fn gateways(v6: bool) -> Vec<(String, IpAddr)> {
+    #[cfg(target_os = "macos")]
+    return route_dump(v6);
+    #[cfg(not(target_os = "macos"))]
+    links()
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|l| {
+            let name = l.name;
+            l.gateways.into_iter().map(move |g| (name.clone(), g))
+        })
+        .filter(|(_, g)| g.is_ipv6() == v6)
+        .collect()
+}
+
+/// IPv4 default routes.
+pub fn default_routes() -> Vec<Route> {
+    gateways(false)
+        .into_iter()
+        .filter_map(|(iface, g)| match g {
+            IpAddr::V4(gateway) => Some(Route { iface, gateway }),
+            IpAddr::V6(_) => None,
+        })
+        .collect()
+}
+
+pub fn has_default_v6() -> bool {
+    !gateways(true).is_empty()
+}
+
+/// Whether an address of `iface` has `gw` in its subnet.
+pub fn on_link(iface: &str, gw: Ipv4Addr) -> bool {
+    link(iface).is_some_and(|l| {
+        l.addrs.iter().any(|&(a, p)| {
+            let net = crate::net::Cidr {
+                addr: a,
+                prefix_len: p,
+            };
+            a.is_ipv4() && p > 0 && net.contains(IpAddr::V4(gw))
+        })
+    })
+}
+
+/// There is no neighbour table to read here.
+pub fn arp_resolved(_ip: Ipv4Addr, _iface: &str) -> bool {
+    false
+}
+
+/// DNS servers of the adapters that are up, in order.
+#[cfg(windows)]
+pub fn dns_servers() -> Vec<IpAddr> {
+    let mut v: Vec<IpAddr> = Vec::new();
+    for l in links().unwrap_or_default() {
+        if l.oper == OperState::Up {
+            for d in l.dns {
+                if !v.contains(&d) {
+                    v.push(d);
+                }
+            }
+        }
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_is_listed_with_its_address() {
+        let l = links().unwrap();
+        let lo = l.iter().find(|l| l.loopback).expect("a loopback link");
+        assert!(lo.index > 0);
+        assert!(lo.addrs.contains(&(IpAddr::V4(Ipv4Addr::LOCALHOST), 8)));
+        assert_eq!(index(&lo.name), Some(lo.index));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn prefix_counts_leading_ones() {
+        assert_eq!(prefix(&[255, 255, 255, 0]), 24);
+        assert_eq!(prefix(&[255, 255, 240, 0]), 20);
+        assert_eq!(prefix(&[255, 0, 255, 0]), 8);
+        assert_eq!(prefix(&[]), 0);
+    }
+
+    /// A route message as the routing sysctl lays it out.
+    fn rt_msg(index: u16, flags: u32, sas: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut m = vec![0u8; 92];
+        m[4..6].copy_from_slice(&index.to_ne_bytes());
+        m[8..12].copy_from_slice(&flags.to_ne_bytes());
+        let addrs = sas.iter().fold(0, |a, (bit, _)| a | bit);
+        m[12..16].copy_from_slice(&addrs.to_ne_bytes());
+        for (_, sa) in sas {
+            m.extend_from_slice(sa);
+            m.resize(
+                m.len() + (4 - sa.len() % 4) % 4 + if sa.is_empty() { 4 } else { 0 },
+                0,
+            );
+        }
+        let len = m.len() as u16;
+        m[0..2].copy_from_slice(&len.to_ne_bytes());
+        m
+    }
+
+    fn sin(a: [u8; 4]) -> Vec<u8> {
+        let mut b = vec![16, 2, 0, 0];
+        b.extend_from_slice(&a);
+        b.resize(16, 0);
+        b
+    }
+
+    #[test]
+    fn default_routes_are_read_from_the_dump() {
+        let mut dump = rt_msg(
+            4,
+            0x3,
+            &[(1, sin([0; 4])), (2, sin([192, 0, 2, 1])), (4, vec![])],
+        );
+        // A host route, and a default route that is not up.
+        dump.extend(rt_msg(
+            5,
+            0x3,
+            &[(1, sin([198, 51, 100, 7])), (2, sin([192, 0, 2, 9]))],
+        ));
+        dump.extend(rt_msg(
+            6,
+            0x2,
+            &[(1, sin([0; 4])), (2, sin([192, 0, 2, 5])), (4, vec![])],
+        ));
+        // A truncated netmask of one byte, all the BSD kernel keeps of 0.
+        dump.extend(rt_msg(
+            7,
+            0x3,
+            &[
+                (1, sin([0; 4])),
+                (2, sin([203, 0, 113, 1])),
+                (4, vec![5, 0, 0, 0, 0]),
+            ],
+        ));
+        assert_eq!(
+            parse_routes(&dump, 2),
+            vec![
+                (4, IpAddr::from([192, 0, 2, 1])),
+                (7, IpAddr::from([203, 0, 113, 1]))
+            ]
+        );
+        assert!(parse_routes(&dump, 30).is_empty());
+        assert!(parse_routes(&dump[..100], 2).is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The last `cap` echo outcomes, as round-trip times in microseconds
+/// (`None` for a lost echo).
+pub struct LatencyWindow {
+    cap: usize,
+    samples: VecDeque<Option<u32>>,
+}
+
+impl LatencyWindow {
+    pub fn new(cap: usize) -> LatencyWindow {
+        LatencyWindow {
+            cap: cap.max(1),
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, rtt: Option<Duration>) {
+        if self.samples.len() == self.cap {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back(rtt.map(|d| d.as_micros().min(u32::MAX as u128) as u32));
+    }
+
+    fn replies(&self) -> Vec<u32> {
+        let mut v: Vec<u32> = self.samples.iter().flatten().copied().collect();
+        v.sort_unstable();
+        v
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let v = self.replies();
+        let sum: u64 = v.iter().map(|&x| x as u64).sum();
+        (!v.is_empty()).then(|| Duration::from_micros(sum / v.len() as u64))
+    }
+
+    pub fn p95(&self) -> Option<Duration> {
+        let v = self.replies();
+        let i = (v.len() * 95).div_ceil(100).saturating_sub(1);
+        v.get(i).map(|&x| Duration::from_micros(x as u64))
+    }
+
+    /// Percentage of the most recent `last` echoes that got no reply.
+    pub fn loss(&self, last: usize) -> f64 {
+        let n = self.samples.len().min(last);
+        if n == 0 {
+            return 0.0;
+        }
+        let lost = self
+            .samples
+            .iter()
+            .rev()
+            .take(n)
+            .filter(|s| s.is_none())
+            .count();
+        lost as f64 * 100.0 / n as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_keeps_the_last_cap_echoes() {
+        let ms = |n: u64| Some(Duration::from_millis(n));
+        let mut w = LatencyWindow::new(4);
+        assert_eq!((w.mean(), w.p95(), w.loss(4)), (None, None, 0.0));
+        for rtt in [ms(100), None, ms(1), ms(3), ms(2)] {
+            w.push(rtt);
+        }
+        // The 100 ms echo dropped out.
+        assert_eq!(w.mean(), ms(2));
+        assert_eq!(w.p95(), ms(3));
+        assert_eq!(w.loss(4), 25.0);
+        assert_eq!(w.loss(3), 0.0);
+        let mut w = LatencyWindow::new(21);
+        w.push(ms(7));
+        assert_eq!(w.p95(), ms(7));
+        for n in 1..=20 {
+            w.push(ms(n));
+        }
+        // 21 samples: the 20th smallest.
+        assert_eq!(w.p95(), ms(19));
+        w.push(ms(21));
+        // The 7 ms echo dropped out; of 1..=21, the 20th.
+        assert_eq!(w.p95(), ms(20));
+    }
+}
@@ -0,0 +1,271 @@
+// What dhclient wrote down about its leases: mask, resolvers and, above
+// all, when they run out. Other clients (udhcpc, dhcpcd) keep no such file,
+// so the address's kernel lifetime stands in for it.
+use crate::clock;
+#[cfg(target_os = "linux")]
+use crate::{history, net, netlink};
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+/// Where dhclient keeps leases on Debian-like and Red Hat-like systems,
+/// per interface first; `{}` is the interface.
+const FILES: [&str; 6] = [
+    "/var/lib/dhcp/dhclient.{}.leases",
+    "/var/lib/dhclient/dhclient-{}.leases",
+    "/var/lib/dhcp/dhclient-{}.leases",
+    "/var/lib/dhclient/dhclient.{}.leases",
+    "/var/lib/dhcp/dhclient.leases",
+    "/var/lib/dhclient/dhclient.leases",
+];
+
+/// Where a [`Lease`] was read from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LeaseSource {
+    #[default]
+    Dhclient,
+    /// The address's valid lifetime, as the DHCP client set it.
+    Kernel,
+}
+
+impl LeaseSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LeaseSource::Dhclient => "dhclient",
+            LeaseSource::Kernel => "kernel",
+        }
+    }
+}
+
+/// One `lease { ... }` block.
+#[derive(Clone, Default)]
+pub struct Lease {
+    pub source: LeaseSource,
+    pub iface: String,
+    pub address: Option<Ipv4Addr>,
+    pub mask: Option<Ipv4Addr>,
+    /// The first of `option routers`.
+    pub gateway: Option<Ipv4Addr>,
+    pub dns: Vec<Ipv4Addr>,
+    /// `option dhcp-lease-time`, seconds.
+    pub lease_time: Option<u64>,
+    /// Unix seconds; `None` for `never` or when missing.
+    pub renew: Option<u64>,
+    pub expire: Option<u64>,
+}
+
+impl Lease {
+    pub fn prefix_len(&self) -> Option<u32> {
+        self.mask.map(|m| u32::from(m).count_ones())
+    }
+
+    /// When less than `remaining` (a fraction) of the lease is left, in
+    /// unix seconds: a renewal is due then.
+    pub fn renew_at(&self, remaining: f64) -> Option<u64> {
+        let left = (self.lease_time? as f64 * remaining) as u64;
+        Some(self.expire?.saturating_sub(left))
+    }
+
+    /// Seconds until the lease runs out, 0 once it has.
+    pub fn remaining(&self, now: u64) -> Option<u64> {
+        Some(self.expire?.saturating_sub(now))
+    }
+}
+
+/// `2 2024/01/31 12:00:00` (weekday, UTC) or `epoch 1706702400`, as dhclient
+/// writes with `db-time-format local`.
+fn when(v: &str) -> Option<u64> {
+    let f: Vec<&str> = v.split_whitespace().collect();
+    if let ["epoch", secs, ..] = f[..] {
+        return secs.parse().ok();
+    }
+    let [_, date, time, ..] = f[..] else {
+        return None;
+    };
+    let num =
+        |s: &str, sep: char| -> Option<Vec<i64>> { s.split(sep).map(|n| n.parse().ok()).collect() };
+    let (d, t) = (num(date, '/')?, num(time, ':')?);
+    let (&[y, mo, day], &[h, mi, s]) = (&d[..], &t[..]) else {
+        return None;
+    };
+    u64::try_from(clock::unix(y, mo, day, h, mi, s)).ok()
+}
+
+fn addrs(v: &str) -> Vec<Ipv4Addr> {
+    v.split(',').filter_map(|a| a.trim().parse().ok()).collect()
+}
+
+/// Every IPv4 lease in a dhclient lease file, oldest first as dhclient
+/// appends them; `lease6` blocks are skipped.
+pub fn parse_leases_file(path: &Path) -> io::Result<Vec<Lease>> {
+    Ok(parse_leases(&fs::read_to_string(path)?))
+}
+
+fn parse_leases(s: &str) -> Vec<Lease> {
+    let mut out = Vec::new();
+    let mut cur: Option<Lease> = None;
+    for line in s.lines() {
+        let line = line.trim();
+        if line == "lease {" {
+            cur = Some(Lease::default());
+            continue;
+        }
+        let Some(l) = cur.as_mut() else {
+            continue;
+        };
+        if line == "}" {
+            out.extend(cur.take());
+            continue;
+        }
+        // `db-time-format local` follows the `;` with the date as a comment.
+        let line = line.rsplit_once(';').map_or(line, |(l, _)| l);
+        let (key, v) = line.split_once(' ').unwrap_or((line, ""));
+        let (key, v) = match key {
+            "option" => v.split_once(' ').unwrap_or((v, "")),
+            _ => (key, v),
+        };
+        let v = v.trim().trim_matches('"');
+        match key {
+            "interface" => l.iface = v.into(),
+            "fixed-address" => l.address = v.parse().ok(),
+            "subnet-mask" => l.mask = v.parse().ok(),
+            "routers" => l.gateway = addrs(v).first().copied(),
+            "domain-name-servers" => l.dns = addrs(v),
+            "dhcp-lease-time" => l.lease_time = v.parse().ok(),
+            "renew" => l.renew = when(v),
+            "expire" => l.expire = when(v),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// The last of `leases` for `iface`: the newest, as dhclient appends.
+fn newest(leases: Vec<Lease>, iface: &str) -> Option<Lease> {
+    leases.into_iter().rev().find(|l| l.iface == iface)
+}
+
+/// The newest lease dhclient recorded for `iface`, from the first lease
+/// file that has one.
+pub fn latest(iface: &str) -> Option<Lease> {
+    FILES.iter().find_map(|f| {
+        let path = f.replace("{}", iface);
+        newest(parse_leases_file(Path::new(&path)).ok()?, iface)
+    })
+}
+
+/// Seconds since boot, from `/proc/uptime`.
+pub(crate) fn uptime() -> Option<f64> {
+    let s = fs::read_to_string("/proc/uptime").ok()?;
+    s.split_whitespace().next()?.parse().ok()
+}
+
+/// A lease made up from what the kernel keeps for `addr` on `iface`: its
+/// valid lifetime left, and the lifetime it was given, taken to be the
+/// lease time, from when it was last set. `None` for addresses that never
+/// expire, which is how static ones and some clients' leases are added.
+#[cfg(target_os = "linux")]
+pub fn from_kernel(iface: &str, addr: Ipv4Addr) -> Option<Lease> {
+    let a = netlink::addr_info(netlink::index(iface).ok()?, addr).ok()??;
+    let valid = u64::from(a.valid?);
+    let since_set = uptime().map_or(0.0, |u| (u - f64::from(a.updated) / 100.0).max(0.0));
+    let mask = u32::MAX
+        .checked_shl(32 - u32::from(a.prefix_len))
+        .unwrap_or(0);
+    Some(Lease {
+        source: LeaseSource::Kernel,
+        iface: iface.into(),
+        address: Some(addr),
+        mask: Some(mask.into()),
+        gateway: net::gateway(iface),
+        lease_time: Some(valid + since_set as u64),
+        expire: Some(history::unix_now() + valid),
+        ..Lease::default()
+    })
+}
+
+/// Only Linux says how long an address has left.
+#[cfg(not(target_os = "linux"))]
+pub fn from_kernel(_iface: &str, _addr: Ipv4Addr) -> Option<Lease> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+default-duid "\000\001\000\001";
+lease {
+  interface "eth0";
+  fixed-address 192.168.1.20;
+  option subnet-mask 255.255.255.0;
+  option routers 192.168.1.1,192.168.1.2;
+  option dhcp-lease-time 86400;
+  option domain-name-servers 192.168.1.1, 9.9.9.9;
+  option domain-name "home.example";
+  renew 3 2024/01/31 12:00:00;
+  expire 4 2024/02/01 00:00:00;
+}
+lease6 {
+  interface "eth0";
+  ia-na 1a:2b:3c:4d {
+    starts 1706702400;
+    iaaddr 2001:db8::20 {
+      starts 1706702400;
+    }
+  }
+}
+lease {
+  interface "wlan0";
+  fixed-address 10.0.0.7;
+  renew epoch 1706702400; # Wed Jan 31 12:00:00 2024
+  expire never;
+}
+lease {
+  interface "eth0";
+  fixed-address 192.168.1.21;
+  option dhcp-lease-time 3600;
+  expire epoch 1706706000;
+}
+"#;
+
+    #[test]
+    fn lease_blocks_are_read_in_order() {
+        let ls = parse_leases(FILE);
+        let names: Vec<(&str, Option<Ipv4Addr>)> =
+            ls.iter().map(|l| (l.iface.as_str(), l.address)).collect();
+        assert_eq!(
+            names,
+            [
+                ("eth0", "192.168.1.20".parse().ok()),
+                ("wlan0", "10.0.0.7".parse().ok()),
+                ("eth0", "192.168.1.21".parse().ok()),
+            ]
+        );
+        let l = &ls[0];
+        assert_eq!(l.prefix_len(), Some(24));
+        assert_eq!(l.gateway, "192.168.1.1".parse().ok());
+        assert_eq!(
+            l.dns,
+            ["192.168.1.1", "9.9.9.9"].map(|a| a.parse::<Ipv4Addr>().unwrap())
+        );
+        assert_eq!(l.lease_time, Some(86400));
+        // Weekday dates are UTC.
+        assert_eq!((l.renew, l.expire), (Some(1706702400), Some(1706745600)));
+        assert_eq!(l.renew_at(0.5), Some(1706702400));
+        assert_eq!(l.remaining(1706745000), Some(600));
+        let w = &ls[1];
+        assert_eq!((w.renew, w.expire), (Some(1706702400), None));
+    }
+
+    #[test]
+    fn the_newest_lease_wins() {
+        let l = newest(parse_leases(FILE), "eth0").unwrap();
+        assert_eq!(l.address, "192.168.1.21".parse().ok());
+        assert_eq!(l.expire, Some(1706706000));
+        assert!(newest(parse_leases(FILE), "eth1").is_none());
+        assert!(parse_leases("lease {\n  interface \"eth0\";\n").is_empty());
+    }
+}
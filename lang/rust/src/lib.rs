@@ -0,0 +1,289 @@
+//! Bringing network interfaces up and telling whether the network behind
+//! them works: what the `cw-netup` binary does, for other programs to
+//! embed.
+//!
+//! The library never exits the process and writes nothing on its own:
+//! logging starts with [`log::init`], and progress goes to the
+//! [`output::OutputSink`] the caller hands over, if any. What went wrong
+//! comes back as a [`WatchdogError`].
+//!
+//! Probing and the report work on Linux, macOS and Windows. Changing the
+//! system is Linux only: bring-up, DHCP, VLANs, namespaces, ARP and
+//! wireless association; elsewhere bring-up only looks, with a warning.
+//!
+//! ```
+//! use cw_netup::{ProbeTarget, WatchdogError};
+//!
+//! let t = ProbeTarget::target("example.net:443").unwrap();
+//! assert_eq!(t.to_string(), "example.net:443");
+//! assert_eq!(WatchdogError::DhcpFailed.exit_code(), 3);
+//! ```
+//!
+//! A one-shot run, as `cw-netup` without options does it:
+//!
+//! ```no_run
+//! use cw_netup::Config;
+//!
+//! let cfg = Config::resolve(None).unwrap();
+//! for i in cw_netup::discover_interfaces(&cfg) {
+//!     println!("{} ({})", i.name, i.kind.as_str());
+//! }
+//! let report = cw_netup::run_checks(&cfg);
+//! match report.outcome() {
+//!     Ok(()) => println!("ONLINE"),
+//!     Err(e) => println!("{} (exit {})", e, e.exit_code()),
+//! }
+//! ```
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+compile_error!("cw-netup builds for Linux, macOS and Windows only");
+
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod arp;
+#[doc(hidden)]
+pub mod audit;
+#[doc(hidden)]
+pub mod availability;
+#[doc(hidden)]
+pub mod backoff;
+#[doc(hidden)]
+pub mod clock;
+pub mod config;
+#[doc(hidden)]
+pub mod daemon;
+#[doc(hidden)]
+pub mod dhcp;
+#[doc(hidden)]
+pub mod dns;
+pub mod error;
+pub mod events;
+pub mod exec;
+#[doc(hidden)]
+pub mod glob;
+#[doc(hidden)]
+pub mod health;
+#[doc(hidden)]
+pub mod history;
+#[doc(hidden)]
+pub mod http;
+pub mod iface;
+#[doc(hidden)]
+pub mod ifaddrs;
+#[doc(hidden)]
+pub mod json;
+#[doc(hidden)]
+pub mod latency;
+#[doc(hidden)]
+pub mod leases;
+pub mod log;
+pub mod manager;
+#[doc(hidden)]
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+#[doc(hidden)]
+pub mod mqtt;
+#[doc(hidden)]
+pub mod net;
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod netlink;
+#[doc(hidden)]
+pub mod netns;
+#[doc(hidden)]
+pub mod notify;
+pub mod output;
+#[doc(hidden)]
+pub mod pidfile;
+#[doc(hidden)]
+pub mod ping;
+#[doc(hidden)]
+pub mod pool;
+pub mod probe;
+pub mod report;
+#[cfg(unix)]
+#[doc(hidden)]
+pub mod sys;
+#[cfg(windows)]
+#[doc(hidden)]
+#[path = "sys_windows.rs"]
+pub mod sys;
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod systemd;
+#[doc(hidden)]
+pub mod tcp;
+#[doc(hidden)]
+pub mod timeline;
+#[doc(hidden)]
+pub mod toml;
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod tunnel;
+#[doc(hidden)]
+pub mod uplink;
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod vlan;
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod wireless;
+
+pub use config::{Config, ProbeMethod as ProbeTarget};
+pub use error::WatchdogError;
+pub use iface::{IfaceKind, IfaceReport};
+pub use manager::{detect_network_manager, NetworkBackend};
+pub use probe::{detect_captive_portal, State, Summary, TargetResult as ProbeResult};
+pub use report::Report;
+
+use exec::Executor;
+use iface::Dhcp;
+use latency::LatencyWindow;
+use output::OutputSink;
+use std::time::Instant;
+
+/// A link discovery found, and what sort it is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Interface {
+    pub name: String,
+    pub kind: IfaceKind,
+}
+
+/// The interfaces `cfg` manages: `[interfaces] manage`, else what
+/// discovery and the include/exclude globs leave.
+pub fn discover_interfaces(cfg: &Config) -> Vec<Interface> {
+    iface::discover(cfg)
+        .into_iter()
+        .map(|name| Interface {
+            kind: iface::classify_iface(&name),
+            name,
+        })
+        .collect()
+}
+
+/// Brings `iface` up as `cfg` says: link, DHCP or the static fallback. The
+/// report lists what went wrong along the way; the error is for a link
+/// refused for lack of privileges, or one left with no address.
+pub fn bring_up(iface: &str, cfg: &Config) -> Result<IfaceReport, WatchdogError> {
+    let r = iface::bring_up(
+        cfg,
+        &[iface.to_string()],
+        Dhcp::from_cfg(cfg),
+        backoff::deadline(cfg),
+        &exec::CommandExecutor,
+    )
+    .pop()
+    .expect("a report per interface");
+    if !r.link_up && r.errors.iter().any(|e| iface::denied(e)) {
+        return Err(WatchdogError::Permission);
+    }
+    if r.dhcp == Some(false) && r.fallback != Some(true) {
+        return Err(WatchdogError::DhcpFailed);
+    }
+    Ok(r)
+}
+
+/// A one-shot run: creates the `[[vlans]]`, brings the interfaces up, or
+/// the first `priority` uplink that works, and probes. Under NetworkManager or networkd (see
+/// [`manager::passive`]) they are only looked at. [`Report::outcome`] has
+/// the verdict.
+pub fn run_checks(cfg: &Config) -> Report {
+    run_checks_with(cfg, &exec::CommandExecutor, &mut output::Quiet)
+}
+
+/// [`run_checks`] through `ex`, telling `out` about each step as it goes.
+pub fn run_checks_with(cfg: &Config, ex: &dyn Executor, out: &mut dyn OutputSink) -> Report {
+    let start = Instant::now();
+    let deadline = backoff::deadline(cfg);
+    let manager = manager::passive(cfg);
+    #[cfg(target_os = "linux")]
+    if manager.is_none() {
+        vlan::setup(cfg, ex);
+    }
+    let (uplinks, rest) = uplink::split(cfg, &iface::discover(cfg));
+    let mut window = LatencyWindow::new(cfg.latency_window);
+    let (interfaces, probes, active) = if let Some(b) = manager {
+        log::info!("{} manages the interfaces; only watching them", b);
+        let mut rs = iface::inspect(cfg, &[&rest[..], &uplinks[..]].concat());
+        b.annotate(&mut rs);
+        out.interfaces(&rs);
+        let s = probe::check(cfg, &mut window, deadline);
+        out.probes(&s);
+        (rs, s, None)
+    } else if uplinks.is_empty() {
+        let (rs, s) = bring_up_all(cfg, &rest, deadline, ex, out, &mut window);
+        (rs, s, None)
+    } else {
+        let mut rs = iface::bring_up(cfg, &rest, Dhcp::from_cfg(cfg), deadline, ex);
+        out.interfaces(&rs);
+        let sel = uplink::select(
+            cfg,
+            &uplinks,
+            Dhcp::from_cfg(cfg),
+            deadline,
+            ex,
+            &mut window,
+        );
+        let mut last = None;
+        for (urs, s) in sel.tried {
+            out.interfaces(&urs);
+            out.probes(&s);
+            for r in urs {
+                rs.retain(|o| o.name != r.name);
+                rs.push(r);
+            }
+            last = Some(s);
+        }
+        rs.sort_by(|a, b| a.name.cmp(&b.name));
+        (rs, last.expect("an uplink was tried"), sel.active)
+    };
+    let trace = (probes.state == State::Offline)
+        .then(|| ping::Trace::run(cfg))
+        .flatten();
+    Report {
+        timestamp: clock::now(),
+        interfaces,
+        probes,
+        uplink: active,
+        quorum: cfg.quorum,
+        dry_run: ex.actions(),
+        trace,
+        manager,
+        namespaces: Vec::new(),
+        window: None,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Brings up `ifaces` together and probes; those skipped as already
+/// configured get a lease after all when connectivity turns out to be
+/// broken.
+fn bring_up_all(
+    cfg: &Config,
+    ifaces: &[String],
+    deadline: Option<Instant>,
+    ex: &dyn Executor,
+    out: &mut dyn OutputSink,
+    window: &mut LatencyWindow,
+) -> (Vec<IfaceReport>, Summary) {
+    let mut interfaces = iface::bring_up(cfg, ifaces, Dhcp::from_cfg(cfg), deadline, ex);
+    out.interfaces(&interfaces);
+    let mut probes = probe::check(cfg, window, deadline);
+    out.probes(&probes);
+    let skipped: Vec<String> = interfaces
+        .iter()
+        .filter(|r| r.configured && r.dhcp.is_none())
+        .map(|r| r.name.clone())
+        .collect();
+    if !probes.online() && !skipped.is_empty() && !cfg.skip_dhcp {
+        let again = iface::bring_up(cfg, &skipped, Dhcp::Always, deadline, ex);
+        out.interfaces(&again);
+        for r in again {
+            if let Some(old) = interfaces.iter_mut().find(|o| o.name == r.name) {
+                *old = r;
+            }
+        }
+        probes = probe::check(cfg, window, deadline);
+        out.probes(&probes);
+    }
+    (interfaces, probes)
+}
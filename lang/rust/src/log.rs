@@ -0,0 +1,295 @@
+// Leveled diagnostics to stderr, syslog or the journal. Results (state
+// lines, JSON) go to stdout through an OutputSink instead.
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// syslog(3) severity.
+    fn severity(self) -> u8 {
+        match self {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug => 7,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Level::Error => "error: ",
+            Level::Warn => "warning: ",
+            Level::Info => "",
+            Level::Debug => "debug: ",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Level, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" | "warning" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where diagnostics go (`log_backend`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// stderr, except in daemon mode: the journal when stderr is connected
+    /// to it, else syslog if there is one.
+    Auto,
+    Stderr,
+    /// `/dev/log` (`/var/run/syslog` on macOS), facility LOG_DAEMON.
+    Syslog,
+    /// The journal's native socket, with IFACE= on per-interface messages.
+    Journald,
+}
+
+impl FromStr for Backend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Backend, ()> {
+        match s {
+            "auto" => Ok(Backend::Auto),
+            "stderr" => Ok(Backend::Stderr),
+            "syslog" => Ok(Backend::Syslog),
+            "journald" => Ok(Backend::Journald),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Auto => "auto",
+            Backend::Stderr => "stderr",
+            Backend::Syslog => "syslog",
+            Backend::Journald => "journald",
+        })
+    }
+}
+
+enum Target {
+    Stderr,
+    /// stderr under systemd: `<N>` prefixes carry the severity.
+    Journal,
+    Syslog(UnixDatagram),
+    /// Native protocol, which takes fields besides MESSAGE.
+    Journald(UnixDatagram),
+}
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+#[cfg(not(target_os = "macos"))]
+const SYSLOG_SOCKET: &str = "/dev/log";
+#[cfg(target_os = "macos")]
+const SYSLOG_SOCKET: &str = "/var/run/syslog";
+
+/// No local datagram sockets to log to on Windows.
+#[cfg(windows)]
+enum UnixDatagram {}
+
+#[cfg(windows)]
+impl UnixDatagram {
+    fn send(&self, _buf: &[u8]) -> io::Result<usize> {
+        match *self {}
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static TARGET: OnceLock<Target> = OnceLock::new();
+
+thread_local! {
+    /// The `[[namespace]]` this thread checks, if any.
+    static SCOPE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Names the namespace the calling thread's messages are about: they get
+/// a `[name] ` prefix, and NETNS= in the journal.
+pub fn set_scope(name: &str) {
+    SCOPE.with(|s| *s.borrow_mut() = Some(name.to_string()));
+}
+
+/// What [`set_scope`] named on this thread.
+pub fn scope() -> Option<String> {
+    SCOPE.with(|s| s.borrow().clone())
+}
+
+#[cfg(unix)]
+fn connect(path: &str) -> io::Result<UnixDatagram> {
+    let s = UnixDatagram::unbound()?;
+    s.connect(path)?;
+    Ok(s)
+}
+
+#[cfg(windows)]
+fn connect(_path: &str) -> io::Result<UnixDatagram> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// `level` if given, else `$CW_LOG`, else info. A backend that cannot be
+/// reached falls back to stderr, with a warning when it was asked for.
+pub fn init(level: Option<Level>, backend: Backend, daemon: bool) {
+    let level = level
+        .or_else(|| std::env::var("CW_LOG").ok()?.parse().ok())
+        .unwrap_or(Level::Info);
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    let (target, failed) = match backend {
+        Backend::Stderr => (Target::Stderr, None),
+        Backend::Auto if !daemon => (Target::Stderr, None),
+        Backend::Auto if std::env::var_os("JOURNAL_STREAM").is_some() => (
+            connect(JOURNAL_SOCKET).map_or(Target::Journal, Target::Journald),
+            None,
+        ),
+        Backend::Auto => (
+            connect(SYSLOG_SOCKET).map_or(Target::Stderr, Target::Syslog),
+            None,
+        ),
+        Backend::Syslog => match connect(SYSLOG_SOCKET) {
+            Ok(s) => (Target::Syslog(s), None),
+            Err(e) => (Target::Stderr, Some((SYSLOG_SOCKET, e))),
+        },
+        Backend::Journald => match connect(JOURNAL_SOCKET) {
+            Ok(s) => (Target::Journald(s), None),
+            Err(e) => (Target::Stderr, Some((JOURNAL_SOCKET, e))),
+        },
+    };
+    let _ = TARGET.set(target);
+    if let Some((path, e)) = failed {
+        emit(
+            Level::Warn,
+            None,
+            format_args!("{} backend: {}: {}, logging to stderr", backend, path, e),
+        );
+    }
+}
+
+/// One `KEY=value` journal field; values with newlines take the binary
+/// form (`KEY\n`, little-endian u64 length, value).
+fn field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+pub fn enabled(l: Level) -> bool {
+    l as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Whether [`init`] has run; nothing is logged before.
+pub fn initialized() -> bool {
+    TARGET.get().is_some()
+}
+
+/// Logs `args` at `l`; `iface` becomes the journal's IFACE= field.
+pub fn emit(l: Level, iface: Option<&str>, args: fmt::Arguments) {
+    let Some(target) = TARGET.get().filter(|_| enabled(l)) else {
+        return;
+    };
+    let scope = scope();
+    let scoped;
+    let args = match &scope {
+        Some(n) => {
+            scoped = format!("[{}] {}", n, args);
+            format_args!("{}", scoped)
+        }
+        None => args,
+    };
+    match target {
+        Target::Stderr => eprintln!("{}{}", l.prefix(), args),
+        Target::Journal => eprintln!("<{}>{}", l.severity(), args),
+        Target::Syslog(s) => {
+            // LOG_DAEMON
+            let msg = format!(
+                "<{}>cw-netup[{}]: {}",
+                24 + l.severity(),
+                std::process::id(),
+                args
+            );
+            if s.send(msg.as_bytes()).is_err() {
+                let _ = writeln!(io::stderr(), "{}{}", l.prefix(), args);
+            }
+        }
+        Target::Journald(s) => {
+            let mut msg = Vec::new();
+            field(&mut msg, "MESSAGE", &args.to_string());
+            field(&mut msg, "PRIORITY", &l.severity().to_string());
+            field(&mut msg, "SYSLOG_IDENTIFIER", "cw-netup");
+            if let Some(i) = iface {
+                field(&mut msg, "IFACE", i);
+            }
+            if let Some(n) = &scope {
+                field(&mut msg, "NETNS", n);
+            }
+            if s.send(&msg).is_err() {
+                let _ = writeln!(io::stderr(), "{}{}", l.prefix(), args);
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log_error {
+    (iface = $i:expr; $($a:tt)*) => {
+        $crate::log::emit($crate::log::Level::Error, Some($i), format_args!($($a)*))
+    };
+    ($($a:tt)*) => { $crate::log::emit($crate::log::Level::Error, None, format_args!($($a)*)) };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log_warn {
+    (iface = $i:expr; $($a:tt)*) => {
+        $crate::log::emit($crate::log::Level::Warn, Some($i), format_args!($($a)*))
+    };
+    ($($a:tt)*) => { $crate::log::emit($crate::log::Level::Warn, None, format_args!($($a)*)) };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log_info {
+    (iface = $i:expr; $($a:tt)*) => {
+        $crate::log::emit($crate::log::Level::Info, Some($i), format_args!($($a)*))
+    };
+    ($($a:tt)*) => { $crate::log::emit($crate::log::Level::Info, None, format_args!($($a)*)) };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log_debug {
+    (iface = $i:expr; $($a:tt)*) => {
+        $crate::log::emit($crate::log::Level::Debug, Some($i), format_args!($($a)*))
+    };
+    ($($a:tt)*) => { $crate::log::emit($crate::log::Level::Debug, None, format_args!($($a)*)) };
+}
+
+// Exported under other names: `warn` alone would clash with the #[warn]
+// attribute, and `error` with the module.
+pub use crate::{log_debug as debug, log_error as error, log_info as info, log_warn as warn};
@@ -1,14 +1,327 @@
-use std::fs;
-use std::process::Command;
+mod actions;
+mod cli;
+mod doctor;
+mod outages;
+mod repair;
+mod status;
+
+use cli::Cli;
+use cw_netup::availability::Availability;
+use cw_netup::config::{Config, NamespaceConfig};
+use cw_netup::error::{Status, WatchdogError};
+use cw_netup::notify::Notifier;
+use cw_netup::report::Report;
+#[cfg(target_os = "linux")]
+use cw_netup::vlan;
+use cw_netup::{
+    audit, backoff, clock, daemon, exec, iface, json, latency, log, manager, netns, notify, output,
+    pidfile, ping, probe,
+};
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+/// Ends the process on a [`WatchdogError`], with its exit status.
+trait Exit {
+    fn exit(self) -> !;
+}
+
+impl Exit for WatchdogError {
+    /// Logs what went wrong, unless the state line already said so, and
+    /// exits with the matching status.
+    fn exit(self) -> ! {
+        if let WatchdogError::Config(m) | WatchdogError::Internal(m) = &self {
+            if log::initialized() {
+                log::error!("{}", m);
+            } else {
+                eprintln!("error: {}", m);
+            }
+        }
+        std::process::exit(self.exit_code())
+    }
+}
+
 fn main() {
-    let ifaces: Vec<String> = fs::read_dir("/sys/class/net")
-        .map(|e| e.filter_map(|e| e.ok()).map(|e| e.file_name().to_string_lossy().to_string()).filter(|n| n != "lo").collect())
-        .unwrap_or_else(|_| vec!["eth0".into()]);
+    let cli = Cli::parse();
+    let mut cfg = match Config::resolve(cli.config.as_deref()) {
+        Ok(c) => c,
+        Err(e) if cli.doctor => doctor::run(Err(format!("config: {}", e)), &mut *sink(&cli, false)),
+        Err(e) => WatchdogError::Config(format!("config: {}", e)).exit(),
+    };
+    cli.apply(&mut cfg);
+    log::init(cli.log_level, cfg.log_backend, cfg.daemon);
+    exec::configure(&cfg);
+    audit::configure(&cfg);
+    if cli.print_config {
+        print!("{}", cfg);
+        return;
+    }
+    let ns = enter_netns(&cli);
+    // Subcommands run once, whatever the config says of daemon mode.
+    let subcommand =
+        cli.doctor || cli.status || cli.history || cli.audit || cli.repair || cli.list_ifaces;
+    let mut out = sink(&cli, cfg.daemon && !subcommand);
+    if cli.doctor {
+        doctor::run(Ok(&cfg), &mut *out);
+    }
+    if cli.status {
+        status::run(&cfg, &mut *out);
+    }
+    if cli.history {
+        outages::run(&cfg, cli.since.unwrap_or(86400), &mut *out);
+    }
+    if cli.audit && !cli.rollback_last {
+        actions::run(&cfg, cli.last.unwrap_or(20), &mut *out);
+    }
+    if cli.list_ifaces {
+        list_ifaces(&cfg, &mut *out);
+        return;
+    }
+    output::benchmark(cli.benchmark);
+    if cli.state_only && cfg.daemon {
+        WatchdogError::Config("--state-only is for one-shot runs".into()).exit();
+    }
+    if cli.once {
+        once(&cfg, &mut *out, cli.wait);
+    }
+    let ex = exec::executor(cli.dry_run);
+    if cli.repair {
+        refuse_beside_daemon(&cfg, &cli);
+        repair::run(&cfg, &cli.ifaces, &*ex, &mut *out);
+    }
+    if cli.rollback_last {
+        refuse_beside_daemon(&cfg, &cli);
+        actions::rollback(&cfg, &*ex, &mut *out);
+    }
+    if cli.release {
+        release(&cfg, &*ex);
+    }
+    if cfg.daemon {
+        // A dry run changes nothing, so it may run next to the daemon.
+        let _pid = cfg
+            .pid_file
+            .as_deref()
+            .filter(|_| !cli.dry_run)
+            .map(|p| pidfile::PidFile::acquire(p).unwrap_or_else(|e| e.exit()));
+        daemon::trap_controls();
+        std::thread::scope(|s| {
+            for ns in &cfg.namespaces {
+                s.spawn(|| namespace_loop(&cfg, ns, &cli));
+            }
+            daemon::WatchdogLoop::new(&cfg, out)
+                .notifiers(notify::from_cfg(&cfg, cli.dry_run, cli.ndjson))
+                .executor(ex)
+                .reloader(Box::new(|| reload(&cli, None)))
+                .run();
+        });
+        return;
+    }
+    refuse_beside_daemon(&cfg, &cli);
+    let mut r = cw_netup::run_checks_with(&cfg, &*ex, &mut *out);
+    r.namespaces = netns::each(&cfg, |c| {
+        cw_netup::run_checks_with(c, &*exec::executor(cli.dry_run), &mut output::Quiet)
+    });
+    drop(ns);
+    out.status(&r);
+    if let Err(e) = r.outcome() {
+        e.exit();
+    }
+}
+
+/// A one-shot run alongside the daemon would fight it over the links;
+/// a dry run changes nothing.
+fn refuse_beside_daemon(cfg: &Config, cli: &Cli) {
+    let running = cfg.pid_file.as_deref().and_then(pidfile::running);
+    if let Some(pid) = running.filter(|_| !cli.dry_run) {
+        WatchdogError::Config(format!("daemon already running (pid {})", pid)).exit();
+    }
+}
+
+/// Joins the `--netns` namespace, if any; exit 4 when that is not
+/// permitted.
+fn enter_netns(cli: &Cli) -> Option<netns::Namespace> {
+    let path = cli
+        .netns_path
+        .clone()
+        .or_else(|| cli.netns.as_deref().map(netns::path))?;
+    match netns::Namespace::enter(&path, cli.netns.as_deref()) {
+        Ok(ns) => Some(ns),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            log::error!(
+                "network namespace {}: {} (CAP_SYS_ADMIN needed)",
+                path.display(),
+                e
+            );
+            WatchdogError::Permission.exit()
+        }
+        Err(e) => {
+            WatchdogError::Config(format!("network namespace {}: {}", path.display(), e)).exit()
+        }
+    }
+}
+
+/// The daemon's loop for `[[namespace]]` `ns`, on a thread that moves
+/// into it for good; it stops with the host's.
+fn namespace_loop(cfg: &Config, ns: &NamespaceConfig, cli: &Cli) {
+    log::set_scope(&ns.name);
+    let c = cfg.for_namespace(ns);
+    let _in = match netns::Namespace::enter(&netns::ns_path(ns), Some(&ns.name)) {
+        Ok(n) => n,
+        Err(e) => {
+            if let WatchdogError::Config(m) = netns::entering(ns, e) {
+                log::error!("{}", m);
+            }
+            return;
+        }
+    };
+    daemon::WatchdogLoop::new(&c, output::sink(cli.json, cli.ndjson))
+        .namespace(&ns.name)
+        .notifiers(notify::from_cfg(&c, cli.dry_run, cli.ndjson))
+        .executor(exec::executor(cli.dry_run))
+        .reloader(Box::new(|| reload(cli, Some(&ns.name))))
+        .run();
+}
+
+/// Where reports, events and what a subcommand found go, as `-q`,
+/// `--state-only` and `--output` ask.
+fn sink(cli: &Cli, daemon: bool) -> Box<dyn output::OutputSink> {
+    match (cli.quiet, cli.state_only) {
+        // The daemon's state lines are its log; -q only quiets the rest.
+        (true, _) if !daemon => Box::new(output::Quiet),
+        (_, true) => Box::new(output::StateOnly),
+        _ => output::sink(cli.json, cli.ndjson),
+    }
+}
+
+/// SIGHUP: the config file read again, or `[[namespace]]` `ns`'s part of
+/// it, with the command line applied over it as at start.
+fn reload(cli: &Cli, ns: Option<&str>) -> Result<(Config, Vec<Box<dyn Notifier>>), String> {
+    let mut c = Config::resolve(cli.config.as_deref()).map_err(|e| format!("config: {}", e))?;
+    cli.apply(&mut c);
+    if let Some(name) = ns {
+        let Some(n) = c.namespaces.iter().find(|n| n.name == name) else {
+            return Err(format!("[[namespace]] {} is gone", name));
+        };
+        c = c.for_namespace(n);
+    }
+    let n = notify::from_cfg(&c, cli.dry_run, cli.ndjson);
+    Ok((c, n))
+}
+
+/// `--once`: one report of things as they stand, exiting with the code of
+/// the state it names. Touches nothing, so it may run next to the daemon.
+/// With `wait`, after the host is ONLINE or the time is up.
+fn once(cfg: &Config, out: &mut dyn output::OutputSink, wait: Option<(u64, u64)>) -> ! {
+    let mut r = match wait {
+        Some(w) => wait_online(cfg, out, w),
+        None => inspect(cfg),
+    };
+    r.namespaces = netns::each(cfg, inspect);
+    out.status(&r);
+    std::process::exit(Status::from(r.outcome()).exit_code());
+}
+
+/// `--wait`: probes every `pause` seconds until the host is ONLINE or
+/// `timeout` seconds are up. The report of the last probe.
+fn wait_online(
+    cfg: &Config,
+    out: &mut dyn output::OutputSink,
+    (timeout, pause): (u64, u64),
+) -> Report {
+    let start = Instant::now();
+    let end = start + Duration::from_secs(timeout);
+    let pause = Duration::from_secs(pause);
+    let mut window = latency::LatencyWindow::new(cfg.latency_window);
+    let mut checks = Availability::new(timeout);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let deadline = backoff::deadline(cfg).map_or(end, |d| d.min(end));
+        let probes = probe::check(cfg, &mut window, Some(deadline));
+        checks.push(&probes);
+        let online = probes.state == probe::State::Online;
+        let last = online || Instant::now() + pause >= end;
+        out.event(output::Event::Wait {
+            attempt,
+            state: probes.state,
+            last,
+        });
+        if last {
+            let mut r = report(cfg, start, probes);
+            r.window = Some(checks.stats());
+            return r;
+        }
+        std::thread::sleep(pause);
+    }
+}
+
+/// What `--once` reports: the interfaces as they are, and a probe.
+fn inspect(cfg: &Config) -> Report {
+    let start = Instant::now();
+    let deadline = backoff::deadline(cfg);
+    let probes = probe::check(
+        cfg,
+        &mut latency::LatencyWindow::new(cfg.latency_window),
+        deadline,
+    );
+    report(cfg, start, probes)
+}
+
+fn report(cfg: &Config, start: Instant, probes: probe::Summary) -> Report {
+    let manager = manager::passive(cfg);
+    let mut interfaces = iface::inspect(cfg, &iface::discover(cfg));
+    if let Some(b) = manager {
+        b.annotate(&mut interfaces);
+    }
+    Report {
+        timestamp: clock::now(),
+        interfaces,
+        trace: (probes.state == probe::State::Offline)
+            .then(|| ping::Trace::run(cfg))
+            .flatten(),
+        probes,
+        uplink: None,
+        quorum: cfg.quorum,
+        dry_run: None,
+        manager,
+        namespaces: Vec::new(),
+        window: None,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// `--list-ifaces`: what discovery and the include/exclude globs leave,
+/// without touching anything.
+fn list_ifaces(cfg: &Config, out: &mut dyn output::OutputSink) {
+    let ifaces = iface::discover(cfg);
+    let mut text = String::new();
+    let mut v = Vec::new();
     for i in &ifaces {
-        let _ = Command::new("ip").args(["link","set",i,"up"]).status();
-        if Command::new("dhclient").args(["-1","-q",i]).status().map(|s|s.success()).unwrap_or(false) { println!("{} up",i); }
+        let kind = iface::classify_iface(i).as_str();
+        let _ = writeln!(text, "{}\t{}", i, kind);
+        v.push(json::Json::obj().set("name", i.as_str()).set("kind", kind));
+    }
+    out.document(&text, json::Json::obj().set("interfaces", v));
+}
+
+/// `--release`: the daemon's exit teardown on its own. Exit 0 when all of
+/// it went through, 4 when privileges were lacking, else 1.
+fn release(cfg: &Config, ex: &dyn exec::Executor) -> ! {
+    let running = cfg.pid_file.as_deref().and_then(pidfile::running);
+    if let Some(pid) = running.filter(|_| ex.live()) {
+        WatchdogError::Config(format!("daemon already running (pid {})", pid)).exit();
+    }
+    let ifaces = iface::discover(cfg);
+    if ifaces.is_empty() {
+        WatchdogError::NoInterfaces.exit();
+    }
+    let failed = iface::tear_down(cfg, &ifaces, cfg.teardown_link_down, ex);
+    #[cfg(target_os = "linux")]
+    let failed = [failed, vlan::remove(cfg, ex)].concat();
+    for (i, e) in &failed {
+        log::error!(iface = i; "{}: {}", i, e);
+    }
+    if failed.iter().any(|(_, e)| iface::denied(e)) {
+        WatchdogError::Permission.exit();
     }
-    let ok = Command::new("ping").args(["-c1","-W3","8.8.8.8"]).status().map(|s|s.success()).unwrap_or(false);
-    println!("{}", if ok {"ONLINE"} else {"OFFLINE"});
-    std::process::exit(if ok {0} else {1});
+    std::process::exit(!failed.is_empty() as i32);
 }
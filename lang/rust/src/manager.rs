@@ -0,0 +1,166 @@
+// Passive monitoring: where NetworkManager or systemd-networkd already owns
+// the interfaces, running DHCP or touching links would fight it, so they
+// are only watched, and the manager is asked what it makes of each.
+use crate::config::Config;
+use crate::exec;
+use crate::iface::IfaceReport;
+use crate::json::Json;
+use crate::log;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How long `nmcli` or `networkctl` gets to answer.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A daemon that manages interfaces on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkBackend {
+    NetworkManager,
+    /// systemd-networkd.
+    Networkd,
+}
+
+impl fmt::Display for NetworkBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            NetworkBackend::NetworkManager => "NetworkManager",
+            NetworkBackend::Networkd => "networkd",
+        })
+    }
+}
+
+impl FromStr for NetworkBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "NetworkManager" | "networkmanager" | "nm" => Ok(NetworkBackend::NetworkManager),
+            "networkd" | "systemd-networkd" => Ok(NetworkBackend::Networkd),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `[interfaces] network_manager`: who owns the interfaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManagerMode {
+    /// Whichever of NetworkManager and systemd-networkd runs, else cw-netup.
+    Auto,
+    /// cw-netup, regardless.
+    None,
+    Backend(NetworkBackend),
+}
+
+impl fmt::Display for ManagerMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManagerMode::Auto => f.write_str("auto"),
+            ManagerMode::None => f.write_str("none"),
+            ManagerMode::Backend(b) => b.fmt(f),
+        }
+    }
+}
+
+impl FromStr for ManagerMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "auto" => Ok(ManagerMode::Auto),
+            "none" => Ok(ManagerMode::None),
+            b => b.parse().map(ManagerMode::Backend),
+        }
+    }
+}
+
+/// NetworkManager, else systemd-networkd, when a process of that name runs.
+pub fn detect_network_manager() -> Option<NetworkBackend> {
+    let mut found = None;
+    for e in fs::read_dir("/proc").ok()?.filter_map(|e| e.ok()) {
+        let name = e.file_name();
+        if !name.to_string_lossy().bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        // comm is cut at 15 bytes: "systemd-network".
+        match fs::read_to_string(e.path().join("comm")).as_deref() {
+            Ok("NetworkManager\n") => return Some(NetworkBackend::NetworkManager),
+            Ok("systemd-network\n") => found = Some(NetworkBackend::Networkd),
+            _ => {}
+        }
+    }
+    found
+}
+
+/// The manager `cfg` defers to, if any; `None` when cw-netup manages the
+/// interfaces itself.
+pub fn passive(cfg: &Config) -> Option<NetworkBackend> {
+    match cfg.network_manager {
+        ManagerMode::Auto => detect_network_manager(),
+        ManagerMode::None => None,
+        ManagerMode::Backend(b) => Some(b),
+    }
+}
+
+impl NetworkBackend {
+    fn query(self) -> Option<String> {
+        let (prog, args): (&str, &[&str]) = match self {
+            NetworkBackend::NetworkManager => ("nmcli", &["-t", "-f", "DEVICE,STATE", "dev"]),
+            NetworkBackend::Networkd => ("networkctl", &["status", "--json=short"]),
+        };
+        let line = format!("{} {}", prog, args.join(" "));
+        let out = exec::sys()
+            .exec(Command::new(prog).args(args), QUERY_TIMEOUT)
+            .map_err(|e| log::debug!("{}: {}", line, e))
+            .ok()?;
+        if !out.success() {
+            log::debug!("{}: {}", line, String::from_utf8_lossy(&out.stderr).trim());
+            return None;
+        }
+        Some(String::from_utf8_lossy(&out.stdout).into_owned())
+    }
+
+    /// What the manager says of each device: NetworkManager's state
+    /// (`connected`, `disconnected`, `unavailable`, ...) or networkd's
+    /// operational state (`routable`, `no-carrier`, ...). Empty when it
+    /// does not answer.
+    pub fn states(self) -> BTreeMap<String, String> {
+        let Some(s) = self.query() else {
+            return BTreeMap::new();
+        };
+        match self {
+            // `eth0:connected`; colons in the name come escaped.
+            NetworkBackend::NetworkManager => s
+                .lines()
+                .filter_map(|l| l.rsplit_once(':'))
+                .map(|(d, st)| (d.replace("\\:", ":"), st.to_string()))
+                .collect(),
+            NetworkBackend::Networkd => {
+                let j = Json::parse(&s)
+                    .map_err(|e| log::debug!("networkctl status: {}", e))
+                    .unwrap_or(Json::Null);
+                j.get("Interfaces")
+                    .and_then(Json::as_arr)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|i| {
+                        let name = i.get("Name")?.as_str()?;
+                        let st = i.get("OperationalState")?.as_str()?;
+                        Some((name.to_string(), st.to_string()))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Fills in `manager_state` on `rs`.
+    pub fn annotate(self, rs: &mut [IfaceReport]) {
+        let mut st = self.states();
+        for r in rs {
+            r.manager_state = st.remove(&r.name);
+        }
+    }
+}
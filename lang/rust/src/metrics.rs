@@ -0,0 +1,417 @@
+// Prometheus text exposition for daemon mode, served by a tiny HTTP/1.0
+// responder on its own thread so scrapes never stall the probe loop; each
+// connection gets a thread, a deadline and a size limit, so a client that
+// sends nothing, or too much, holds up no other.
+use crate::availability::WindowStats;
+use crate::history;
+use crate::iface::IfaceReport;
+use crate::net::IfaceStats;
+use crate::probe::{State, Summary};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a client has to send its request, line and headers, and how
+/// many bytes of them it may send.
+const REQUEST_TIME: Duration = Duration::from_secs(5);
+const REQUEST_MAX: usize = 8192;
+
+/// Connections answered at once; those past it are closed unanswered.
+const CONNECTIONS: usize = 16;
+
+/// RTT samples kept for the latency quantiles.
+const WINDOW: usize = 128;
+
+/// Upper bounds of the per-target RTT histogram, in seconds.
+const BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+const STATES: [State; 5] = [
+    State::Online,
+    State::Degraded,
+    State::DnsBroken,
+    State::CaptivePortal,
+    State::Offline,
+];
+
+type Counter = fn(&IfaceStats) -> u64;
+
+#[derive(Default)]
+struct IfaceMetrics {
+    up: bool,
+    dhcp_renewals: u64,
+    dhcp_ok: u64,
+    dhcp_failed: u64,
+    stats: IfaceStats,
+    /// Received and sent bytes per second over the last cycle.
+    rates: Option<(f64, f64)>,
+    pmtu: Option<u16>,
+    /// Unix seconds; from the last bring-up's lease.
+    lease_expires: Option<u64>,
+}
+
+#[derive(Default)]
+struct TargetMetrics {
+    method: &'static str,
+    success: bool,
+    /// Cumulative counts per [`BUCKETS`] bound.
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    ifaces: BTreeMap<String, IfaceMetrics>,
+    targets: BTreeMap<String, TargetMetrics>,
+    rtts: VecDeque<f64>,
+    online: bool,
+    state: Option<State>,
+    transitions: u64,
+    /// From the last throughput download that worked.
+    throughput: Option<f64>,
+    window: Option<WindowStats>,
+    /// Seconds to the next check, and whether it probes one target.
+    pace: Option<(u64, bool)>,
+}
+
+pub type Shared = Arc<Mutex<Metrics>>;
+
+impl Metrics {
+    pub fn record_ifaces(&mut self, rs: &[IfaceReport]) {
+        for r in rs {
+            let m = self.ifaces.entry(r.name.clone()).or_default();
+            m.up = r.link_up;
+            m.pmtu = r.pmtu;
+            m.lease_expires = r.lease.as_ref().and_then(|l| l.expire);
+            m.dhcp_renewals += r.dhcp.is_some() as u64 + r.dhcp6.is_some() as u64;
+            for ok in [r.dhcp, r.dhcp6].into_iter().flatten() {
+                if ok {
+                    m.dhcp_ok += 1;
+                } else {
+                    m.dhcp_failed += 1;
+                }
+            }
+        }
+    }
+
+    /// An interface that vanished; its series end with it.
+    pub fn forget_iface(&mut self, iface: &str) {
+        self.ifaces.remove(iface);
+    }
+
+    pub fn record_traffic(&mut self, iface: &str, stats: IfaceStats, rates: Option<(f64, f64)>) {
+        let m = self.ifaces.entry(iface.to_string()).or_default();
+        m.stats = stats;
+        m.rates = rates;
+    }
+
+    pub fn record_probe(&mut self, s: &Summary) {
+        self.online = s.online();
+        if self.state.is_some_and(|p| p != s.state) {
+            self.transitions += 1;
+        }
+        self.state = Some(s.state);
+        if let Some(t) = s.throughput.as_ref().filter(|t| t.error.is_none()) {
+            self.throughput = Some(t.kbps() * 1000.0 / 8.0);
+        }
+        for r in &s.results {
+            let t = self.targets.entry(r.target.to_string()).or_default();
+            t.method = r.target.method();
+            t.success = r.rtt.is_some();
+            if let Some(d) = r.rtt {
+                let secs = d.as_secs_f64();
+                for (b, &le) in t.buckets.iter_mut().zip(&BUCKETS) {
+                    *b += (secs <= le) as u64;
+                }
+                t.sum += secs;
+                t.count += 1;
+            }
+        }
+        for d in s
+            .results
+            .iter()
+            .filter(|r| !r.target.is_dns())
+            .filter_map(|r| r.rtt)
+        {
+            if self.rtts.len() == WINDOW {
+                self.rtts.pop_front();
+            }
+            self.rtts.push_back(d.as_secs_f64());
+        }
+    }
+
+    pub fn record_pace(&mut self, interval: u64, relaxed: bool) {
+        self.pace = Some((interval, relaxed));
+    }
+
+    pub fn record_window(&mut self, w: &WindowStats) {
+        self.window = Some(w.clone());
+    }
+
+    fn quantile(sorted: &[f64], q: f64) -> f64 {
+        let i = ((sorted.len() as f64 - 1.0) * q).round() as usize;
+        sorted[i]
+    }
+
+    pub fn render(&self) -> String {
+        let mut o = String::new();
+        o.push_str("# HELP watchdog_online Whether the last check reported ONLINE.\n# TYPE watchdog_online gauge\n");
+        let _ = writeln!(o, "watchdog_online {}", self.online as u8);
+        o.push_str("# HELP watchdog_state Current verdict, 1 for the active state.\n# TYPE watchdog_state gauge\n");
+        for st in STATES {
+            let _ = writeln!(
+                o,
+                "watchdog_state{{state=\"{}\"}} {}",
+                st.as_str(),
+                (self.state == Some(st)) as u8
+            );
+        }
+        o.push_str("# HELP watchdog_state_transitions_total Verdict changes since start.\n# TYPE watchdog_state_transitions_total counter\n");
+        let _ = writeln!(o, "watchdog_state_transitions_total {}", self.transitions);
+        o.push_str("# HELP watchdog_probe_success Whether the target answered in the last check.\n# TYPE watchdog_probe_success gauge\n");
+        for (n, t) in &self.targets {
+            let _ = writeln!(
+                o,
+                "watchdog_probe_success{{target=\"{}\",method=\"{}\"}} {}",
+                n, t.method, t.success as u8
+            );
+        }
+        o.push_str("# HELP watchdog_probe_rtt_seconds Round-trip time per target.\n# TYPE watchdog_probe_rtt_seconds histogram\n");
+        for (n, t) in &self.targets {
+            let l = format!("target=\"{}\",method=\"{}\"", n, t.method);
+            for (b, le) in t.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    o,
+                    "watchdog_probe_rtt_seconds_bucket{{{},le=\"{}\"}} {}",
+                    l, le, b
+                );
+            }
+            let _ = writeln!(
+                o,
+                "watchdog_probe_rtt_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                l, t.count
+            );
+            let _ = writeln!(o, "watchdog_probe_rtt_seconds_sum{{{}}} {}", l, t.sum);
+            let _ = writeln!(o, "watchdog_probe_rtt_seconds_count{{{}}} {}", l, t.count);
+        }
+        o.push_str("# HELP watchdog_interface_up Whether the link could be set up.\n# TYPE watchdog_interface_up gauge\n");
+        for (n, m) in &self.ifaces {
+            let _ = writeln!(o, "watchdog_interface_up{{iface=\"{}\"}} {}", n, m.up as u8);
+        }
+        o.push_str("# HELP watchdog_dhcp_renewals_total DHCP client runs per interface.\n# TYPE watchdog_dhcp_renewals_total counter\n");
+        for (n, m) in &self.ifaces {
+            let _ = writeln!(
+                o,
+                "watchdog_dhcp_renewals_total{{iface=\"{}\"}} {}",
+                n, m.dhcp_renewals
+            );
+        }
+        o.push_str("# HELP watchdog_dhcp_total DHCP client runs per interface by outcome.\n# TYPE watchdog_dhcp_total counter\n");
+        for (n, m) in &self.ifaces {
+            for (res, v) in [("success", m.dhcp_ok), ("failure", m.dhcp_failed)] {
+                let _ = writeln!(
+                    o,
+                    "watchdog_dhcp_total{{iface=\"{}\",result=\"{}\"}} {}",
+                    n, res, v
+                );
+            }
+        }
+        let counters: [(&str, &str, Counter); 6] = [
+            ("rx_bytes", "Bytes received.", |s| s.rx_bytes),
+            ("tx_bytes", "Bytes sent.", |s| s.tx_bytes),
+            ("rx_packets", "Packets received.", |s| s.rx_packets),
+            ("tx_packets", "Packets sent.", |s| s.tx_packets),
+            ("rx_errors", "Receive errors.", |s| s.rx_errors),
+            ("tx_errors", "Transmit errors.", |s| s.tx_errors),
+        ];
+        for (name, help, get) in counters {
+            let _ = writeln!(
+                o,
+                "# HELP watchdog_interface_{0}_total {1}\n# TYPE watchdog_interface_{0}_total counter",
+                name, help
+            );
+            for (n, m) in &self.ifaces {
+                let _ = writeln!(
+                    o,
+                    "watchdog_interface_{}_total{{iface=\"{}\"}} {}",
+                    name,
+                    n,
+                    get(&m.stats)
+                );
+            }
+        }
+        for (dir, i) in [("rx", 0), ("tx", 1)] {
+            let _ = writeln!(
+                o,
+                "# HELP watchdog_interface_{0}_bytes_per_second Throughput over the last cycle.\n# TYPE watchdog_interface_{0}_bytes_per_second gauge",
+                dir
+            );
+            for (n, m) in &self.ifaces {
+                if let Some(r) = m.rates {
+                    let v = if i == 0 { r.0 } else { r.1 };
+                    let _ = writeln!(
+                        o,
+                        "watchdog_interface_{}_bytes_per_second{{iface=\"{}\"}} {}",
+                        dir, n, v
+                    );
+                }
+            }
+        }
+        o.push_str("# HELP watchdog_interface_pmtu_bytes Path MTU to the first IP target at the last bring-up.\n# TYPE watchdog_interface_pmtu_bytes gauge\n");
+        for (n, m) in &self.ifaces {
+            if let Some(v) = m.pmtu {
+                let _ = writeln!(o, "watchdog_interface_pmtu_bytes{{iface=\"{}\"}} {}", n, v);
+            }
+        }
+        o.push_str("# HELP watchdog_lease_remaining_seconds Time left on the interface's DHCP lease.\n# TYPE watchdog_lease_remaining_seconds gauge\n");
+        let now = history::unix_now();
+        for (n, m) in &self.ifaces {
+            if let Some(t) = m.lease_expires {
+                let _ = writeln!(
+                    o,
+                    "watchdog_lease_remaining_seconds{{iface=\"{}\"}} {}",
+                    n,
+                    t.saturating_sub(now)
+                );
+            }
+        }
+        if let Some(v) = self.throughput {
+            o.push_str("# HELP watchdog_throughput_bytes_per_second Download rate of the last throughput check.\n# TYPE watchdog_throughput_bytes_per_second gauge\n");
+            let _ = writeln!(o, "watchdog_throughput_bytes_per_second {}", v);
+        }
+        o.push_str("# HELP watchdog_ping_latency_seconds Probe round-trip time over recent replies.\n# TYPE watchdog_ping_latency_seconds summary\n");
+        let mut s: Vec<f64> = self.rtts.iter().copied().collect();
+        s.sort_by(f64::total_cmp);
+        if !s.is_empty() {
+            for q in ["0.5", "0.95", "0.99"] {
+                let v = Metrics::quantile(&s, q.parse().unwrap());
+                let _ = writeln!(
+                    o,
+                    "watchdog_ping_latency_seconds{{quantile=\"{}\"}} {}",
+                    q, v
+                );
+            }
+        }
+        let _ = writeln!(
+            o,
+            "watchdog_ping_latency_seconds_sum {}",
+            s.iter().sum::<f64>()
+        );
+        let _ = writeln!(o, "watchdog_ping_latency_seconds_count {}", s.len());
+        if let Some(w) = &self.window {
+            o.push_str("# HELP watchdog_availability_ratio Share of the checks over the availability window that found the host up.\n# TYPE watchdog_availability_ratio gauge\n");
+            if let Some(r) = w.success_rate() {
+                let _ = writeln!(o, "watchdog_availability_ratio {}", r / 100.0);
+            }
+            o.push_str("# HELP watchdog_availability_checks Checks in the availability window.\n# TYPE watchdog_availability_checks gauge\n");
+            let _ = writeln!(o, "watchdog_availability_checks {}", w.checks);
+            o.push_str("# HELP watchdog_availability_rtt_p95_seconds 95th percentile of the checks' fastest reply over the window.\n# TYPE watchdog_availability_rtt_p95_seconds gauge\n");
+            if let Some(d) = w.p95 {
+                let _ = writeln!(
+                    o,
+                    "watchdog_availability_rtt_p95_seconds {}",
+                    d.as_secs_f64()
+                );
+            }
+            o.push_str("# HELP watchdog_check_streak Checks in a row up to the latest that found the host up, or not.\n# TYPE watchdog_check_streak gauge\n");
+            for (res, v) in [("success", w.successes), ("failure", w.failures)] {
+                let _ = writeln!(o, "watchdog_check_streak{{result=\"{}\"}} {}", res, v);
+            }
+            o.push_str("# HELP watchdog_probe_duty_cycle_ratio Share of the availability window spent probing.\n# TYPE watchdog_probe_duty_cycle_ratio gauge\n");
+            if let Some(d) = w.duty_cycle {
+                let _ = writeln!(o, "watchdog_probe_duty_cycle_ratio {}", d / 100.0);
+            }
+        }
+        if let Some((interval, relaxed)) = self.pace {
+            o.push_str("# HELP watchdog_check_interval_seconds Time from one check to the next, as adaptive probing paces them.\n# TYPE watchdog_check_interval_seconds gauge\n");
+            let _ = writeln!(o, "watchdog_check_interval_seconds {}", interval);
+            o.push_str("# HELP watchdog_check_relaxed Whether checks probe one target while the link stays healthy.\n# TYPE watchdog_check_relaxed gauge\n");
+            let _ = writeln!(o, "watchdog_check_relaxed {}", relaxed as u8);
+        }
+        o
+    }
+}
+
+/// Reads a request's head, within 5 seconds and 8 KiB; its request line.
+/// Headers are read and ignored, so the close is not a reset.
+pub fn read_request(c: &mut TcpStream) -> io::Result<String> {
+    let end = Instant::now() + REQUEST_TIME;
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        if let Some(n) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+            head.truncate(n);
+            break;
+        }
+        if head.len() >= REQUEST_MAX {
+            return Err(io::Error::new(ErrorKind::InvalidData, "request too long"));
+        }
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(ErrorKind::TimedOut.into());
+        }
+        c.set_read_timeout(Some(left))?;
+        let want = buf.len().min(REQUEST_MAX - head.len());
+        match c.read(&mut buf[..want])? {
+            0 => break,
+            n => head.extend_from_slice(&buf[..n]),
+        }
+    }
+    let line = head.split(|b| *b == b'\n').next().unwrap_or_default();
+    Ok(String::from_utf8_lossy(line).trim_end().to_string())
+}
+
+/// Accepts on `l` from a background thread and answers each connection
+/// with `f` on one of its own, up to 16 at once.
+pub fn accept<F>(l: TcpListener, f: F)
+where
+    F: Fn(&mut TcpStream) -> io::Result<()> + Send + Sync + 'static,
+{
+    let f = Arc::new(f);
+    let busy = Arc::new(AtomicUsize::new(0));
+    std::thread::spawn(move || {
+        for mut c in l.incoming().flatten() {
+            if busy.fetch_add(1, Ordering::SeqCst) >= CONNECTIONS {
+                busy.fetch_sub(1, Ordering::SeqCst);
+                continue;
+            }
+            let (f, busy) = (f.clone(), busy.clone());
+            std::thread::spawn(move || {
+                let _ = c.set_write_timeout(Some(REQUEST_TIME));
+                let _ = f(&mut c);
+                busy.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+}
+
+fn handle(c: &mut TcpStream, m: &Shared) -> io::Result<()> {
+    let line = read_request(c)?;
+    let path = line.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        ("200 OK", m.lock().unwrap().render())
+    } else {
+        (
+            "503 Service Unavailable",
+            "only /metrics is served\n".to_string(),
+        )
+    };
+    write!(
+        c,
+        "HTTP/1.0 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Binds `addr` and serves `/metrics` from a background thread.
+pub fn serve(addr: SocketAddr, m: Shared) -> io::Result<()> {
+    let l = TcpListener::bind(addr)?;
+    accept(l, move |c| handle(c, &m));
+    Ok(())
+}
@@ -0,0 +1,458 @@
+// State publishing over MQTT 3.1.1 for home-automation setups: each
+// connectivity change (or each check) goes to the broker as a retained
+// QoS 0 message, over a short-lived connection from a worker thread, so an
+// unreachable broker never holds up the loop.
+use crate::config::Config;
+use crate::json::Json;
+use crate::log;
+use crate::notify::{Notification, Notifier};
+#[cfg(unix)]
+use crate::sys;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// Between attempts while the broker is unreachable.
+const RETRY: Duration = Duration::from_secs(30);
+const KEEPALIVE: u16 = 60;
+
+/// Where and how to connect.
+struct Broker {
+    host: String,
+    port: u16,
+    tls: bool,
+    ca_file: Option<PathBuf>,
+    client_id: String,
+    username: Option<String>,
+    password: Option<String>,
+    timeout: Duration,
+}
+
+impl Broker {
+    fn from_cfg(cfg: &Config) -> Option<Broker> {
+        let (host, port) = cfg.mqtt_broker.clone()?;
+        Some(Broker {
+            port: port.unwrap_or(if cfg.mqtt_tls { 8883 } else { 1883 }),
+            host,
+            tls: cfg.mqtt_tls,
+            ca_file: cfg.mqtt_ca_file.clone(),
+            client_id: cfg.mqtt_client_id.clone(),
+            username: cfg.mqtt_username.clone(),
+            password: cfg.mqtt_password.clone(),
+            timeout: Duration::from_secs(cfg.mqtt_timeout),
+        })
+    }
+
+    fn addr(&self) -> String {
+        match self.host.contains(':') {
+            true => format!("[{}]:{}", self.host, self.port),
+            false => format!("{}:{}", self.host, self.port),
+        }
+    }
+
+    /// Connects, publishes `msgs` retained and disconnects.
+    fn publish(&self, msgs: &[(String, String)]) -> Result<(), String> {
+        let deadline = Instant::now() + self.timeout;
+        let mut c = Conn::open(self, deadline).map_err(|e| e.to_string())?;
+        c.send(&self.connect_packet())?;
+        let mut ack = [0u8; 4];
+        c.read_exact(&mut ack, deadline)?;
+        if ack[0] != 0x20 || ack[1] != 2 {
+            return Err("not an MQTT broker (no CONNACK)".into());
+        }
+        if ack[3] != 0 {
+            return Err(format!("connection refused: {}", refusal(ack[3])));
+        }
+        for (topic, payload) in msgs {
+            c.send(&publish_packet(topic, payload.as_bytes()))?;
+        }
+        c.send(&[0xe0, 0])?;
+        c.close(deadline);
+        Ok(())
+    }
+
+    fn connect_packet(&self) -> Vec<u8> {
+        let mut b = Vec::new();
+        string(&mut b, b"MQTT");
+        b.push(4);
+        // Clean session; a password only goes with a username.
+        let mut flags = 0x02;
+        if self.username.is_some() {
+            flags |= 0x80;
+            if self.password.is_some() {
+                flags |= 0x40;
+            }
+        }
+        b.push(flags);
+        b.extend_from_slice(&KEEPALIVE.to_be_bytes());
+        string(&mut b, self.client_id.as_bytes());
+        if let Some(u) = &self.username {
+            string(&mut b, u.as_bytes());
+            if let Some(p) = &self.password {
+                string(&mut b, p.as_bytes());
+            }
+        }
+        packet(0x10, &b)
+    }
+}
+
+fn refusal(rc: u8) -> &'static str {
+    match rc {
+        1 => "unacceptable protocol version",
+        2 => "client identifier rejected",
+        3 => "server unavailable",
+        4 => "bad username or password",
+        5 => "not authorized",
+        _ => "unknown return code",
+    }
+}
+
+/// A length-prefixed UTF-8 string.
+fn string(b: &mut Vec<u8>, s: &[u8]) {
+    b.extend_from_slice(&(s.len().min(u16::MAX as usize) as u16).to_be_bytes());
+    b.extend_from_slice(&s[..s.len().min(u16::MAX as usize)]);
+}
+
+/// A fixed header of `kind` and the remaining length, then `rest`.
+fn packet(kind: u8, rest: &[u8]) -> Vec<u8> {
+    let mut b = vec![kind];
+    let mut n = rest.len();
+    loop {
+        let mut d = (n % 128) as u8;
+        n /= 128;
+        if n > 0 {
+            d |= 0x80;
+        }
+        b.push(d);
+        if n == 0 {
+            break;
+        }
+    }
+    b.extend_from_slice(rest);
+    b
+}
+
+/// QoS 0 with the retain flag.
+fn publish_packet(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut b = Vec::new();
+    string(&mut b, topic.as_bytes());
+    b.extend_from_slice(payload);
+    packet(0x31, &b)
+}
+
+/// Plain TCP, or TLS through an `openssl s_client` child.
+enum Conn {
+    Tcp(TcpStream),
+    Tls(Child),
+}
+
+impl Conn {
+    fn open(b: &Broker, deadline: Instant) -> io::Result<Conn> {
+        if b.tls {
+            let mut cmd = Command::new("openssl");
+            cmd.args(["s_client", "-quiet", "-verify_return_error", "-connect"])
+                .arg(b.addr());
+            match b.host.parse::<IpAddr>() {
+                Ok(_) => cmd.args(["-verify_ip", &b.host]),
+                Err(_) => cmd.args(["-servername", &b.host, "-verify_hostname", &b.host]),
+            };
+            if let Some(ca) = &b.ca_file {
+                cmd.arg("-CAfile").arg(ca);
+            }
+            let child = cmd
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| match e.kind() {
+                    io::ErrorKind::NotFound => {
+                        io::Error::new(e.kind(), "tls needs openssl, which was not found")
+                    }
+                    _ => e,
+                })?;
+            return Ok(Conn::Tls(child));
+        }
+        let mut last = None;
+        for a in (b.host.as_str(), b.port).to_socket_addrs()? {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match TcpStream::connect_timeout(&a, left.max(Duration::from_millis(1))) {
+                Ok(s) => {
+                    s.set_write_timeout(Some(b.timeout))?;
+                    return Ok(Conn::Tcp(s));
+                }
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for host")))
+    }
+
+    fn send(&mut self, b: &[u8]) -> Result<(), String> {
+        let res = match self {
+            Conn::Tcp(s) => s.write_all(b),
+            Conn::Tls(c) => c.stdin.as_mut().map_or(Ok(()), |i| i.write_all(b)),
+        };
+        res.map_err(|e| self.why(e))
+    }
+
+    fn read_exact(&mut self, mut buf: &mut [u8], deadline: Instant) -> Result<(), String> {
+        while !buf.is_empty() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err("timed out waiting for the broker".into());
+            }
+            let res = match self {
+                Conn::Tcp(s) => s.set_read_timeout(Some(left)).and_then(|()| s.read(buf)),
+                #[cfg(unix)]
+                Conn::Tls(c) => {
+                    let out = c.stdout.as_mut().expect("piped");
+                    match sys::poll_in(&[out.as_raw_fd()], left) {
+                        Ok(r) if r[0] => out.read(buf),
+                        Ok(_) => continue,
+                        Err(e) => Err(e),
+                    }
+                }
+                // Pipes cannot be polled there: the read gets a thread,
+                // which killing `openssl` ends once out of time.
+                #[cfg(windows)]
+                Conn::Tls(c) => {
+                    let mut out = c.stdout.take().expect("piped");
+                    let (tx, rx) = mpsc::channel();
+                    let res = std::thread::scope(|s| {
+                        let reader = s.spawn(|| {
+                            let r = out.read(buf);
+                            let _ = tx.send(());
+                            r
+                        });
+                        let late = rx.recv_timeout(left).is_err();
+                        if late {
+                            let _ = c.kill();
+                        }
+                        let r = reader
+                            .join()
+                            .unwrap_or_else(|_| Err(io::Error::other("panicked")));
+                        if late {
+                            return Err(io::ErrorKind::TimedOut.into());
+                        }
+                        r
+                    });
+                    c.stdout = Some(out);
+                    res
+                }
+            };
+            match res {
+                Ok(0) => return Err(self.why(io::ErrorKind::UnexpectedEof.into())),
+                Ok(n) => buf = &mut buf[n..],
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(self.why(e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// What went wrong: for TLS, what `openssl` said when it gave up.
+    fn why(&mut self, e: io::Error) -> String {
+        let Conn::Tls(c) = self else {
+            return e.to_string();
+        };
+        let _ = c.kill();
+        let _ = c.wait();
+        let mut err = String::new();
+        if let Some(mut s) = c.stderr.take() {
+            let _ = s.read_to_string(&mut err);
+        }
+        // The last line is usually the verification or connect error.
+        match err.lines().rev().find(|l| !l.trim().is_empty()) {
+            Some(l) => format!("openssl: {}", l.trim()),
+            None => e.to_string(),
+        }
+    }
+
+    /// Lets the broker take the DISCONNECT and hang up, up to `deadline`.
+    fn close(mut self, deadline: Instant) {
+        if let Conn::Tcp(s) = &self {
+            let _ = s.shutdown(std::net::Shutdown::Write);
+            return;
+        }
+        // Buffered data only reaches the broker if openssl is left running
+        // until it closes the connection.
+        let mut b = [0u8; 1];
+        while self.read_exact(&mut b, deadline).is_ok() {}
+    }
+}
+
+impl Drop for Conn {
+    fn drop(&mut self) {
+        if let Conn::Tls(c) = self {
+            let _ = c.kill();
+            let _ = c.wait();
+        }
+    }
+}
+
+/// Publishes connectivity changes (not link flips) as JSON on `[mqtt]
+/// topic`, plus Home Assistant discovery configs on startup. While the
+/// broker is unreachable the latest message per topic is held and retried.
+/// With `dry_run` it only logs what it would publish.
+pub struct MqttNotifier {
+    tx: Option<Sender<(String, String)>>,
+    topic: String,
+    every_check: bool,
+}
+
+impl MqttNotifier {
+    pub fn new(cfg: &Config, dry_run: bool) -> MqttNotifier {
+        let first = if cfg.mqtt_discovery {
+            discovery(cfg)
+        } else {
+            Vec::new()
+        };
+        let broker = Broker::from_cfg(cfg).filter(|_| !dry_run);
+        let tx = match broker {
+            None => {
+                for (t, p) in &first {
+                    log::info!("mqtt (dry run): {} {}", t, p);
+                }
+                None
+            }
+            Some(b) => {
+                let (tx, rx) = mpsc::channel();
+                for m in first {
+                    let _ = tx.send(m);
+                }
+                std::thread::spawn(move || worker(b, rx));
+                Some(tx)
+            }
+        };
+        MqttNotifier {
+            tx,
+            topic: cfg.mqtt_topic.clone(),
+            every_check: cfg.mqtt_every_check,
+        }
+    }
+
+    fn publish(&self, n: &Notification) {
+        if n.iface.is_some() {
+            return;
+        }
+        let body = n.to_json().to_string();
+        match &self.tx {
+            Some(tx) => {
+                let _ = tx.send((self.topic.clone(), body));
+            }
+            None => log::info!("mqtt (dry run): {} {}", self.topic, body),
+        }
+    }
+}
+
+impl Notifier for MqttNotifier {
+    fn notify(&self, n: &Notification) {
+        self.publish(n);
+    }
+
+    fn checked(&self, n: &Notification) {
+        if self.every_check {
+            self.publish(n);
+        }
+    }
+}
+
+fn worker(b: Broker, rx: mpsc::Receiver<(String, String)>) {
+    let mut pending: Vec<(String, String)> = Vec::new();
+    let mut down = false;
+    let queue = |pending: &mut Vec<(String, String)>, (t, p): (String, String)| match pending
+        .iter_mut()
+        .find(|(q, _)| *q == t)
+    {
+        Some(m) => m.1 = p,
+        None => pending.push((t, p)),
+    };
+    loop {
+        let m = match pending.is_empty() {
+            true => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            false => rx.recv_timeout(RETRY),
+        };
+        match m {
+            Ok(m) => queue(&mut pending, m),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        while let Ok(m) = rx.try_recv() {
+            queue(&mut pending, m);
+        }
+        match b.publish(&pending) {
+            Ok(()) => {
+                if down {
+                    log::info!("mqtt: {}: reachable again", b.addr());
+                }
+                for (t, p) in pending.drain(..) {
+                    log::debug!("mqtt: published {} {}", t, p);
+                }
+                down = false;
+            }
+            Err(e) if down => log::debug!("mqtt: {}: {}", b.addr(), e),
+            Err(e) => {
+                log::warn!(
+                    "mqtt: {}: {}; holding the latest state until it is back",
+                    b.addr(),
+                    e
+                );
+                down = true;
+            }
+        }
+    }
+}
+
+/// Home Assistant discovery: a connectivity binary sensor and a state
+/// sensor reading the state topic, under one device named by the client ID.
+fn discovery(cfg: &Config) -> Vec<(String, String)> {
+    let node: String = cfg
+        .mqtt_client_id
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+    let device = || {
+        Json::obj()
+            .set("identifiers", vec![node.as_str()])
+            .set("name", format!("cyber-watchdog {}", cfg.mqtt_client_id))
+            .set("sw_version", env!("CARGO_PKG_VERSION"))
+    };
+    let topic = |component: &str, object: &str| {
+        format!(
+            "{}/{}/{}/{}/config",
+            cfg.mqtt_discovery_prefix, component, node, object
+        )
+    };
+    let connectivity = Json::obj()
+        .set("name", "Internet")
+        .set("unique_id", format!("{}_connectivity", node))
+        .set("state_topic", cfg.mqtt_topic.as_str())
+        .set("device_class", "connectivity")
+        .set(
+            "value_template",
+            "{{ 'ON' if value_json.status == 'ONLINE' else 'OFF' }}",
+        )
+        .set("device", device());
+    let state = Json::obj()
+        .set("name", "State")
+        .set("unique_id", format!("{}_state", node))
+        .set("state_topic", cfg.mqtt_topic.as_str())
+        .set("value_template", "{{ value_json.status }}")
+        .set("json_attributes_topic", cfg.mqtt_topic.as_str())
+        .set("device", device());
+    vec![
+        (
+            topic("binary_sensor", "connectivity"),
+            connectivity.to_string(),
+        ),
+        (topic("sensor", "state"), state.to_string()),
+    ]
+}
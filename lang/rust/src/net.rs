@@ -0,0 +1,398 @@
+#[cfg(target_os = "linux")]
+use crate::{netlink, sys};
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+pub struct Route {
+    pub iface: String,
+    pub gateway: Ipv4Addr,
+}
+
+// Off Linux the links, their state and counters and the routes come from
+// getifaddrs(3) and the routing sysctl, or GetAdaptersAddresses.
+#[cfg(not(target_os = "linux"))]
+pub use crate::ifaddrs::{
+    admin_up, arp_resolved, carrier, carrier_changes, default_routes, exists, has_default_v6,
+    index, ipv4_addr, mac_addr, on_link, read_duplex, read_iface_stats, read_link_speed,
+    read_oper_state,
+};
+
+#[cfg(target_os = "linux")]
+const RTF_UP: u32 = 0x1;
+#[cfg(target_os = "linux")]
+const IFF_UP: u32 = 0x1;
+
+/// IPv4 default routes from `/proc/net/route`.
+#[cfg(target_os = "linux")]
+pub fn default_routes() -> Vec<Route> {
+    let Ok(s) = fs::read_to_string("/proc/thread-self/net/route") else {
+        return Vec::new();
+    };
+    s.lines()
+        .skip(1)
+        .filter_map(|l| {
+            // Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+            let f: Vec<&str> = l.split_whitespace().collect();
+            let hex = |i: usize| f.get(i).and_then(|v| u32::from_str_radix(v, 16).ok());
+            if hex(1)? != 0 || hex(7)? != 0 || hex(3)? & RTF_UP == 0 {
+                return None;
+            }
+            Some(Route {
+                iface: f[0].to_string(),
+                // The kernel prints addresses in host (little-endian) order.
+                gateway: Ipv4Addr::from(hex(2)?.swap_bytes()),
+            })
+        })
+        .collect()
+}
+
+/// Whether a connected route on `iface` (no gateway of its own) covers
+/// `gw`, which is what makes the kernel take it as on-link.
+#[cfg(target_os = "linux")]
+pub fn on_link(iface: &str, gw: Ipv4Addr) -> bool {
+    let Ok(s) = fs::read_to_string("/proc/thread-self/net/route") else {
+        return false;
+    };
+    let gw = u32::from(gw).swap_bytes();
+    s.lines().skip(1).any(|l| {
+        let f: Vec<&str> = l.split_whitespace().collect();
+        let hex = |i: usize| f.get(i).and_then(|v| u32::from_str_radix(v, 16).ok());
+        let (Some(dst), Some(0), Some(mask)) = (hex(1), hex(2), hex(7)) else {
+            return false;
+        };
+        f[0] == iface && mask != 0 && gw & mask == dst & mask
+    })
+}
+
+/// Whether an IPv6 default route exists, per `/proc/net/ipv6_route`; the
+/// kernel's own unreachable entries on `lo` do not count.
+#[cfg(target_os = "linux")]
+pub fn has_default_v6() -> bool {
+    const RTF_REJECT: u32 = 0x200;
+    let Ok(s) = fs::read_to_string("/proc/thread-self/net/ipv6_route") else {
+        return false;
+    };
+    s.lines().any(|l| {
+        // dst dst_len src src_len next_hop metric refcnt use flags iface
+        let f: Vec<&str> = l.split_whitespace().collect();
+        let flags = f.get(8).and_then(|v| u32::from_str_radix(v, 16).ok());
+        f.len() >= 10
+            && f[0].bytes().all(|b| b == b'0')
+            && f[1] == "00"
+            && f[9] != "lo"
+            && flags.is_some_and(|x| x & RTF_UP != 0 && x & RTF_REJECT == 0)
+    })
+}
+
+/// Counters of one interface, from `/sys/class/net/<iface>/statistics` on
+/// Linux.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IfaceStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+}
+
+/// `None` when the interface is gone.
+#[cfg(target_os = "linux")]
+pub fn read_iface_stats(iface: &str) -> Option<IfaceStats> {
+    let dir = format!("/sys/class/net/{}/statistics", iface);
+    let read = |f: &str| -> Option<u64> {
+        fs::read_to_string(format!("{}/{}", dir, f))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+    Some(IfaceStats {
+        rx_bytes: read("rx_bytes")?,
+        tx_bytes: read("tx_bytes")?,
+        rx_packets: read("rx_packets").unwrap_or(0),
+        tx_packets: read("tx_packets").unwrap_or(0),
+        rx_errors: read("rx_errors").unwrap_or(0),
+        tx_errors: read("tx_errors").unwrap_or(0),
+    })
+}
+
+/// How far a counter moved from `prev` to `now`. One that went backwards
+/// from within 32 bits is taken to have wrapped (32-bit kernels keep
+/// `unsigned long` counters); any other drop is a reset, and `None`.
+pub fn counter_delta(prev: u64, now: u64) -> Option<u64> {
+    match now.checked_sub(prev) {
+        Some(d) => Some(d),
+        None if prev <= u32::MAX as u64 => Some(now + (1 << 32) - prev),
+        None => None,
+    }
+}
+
+/// Whether a link of that name exists in this namespace now.
+#[cfg(target_os = "linux")]
+pub fn exists(iface: &str) -> bool {
+    index(iface).is_some()
+}
+
+#[cfg(target_os = "linux")]
+pub fn index(iface: &str) -> Option<u32> {
+    netlink::index(iface).ok()
+}
+
+#[cfg(target_os = "linux")]
+pub fn ipv4_addr(iface: &str) -> Option<Ipv4Addr> {
+    sys::if_ipv4(iface).ok()
+}
+
+/// `/sys/class/net/<if>/address`; `None` for links without one.
+#[cfg(target_os = "linux")]
+pub fn mac_addr(iface: &str) -> Option<String> {
+    let s = fs::read_to_string(format!("/sys/class/net/{}/address", iface)).ok()?;
+    let s = s.trim();
+    (!s.is_empty() && s.split(':').any(|h| h != "00")).then(|| s.to_string())
+}
+
+/// Negotiated speed in Mbps from `/sys/class/net/<if>/speed`; `None` while
+/// the link is down and for links that do not report one (-1, or the
+/// all-ones "unknown" of virtual ones).
+#[cfg(target_os = "linux")]
+pub fn read_link_speed(iface: &str) -> Option<u32> {
+    let s = fs::read_to_string(format!("/sys/class/net/{}/speed", iface)).ok()?;
+    s.trim()
+        .parse::<i64>()
+        .ok()
+        .and_then(|v| u32::try_from(v).ok())
+        .filter(|&v| v > 0 && v != u32::MAX)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplex {
+    Full,
+    Half,
+}
+
+impl Duplex {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Duplex::Full => "full",
+            Duplex::Half => "half",
+        }
+    }
+}
+
+/// `/sys/class/net/<if>/duplex`; `None` when it says `unknown` or cannot
+/// be read.
+#[cfg(target_os = "linux")]
+pub fn read_duplex(iface: &str) -> Option<Duplex> {
+    match fs::read_to_string(format!("/sys/class/net/{}/duplex", iface))
+        .ok()?
+        .trim()
+    {
+        "full" => Some(Duplex::Full),
+        "half" => Some(Duplex::Half),
+        _ => None,
+    }
+}
+
+/// IFF_UP in `/sys/class/net/<if>/flags`: set administratively up, with or
+/// without carrier.
+#[cfg(target_os = "linux")]
+pub fn admin_up(iface: &str) -> bool {
+    fs::read_to_string(format!("/sys/class/net/{}/flags", iface))
+        .ok()
+        .and_then(|s| u32::from_str_radix(s.trim().trim_start_matches("0x"), 16).ok())
+        .is_some_and(|f| f & IFF_UP != 0)
+}
+
+/// RFC 2863 operational state, as `/sys/class/net/<if>/operstate` has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperState {
+    Up,
+    Down,
+    /// What drivers without carrier reporting say, `lo` among them.
+    Unknown,
+    LowerLayerDown,
+    Testing,
+    Dormant,
+    NotPresent,
+}
+
+impl OperState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OperState::Up => "up",
+            OperState::Down => "down",
+            OperState::Unknown => "unknown",
+            OperState::LowerLayerDown => "lowerlayerdown",
+            OperState::Testing => "testing",
+            OperState::Dormant => "dormant",
+            OperState::NotPresent => "notpresent",
+        }
+    }
+}
+
+/// `Unknown` also when the file is missing or holds something else.
+#[cfg(target_os = "linux")]
+pub fn read_oper_state(iface: &str) -> OperState {
+    let s = fs::read_to_string(format!("/sys/class/net/{}/operstate", iface)).unwrap_or_default();
+    match s.trim() {
+        "up" => OperState::Up,
+        "down" => OperState::Down,
+        "lowerlayerdown" => OperState::LowerLayerDown,
+        "testing" => OperState::Testing,
+        "dormant" => OperState::Dormant,
+        "notpresent" => OperState::NotPresent,
+        _ => OperState::Unknown,
+    }
+}
+
+/// `/sys/class/net/<if>/carrier`, or the operstate where that cannot be
+/// read (EINVAL while the link is down); `None` when neither tells.
+#[cfg(target_os = "linux")]
+pub fn carrier(iface: &str) -> Option<bool> {
+    if let Ok(s) = fs::read_to_string(format!("/sys/class/net/{}/carrier", iface)) {
+        return Some(s.trim() == "1");
+    }
+    match read_oper_state(iface) {
+        OperState::Up => Some(true),
+        OperState::Down | OperState::LowerLayerDown | OperState::NotPresent => Some(false),
+        _ => None,
+    }
+}
+
+/// Carrier changes since the interface was created; `None` when it is gone
+/// or the kernel does not count them.
+#[cfg(target_os = "linux")]
+pub fn carrier_changes(iface: &str) -> Option<u64> {
+    fs::read_to_string(format!("/sys/class/net/{}/carrier_changes", iface))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Carrier positively present, e.g. to notice a cable being plugged in.
+pub fn has_carrier(iface: &str) -> bool {
+    carrier(iface) == Some(true)
+}
+
+/// Up already, or `unknown` with carrier: setting it up again would change
+/// nothing.
+pub fn already_up(iface: &str) -> bool {
+    match read_oper_state(iface) {
+        OperState::Up => true,
+        OperState::Unknown => admin_up(iface) && carrier(iface) != Some(false),
+        _ => false,
+    }
+}
+
+/// Polls for carrier for up to `wait`; some drivers only report it a
+/// moment after the link was set up.
+pub fn wait_carrier(iface: &str, wait: Duration) -> Option<bool> {
+    let end = Instant::now() + wait;
+    loop {
+        let c = carrier(iface);
+        if c != Some(false) || Instant::now() >= end {
+            return c;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Whether `/proc/net/arp` has a complete entry for `ip` on `iface`: the
+/// neighbour answered ARP, whether or not it answers pings.
+#[cfg(target_os = "linux")]
+pub fn arp_resolved(ip: Ipv4Addr, iface: &str) -> bool {
+    let Ok(s) = fs::read_to_string("/proc/thread-self/net/arp") else {
+        return false;
+    };
+    // IP address  HW type  Flags  HW address  Mask  Device; 0x2 = ATF_COM
+    s.lines().skip(1).any(|l| {
+        let f: Vec<&str> = l.split_whitespace().collect();
+        f.len() >= 6
+            && f[0].parse() == Ok(ip)
+            && f[5] == iface
+            && u32::from_str_radix(f[2].trim_start_matches("0x"), 16).is_ok_and(|v| v & 0x2 != 0)
+    })
+}
+
+/// Gateway of the default route through `iface`, if there is one.
+pub fn gateway(iface: &str) -> Option<Ipv4Addr> {
+    default_routes()
+        .into_iter()
+        .find(|r| r.iface == iface)
+        .map(|r| r.gateway)
+}
+
+/// An address range, `10.0.0.0/8` or `2001:db8::/32`; a bare address is
+/// the one address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, a: IpAddr) -> bool {
+        let bits = |a: IpAddr| match a {
+            IpAddr::V4(a) => (u128::from(u32::from(a)) << 96, 32),
+            IpAddr::V6(a) => (u128::from(a), 128),
+        };
+        let ((net, width), (a, a_width)) = (bits(self.addr), bits(a));
+        if width != a_width {
+            return false;
+        }
+        let mask = u128::MAX
+            .checked_shl(128 - u32::from(self.prefix_len))
+            .unwrap_or(0);
+        net & mask == a & mask
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (a, len) = s.split_once('/').unwrap_or((s, ""));
+        let addr: IpAddr = a.parse().map_err(|_| format!("{}: not an address", a))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match len {
+            "" => max,
+            l => l
+                .parse()
+                .ok()
+                .filter(|&l| l <= max)
+                .ok_or_else(|| format!("{}: prefix length must be 0-{}", s, max))?,
+        };
+        Ok(Cidr { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Loopback, link-local, RFC 1918, shared (CGNAT) and unique local
+/// addresses: where no public check host lives.
+pub fn is_private(a: IpAddr) -> bool {
+    match a {
+        IpAddr::V4(a) => {
+            let o = a.octets();
+            a.is_unspecified()
+                || a.is_loopback()
+                || a.is_link_local()
+                || a.is_private()
+                || (o[0] == 100 && o[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(a) => {
+            let hi = a.segments()[0];
+            a.is_unspecified() || a.is_loopback() || hi & 0xfe00 == 0xfc00 || hi & 0xffc0 == 0xfe80
+        }
+    }
+}
@@ -0,0 +1,668 @@
+// rtnetlink without iproute2: link listing, IFF_UP, addresses, the
+// default route, route lookups and neighbour entries over an
+// AF_NETLINK/NETLINK_ROUTE socket.
+use crate::sys;
+use std::ffi::CString;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::time::Duration;
+
+const AF_NETLINK: c_int = 16;
+const NETLINK_ROUTE: c_int = 0;
+const RTMGRP_LINK: u32 = 1;
+const MSG_DONTWAIT: c_int = 0x40;
+
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const RTM_NEWLINK: u16 = 16;
+const RTM_DELLINK: u16 = 17;
+const RTM_GETLINK: u16 = 18;
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+const RTM_GETADDR: u16 = 22;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_DELROUTE: u16 = 25;
+const RTM_GETROUTE: u16 = 26;
+const RTM_NEWNEIGH: u16 = 28;
+const RTM_DELNEIGH: u16 = 29;
+const RTM_GETNEIGH: u16 = 30;
+
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+const NLM_F_REPLACE: u16 = 0x100;
+const NLM_F_EXCL: u16 = 0x200;
+const NLM_F_DUMP: u16 = 0x300;
+const NLM_F_CREATE: u16 = 0x400;
+
+const IFF_UP: u32 = 0x1;
+const IFLA_IFNAME: u16 = 3;
+const IFLA_LINK: u16 = 5;
+const IFLA_MASTER: u16 = 10;
+const IFLA_LINKINFO: u16 = 18;
+const IFLA_INFO_KIND: u16 = 1;
+const IFLA_INFO_DATA: u16 = 2;
+const IFLA_VLAN_ID: u16 = 1;
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+const IFA_CACHEINFO: u16 = 6;
+const RTA_DST: u16 = 1;
+const RTA_SRC: u16 = 2;
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_PREFSRC: u16 = 7;
+const RTA_MARK: u16 = 16;
+const NDA_DST: u16 = 1;
+const NDA_LLADDR: u16 = 2;
+const NUD_INCOMPLETE: u16 = 0x01;
+const NUD_FAILED: u16 = 0x20;
+
+const RT_TABLE_MAIN: u8 = 254;
+const RTPROT_BOOT: u8 = 3;
+const RTN_UNICAST: u8 = 1;
+
+extern "C" {
+    fn send(fd: c_int, buf: *const c_void, len: usize, flags: c_int) -> isize;
+    fn recv(fd: c_int, buf: *mut c_void, len: usize, flags: c_int) -> isize;
+    fn bind(fd: c_int, addr: *const c_void, len: u32) -> c_int;
+    fn if_nametoindex(name: *const c_char) -> c_uint;
+    fn if_indextoname(index: c_uint, name: *mut c_char) -> *mut c_char;
+}
+
+/// One entry of an RTM_GETLINK dump.
+pub struct Link {
+    pub index: u32,
+    pub name: String,
+    /// ARPHRD_*.
+    pub arp_type: u16,
+    /// Index of the bridge or bond this link is enslaved to.
+    pub master: Option<u32>,
+    /// IFLA_LINK: a VLAN's parent, a veth's peer.
+    pub parent: Option<u32>,
+    /// The driver's kind of link (`bond`, `team`, `veth`, ...); `None` for
+    /// physical devices.
+    pub kind: Option<String>,
+}
+
+/// What the kernel keeps about an IPv4 address besides the address.
+pub struct AddrInfo {
+    pub prefix_len: u8,
+    /// Seconds of valid lifetime left; `None` for `forever`, as addresses
+    /// added without a lifetime are.
+    pub valid: Option<u32>,
+    /// When the address was last added or replaced, in hundredths of a
+    /// second since boot.
+    pub updated: u32,
+}
+
+/// A neighbour entry: what ARP found out about an address on a link.
+pub struct Neighbour {
+    /// NUD_*.
+    pub state: u16,
+    /// The link-layer address, while it has one.
+    pub lladdr: Option<Vec<u8>>,
+}
+
+impl Neighbour {
+    /// The state as `ip neigh` names it.
+    pub fn state_str(&self) -> &'static str {
+        match self.state {
+            0x00 => "NONE",
+            0x01 => "INCOMPLETE",
+            0x02 => "REACHABLE",
+            0x04 => "STALE",
+            0x08 => "DELAY",
+            0x10 => "PROBE",
+            0x20 => "FAILED",
+            0x40 => "NOARP",
+            0x80 => "PERMANENT",
+            _ => "UNKNOWN",
+        }
+    }
+
+    /// Resolution was tried and got no answer, or is still waiting for one.
+    pub fn failed(&self) -> bool {
+        self.state & (NUD_INCOMPLETE | NUD_FAILED) != 0
+    }
+
+    /// `aa:bb:cc:dd:ee:ff`.
+    pub fn mac(&self) -> Option<String> {
+        let a = self.lladdr.as_ref().filter(|a| !a.is_empty())?;
+        let hex: Vec<String> = a.iter().map(|b| format!("{:02x}", b)).collect();
+        Some(hex.join(":"))
+    }
+}
+
+fn align(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// A request being assembled: header, fixed part, then attributes.
+struct Msg(Vec<u8>);
+
+impl Msg {
+    fn new(ty: u16, flags: u16, body: &[u8]) -> Msg {
+        let mut b = Vec::with_capacity(64);
+        b.extend_from_slice(&0u32.to_ne_bytes()); // length, set in send()
+        b.extend_from_slice(&ty.to_ne_bytes());
+        b.extend_from_slice(&(flags | NLM_F_REQUEST).to_ne_bytes());
+        b.extend_from_slice(&1u32.to_ne_bytes()); // seq
+        b.extend_from_slice(&0u32.to_ne_bytes()); // pid: the kernel fills it in
+        b.extend_from_slice(body);
+        Msg(b)
+    }
+
+    fn attr(mut self, ty: u16, data: &[u8]) -> Msg {
+        self.0.extend_from_slice(&rta(ty, data));
+        self
+    }
+}
+
+/// One attribute, padded; nested ones are attributes as data.
+fn rta(ty: u16, data: &[u8]) -> Vec<u8> {
+    let mut b = Vec::with_capacity(align(4 + data.len()));
+    b.extend_from_slice(&((4 + data.len()) as u16).to_ne_bytes());
+    b.extend_from_slice(&ty.to_ne_bytes());
+    b.extend_from_slice(data);
+    b.resize(align(b.len()), 0);
+    b
+}
+
+/// `struct ifinfomsg`.
+fn ifinfo(index: u32, flags: u32, change: u32) -> [u8; 16] {
+    let mut b = [0u8; 16];
+    b[4..8].copy_from_slice(&index.to_ne_bytes());
+    b[8..12].copy_from_slice(&flags.to_ne_bytes());
+    b[12..16].copy_from_slice(&change.to_ne_bytes());
+    b
+}
+
+/// Replies are bounded so a wedged kernel request cannot hang bring-up.
+fn open() -> io::Result<OwnedFd> {
+    let fd = sys::socket(AF_NETLINK, sys::SOCK_RAW, NETLINK_ROUTE)?;
+    sys::recv_timeout(&fd, Duration::from_secs(5))?;
+    Ok(fd)
+}
+
+fn send_msg(fd: &OwnedFd, mut m: Msg) -> io::Result<()> {
+    let len = m.0.len() as u32;
+    m.0[..4].copy_from_slice(&len.to_ne_bytes());
+    if unsafe { send(fd.as_raw_fd(), m.0.as_ptr().cast(), m.0.len(), 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Calls `f(type, payload)` for every message until the kernel says done
+/// or acknowledges; an NLMSG_ERROR with a non-zero code becomes `Err`.
+fn recv_all(fd: &OwnedFd, mut f: impl FnMut(u16, &[u8])) -> io::Result<()> {
+    let mut buf = vec![0u8; 32 * 1024];
+    loop {
+        let n = unsafe { recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut b = &buf[..n as usize];
+        while b.len() >= 16 {
+            let len = u32::from_ne_bytes(b[0..4].try_into().unwrap()) as usize;
+            let ty = u16::from_ne_bytes(b[4..6].try_into().unwrap());
+            if len < 16 || len > b.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "short netlink message",
+                ));
+            }
+            let payload = &b[16..len];
+            match ty {
+                NLMSG_DONE => return Ok(()),
+                NLMSG_ERROR => {
+                    let code = payload
+                        .get(..4)
+                        .map_or(0, |c| i32::from_ne_bytes(c.try_into().unwrap()));
+                    return match code {
+                        0 => Ok(()),
+                        e => Err(io::Error::from_raw_os_error(-e)),
+                    };
+                }
+                _ => f(ty, payload),
+            }
+            b = &b[align(len).min(b.len())..];
+        }
+    }
+}
+
+/// Runs a request that only wants an acknowledgement.
+fn request(m: Msg) -> io::Result<()> {
+    let fd = open()?;
+    send_msg(&fd, m)?;
+    recv_all(&fd, |_, _| {})
+}
+
+/// Every link the kernel knows about, loopback included.
+pub fn list_links() -> io::Result<Vec<Link>> {
+    let fd = open()?;
+    send_msg(&fd, Msg::new(RTM_GETLINK, NLM_F_DUMP, &ifinfo(0, 0, 0)))?;
+    let mut links = Vec::new();
+    recv_all(&fd, |ty, p| {
+        if ty != RTM_NEWLINK || p.len() < 16 {
+            return;
+        }
+        let mut l = Link {
+            index: u32::from_ne_bytes(p[4..8].try_into().unwrap()),
+            name: String::new(),
+            arp_type: u16::from_ne_bytes(p[2..4].try_into().unwrap()),
+            master: None,
+            parent: None,
+            kind: None,
+        };
+        let mut a = &p[16..];
+        while a.len() >= 4 {
+            let len = u16::from_ne_bytes([a[0], a[1]]) as usize;
+            let ty = u16::from_ne_bytes([a[2], a[3]]) & 0x3fff;
+            if len < 4 || len > a.len() {
+                break;
+            }
+            let data = &a[4..len];
+            match ty {
+                IFLA_IFNAME => {
+                    let end = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+                    l.name = String::from_utf8_lossy(&data[..end]).into_owned();
+                }
+                IFLA_MASTER if data.len() >= 4 => {
+                    l.master = Some(u32::from_ne_bytes(data[..4].try_into().unwrap()))
+                }
+                IFLA_LINK if data.len() >= 4 => {
+                    l.parent = Some(u32::from_ne_bytes(data[..4].try_into().unwrap()))
+                }
+                IFLA_LINKINFO => l.kind = info_kind(data),
+                _ => {}
+            }
+            a = &a[align(len).min(a.len())..];
+        }
+        links.push(l);
+    })?;
+    Ok(links)
+}
+
+/// IFLA_INFO_KIND from the attributes nested in IFLA_LINKINFO.
+fn info_kind(mut a: &[u8]) -> Option<String> {
+    while a.len() >= 4 {
+        let len = u16::from_ne_bytes([a[0], a[1]]) as usize;
+        let ty = u16::from_ne_bytes([a[2], a[3]]) & 0x3fff;
+        if len < 4 || len > a.len() {
+            break;
+        }
+        if ty == IFLA_INFO_KIND {
+            let data = &a[4..len];
+            let end = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+            return Some(String::from_utf8_lossy(&data[..end]).into_owned());
+        }
+        a = &a[align(len).min(a.len())..];
+    }
+    None
+}
+
+pub fn index(name: &str) -> io::Result<u32> {
+    let c = CString::new(name).map_err(|_| io::ErrorKind::InvalidInput)?;
+    match unsafe { if_nametoindex(c.as_ptr()) } {
+        0 => Err(io::Error::last_os_error()),
+        i => Ok(i),
+    }
+}
+
+pub fn name(index: u32) -> io::Result<String> {
+    let mut b = [0 as c_char; 16];
+    if unsafe { if_indextoname(index, b.as_mut_ptr()) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let b: Vec<u8> = b
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c.to_ne_bytes()[0])
+        .collect();
+    Ok(String::from_utf8_lossy(&b).into_owned())
+}
+
+/// Sets IFF_UP on link `index` (RTM_NEWLINK).
+pub fn set_link_up(index: u32) -> io::Result<()> {
+    request(Msg::new(
+        RTM_NEWLINK,
+        NLM_F_ACK,
+        &ifinfo(index, IFF_UP, IFF_UP),
+    ))
+}
+
+/// Clears IFF_UP on link `index`.
+pub fn set_link_down(index: u32) -> io::Result<()> {
+    request(Msg::new(RTM_NEWLINK, NLM_F_ACK, &ifinfo(index, 0, IFF_UP)))
+}
+
+fn addr_msg(ty: u16, flags: u16, index: u32, addr: Ipv4Addr, prefix: u8) -> Msg {
+    // struct ifaddrmsg: family, prefixlen, flags, scope, index
+    let mut body = [sys::AF_INET as u8, prefix, 0, 0, 0, 0, 0, 0];
+    body[4..8].copy_from_slice(&index.to_ne_bytes());
+    Msg::new(ty, NLM_F_ACK | flags, &body)
+        .attr(IFA_LOCAL, &addr.octets())
+        .attr(IFA_ADDRESS, &addr.octets())
+}
+
+/// Adds `addr/prefix` to link `index`, replacing it if already there.
+pub fn replace_address(index: u32, addr: Ipv4Addr, prefix: u8) -> io::Result<()> {
+    request(addr_msg(
+        RTM_NEWADDR,
+        NLM_F_CREATE | NLM_F_REPLACE,
+        index,
+        addr,
+        prefix,
+    ))
+}
+
+/// Removes `addr/prefix` from link `index`.
+pub fn delete_address(index: u32, addr: Ipv4Addr, prefix: u8) -> io::Result<()> {
+    request(addr_msg(RTM_DELADDR, 0, index, addr, prefix))
+}
+
+/// The prefix and lifetime of `addr` on link `index`, if it has it.
+pub fn addr_info(index: u32, addr: Ipv4Addr) -> io::Result<Option<AddrInfo>> {
+    let fd = open()?;
+    let body = [sys::AF_INET as u8, 0, 0, 0, 0, 0, 0, 0];
+    send_msg(&fd, Msg::new(RTM_GETADDR, NLM_F_DUMP, &body))?;
+    let mut found = None;
+    recv_all(&fd, |ty, p| {
+        if ty != RTM_NEWADDR || p.len() < 8 {
+            return;
+        }
+        if u32::from_ne_bytes(p[4..8].try_into().unwrap()) != index {
+            return;
+        }
+        let (mut local, mut cache) = (None, None);
+        let mut a = &p[8..];
+        while a.len() >= 4 {
+            let len = u16::from_ne_bytes([a[0], a[1]]) as usize;
+            let ty = u16::from_ne_bytes([a[2], a[3]]);
+            if len < 4 || len > a.len() {
+                break;
+            }
+            let data = &a[4..len];
+            match ty {
+                IFA_LOCAL if data.len() == 4 => {
+                    local = Some(Ipv4Addr::new(data[0], data[1], data[2], data[3]))
+                }
+                // struct ifa_cacheinfo: preferred, valid, cstamp, tstamp
+                IFA_CACHEINFO if data.len() >= 16 => {
+                    let word = |i: usize| u32::from_ne_bytes(data[i..i + 4].try_into().unwrap());
+                    cache = Some((word(4), word(12)));
+                }
+                _ => {}
+            }
+            a = &a[align(len).min(a.len())..];
+        }
+        if let (Some(l), Some((valid, updated))) = (local, cache) {
+            if l == addr && found.is_none() {
+                found = Some(AddrInfo {
+                    prefix_len: p[1],
+                    valid: (valid != u32::MAX).then_some(valid),
+                    updated,
+                });
+            }
+        }
+    })?;
+    Ok(found)
+}
+
+fn default_route_msg(ty: u16, flags: u16, index: u32, gw: Ipv4Addr) -> Msg {
+    // struct rtmsg: family, dst_len, src_len, tos, table, protocol, scope,
+    // type, flags
+    let body = [
+        sys::AF_INET as u8,
+        0,
+        0,
+        0,
+        RT_TABLE_MAIN,
+        RTPROT_BOOT,
+        0,
+        RTN_UNICAST,
+        0,
+        0,
+        0,
+        0,
+    ];
+    Msg::new(ty, NLM_F_ACK | flags, &body)
+        .attr(RTA_GATEWAY, &gw.octets())
+        .attr(RTA_OIF, &index.to_ne_bytes())
+}
+
+/// Points the main table's IPv4 default route at `gw` via link `index`.
+pub fn replace_default_route(index: u32, gw: Ipv4Addr) -> io::Result<()> {
+    request(default_route_msg(
+        RTM_NEWROUTE,
+        NLM_F_CREATE | NLM_F_REPLACE,
+        index,
+        gw,
+    ))
+}
+
+/// Creates `name`, a VLAN with tag `id` on link `parent`.
+pub fn add_vlan(parent: u32, name: &str, id: u16) -> io::Result<()> {
+    let info = [
+        rta(IFLA_INFO_KIND, b"vlan\0"),
+        rta(IFLA_INFO_DATA, &rta(IFLA_VLAN_ID, &id.to_ne_bytes())),
+    ]
+    .concat();
+    let c = CString::new(name).map_err(|_| io::ErrorKind::InvalidInput)?;
+    request(
+        Msg::new(
+            RTM_NEWLINK,
+            NLM_F_ACK | NLM_F_CREATE | NLM_F_EXCL,
+            &ifinfo(0, 0, 0),
+        )
+        .attr(IFLA_LINK, &parent.to_ne_bytes())
+        .attr(IFLA_IFNAME, c.as_bytes_with_nul())
+        .attr(IFLA_LINKINFO, &info),
+    )
+}
+
+/// Deletes link `index`.
+pub fn delete_link(index: u32) -> io::Result<()> {
+    request(Msg::new(RTM_DELLINK, NLM_F_ACK, &ifinfo(index, 0, 0)))
+}
+
+/// Removes the default route via `gw` on link `index`.
+pub fn delete_default_route(index: u32, gw: Ipv4Addr) -> io::Result<()> {
+    request(default_route_msg(RTM_DELROUTE, 0, index, gw))
+}
+
+/// `struct ndmsg`: family, padding, ifindex, state, flags, type.
+fn ndmsg(index: u32) -> [u8; 12] {
+    let mut b = [0u8; 12];
+    b[0] = sys::AF_INET as u8;
+    b[4..8].copy_from_slice(&index.to_ne_bytes());
+    b
+}
+
+/// The neighbour entry for `ip` on link `index`, if there is one.
+pub fn neighbour(index: u32, ip: Ipv4Addr) -> io::Result<Option<Neighbour>> {
+    let fd = open()?;
+    send_msg(&fd, Msg::new(RTM_GETNEIGH, NLM_F_DUMP, &ndmsg(0)))?;
+    let mut found = None;
+    recv_all(&fd, |ty, p| {
+        if ty != RTM_NEWNEIGH || p.len() < 12 || found.is_some() {
+            return;
+        }
+        if u32::from_ne_bytes(p[4..8].try_into().unwrap()) != index {
+            return;
+        }
+        let (mut dst, mut lladdr) = (None, None);
+        let mut a = &p[12..];
+        while a.len() >= 4 {
+            let len = u16::from_ne_bytes([a[0], a[1]]) as usize;
+            let ty = u16::from_ne_bytes([a[2], a[3]]);
+            if len < 4 || len > a.len() {
+                break;
+            }
+            let data = &a[4..len];
+            match ty {
+                NDA_DST if data.len() == 4 => {
+                    dst = Some(Ipv4Addr::new(data[0], data[1], data[2], data[3]))
+                }
+                NDA_LLADDR => lladdr = Some(data.to_vec()),
+                _ => {}
+            }
+            a = &a[align(len).min(a.len())..];
+        }
+        if dst == Some(ip) {
+            found = Some(Neighbour {
+                state: u16::from_ne_bytes([p[8], p[9]]),
+                lladdr,
+            });
+        }
+    })?;
+    Ok(found)
+}
+
+/// Deletes the neighbour entry for `ip` on link `index`, so the next packet
+/// to it asks afresh.
+pub fn delete_neighbour(index: u32, ip: Ipv4Addr) -> io::Result<()> {
+    request(Msg::new(RTM_DELNEIGH, NLM_F_ACK, &ndmsg(index)).attr(NDA_DST, &ip.octets()))
+}
+
+fn octets(a: IpAddr) -> Vec<u8> {
+    match a {
+        IpAddr::V4(a) => a.octets().to_vec(),
+        IpAddr::V6(a) => a.octets().to_vec(),
+    }
+}
+
+/// The way out to `dst` the kernel picks, as `ip route get` asks for it:
+/// through link `oif`, from `src` and with firewall mark `mark` when given.
+/// The outgoing link's index and the source address it would use.
+pub fn route_get(
+    dst: IpAddr,
+    oif: Option<u32>,
+    src: Option<IpAddr>,
+    mark: Option<u32>,
+) -> io::Result<(u32, Option<IpAddr>)> {
+    let (family, bits) = match dst {
+        IpAddr::V4(_) => (sys::AF_INET as u8, 32),
+        IpAddr::V6(_) => (sys::AF_INET6 as u8, 128),
+    };
+    // struct rtmsg, as in default_route_msg
+    let body = [
+        family,
+        bits,
+        if src.is_some() { bits } else { 0 },
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ];
+    let mut m = Msg::new(RTM_GETROUTE, NLM_F_ACK, &body).attr(RTA_DST, &octets(dst));
+    if let Some(a) = src {
+        m = m.attr(RTA_SRC, &octets(a));
+    }
+    if let Some(i) = oif {
+        m = m.attr(RTA_OIF, &i.to_ne_bytes());
+    }
+    if let Some(v) = mark {
+        m = m.attr(RTA_MARK, &v.to_ne_bytes());
+    }
+    let fd = open()?;
+    send_msg(&fd, m)?;
+    let mut found = None;
+    recv_all(&fd, |ty, p| {
+        if ty != RTM_NEWROUTE || p.len() < 12 || found.is_some() {
+            return;
+        }
+        let (mut oif, mut prefsrc) = (None, None);
+        let mut a = &p[12..];
+        while a.len() >= 4 {
+            let len = u16::from_ne_bytes([a[0], a[1]]) as usize;
+            let ty = u16::from_ne_bytes([a[2], a[3]]);
+            if len < 4 || len > a.len() {
+                break;
+            }
+            let data = &a[4..len];
+            match (ty, data.len()) {
+                (RTA_OIF, 4) => oif = Some(u32::from_ne_bytes(data.try_into().unwrap())),
+                (RTA_PREFSRC, 4) => {
+                    prefsrc = Some(IpAddr::V4(Ipv4Addr::new(
+                        data[0], data[1], data[2], data[3],
+                    )))
+                }
+                (RTA_PREFSRC, 16) => {
+                    let o: [u8; 16] = data.try_into().unwrap();
+                    prefsrc = Some(IpAddr::V6(Ipv6Addr::from(o)))
+                }
+                _ => {}
+            }
+            a = &a[align(len).min(a.len())..];
+        }
+        found = oif.map(|i| (i, prefsrc));
+    })?;
+    found.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no route"))
+}
+
+/// A socket the kernel tells of links being added, changed and removed
+/// (the RTMGRP_LINK group), for noticing hotplugged interfaces.
+pub struct Monitor(OwnedFd);
+
+impl Monitor {
+    pub fn open() -> io::Result<Monitor> {
+        let fd = sys::socket(AF_NETLINK, sys::SOCK_RAW, NETLINK_ROUTE)?;
+        // struct sockaddr_nl: family, pad, pid (the kernel picks), groups
+        let mut sa = [0u8; 12];
+        sa[..2].copy_from_slice(&(AF_NETLINK as u16).to_ne_bytes());
+        sa[8..].copy_from_slice(&RTMGRP_LINK.to_ne_bytes());
+        if unsafe { bind(fd.as_raw_fd(), sa.as_ptr().cast(), sa.len() as u32) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Monitor(fd))
+    }
+
+    /// Reads what has arrived, without waiting; whether it said anything
+    /// of links. Messages lost to a full buffer count as having done so.
+    pub fn drain(&self) -> bool {
+        let mut buf = vec![0u8; 16 * 1024];
+        let mut links = false;
+        loop {
+            let n = unsafe {
+                recv(
+                    self.0.as_raw_fd(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    MSG_DONTWAIT,
+                )
+            };
+            if n < 0 {
+                let e = io::Error::last_os_error();
+                return match e.kind() {
+                    io::ErrorKind::WouldBlock => links,
+                    io::ErrorKind::Interrupted => continue,
+                    _ => true,
+                };
+            }
+            let mut b = &buf[..n as usize];
+            while b.len() >= 16 {
+                let len = u32::from_ne_bytes(b[0..4].try_into().unwrap()) as usize;
+                let ty = u16::from_ne_bytes(b[4..6].try_into().unwrap());
+                links |= matches!(ty, RTM_NEWLINK | RTM_DELLINK);
+                if len < 16 {
+                    break;
+                }
+                b = &b[align(len).min(b.len())..];
+            }
+        }
+    }
+}
+
+impl AsRawFd for Monitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
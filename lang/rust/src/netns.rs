@@ -0,0 +1,221 @@
+// Running inside another network namespace (`--netns`, `[[namespace]]`):
+// the calling thread joins it before anything looks at links, and gets a
+// /sys of its own that lists that namespace's interfaces. Linux only;
+// elsewhere entering one fails, as a config error.
+use crate::config::{Config, NamespaceConfig};
+use crate::log;
+use crate::report::{NamespaceReport, Report};
+use crate::WatchdogError;
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::{
+    ffi::CString,
+    fs::{self, File},
+    os::fd::AsRawFd,
+    os::raw::{c_char, c_int, c_ulong, c_void},
+    ptr,
+};
+
+#[cfg(target_os = "linux")]
+const CLONE_FS: c_int = 0x0000_0200;
+#[cfg(target_os = "linux")]
+const CLONE_NEWNS: c_int = 0x0002_0000;
+#[cfg(target_os = "linux")]
+const CLONE_NEWNET: c_int = 0x4000_0000;
+#[cfg(target_os = "linux")]
+const MS_BIND: c_ulong = 0x1000;
+#[cfg(target_os = "linux")]
+const MS_REC: c_ulong = 0x4000;
+#[cfg(target_os = "linux")]
+const MS_SLAVE: c_ulong = 1 << 19;
+#[cfg(target_os = "linux")]
+const MNT_DETACH: c_int = 2;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn setns(fd: c_int, nstype: c_int) -> c_int;
+    fn unshare(flags: c_int) -> c_int;
+    fn mount(
+        src: *const c_char,
+        target: *const c_char,
+        fstype: *const c_char,
+        flags: c_ulong,
+        data: *const c_void,
+    ) -> c_int;
+    fn umount2(target: *const c_char, flags: c_int) -> c_int;
+}
+
+#[cfg(target_os = "linux")]
+fn cvt(r: c_int) -> io::Result<()> {
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn cstr(p: &Path) -> io::Result<CString> {
+    CString::new(p.as_os_str().as_encoded_bytes()).map_err(|_| io::ErrorKind::InvalidInput.into())
+}
+
+/// Where `ip netns add` keeps named namespaces.
+pub fn path(name: &str) -> PathBuf {
+    Path::new("/run/netns").join(name)
+}
+
+/// Where `[[namespace]]` entry `ns` is.
+pub fn ns_path(ns: &NamespaceConfig) -> PathBuf {
+    ns.path.clone().unwrap_or_else(|| path(&ns.name))
+}
+
+/// Runs `f` in each of `cfg`'s namespaces at once, a thread apiece, with
+/// the configuration [`Config::for_namespace`] makes for it.
+pub fn each(cfg: &Config, f: impl Fn(&Config) -> Report + Sync) -> Vec<NamespaceReport> {
+    std::thread::scope(|s| {
+        let runs: Vec<_> = cfg
+            .namespaces
+            .iter()
+            .map(|ns| {
+                let f = &f;
+                s.spawn(move || {
+                    log::set_scope(&ns.name);
+                    let c = cfg.for_namespace(ns);
+                    let _in = Namespace::enter(&ns_path(ns), Some(&ns.name))
+                        .map_err(|e| entering(ns, e))?;
+                    Ok(f(&c))
+                })
+            })
+            .collect();
+        cfg.namespaces
+            .iter()
+            .zip(runs)
+            .map(|(ns, t)| NamespaceReport {
+                name: ns.name.clone(),
+                result: t
+                    .join()
+                    .unwrap_or_else(|_| Err(WatchdogError::Internal("panicked".into()))),
+            })
+            .collect()
+    })
+}
+
+/// Why `ns` could not be entered, as an exit status sees it.
+pub fn entering(ns: &NamespaceConfig, e: io::Error) -> WatchdogError {
+    let msg = format!("network namespace {}: {}", ns_path(ns).display(), e);
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => {
+            log::error!("{} (CAP_SYS_ADMIN needed)", msg);
+            WatchdogError::Permission
+        }
+        _ => WatchdogError::Config(msg),
+    }
+}
+
+/// Inside another network namespace until dropped, which goes back to the
+/// namespaces the thread was in before.
+#[cfg(target_os = "linux")]
+pub struct Namespace {
+    net: File,
+    mnt: File,
+}
+
+#[cfg(target_os = "linux")]
+impl Namespace {
+    /// Joins the network namespace at `path` and mounts a fresh /sys, in a
+    /// mount namespace of our own, so it describes that namespace. With
+    /// `name`, files in `/etc/netns/<name>` are bind-mounted over their
+    /// `/etc` counterparts, as `ip netns exec` does. Only the calling thread
+    /// moves, taking the threads and commands it starts from then on with
+    /// it; CAP_SYS_ADMIN is needed.
+    pub fn enter(path: &Path, name: Option<&str>) -> io::Result<Namespace> {
+        let saved = Namespace {
+            net: File::open("/proc/thread-self/ns/net")?,
+            mnt: File::open("/proc/thread-self/ns/mnt")?,
+        };
+        let ns = File::open(path)?;
+        cvt(unsafe { setns(ns.as_raw_fd(), CLONE_NEWNET) })?;
+        cvt(unsafe { unshare(CLONE_NEWNS) })?;
+        let root = cstr(Path::new("/"))?;
+        let sys = cstr(Path::new("/sys"))?;
+        let sysfs = cstr(Path::new("sysfs"))?;
+        unsafe {
+            // Keep the remount from propagating back to the host.
+            cvt(mount(
+                ptr::null(),
+                root.as_ptr(),
+                ptr::null(),
+                MS_SLAVE | MS_REC,
+                ptr::null(),
+            ))?;
+            // Fails harmlessly where /sys is not a mount point of its own.
+            umount2(sys.as_ptr(), MNT_DETACH);
+            cvt(mount(
+                sysfs.as_ptr(),
+                sys.as_ptr(),
+                sysfs.as_ptr(),
+                0,
+                ptr::null(),
+            ))?;
+        }
+        if let Some(n) = name {
+            bind_etc(n);
+        }
+        Ok(saved)
+    }
+}
+
+/// None to be in elsewhere.
+#[cfg(not(target_os = "linux"))]
+pub enum Namespace {}
+
+#[cfg(not(target_os = "linux"))]
+impl Namespace {
+    /// Network namespaces are Linux's.
+    pub fn enter(_path: &Path, _name: Option<&str>) -> io::Result<Namespace> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "network namespaces need Linux",
+        ))
+    }
+}
+
+/// Never made, so never dropped; here so that dropping one to leave it
+/// reads the same everywhere.
+#[cfg(not(target_os = "linux"))]
+impl Drop for Namespace {
+    fn drop(&mut self) {
+        match *self {}
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn bind_etc(name: &str) {
+    let Ok(dir) = fs::read_dir(Path::new("/etc/netns").join(name)) else {
+        return;
+    };
+    for e in dir.filter_map(|e| e.ok()) {
+        let dst = Path::new("/etc").join(e.file_name());
+        let res = cstr(&e.path()).and_then(|src| {
+            let to = cstr(&dst)?;
+            cvt(unsafe { mount(src.as_ptr(), to.as_ptr(), ptr::null(), MS_BIND, ptr::null()) })
+        });
+        if let Err(err) = res {
+            log::warn!("netns {}: bind-mounting {}: {}", name, dst.display(), err);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Namespace {
+    fn drop(&mut self) {
+        // Threads started in here share our filesystem context, which
+        // would keep us from changing mount namespace.
+        let res = cvt(unsafe { unshare(CLONE_FS) })
+            .and_then(|()| cvt(unsafe { setns(self.mnt.as_raw_fd(), CLONE_NEWNS) }))
+            .and_then(|()| cvt(unsafe { setns(self.net.as_raw_fd(), CLONE_NEWNET) }));
+        if let Err(e) = res {
+            log::warn!("cannot return to the original network namespace: {}", e);
+        }
+    }
+}
@@ -0,0 +1,497 @@
+// State-change notifications. Webhooks, Telegram and hooks run on worker
+// threads so a slow endpoint or command never holds up the watchdog loop.
+use crate::config::Config;
+use crate::events::Record;
+#[cfg(feature = "telegram")]
+use crate::history;
+use crate::http::{self, Url};
+use crate::json::Json;
+use crate::{audit, exec, iface};
+use crate::{log, output};
+use std::collections::VecDeque;
+use std::fmt;
+use std::process::Command;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const ATTEMPTS: u32 = 3;
+
+/// Undelivered messages kept for when connectivity returns; the oldest
+/// are dropped beyond that.
+const QUEUE: usize = 64;
+
+#[derive(Clone)]
+pub struct Notification {
+    pub status: String,
+    /// Set for interface up/down flips, `None` for connectivity changes.
+    pub iface: Option<String>,
+    pub timestamp: String,
+    pub previous_status: Option<String>,
+    /// The login page, when the change is to CAPTIVE_PORTAL.
+    pub portal_url: Option<String>,
+    /// On the change that ends an OFFLINE or RECOVERING spell, its length.
+    pub outage_secs: Option<u64>,
+    /// The `[[namespace]]` it happened in; `None` on the host.
+    pub netns: Option<String>,
+}
+
+impl Notification {
+    pub fn to_json(&self) -> Json {
+        Json::obj()
+            .set("status", self.status.as_str())
+            .set("iface", self.iface.as_deref())
+            .set("timestamp", self.timestamp.as_str())
+            .set("previous_status", self.previous_status.as_deref())
+            .set("portal_url", self.portal_url.as_deref())
+            .set("outage_secs", self.outage_secs)
+            .set("netns", self.netns.as_deref())
+    }
+}
+
+pub trait Notifier {
+    fn notify(&self, n: &Notification);
+
+    /// Acts on the host rather than just reporting; held back while the
+    /// link is flapping.
+    fn remediates(&self) -> bool {
+        false
+    }
+
+    /// After a check that left the connectivity state as it was.
+    fn checked(&self, _n: &Notification) {}
+}
+
+/// Where a push notification goes.
+enum Target {
+    /// A JSON POST of [`Notification::to_json`].
+    Webhook(Url),
+    /// A Telegram bot's message to one chat.
+    #[cfg(feature = "telegram")]
+    Telegram { token: String, chat_id: String },
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Webhook(u) => u.fmt(f),
+            // The token stays out of logs.
+            #[cfg(feature = "telegram")]
+            Target::Telegram { chat_id, .. } => write!(f, "telegram chat {}", chat_id),
+        }
+    }
+}
+
+impl Target {
+    /// Interface flips only go to webhooks.
+    fn wants(&self, n: &Notification) -> bool {
+        matches!(self, Target::Webhook(_)) || n.iface.is_none()
+    }
+
+    fn request(&self, n: &Notification) -> Result<(Url, String), String> {
+        match self {
+            Target::Webhook(u) => Ok((u.clone(), n.to_json().to_string())),
+            #[cfg(feature = "telegram")]
+            Target::Telegram { token, chat_id } => {
+                let u = format!("https://api.telegram.org/bot{}/sendMessage", token).parse()?;
+                let j = Json::obj()
+                    .set("chat_id", chat_id.as_str())
+                    .set("text", telegram_text(n));
+                Ok((u, j.to_string()))
+            }
+        }
+    }
+
+    fn send(&self, n: &Notification, timeout: Duration) -> Result<(), String> {
+        let (u, body) = self.request(n)?;
+        match http::request(
+            "POST",
+            &u,
+            Some(("application/json", body.as_bytes())),
+            timeout,
+        ) {
+            Ok(r) if (200..300).contains(&r.status) => Ok(()),
+            Ok(r) => Err(format!("HTTP {}", r.status)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// [`Target::send`], retried with exponential back-off.
+    fn deliver(&self, n: &Notification, timeout: Duration) -> Result<(), String> {
+        let mut delay = Duration::from_secs(1);
+        let mut k = 1;
+        loop {
+            match self.send(n, timeout) {
+                Ok(()) => return Ok(()),
+                Err(e) if k == ATTEMPTS => return Err(e),
+                Err(_) => {}
+            }
+            std::thread::sleep(delay);
+            delay *= 2;
+            k += 1;
+        }
+    }
+}
+
+#[cfg(feature = "telegram")]
+fn telegram_text(n: &Notification) -> String {
+    let host = crate::sys::hostname();
+    let mut s = match &n.netns {
+        Some(ns) => format!("{} [{}]: {}", host.trim(), ns, n.status),
+        None => format!("{}: {}", host.trim(), n.status),
+    };
+    match (n.outage_secs, &n.previous_status) {
+        (Some(d), _) => s += &format!(" after {} without connectivity", history::span(d)),
+        (None, Some(p)) => s += &format!(" (was {})", p),
+        (None, None) => {}
+    }
+    s += &format!(" at {}", n.timestamp);
+    if let Some(u) = &n.portal_url {
+        s += &format!("; log in at {}", u);
+    }
+    s
+}
+
+enum Msg {
+    Send(Notification),
+    /// Connectivity is back: retry what could not be delivered.
+    Flush,
+}
+
+/// Sends what `rx` brings to `targets`. What still fails after the
+/// retries is kept (up to [`QUEUE`]) and tried again with the next message
+/// or flush; a target's messages go in order, so one that fails holds back
+/// the rest.
+fn worker(targets: &[Target], timeout: Duration, rx: mpsc::Receiver<Msg>) {
+    // Target, message, and whether it missed its first chance.
+    let mut queue: VecDeque<(usize, Notification, bool)> = VecDeque::new();
+    for m in rx {
+        if let Msg::Send(n) = m {
+            for i in (0..targets.len()).filter(|&i| targets[i].wants(&n)) {
+                if queue.len() == QUEUE {
+                    queue.pop_front();
+                }
+                queue.push_back((i, n.clone(), false));
+            }
+        }
+        let mut failing = vec![false; targets.len()];
+        for (i, n, late) in std::mem::take(&mut queue) {
+            let t = &targets[i];
+            if failing[i] {
+                queue.push_back((i, n, true));
+                continue;
+            }
+            match t.deliver(&n, timeout) {
+                Ok(()) if late => {
+                    log::info!(
+                        "notify: {}: delivered {} of {} late",
+                        t,
+                        n.status,
+                        n.timestamp
+                    )
+                }
+                Ok(()) => {}
+                Err(e) => {
+                    if late {
+                        log::debug!("notify: {}: still failing: {}", t, e);
+                    } else {
+                        log::warn!(
+                            "notify: {}: giving up after {} attempts: {}; kept for when connectivity returns",
+                            t,
+                            ATTEMPTS,
+                            e
+                        );
+                    }
+                    failing[i] = true;
+                    queue.push_back((i, n, true));
+                }
+            }
+        }
+    }
+}
+
+/// Holds back and spaces out connectivity changes: an outage shorter than
+/// `min_outage` goes unreported, up and down, and a message due sooner than
+/// `min_interval` after the last one waits, the latest replacing any other
+/// waiting.
+struct Gate {
+    min_outage: Duration,
+    min_interval: Duration,
+    /// An outage not yet `min_outage` long: when it began, and the change
+    /// that began it with the latest state.
+    down: Option<(Instant, Notification)>,
+    waiting: Option<Notification>,
+    /// When the last message went, and its state.
+    last: Option<(Instant, String)>,
+}
+
+impl Gate {
+    /// What goes out now that `n` happened.
+    fn change(&mut self, n: &Notification, now: Instant) -> Vec<Notification> {
+        if let Some((_, held)) = self.down.as_mut().filter(|_| n.status != "ONLINE") {
+            held.status = n.status.clone();
+            held.portal_url = n.portal_url.clone();
+            return self.due(now).into_iter().collect();
+        }
+        if n.status != "ONLINE"
+            && !self.min_outage.is_zero()
+            && n.previous_status.as_deref().is_none_or(|p| p == "ONLINE")
+        {
+            self.down = Some((now, n.clone()));
+            return Vec::new();
+        }
+        // Checks are far apart: the outage may have lasted long enough since.
+        let mut out: Vec<_> = self.due(now).into_iter().collect();
+        if n.status == "ONLINE" && self.down.take().is_some() {
+            log::debug!("notify: outage shorter than min_outage, not reported");
+            return out;
+        }
+        out.extend(self.space(n.clone(), now));
+        out
+    }
+
+    /// A held-back outage that has now lasted `min_outage`, or a message
+    /// whose wait is over.
+    fn due(&mut self, now: Instant) -> Option<Notification> {
+        if let Some((t, _)) = &self.down {
+            if now.duration_since(*t) < self.min_outage {
+                return None;
+            }
+            let (_, n) = self.down.take()?;
+            return self.space(n, now);
+        }
+        let n = self.waiting.take()?;
+        self.space(n, now)
+    }
+
+    fn space(&mut self, n: Notification, now: Instant) -> Option<Notification> {
+        if let Some((t, st)) = &self.last {
+            if now.duration_since(*t) < self.min_interval {
+                self.waiting = Some(n);
+                return None;
+            }
+            // Changes that waited may have come back to what was last said.
+            if *st == n.status {
+                return None;
+            }
+        }
+        self.last = Some((now, n.status.clone()));
+        Some(n)
+    }
+}
+
+/// Webhook POSTs and, with the `telegram` feature, Telegram messages,
+/// from a worker thread. Connectivity changes pass a `Gate` first;
+/// messages that cannot be delivered, say because the host is offline, are
+/// sent once it is ONLINE again. With `dry_run` it only logs what it would
+/// send.
+pub struct PushNotifier {
+    tx: Option<Sender<Msg>>,
+    targets: Arc<Vec<Target>>,
+    gate: Mutex<Gate>,
+}
+
+impl PushNotifier {
+    fn new(cfg: &Config, targets: Vec<Target>, dry_run: bool) -> PushNotifier {
+        let targets = Arc::new(targets);
+        let tx = (!dry_run).then(|| {
+            let (tx, rx) = mpsc::channel();
+            let t = targets.clone();
+            let timeout = Duration::from_secs(cfg.webhook_timeout);
+            std::thread::spawn(move || worker(&t, timeout, rx));
+            tx
+        });
+        PushNotifier {
+            tx,
+            targets,
+            gate: Mutex::new(Gate {
+                min_outage: Duration::from_secs(cfg.notify_min_outage),
+                min_interval: Duration::from_secs(cfg.notify_min_interval),
+                down: None,
+                waiting: None,
+                last: None,
+            }),
+        }
+    }
+
+    fn push(&self, n: Notification) {
+        match &self.tx {
+            Some(tx) => {
+                let _ = tx.send(Msg::Send(n));
+            }
+            None => {
+                for t in self.targets.iter().filter(|t| t.wants(&n)) {
+                    match t.request(&n) {
+                        Ok((_, body)) => log::info!("notify (dry run): POST {} {}", t, body),
+                        Err(e) => log::warn!("notify: {}: {}", t, e),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Notifier for PushNotifier {
+    fn notify(&self, n: &Notification) {
+        let due = match n.iface {
+            Some(_) => vec![n.clone()],
+            None => self.gate.lock().unwrap().change(n, Instant::now()),
+        };
+        for n in due {
+            self.push(n);
+        }
+    }
+
+    fn checked(&self, n: &Notification) {
+        if let Some(d) = self.gate.lock().unwrap().due(Instant::now()) {
+            self.push(d);
+        }
+        if let (Some(tx), "ONLINE") = (&self.tx, n.status.as_str()) {
+            let _ = tx.send(Msg::Flush);
+        }
+    }
+}
+
+/// A hook command and its `CW_*` environment.
+type HookRun = (String, Vec<(&'static str, String)>);
+
+/// User commands run through `sh -c` on connectivity changes (not link
+/// flips), with the event in `CW_*` variables. Exit status is logged, and
+/// with `--output ndjson` also reported as a `hook` event.
+pub struct HookNotifier {
+    tx: Option<Sender<HookRun>>,
+    events: bool,
+    on_online: Option<String>,
+    on_offline: Option<String>,
+    on_change: Option<String>,
+    ifaces: String,
+}
+
+impl HookNotifier {
+    pub fn new(cfg: &Config, dry_run: bool, events: bool) -> HookNotifier {
+        let tx = (!dry_run).then(|| {
+            let (tx, rx) = mpsc::channel::<HookRun>();
+            let timeout = Duration::from_secs(cfg.hook_timeout);
+            std::thread::spawn(move || {
+                for (cmd, env) in rx {
+                    let state = env
+                        .iter()
+                        .find(|(k, _)| *k == "CW_STATE")
+                        .map(|(_, v)| v.clone())
+                        .unwrap_or_default();
+                    let res = audit::action("hook", &cmd, || {
+                        exec::run(Command::new("sh").args(["-c", &cmd]).envs(env), timeout)
+                    });
+                    match &res {
+                        Ok(()) => log::info!("hook `{}`: exited 0", cmd),
+                        Err(e) => log::warn!("hook `{}`: {}", cmd, e),
+                    }
+                    if events {
+                        output::json_event(
+                            Record::Hook {
+                                command: cmd,
+                                state,
+                                dry_run: false,
+                                ok: Some(res.is_ok()),
+                                error: res.err(),
+                            }
+                            .to_json(),
+                        );
+                    }
+                }
+            });
+            tx
+        });
+        HookNotifier {
+            tx,
+            events,
+            on_online: cfg.on_online.clone(),
+            on_offline: cfg.on_offline.clone(),
+            on_change: cfg.on_change.clone(),
+            ifaces: iface::discover(cfg).join(" "),
+        }
+    }
+}
+
+impl Notifier for HookNotifier {
+    fn remediates(&self) -> bool {
+        true
+    }
+
+    fn notify(&self, n: &Notification) {
+        if n.iface.is_some() {
+            return;
+        }
+        let specific = match n.status.as_str() {
+            "ONLINE" => &self.on_online,
+            "OFFLINE" => &self.on_offline,
+            _ => &None,
+        };
+        let env = vec![
+            ("CW_STATE", n.status.clone()),
+            (
+                "CW_PREV_STATE",
+                n.previous_status.clone().unwrap_or_default(),
+            ),
+            ("CW_IFACE", self.ifaces.clone()),
+            ("CW_TIMESTAMP", n.timestamp.clone()),
+            ("CW_PORTAL_URL", n.portal_url.clone().unwrap_or_default()),
+            ("CW_NETNS", n.netns.clone().unwrap_or_default()),
+        ];
+        for cmd in [specific, &self.on_change].into_iter().flatten() {
+            match &self.tx {
+                Some(tx) => {
+                    let _ = tx.send((cmd.clone(), env.clone()));
+                }
+                None => {
+                    log::info!("hook (dry run): {} (CW_STATE={})", cmd, n.status);
+                    if self.events {
+                        output::json_event(
+                            Record::Hook {
+                                command: cmd.clone(),
+                                state: n.status.clone(),
+                                dry_run: true,
+                                ok: None,
+                                error: None,
+                            }
+                            .to_json(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The notifiers `[notifications]`, `[hooks]` and `[mqtt]` ask for; with
+/// `events`, hook runs are printed as `--output ndjson` events.
+pub fn from_cfg(cfg: &Config, dry_run: bool, events: bool) -> Vec<Box<dyn Notifier>> {
+    let mut v: Vec<Box<dyn Notifier>> = Vec::new();
+    #[allow(unused_mut)]
+    let mut targets: Vec<Target> = cfg.webhooks.iter().cloned().map(Target::Webhook).collect();
+    if let Some((token, chat_id)) = &cfg.telegram {
+        #[cfg(feature = "telegram")]
+        targets.push(Target::Telegram {
+            token: token.clone(),
+            chat_id: chat_id.clone(),
+        });
+        #[cfg(not(feature = "telegram"))]
+        {
+            let _ = (token, chat_id);
+            log::warn!("telegram: built without the telegram feature; telegram_token ignored");
+        }
+    }
+    if !targets.is_empty() {
+        v.push(Box::new(PushNotifier::new(cfg, targets, dry_run)));
+    }
+    if cfg.on_online.is_some() || cfg.on_offline.is_some() || cfg.on_change.is_some() {
+        v.push(Box::new(HookNotifier::new(cfg, dry_run, events)));
+    }
+    if cfg.mqtt_broker.is_some() {
+        #[cfg(feature = "mqtt")]
+        v.push(Box::new(crate::mqtt::MqttNotifier::new(cfg, dry_run)));
+        #[cfg(not(feature = "mqtt"))]
+        log::warn!("mqtt: built without the mqtt feature; [mqtt] broker ignored");
+    }
+    v
+}
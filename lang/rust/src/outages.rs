@@ -0,0 +1,83 @@
+// `history`: the outages the daemon recorded in its history file over a
+// window, with total down time, availability and the longest of them.
+use cw_netup::config::Config;
+use cw_netup::history::{rfc3339, span};
+use cw_netup::output::OutputSink;
+use cw_netup::timeline::{self, Outage, Stamp};
+use cw_netup::{log, pidfile};
+use std::fmt::Write as _;
+
+fn end(o: &Outage) -> String {
+    match (o.ongoing, o.recovered) {
+        (true, _) => "ongoing".into(),
+        (_, true) => rfc3339(o.end),
+        _ => "?".into(),
+    }
+}
+
+/// The outage's length; `+` where it lasted longer than was seen.
+fn length(o: &Outage) -> String {
+    let plus = if o.recovered || o.ongoing { "" } else { "+" };
+    format!("{}{}", span(o.duration()), plus)
+}
+
+/// Puts the last `window` seconds of the history file through `out`, as a
+/// table or with `--output json` an object. Exits 1 when there is none.
+pub fn run(cfg: &Config, window: u64, out: &mut dyn OutputSink) -> ! {
+    let Some(path) = &cfg.history_file else {
+        log::error!("no history_file configured");
+        std::process::exit(1);
+    };
+    let records = timeline::read(path);
+    if records.is_empty() {
+        log::error!("no history recorded in {}", path.display());
+        std::process::exit(1);
+    }
+    // Without a PID file, a daemon that did not write STOPPED still runs.
+    let running = cfg
+        .pid_file
+        .as_deref()
+        .is_none_or(|p| pidfile::running(p).is_some());
+    let st = timeline::stats(&records, &Stamp::now(), window, running);
+    let mut t = String::new();
+    if st.outages.is_empty() {
+        let _ = writeln!(t, "no outages in the last {}", span(window));
+    } else {
+        let _ = writeln!(
+            t,
+            "{:<20}  {:<20}  {:<12}  CAUSE",
+            "START", "END", "DURATION"
+        );
+        for o in &st.outages {
+            let _ = writeln!(
+                t,
+                "{:<20}  {:<20}  {:<12}  {}",
+                rfc3339(o.start),
+                end(o),
+                length(o),
+                o.cause
+            );
+        }
+    }
+    let avail = st
+        .availability()
+        .map_or("-".into(), |a| format!("{:.3}%", a));
+    let _ = writeln!(
+        t,
+        "\nlast {}: down {}, available {} of {} observed",
+        span(window),
+        span(st.downtime),
+        avail,
+        span(st.observed)
+    );
+    if let Some(o) = st.longest() {
+        let _ = writeln!(
+            t,
+            "longest outage: {}, from {}",
+            length(o),
+            rfc3339(o.start)
+        );
+    }
+    out.document(&t, st.to_json().set("window_secs", window));
+    std::process::exit(0)
+}
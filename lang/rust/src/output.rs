@@ -0,0 +1,719 @@
+use crate::backoff::Attempt;
+use crate::clock;
+use crate::config::ProbeMethod;
+use crate::daemon::LinkState;
+use crate::error::Status;
+use crate::events::{self, Record};
+use crate::history;
+use crate::iface::{IfaceReport, LinkStatus, Member, RouteProblem};
+use crate::json::Json;
+use crate::leases::Lease;
+use crate::log;
+use crate::net::IfaceStats;
+use crate::probe::{self, State, Summary, TargetResult};
+use crate::report::{self, Report};
+use std::io::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+pub enum Event<'a> {
+    /// A daemon cycle's connectivity check begins.
+    Check,
+    /// `--wait`: a probe while waiting for ONLINE; `last` when the wait
+    /// ends with it.
+    Wait {
+        attempt: u32,
+        state: State,
+        last: bool,
+    },
+    /// How one target answered.
+    Probe {
+        result: &'a TargetResult,
+    },
+    /// A target a relaxed check left out.
+    Skipped {
+        target: &'a ProbeMethod,
+    },
+    InterfaceUp {
+        iface: &'a str,
+        /// How long it took, for `--benchmark`.
+        elapsed: Duration,
+    },
+    /// Still no carrier after `carrier_wait`; DHCP was skipped.
+    NoCarrier {
+        iface: &'a str,
+    },
+    Dhcp {
+        iface: &'a str,
+        family: u8,
+        ok: bool,
+        elapsed: Duration,
+    },
+    Address {
+        iface: &'a str,
+        addr: Ipv6Addr,
+    },
+    /// A DHCP lease gave `iface` another address than it had.
+    IpChanged {
+        iface: &'a str,
+        old: Ipv4Addr,
+        new: Ipv4Addr,
+    },
+    /// A wireless interface associated (or was found associated).
+    Wireless {
+        iface: &'a str,
+        ssid: Option<&'a str>,
+        signal_dbm: Option<i32>,
+    },
+    /// How a bond's or team's members are doing.
+    Members {
+        iface: &'a str,
+        members: &'a [Member],
+    },
+    /// The static fallback was applied after DHCP failed.
+    Fallback {
+        iface: &'a str,
+        addr: Option<Ipv4Addr>,
+    },
+    /// Something amiss with the default route once `iface` is up.
+    Route {
+        iface: &'a str,
+        problem: RouteProblem,
+    },
+    /// The lease `iface` holds, from dhclient's file or the kernel.
+    Lease {
+        iface: &'a str,
+        lease: &'a Lease,
+        renew_at: Option<u64>,
+    },
+    Error {
+        iface: &'a str,
+        msg: &'a str,
+    },
+    /// A failed DHCP run (`iface` set) or probe round about to be retried.
+    Retry {
+        iface: Option<&'a str>,
+        attempt: &'a Attempt,
+    },
+    /// Interface counters each daemon cycle, with rates since the last one.
+    Traffic {
+        iface: &'a str,
+        stats: &'a IfaceStats,
+        /// Bytes per second received and sent; `None` on the first reading.
+        rates: Option<(f64, f64)>,
+    },
+    /// Daemon state change; `from` is `None` for the first verdict.
+    State {
+        from: Option<LinkState>,
+        to: LinkState,
+        ipv6: bool,
+    },
+    /// More transitions than the threshold allows; remediation is held back.
+    /// With `iface`, its carrier changes, and it is brought up again only
+    /// once they have stopped for `hold` seconds.
+    Flapping {
+        iface: Option<&'a str>,
+        transitions: usize,
+        window_min: u64,
+        hold: u64,
+    },
+    /// An interface appeared (`added`) or vanished (daemon mode).
+    Hotplug {
+        iface: &'a str,
+        added: bool,
+    },
+    /// SIGHUP: the config file was read again; `error` when it did not
+    /// load, and the old one stays.
+    Reload {
+        error: Option<&'a str>,
+    },
+    /// SIGUSR2: everything the daemon holds.
+    Dump {
+        state: &'a Json,
+    },
+    Stopping {
+        state: Option<LinkState>,
+        ipv6: bool,
+    },
+}
+
+/// Where progress and results go. Everything that used to `println!`
+/// writes through one of these instead.
+pub trait OutputSink {
+    fn event(&mut self, e: Event);
+    /// The final verdict of a one-shot run.
+    fn status(&mut self, r: &Report);
+    /// What a subcommand (`history`, `audit`, `repair`) found: `text` as
+    /// people read it, or `j` with `--output json`.
+    fn document(&mut self, text: &str, j: Json);
+
+    /// Progress of a connectivity check, ahead of its verdict.
+    fn probes(&mut self, s: &Summary) {
+        for attempt in &s.retries {
+            self.event(Event::Retry {
+                iface: None,
+                attempt,
+            });
+        }
+        for result in &s.results {
+            self.event(Event::Probe { result });
+        }
+        for target in &s.skipped {
+            self.event(Event::Skipped { target });
+        }
+    }
+
+    fn interfaces(&mut self, rs: &[IfaceReport]) {
+        for r in rs {
+            let iface = r.name.as_str();
+            for attempt in &r.retries {
+                self.event(Event::Retry {
+                    iface: Some(iface),
+                    attempt,
+                });
+            }
+            for msg in &r.errors {
+                self.event(Event::Error { iface, msg });
+            }
+            if r.link_up {
+                self.event(Event::InterfaceUp {
+                    iface,
+                    elapsed: r.timing.link_up,
+                });
+            }
+            if r.link == LinkStatus::NoCarrier {
+                self.event(Event::NoCarrier { iface });
+            }
+            if !r.members.is_empty() {
+                self.event(Event::Members {
+                    iface,
+                    members: &r.members,
+                });
+            }
+            if r.associated == Some(true) || r.signal_dbm.is_some() {
+                self.event(Event::Wireless {
+                    iface,
+                    ssid: r.ssid.as_deref(),
+                    signal_dbm: r.signal_dbm,
+                });
+            }
+            if let Some(ok) = r.dhcp {
+                self.event(Event::Dhcp {
+                    iface,
+                    family: 4,
+                    ok,
+                    elapsed: r.timing.dhcp,
+                });
+            }
+            if let (Some(old), Some(new)) = (r.previous, r.ipv4) {
+                self.event(Event::IpChanged { iface, old, new });
+            }
+            if r.fallback == Some(true) {
+                self.event(Event::Fallback {
+                    iface,
+                    addr: r.ipv4,
+                });
+            }
+            if let Some(ok) = r.dhcp6 {
+                self.event(Event::Dhcp {
+                    iface,
+                    family: 6,
+                    ok,
+                    elapsed: r.timing.dhcp6,
+                });
+            }
+            for &addr in &r.v6_addrs {
+                self.event(Event::Address { iface, addr });
+            }
+            for &problem in &r.routes {
+                self.event(Event::Route { iface, problem });
+            }
+            if let Some(lease) = &r.lease {
+                self.event(Event::Lease {
+                    iface,
+                    lease,
+                    renew_at: r.renew_at,
+                });
+            }
+        }
+    }
+}
+
+static BENCHMARK: AtomicBool = AtomicBool::new(false);
+
+/// `--benchmark`: phase timings in the report, and in the events that
+/// have one with `--output ndjson`.
+pub fn benchmark(on: bool) {
+    BENCHMARK.store(on, Ordering::Relaxed);
+}
+
+pub fn benchmarking() -> bool {
+    BENCHMARK.load(Ordering::Relaxed)
+}
+
+/// With `json`, a [`JsonSink`] that prints every event as well when
+/// `events` (`--output ndjson`).
+pub fn sink(json: bool, events: bool) -> Box<dyn OutputSink> {
+    if json {
+        Box::new(JsonSink { events })
+    } else {
+        Box::new(PlainSink)
+    }
+}
+
+/// Drops everything: the library's own runs, for callers that only want
+/// the [`Report`].
+pub struct Quiet;
+
+impl OutputSink for Quiet {
+    fn event(&mut self, _: Event) {}
+    fn status(&mut self, _: &Report) {}
+    fn document(&mut self, _: &str, _: Json) {}
+}
+
+/// The traditional human-readable lines: state on stdout, progress and
+/// problems through the log.
+pub struct PlainSink;
+
+impl OutputSink for PlainSink {
+    fn event(&mut self, e: Event) {
+        match e {
+            Event::Check
+            | Event::Probe { .. }
+            | Event::Skipped { .. }
+            | Event::InterfaceUp { .. }
+            | Event::Dhcp { ok: false, .. }
+            | Event::Traffic { .. } => return,
+            Event::Wait { last, .. } => {
+                // A dot a probe, on stderr, which the report does not use.
+                eprint!(".");
+                if last {
+                    eprintln!();
+                }
+                return;
+            }
+            Event::NoCarrier { iface } => {
+                log::warn!(iface = &iface; "no carrier on {}, DHCP skipped", iface)
+            }
+            Event::Dhcp {
+                iface, family: 4, ..
+            } => log::info!(iface = &iface; "{} up", iface),
+            Event::Dhcp { iface, .. } => log::info!(iface = &iface; "{} up (DHCPv6)", iface),
+            Event::Address { iface, addr } => {
+                log::info!(iface = &iface; "{} inet6 {}", iface, addr)
+            }
+            Event::IpChanged { iface, old, new } => {
+                log::warn!(iface = &iface; "{}: address changed from {} to {}", iface, old, new)
+            }
+            Event::Wireless {
+                iface,
+                ssid,
+                signal_dbm,
+            } => {
+                let to = ssid.map_or(String::new(), |s| format!(" to \"{}\"", s));
+                match signal_dbm {
+                    Some(dbm) => {
+                        log::info!(iface = &iface; "{} associated{}, signal {} dBm", iface, to, dbm)
+                    }
+                    None => log::info!(iface = &iface; "{} associated{}", iface, to),
+                }
+            }
+            Event::Members { iface, members } => {
+                let list = members
+                    .iter()
+                    .map(|m| format!("{} {}", m.name, m.link.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if members.iter().all(|m| m.link == LinkStatus::Up) {
+                    log::info!(iface = &iface; "{} members: {}", iface, list)
+                } else {
+                    log::warn!(iface = &iface; "{} members: {}", iface, list)
+                }
+            }
+            Event::Fallback { iface, addr } => log::warn!(
+                iface = &iface;
+                "{}: no DHCP lease, using static address {}",
+                iface,
+                addr.map_or("(none)".into(), |a| a.to_string())
+            ),
+            Event::Route {
+                iface,
+                problem: p @ RouteProblem::Missing { repaired: true, .. },
+            } => log::info!(iface = &iface; "{}: {}", iface, p),
+            Event::Route { iface, problem } => {
+                log::warn!(iface = &iface; "{}: {}", iface, problem)
+            }
+            Event::Lease {
+                iface,
+                lease,
+                renew_at,
+            } => {
+                let now = history::unix_now();
+                match lease.remaining(now) {
+                    Some(left) => log::info!(
+                        iface = &iface;
+                        "{}: lease {} left ({}){}",
+                        iface,
+                        history::span(left),
+                        lease.source.as_str(),
+                        renew_at.map_or(String::new(), |t| format!(
+                            ", renewal in {}",
+                            history::span(t.saturating_sub(now))
+                        ))
+                    ),
+                    None => log::debug!(
+                        iface = &iface; "{}: lease without expiry ({})", iface, lease.source.as_str()
+                    ),
+                }
+            }
+            Event::Error { iface, msg } => log::error!(iface = &iface; "{}: {}", iface, msg),
+            Event::Retry { iface, attempt: a } => log::warn!(
+                "{}{} (attempt {}/{}), retrying in {:.1}s",
+                iface.map_or(String::new(), |i| format!("{}: ", i)),
+                a.error,
+                a.n,
+                a.of,
+                a.delay.as_secs_f64()
+            ),
+            Event::State {
+                from: None,
+                to,
+                ipv6,
+            } => println!("{}{}", scope(), to.describe(ipv6)),
+            Event::State {
+                from: Some(f),
+                to,
+                ipv6,
+            } => {
+                println!("{}{} -> {}", scope(), f.describe(ipv6), to.describe(ipv6))
+            }
+            Event::Flapping {
+                iface: None,
+                transitions,
+                window_min,
+                ..
+            } => log::warn!(
+                "FLAPPING ({} transitions in {} min), remediation hooks suppressed",
+                transitions,
+                window_min
+            ),
+            Event::Flapping {
+                iface: Some(i),
+                transitions,
+                window_min,
+                hold,
+            } => match hold {
+                0 => log::warn!(
+                    iface = i;
+                    "{}: FLAPPING ({} carrier changes in {} min)",
+                    i,
+                    transitions,
+                    window_min
+                ),
+                h => log::warn!(
+                    iface = i;
+                    "{}: FLAPPING ({} carrier changes in {} min), not brought up until stable for {}s",
+                    i,
+                    transitions,
+                    window_min,
+                    h
+                ),
+            },
+            Event::Hotplug { iface, added: true } => {
+                log::info!(iface = &iface; "{} appeared", iface)
+            }
+            Event::Hotplug {
+                iface,
+                added: false,
+            } => {
+                log::warn!(iface = &iface; "{} gone", iface)
+            }
+            Event::Reload { error: None } => log::info!("config reloaded"),
+            Event::Reload { error: Some(e) } => {
+                log::error!("config reload: {}; keeping the old one", e)
+            }
+            Event::Dump { state } => println!("{}{}", scope(), state),
+            Event::Stopping {
+                state: Some(s),
+                ipv6,
+            } => println!("{}{} (stopping)", scope(), s.describe(ipv6)),
+            Event::Stopping { state: None, .. } => return,
+        }
+        let _ = std::io::stdout().flush();
+    }
+
+    fn status(&mut self, r: &Report) {
+        plain_report(&mut std::io::stdout().lock(), r);
+    }
+
+    fn document(&mut self, text: &str, _: Json) {
+        print!("{}", text);
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// `--state-only`: the [`Status`] word alone on stdout, for scripts; the
+/// lines [`PlainSink`] would print go to stderr.
+pub struct StateOnly;
+
+impl OutputSink for StateOnly {
+    fn event(&mut self, e: Event) {
+        PlainSink.event(e)
+    }
+
+    fn status(&mut self, r: &Report) {
+        plain_report(&mut std::io::stderr().lock(), r);
+        println!("{}", Status::from(r.outcome()));
+    }
+
+    /// There is no status word to it: all of it goes to stderr.
+    fn document(&mut self, text: &str, _: Json) {
+        eprint!("{}", text);
+    }
+}
+
+/// `[name] ` on a `[[namespace]]`'s thread, as log messages have.
+fn scope() -> String {
+    log::scope().map_or_else(String::new, |n| format!("[{}] ", n))
+}
+
+/// A one-shot verdict, the namespaces' after the host's.
+fn plain_report(w: &mut dyn Write, r: &Report) {
+    plain_status(w, r, "");
+    for n in &r.namespaces {
+        let prefix = format!("[{}] ", n.name);
+        match &n.result {
+            Ok(nr) => plain_status(w, nr, &prefix),
+            Err(e) => {
+                let _ = writeln!(w, "{}{}", prefix, e);
+            }
+        }
+    }
+}
+
+/// The lines of a one-shot verdict, each after `prefix`.
+fn plain_status(w: &mut dyn Write, r: &Report, prefix: &str) {
+    let _ = writeln!(w, "{}{}", prefix, r.probes);
+    if let Some(u) = &r.uplink {
+        let _ = writeln!(w, "{}uplink {}", prefix, u);
+    }
+    for i in r.interfaces.iter().filter(|i| i.fallback == Some(true)) {
+        let addr = i.ipv4.map_or("(none)".into(), |a| a.to_string());
+        let _ = writeln!(w, "{}{}: STATIC_FALLBACK {}", prefix, i.name, addr);
+    }
+    for i in r.interfaces.iter().filter(|i| i.timed_out()) {
+        let _ = writeln!(w, "{}{}: TIMEOUT", prefix, i.name);
+    }
+    if let Some(b) = r.manager {
+        for i in &r.interfaces {
+            let st = i.manager_state.as_deref().unwrap_or("unknown");
+            let _ = writeln!(w, "{}{}: {} {}", prefix, i.name, b, st);
+        }
+    }
+    if let Some(t) = &r.trace {
+        for l in t.to_string().lines() {
+            let _ = writeln!(w, "{}{}", prefix, l);
+        }
+    }
+    if benchmarking() {
+        let ms = |d: Duration| d.as_millis().to_string();
+        for i in &r.interfaces {
+            let t = &i.timing;
+            let _ = writeln!(
+                w,
+                "{}benchmark {}: link_up_ms={} dhcp_ms={} probe_ms={} total_ms={}",
+                prefix,
+                i.name,
+                ms(t.link_up),
+                ms(t.dhcp + t.dhcp6),
+                r.probe_time(&i.name).map_or("-".into(), ms),
+                ms(t.total)
+            );
+        }
+        let _ = writeln!(
+            w,
+            "{}benchmark: probe_ms={} total_ms={}",
+            prefix,
+            ms(r.probes.elapsed),
+            ms(r.elapsed)
+        );
+    }
+}
+
+/// `--output json`: the report of a one-shot run as one document, and a
+/// line for each state change of the daemon's (`state`, `dump` and
+/// `stopping`). With `events` (`--output ndjson`), every other event too,
+/// as a line of its own: `{"event":...,"seq":...,"ts":...}`, the kinds
+/// [`events::Record`] describes among them.
+pub struct JsonSink {
+    pub events: bool,
+}
+
+/// Numbers event lines across the process, the hook worker's included.
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Prints event `j` as one line with its sequence number and timestamp,
+/// flushed so a pipe sees it at once.
+pub fn json_event(j: Json) {
+    let stamp = events::Stamp {
+        seq: SEQ.fetch_add(1, Ordering::Relaxed) + 1,
+        ts: clock::now(),
+        netns: log::scope(),
+    };
+    let j = stamp.on(j);
+    let mut out = std::io::stdout().lock();
+    let _ = writeln!(out, "{}", j);
+    let _ = out.flush();
+}
+
+/// The benchmark time `d` in milliseconds, when benchmarking.
+fn bench_ms(d: Duration) -> Option<f64> {
+    benchmarking().then(|| probe::ms(d))
+}
+
+impl OutputSink for JsonSink {
+    fn event(&mut self, e: Event) {
+        let document = matches!(
+            e,
+            Event::State { .. } | Event::Dump { .. } | Event::Stopping { .. }
+        );
+        if !self.events && !document {
+            return;
+        }
+        let j = match e {
+            Event::Check => Record::Check.to_json(),
+            Event::Wait { attempt, state, .. } => Json::obj()
+                .set("event", "wait")
+                .set("attempt", attempt as u64)
+                .set("status", state.as_str()),
+            Event::Probe { result } => Record::Probe(result.into()).to_json(),
+            Event::Skipped { target } => Record::Probe(events::Probe::skipped(target)).to_json(),
+            Event::InterfaceUp { iface, elapsed } => Record::InterfaceUp {
+                iface: iface.into(),
+                link_up_ms: bench_ms(elapsed),
+            }
+            .to_json(),
+            Event::NoCarrier { iface } => {
+                Json::obj().set("event", "no_carrier").set("iface", iface)
+            }
+            Event::Dhcp {
+                iface,
+                family,
+                ok,
+                elapsed,
+            } => Record::Dhcp {
+                iface: iface.into(),
+                family,
+                ok,
+                dhcp_ms: bench_ms(elapsed),
+            }
+            .to_json(),
+            Event::Address { iface, addr } => Json::obj()
+                .set("event", "address")
+                .set("iface", iface)
+                .set("addr", addr.to_string()),
+            Event::IpChanged { iface, old, new } => Json::obj()
+                .set("event", "ip_changed")
+                .set("iface", iface)
+                .set("old", old.to_string())
+                .set("new", new.to_string()),
+            Event::Wireless {
+                iface,
+                ssid,
+                signal_dbm,
+            } => Json::obj()
+                .set("event", "wireless")
+                .set("iface", iface)
+                .set("ssid", ssid)
+                .set("signal_dbm", signal_dbm.map(i64::from)),
+            Event::Members { iface, members } => Json::obj()
+                .set("event", "members")
+                .set("iface", iface)
+                .set("members", report::members_json(members)),
+            Event::Fallback { iface, addr } => Json::obj()
+                .set("event", "static_fallback")
+                .set("iface", iface)
+                .set("ipv4", addr.map(|a| a.to_string())),
+            Event::Route { iface, problem } => report::route_json(problem)
+                .set("event", "route")
+                .set("iface", iface),
+            Event::Lease {
+                iface,
+                lease,
+                renew_at,
+            } => report::lease_json(lease, renew_at)
+                .set("event", "lease")
+                .set("iface", iface),
+            Event::Error { iface, msg } => Json::obj()
+                .set("event", "error")
+                .set("iface", iface)
+                .set("error", msg),
+            Event::Retry { iface, attempt: a } => Json::obj()
+                .set("event", "retry")
+                .set("iface", iface)
+                .set("what", if iface.is_some() { "dhcp" } else { "probe" })
+                .set("attempt", a.n as u64)
+                .set("attempts", a.of as u64)
+                .set("delay_ms", probe::ms(a.delay))
+                .set("error", a.error.as_str()),
+            Event::Traffic {
+                iface,
+                stats,
+                rates,
+            } => Json::obj()
+                .set("event", "traffic")
+                .set("iface", iface)
+                .set("stats", report::stats_json(stats))
+                .set("rx_bytes_per_sec", rates.map(|r| r.0.round()))
+                .set("tx_bytes_per_sec", rates.map(|r| r.1.round())),
+            Event::State { from, to, .. } => {
+                let (v4_ok, v6_ok) = match to {
+                    LinkState::Up { v4_ok, v6_ok } => (Some(v4_ok), Some(v6_ok)),
+                    _ => (None, None),
+                };
+                Record::State {
+                    status: to.to_string(),
+                    previous_status: from.map(|f| f.to_string()),
+                    v4_ok,
+                    v6_ok,
+                }
+                .to_json()
+            }
+            Event::Flapping {
+                iface,
+                transitions,
+                window_min,
+                hold,
+            } => Json::obj()
+                .set("event", "flapping")
+                .set("iface", iface)
+                .set("transitions", transitions)
+                .set("window_min", window_min)
+                .set("hold_secs", iface.map(|_| hold)),
+            Event::Hotplug { iface, added } => Json::obj()
+                .set("event", "hotplug")
+                .set("iface", iface)
+                .set("action", if added { "added" } else { "removed" }),
+            Event::Reload { error } => Json::obj()
+                .set("event", "reload")
+                .set("ok", error.is_none())
+                .set("error", error),
+            Event::Dump { state } => Json::obj().set("event", "dump").set("state", state.clone()),
+            Event::Stopping { state, .. } => Json::obj()
+                .set("event", "stopping")
+                .set("status", state.map(|s| s.to_string())),
+        };
+        json_event(j);
+    }
+
+    fn status(&mut self, r: &Report) {
+        println!("{}", r.to_json());
+        let _ = std::io::stdout().flush();
+    }
+
+    fn document(&mut self, _: &str, j: Json) {
+        println!("{}", j);
+        let _ = std::io::stdout().flush();
+    }
+}
@@ -0,0 +1,98 @@
+// The daemon's PID file, which doubles as a single-instance lock: it is kept
+// flock'ed while the daemon runs, so the check and the write cannot race.
+use crate::error::WatchdogError;
+use crate::sys;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub struct PidFile {
+    path: PathBuf,
+    _lock: File,
+}
+
+/// The PID `path` names while an instance holds its lock. One left by an
+/// instance that was killed names no one, whoever has that PID now.
+pub fn running(path: &Path) -> Option<i32> {
+    let f = File::open(path).ok()?;
+    if sys::try_lock(&f).unwrap_or(true) {
+        return None;
+    }
+    // The holder writes it just after taking the lock.
+    for _ in 0..10 {
+        let pid = fs::read_to_string(path).ok()?.trim().parse().ok();
+        if pid.is_some() {
+            return pid;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    None
+}
+
+impl PidFile {
+    /// Locks `path` and writes our PID to it; fails when another instance
+    /// holds the lock. The file is removed again on drop.
+    pub fn acquire(path: &Path) -> Result<PidFile, WatchdogError> {
+        let err = |e: std::io::Error| WatchdogError::Internal(format!("{}: {}", path.display(), e));
+        let busy = |pid: Option<i32>| {
+            WatchdogError::Config(match pid {
+                Some(p) => format!("already running (pid {}, {})", p, path.display()),
+                None => format!("already running ({} is locked)", path.display()),
+            })
+        };
+        let mut f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(err)?;
+        if !sys::try_lock(&f).map_err(err)? {
+            return Err(busy(running(path)));
+        }
+        // The holder may have removed the file between our open and lock;
+        // a lock on the removed file would protect nothing.
+        let id = sys::file_id(&f).map_err(err)?;
+        if File::open(path).and_then(|g| sys::file_id(&g)).ok() != Some(id) {
+            return Err(busy(running(path)));
+        }
+        // Whatever it holds was left by an instance that is gone.
+        f.set_len(0).map_err(err)?;
+        f.rewind().map_err(err)?;
+        writeln!(f, "{}", std::process::id()).map_err(err)?;
+        Ok(PidFile {
+            path: path.to_path_buf(),
+            _lock: f,
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_lock_tells_who_runs() {
+        let path = std::env::temp_dir().join(format!("cw-pid-{}", std::process::id()));
+        // Left by a killed instance, with a PID that is alive again.
+        let own = std::process::id() as i32;
+        fs::write(&path, format!("{}\n", own)).unwrap();
+        assert_eq!(running(&path), None);
+        let p = PidFile::acquire(&path).unwrap();
+        assert_eq!(running(&path), Some(own));
+        assert!(matches!(
+            PidFile::acquire(&path),
+            Err(WatchdogError::Config(m)) if m.contains(&format!("pid {}", own))
+        ));
+        drop(p);
+        assert!(!path.exists());
+        assert_eq!(running(&path), None);
+    }
+}
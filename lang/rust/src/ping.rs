@@ -0,0 +1,641 @@
+use crate::config::{Config, ProbeMethod};
+use crate::probe::Probe;
+use crate::sys::Bind;
+use crate::{exec, log, sys};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr};
+#[cfg(unix)]
+use std::net::{SocketAddr, UdpSocket};
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+use std::process::Command;
+#[cfg(unix)]
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub enum ProbeError {
+    /// Neither a ping socket nor a raw socket may be opened by this user.
+    PermissionDenied,
+    Timeout,
+    /// Larger than the path takes with DF set, with the next-hop MTU a
+    /// fragmentation-needed or packet-too-big reply named.
+    TooBig(Option<u16>),
+    Io(io::Error),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProbeError::PermissionDenied => f.write_str("permission denied opening ICMP socket"),
+            ProbeError::Timeout => f.write_str("timed out"),
+            ProbeError::TooBig(Some(m)) => write!(f, "too big, next-hop MTU {}", m),
+            ProbeError::TooBig(None) => f.write_str("too big"),
+            ProbeError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<io::Error> for ProbeError {
+    fn from(e: io::Error) -> Self {
+        if e.raw_os_error() == Some(EMSGSIZE) {
+            return ProbeError::TooBig(None);
+        }
+        match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ProbeError::Timeout,
+            io::ErrorKind::PermissionDenied => ProbeError::PermissionDenied,
+            _ => ProbeError::Io(e),
+        }
+    }
+}
+
+#[cfg(unix)]
+const ICMP_ECHO: u8 = 8;
+#[cfg(unix)]
+const ICMP_ECHOREPLY: u8 = 0;
+#[cfg(unix)]
+const ICMP6_ECHO: u8 = 128;
+#[cfg(unix)]
+const ICMP6_ECHOREPLY: u8 = 129;
+#[cfg(unix)]
+const ICMP_UNREACH: u8 = 3;
+#[cfg(unix)]
+const ICMP_FRAG_NEEDED: u8 = 4;
+#[cfg(unix)]
+const ICMP6_PACKET_TOO_BIG: u8 = 2;
+/// What sending more than the path MTU known to the kernel fails with.
+#[cfg(target_os = "linux")]
+const EMSGSIZE: i32 = 90;
+#[cfg(target_os = "macos")]
+const EMSGSIZE: i32 = 40;
+/// WSAEMSGSIZE.
+#[cfg(windows)]
+const EMSGSIZE: i32 = 10040;
+
+#[cfg(unix)]
+static SEQ: AtomicU16 = AtomicU16::new(1);
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Opens an unprivileged ping socket, falling back to a raw one (needs
+/// CAP_NET_RAW), bound as `bind` says. Returns the socket and whether
+/// replies carry an IP header, as IPv4 ones on a macOS ping socket do.
+#[cfg(unix)]
+fn open(addr: IpAddr, bind: Bind) -> Result<(UdpSocket, bool), ProbeError> {
+    let (dom, proto) = match addr {
+        IpAddr::V4(_) => (sys::AF_INET, 1),
+        IpAddr::V6(_) => (sys::AF_INET6, 58),
+    };
+    let (fd, raw) = match sys::socket(dom, sys::SOCK_DGRAM, proto) {
+        Ok(fd) => (fd, cfg!(target_os = "macos") && addr.is_ipv4()),
+        Err(_) => (sys::socket(dom, sys::SOCK_RAW, proto)?, addr.is_ipv4()),
+    };
+    bind.apply(&fd, addr.is_ipv6())?;
+    Ok((UdpSocket::from(fd), raw))
+}
+
+#[cfg(unix)]
+fn checksum(b: &[u8]) -> u16 {
+    let mut sum: u32 = b
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// An echo request of at least `len` bytes; the kernel fills in the ICMPv6
+/// checksum.
+#[cfg(unix)]
+fn echo_request(addr: IpAddr, id: u16, seq: u16, len: usize) -> Vec<u8> {
+    let ty = if addr.is_ipv4() {
+        ICMP_ECHO
+    } else {
+        ICMP6_ECHO
+    };
+    let mut pkt = vec![ty, 0, 0, 0];
+    pkt.extend_from_slice(&id.to_be_bytes());
+    pkt.extend_from_slice(&seq.to_be_bytes());
+    pkt.extend_from_slice(b"cw-netup-probe..");
+    pkt.resize(len.max(pkt.len()), 0);
+    if addr.is_ipv4() {
+        let c = checksum(&pkt);
+        pkt[2..4].copy_from_slice(&c.to_be_bytes());
+    }
+    pkt
+}
+
+/// Sends one ICMP echo request and waits for the matching reply. With `mtu`
+/// the request is padded to a packet of that size and may not be fragmented.
+#[cfg(unix)]
+fn icmp_probe(
+    addr: IpAddr,
+    timeout: Duration,
+    bind: Bind,
+    mtu: Option<u16>,
+) -> Result<Duration, ProbeError> {
+    let (sock, raw) = open(addr, bind)?;
+    let reply = if addr.is_ipv4() {
+        ICMP_ECHOREPLY
+    } else {
+        ICMP6_ECHOREPLY
+    };
+    let id = std::process::id() as u16;
+    let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+    let mut len = 0;
+    if let Some(m) = mtu {
+        sys::dont_fragment(&sock, addr.is_ipv6())?;
+        let ip = if addr.is_ipv4() { 20 } else { 40 };
+        len = (m as usize).saturating_sub(ip);
+    }
+    let pkt = echo_request(addr, id, seq, len);
+    let start = Instant::now();
+    sock.send_to(&pkt, SocketAddr::new(addr, 0))?;
+    let mut buf = [0u8; 1500];
+    loop {
+        let left = timeout
+            .checked_sub(start.elapsed())
+            .filter(|d| !d.is_zero())
+            .ok_or(ProbeError::Timeout)?;
+        sock.set_read_timeout(Some(left))?;
+        let (n, from) = sock.recv_from(&mut buf)?;
+        let b = if raw {
+            &buf[((buf[0] & 0x0f) as usize * 4).min(n)..n]
+        } else {
+            &buf[..n]
+        };
+        if let Some(m) = too_big(b, addr.is_ipv6(), seq) {
+            return Err(ProbeError::TooBig(m));
+        }
+        // Ping sockets rewrite the identifier, so only raw sockets check it.
+        if from.ip() == addr
+            && b.len() >= 8
+            && b[0] == reply
+            && u16::from_be_bytes([b[6], b[7]]) == seq
+            && (!raw || u16::from_be_bytes([b[4], b[5]]) == id)
+        {
+            return Ok(start.elapsed());
+        }
+    }
+}
+
+/// The same through IcmpSendEcho2, which builds the request itself; the
+/// reply must come from `addr`.
+#[cfg(windows)]
+fn icmp_probe(
+    addr: IpAddr,
+    timeout: Duration,
+    bind: Bind,
+    mtu: Option<u16>,
+) -> Result<Duration, ProbeError> {
+    let from = source(addr, bind)?;
+    let header = if addr.is_ipv4() { 28 } else { 48 };
+    let payload = mtu.map_or(16, |m| (m as usize).saturating_sub(header));
+    let start = Instant::now();
+    match sys::icmp_echo(addr, from, payload, None, mtu.is_some(), timeout)? {
+        sys::Echo::Reply(a) if a == addr => Ok(start.elapsed()),
+        sys::Echo::TooBig => Err(ProbeError::TooBig(None)),
+        _ => Err(ProbeError::Timeout),
+    }
+}
+
+/// Where an echo leaves from: Windows ICMP only takes a source address, so
+/// an interface stands for its first one of the family.
+#[cfg(windows)]
+fn source(addr: IpAddr, bind: Bind) -> Result<Option<IpAddr>, ProbeError> {
+    if let Some(m) = bind.mark {
+        let e = format!("fwmark {}: needs Linux", m);
+        return Err(io::Error::new(io::ErrorKind::Unsupported, e).into());
+    }
+    let Some(dev) = bind.dev.filter(|_| bind.source.is_none()) else {
+        return Ok(bind.source);
+    };
+    crate::ifaddrs::links()?
+        .into_iter()
+        .find(|l| l.name == dev)
+        .and_then(|l| {
+            l.addrs
+                .into_iter()
+                .map(|(a, _)| a)
+                .find(|a| a.is_ipv4() == addr.is_ipv4())
+        })
+        .map(Some)
+        .ok_or_else(|| {
+            let e = format!("{}: no address to send from", dev);
+            io::Error::new(io::ErrorKind::AddrNotAvailable, e).into()
+        })
+}
+
+/// Whether `b` is a router's fragmentation-needed (packet-too-big) reply
+/// to echo `seq`, with the next-hop MTU it names. Only raw sockets see
+/// these; on a ping socket the kernel takes note, and the next send of the
+/// size fails with EMSGSIZE.
+#[cfg(unix)]
+fn too_big(b: &[u8], v6: bool, seq: u16) -> Option<Option<u16>> {
+    let (mtu, inner) = match (b.first()?, b.get(1)?) {
+        (&ICMP_UNREACH, &ICMP_FRAG_NEEDED) if !v6 => {
+            let ip = b.get(8)?;
+            (
+                u16::from_be_bytes([b[6], b[7]]),
+                8 + (ip & 0x0f) as usize * 4,
+            )
+        }
+        (&ICMP6_PACKET_TOO_BIG, 0) if v6 && b.len() >= 8 => {
+            let m = u32::from_be_bytes([b[4], b[5], b[6], b[7]]);
+            (u16::try_from(m).unwrap_or(u16::MAX), 8 + 40)
+        }
+        _ => return None,
+    };
+    let echo = b.get(inner..inner + 8)?;
+    (u16::from_be_bytes([echo[6], echo[7]]) == seq).then_some((mtu > 0).then_some(mtu))
+}
+
+/// Falls back to the system `ping` binary, reading the RTT from its output.
+/// `-I` takes an interface or a source address, not both; the interface
+/// wins.
+fn ping_binary(addr: IpAddr, timeout: Duration, count: u8, bind: Bind) -> ProbeResult {
+    let mut r = ProbeResult::none(Some(addr), count);
+    let wait = timeout.as_secs().max(1);
+    let mut cmd = Command::new("ping");
+    let from = bind
+        .dev
+        .map(String::from)
+        .or(bind.source.map(|a| a.to_string()));
+    if let Some(i) = from {
+        cmd.args(["-I", &i]);
+    }
+    if let Some(m) = bind.mark {
+        cmd.args(["-m", &m.to_string()]);
+    }
+    cmd.args([
+        "-c",
+        &count.to_string(),
+        "-W",
+        &wait.to_string(),
+        &addr.to_string(),
+    ]);
+    // ping waits a second between echoes, and `-W` after the last.
+    let t = exec::limits()
+        .ping
+        .unwrap_or(Duration::from_secs(count as u64 + wait + 1));
+    let Ok(out) = exec::sys()
+        .exec(&mut cmd, t)
+        .inspect_err(|e| log::debug!("ping {}: {}", addr, e))
+    else {
+        return r;
+    };
+    let s = String::from_utf8_lossy(&out.stdout);
+    // "3 packets transmitted, 2 received" / "rtt min/avg/max/mdev = 1.1/2.2/3.3/0.4 ms"
+    r.received = s
+        .split(", ")
+        .find_map(|p| {
+            p.strip_suffix(" packets received")
+                .or_else(|| p.strip_suffix(" received"))
+        })
+        .and_then(|n| n.trim().parse().ok())
+        .unwrap_or(out.success() as u8);
+    let stats: Vec<f64> = s
+        .split(" = ")
+        .nth(1)
+        .map(|t| t.split('/').filter_map(|v| v.parse().ok()).collect())
+        .unwrap_or_default();
+    if r.received > 0 {
+        let ms = |i: usize| Duration::from_secs_f64(stats.get(i).copied().unwrap_or(0.0) / 1000.0);
+        (r.min, r.rtt, r.max) = (Some(ms(0)), Some(ms(1)), Some(ms(2)));
+    }
+    r
+}
+
+pub struct ProbeResult {
+    /// The address probed; `None` when a host name did not resolve.
+    pub addr: Option<IpAddr>,
+    pub sent: u8,
+    pub received: u8,
+    /// Mean round-trip time over the replies received.
+    pub rtt: Option<Duration>,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+}
+
+impl ProbeResult {
+    /// `sent` requests, none answered.
+    pub fn none(addr: Option<IpAddr>, sent: u8) -> ProbeResult {
+        ProbeResult {
+            addr,
+            sent,
+            received: 0,
+            rtt: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// `sent` requests answered after `rtts`.
+    pub fn from_rtts(addr: Option<IpAddr>, sent: u8, rtts: &[Duration]) -> ProbeResult {
+        ProbeResult {
+            addr,
+            sent,
+            received: rtts.len() as u8,
+            rtt: (!rtts.is_empty()).then(|| rtts.iter().sum::<Duration>() / rtts.len() as u32),
+            min: rtts.iter().min().copied(),
+            max: rtts.iter().max().copied(),
+        }
+    }
+}
+
+/// Sends `count` echo requests, `timeout` each. Without permission to open an
+/// ICMP socket this warns once and shells out to `ping` instead. Echoes
+/// leave as `bind` says.
+pub fn probe_icmp(addr: IpAddr, timeout: Duration, count: u8, bind: Bind) -> ProbeResult {
+    if exec::sys().scripts("ping") {
+        return ping_binary(addr, timeout, count, bind);
+    }
+    let mut rtts = Vec::new();
+    for _ in 0..count {
+        match icmp_probe(addr, timeout, bind, None) {
+            Ok(d) => rtts.push(d),
+            Err(ProbeError::PermissionDenied) => {
+                if !WARNED.swap(true, Ordering::Relaxed) {
+                    log::warn!(
+                        "cannot open ICMP socket (needs CAP_NET_RAW), falling back to ping(8)"
+                    );
+                }
+                return ping_binary(addr, timeout, count, bind);
+            }
+            Err(_) => {}
+        }
+    }
+    ProbeResult::from_rtts(Some(addr), count, &rtts)
+}
+
+/// ICMPv6 echo; same socket strategy and fallback as [`probe_icmp`].
+pub fn probe_icmpv6(addr: Ipv6Addr, timeout: Duration, count: u8, bind: Bind) -> ProbeResult {
+    probe_icmp(IpAddr::V6(addr), timeout, count, bind)
+}
+
+/// How long each size gets to be answered in [`measure_pmtu`].
+const PMTU_WAIT: Duration = Duration::from_millis(500);
+/// Echoes per size: large pings are often rate-limited.
+const PMTU_TRIES: u8 = 2;
+
+/// Whether a packet of `mtu` bytes gets to `target` and back with DF set,
+/// with the next-hop MTU a router named when it did not.
+fn fits(target: IpAddr, bind: Bind, mtu: u16) -> (bool, Option<u16>) {
+    for _ in 0..PMTU_TRIES {
+        match icmp_probe(target, PMTU_WAIT, bind, Some(mtu)) {
+            Ok(_) => return (true, None),
+            Err(ProbeError::Timeout) => continue,
+            Err(ProbeError::TooBig(m)) => return (false, m.filter(|&m| m < mtu)),
+            Err(_) => return (false, None),
+        }
+    }
+    (false, None)
+}
+
+/// The path MTU towards `target` through `iface`, found as RFC 1191 does
+/// but from user space: echoes with DF set, first carrying `payload` bytes
+/// (1472 fill a 1500-byte MTU), then bisecting down to the family's
+/// minimum, each size tried twice. A router's fragmentation-needed reply
+/// names the size to try next. `None` when not even a small echo comes
+/// back.
+pub fn measure_pmtu(iface: &str, target: IpAddr, payload: u16) -> Option<u16> {
+    let bind = Bind::dev(Some(iface));
+    icmp_probe(target, PMTU_WAIT, bind, None)
+        .inspect_err(|e| log::debug!(iface = iface; "pmtu {} via {}: {}", target, iface, e))
+        .ok()?;
+    let (floor, header) = if target.is_ipv4() {
+        (68, 28)
+    } else {
+        (1280, 48)
+    };
+    let max = payload.saturating_add(header).max(floor);
+    let (ok, mut hint) = fits(target, bind, max);
+    if ok {
+        return Some(max);
+    }
+    // `lo` fits, `hi` does not.
+    let (mut lo, mut hi) = (floor, max);
+    if !fits(target, bind, lo).0 {
+        return None;
+    }
+    while hi - lo > 1 {
+        let named = hint.take().filter(|&m| m > lo && m < hi);
+        let m = named.unwrap_or(lo + (hi - lo) / 2);
+        let (ok, h) = fits(target, bind, m);
+        match (ok, named) {
+            // Nothing past a hop's MTU gets through it.
+            (true, Some(_)) => return Some(m),
+            (true, None) => lo = m,
+            (false, _) => (hi, hint) = (m, h),
+        }
+    }
+    Some(lo)
+}
+
+/// One hop of a [`traceroute`].
+pub struct HopResult {
+    /// The TTL the echo was sent with.
+    pub hop: u8,
+    /// Who answered; `None` when nothing did within the timeout.
+    pub addr: Option<IpAddr>,
+    pub rtt: Option<Duration>,
+}
+
+/// A [`traceroute`] to the first IP target, for an OFFLINE verdict.
+pub struct Trace {
+    pub target: IpAddr,
+    pub hops: Vec<HopResult>,
+}
+
+impl Trace {
+    /// `None` with `traceroute_hops` 0 or no IP target.
+    pub fn run(cfg: &Config) -> Option<Trace> {
+        if cfg.traceroute_hops == 0 {
+            return None;
+        }
+        let target = cfg.targets.iter().find_map(|t| match t {
+            ProbeMethod::Icmp(a) => Some(*a),
+            _ => None,
+        })?;
+        let t = Duration::from_secs(cfg.ping_timeout.max(1));
+        Some(Trace {
+            target,
+            hops: traceroute(target, cfg.traceroute_hops, t),
+        })
+    }
+}
+
+/// `traceroute to 192.0.2.1: 1 10.0.0.1 0.42 ms, 2 *, 3 *`
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "traceroute to {}:", self.target)?;
+        if self.hops.is_empty() {
+            return f.write_str(" no hops (no ICMP socket)");
+        }
+        for (n, h) in self.hops.iter().enumerate() {
+            let sep = if n == 0 { " " } else { ", " };
+            match (h.addr, h.rtt) {
+                (Some(a), Some(d)) => write!(
+                    f,
+                    "{}{} {} {:.2} ms",
+                    sep,
+                    h.hop,
+                    a,
+                    d.as_secs_f64() * 1000.0
+                )?,
+                _ => write!(f, "{}{} *", sep, h.hop)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hops in a row without an answer after which a trace gives up.
+const SILENT_HOPS: usize = 3;
+
+/// ICMP echoes to `target` with a TTL of 1, 2, ... `max_hops`, each answered
+/// by the router it runs out at (time exceeded), until `target` replies, a
+/// router reports it unreachable or `SILENT_HOPS` hops in a row stay quiet.
+/// Empty without permission to open an ICMP socket.
+pub fn traceroute(target: IpAddr, max_hops: u8, timeout: Duration) -> Vec<HopResult> {
+    let mut hops = Vec::new();
+    for ttl in 1..=max_hops {
+        let (h, last) = match hop(target, ttl, timeout) {
+            Ok(h) => h,
+            Err(ProbeError::PermissionDenied) => break,
+            Err(e) => {
+                log::debug!("traceroute {}: hop {}: {}", target, ttl, e);
+                let none = HopResult {
+                    hop: ttl,
+                    addr: None,
+                    rtt: None,
+                };
+                (none, false)
+            }
+        };
+        hops.push(h);
+        let silent = hops.iter().rev().take_while(|h| h.addr.is_none()).count();
+        if last || silent >= SILENT_HOPS {
+            break;
+        }
+    }
+    hops
+}
+
+/// The start of our packet as an ICMP error quotes it, after its own 8
+/// bytes.
+#[cfg(unix)]
+fn quoted(b: &[u8], v6: bool) -> Option<&[u8]> {
+    match v6 {
+        true => b.get(48..),
+        false => b
+            .get(8..)
+            .and_then(|ip| ip.get((*ip.first()? & 0x0f) as usize * 4..)),
+    }
+}
+
+/// What ICMP read back says about our echo `seq`; `Some(true)` when the
+/// trace ends there. Ping sockets rewrite the identifier, so `id` is only
+/// checked when given.
+#[cfg(unix)]
+fn hop_reply(b: &[u8], v6: bool, id: Option<u16>, seq: u16) -> Option<bool> {
+    let ours = |icmp: &[u8]| {
+        icmp.len() >= 8
+            && id.is_none_or(|id| u16::from_be_bytes([icmp[4], icmp[5]]) == id)
+            && u16::from_be_bytes([icmp[6], icmp[7]]) == seq
+    };
+    let (echo_reply, exceeded, unreachable) = match v6 {
+        true => (ICMP6_ECHOREPLY, 3, 1),
+        false => (ICMP_ECHOREPLY, 11, 3),
+    };
+    match *b.first()? {
+        t if t == echo_reply && ours(b) => Some(true),
+        t if t == exceeded && quoted(b, v6).is_some_and(ours) => Some(false),
+        t if t == unreachable && quoted(b, v6).is_some_and(ours) => Some(true),
+        _ => None,
+    }
+}
+
+/// One echo with TTL `ttl`; whether it ends the trace comes with it.
+#[cfg(unix)]
+fn hop(target: IpAddr, ttl: u8, timeout: Duration) -> Result<(HopResult, bool), ProbeError> {
+    let (sock, raw) = open(target, Bind::default())?;
+    let v6 = target.is_ipv6();
+    sys::set_ttl(&sock, v6, ttl)?;
+    sock.set_nonblocking(true)?;
+    let id = std::process::id() as u16;
+    let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+    let mut h = HopResult {
+        hop: ttl,
+        addr: None,
+        rtt: None,
+    };
+    let start = Instant::now();
+    sock.send_to(
+        &echo_request(target, id, seq, 0),
+        SocketAddr::new(target, 0),
+    )?;
+    let mut buf = [0u8; 1500];
+    while let Some(left) = timeout
+        .checked_sub(start.elapsed())
+        .filter(|d| !d.is_zero())
+    {
+        sys::poll_in(&[sock.as_raw_fd()], left)?;
+        // Ping sockets hand ICMP errors over through the error queue only.
+        if let Some(e) = sys::recv_icmp_error(&sock)? {
+            (h.addr, h.rtt) = (e.from, Some(start.elapsed()));
+            let exceeded = if v6 { e.ty == 3 } else { e.ty == 11 };
+            return Ok((h, !exceeded));
+        }
+        let Ok((n, from)) = sock.recv_from(&mut buf) else {
+            continue;
+        };
+        let b = if raw {
+            &buf[((buf[0] & 0x0f) as usize * 4).min(n)..n]
+        } else {
+            &buf[..n]
+        };
+        if let Some(last) = hop_reply(b, v6, raw.then_some(id), seq) {
+            (h.addr, h.rtt) = (Some(from.ip()), Some(start.elapsed()));
+            return Ok((h, last));
+        }
+    }
+    Ok((h, false))
+}
+
+/// One echo with TTL `ttl` through IcmpSendEcho2; whether it ends the
+/// trace comes with it.
+#[cfg(windows)]
+fn hop(target: IpAddr, ttl: u8, timeout: Duration) -> Result<(HopResult, bool), ProbeError> {
+    let start = Instant::now();
+    let (addr, last) = match sys::icmp_echo(target, None, 16, Some(ttl), false, timeout)? {
+        sys::Echo::Reply(a) => (Some(a), true),
+        sys::Echo::Expired(a) => (Some(a), false),
+        sys::Echo::Unreachable(a) => (a, true),
+        sys::Echo::TooBig => return Err(ProbeError::TooBig(None)),
+    };
+    let h = HopResult {
+        hop: ttl,
+        addr,
+        rtt: Some(start.elapsed()),
+    };
+    Ok((h, last))
+}
+
+pub struct Icmp<'a> {
+    pub addr: IpAddr,
+    pub count: u8,
+    pub bind: Bind<'a>,
+}
+
+impl Probe for Icmp<'_> {
+    fn check(&self, timeout: Duration) -> ProbeResult {
+        match self.addr {
+            IpAddr::V4(_) => probe_icmp(self.addr, timeout, self.count, self.bind),
+            IpAddr::V6(a) => probe_icmpv6(a, timeout, self.count, self.bind),
+        }
+    }
+}
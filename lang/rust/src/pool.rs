@@ -0,0 +1,62 @@
+// Bounded parallel map over scoped threads. Built with `--features sync`
+// everything runs inline on the calling thread, for small embedded targets.
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+/// `f(item)`, or what it panicked with.
+fn call<T, R>(f: &impl Fn(&T) -> R, it: &T) -> Result<R, String> {
+    panic::catch_unwind(AssertUnwindSafe(|| f(it))).map_err(|p: Box<dyn Any + Send>| {
+        p.downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| p.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panicked".into())
+    })
+}
+
+/// Applies `f` to every item with at most `jobs` workers (0 = one per item).
+/// Results come back in input order; an item whose `f` panicked gets the
+/// panic message and the others are unaffected.
+#[cfg(not(feature = "sync"))]
+pub fn map<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<Result<R, String>> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    let jobs = if jobs == 0 {
+        items.len()
+    } else {
+        jobs.min(items.len())
+    };
+    if jobs <= 1 {
+        return items.iter().map(|it| call(&f, it)).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let out: Mutex<Vec<Option<Result<R, String>>>> =
+        Mutex::new((0..items.len()).map(|_| None).collect());
+    std::thread::scope(|s| {
+        for _ in 0..jobs {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(it) = items.get(i) else { return };
+                let r = call(&f, it);
+                out.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(r);
+            });
+        }
+    });
+    out.into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .map(|r| r.expect("worker finished"))
+        .collect()
+}
+
+#[cfg(feature = "sync")]
+pub fn map<T: Sync, R: Send>(
+    items: &[T],
+    _jobs: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<Result<R, String>> {
+    items.iter().map(|it| call(&f, it)).collect()
+}
@@ -0,0 +1,1243 @@
+use crate::backoff::{Attempt, Backoff};
+use crate::config::{Config, Family, ProbeMethod, ProbeMode};
+use crate::http::Url;
+use crate::latency::LatencyWindow;
+use crate::ping::ProbeResult;
+#[cfg(target_os = "linux")]
+use crate::{arp, log, netlink, tunnel};
+use crate::{dns, http, iface, net, ping, sys, tcp};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+pub struct TargetResult {
+    pub target: ProbeMethod,
+    /// What was actually probed; resolved from the host name for TCP.
+    pub addr: Option<IpAddr>,
+    pub rtt: Option<Duration>,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    pub sent: u8,
+    pub received: u8,
+    /// Answered, but with more loss or a slower mean than allowed.
+    pub degraded: bool,
+    /// Share of a weighted quorum.
+    pub weight: u32,
+    /// Interface the probe left through, and from which address: what it
+    /// was bound to, else what the routing table picked.
+    pub iface: Option<String>,
+    pub source: Option<IpAddr>,
+    /// Why it was not probed at all, such as an interface that is gone.
+    pub error: Option<String>,
+}
+
+impl TargetResult {
+    pub fn is_ipv6(&self) -> bool {
+        self.addr.is_some_and(|a| a.is_ipv6())
+    }
+
+    /// Percent of requests unanswered.
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        self.sent.saturating_sub(self.received) as f64 * 100.0 / self.sent as f64
+    }
+
+    /// Whether it counts towards the quorum.
+    fn up(&self, cfg: &Config) -> bool {
+        self.rtt.is_some() && !(self.degraded && cfg.degraded_fail)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpVerdict {
+    Ok,
+    /// A page or redirect where the expected status was due.
+    CaptivePortal,
+    Failed(String),
+}
+
+pub struct HttpResult {
+    pub url: String,
+    pub status: Option<u16>,
+    pub rtt: Option<Duration>,
+    pub verdict: HttpVerdict,
+    /// Where a portal's redirect points.
+    pub location: Option<String>,
+    /// The address that answered.
+    pub server: Option<IpAddr>,
+}
+
+/// Reachability through one interface (`--per-interface`).
+pub struct IfaceVerdict {
+    pub iface: String,
+    pub online: bool,
+    /// Fastest answering target.
+    pub rtt: Option<Duration>,
+    /// Unplugged while its targets were probed.
+    pub gone: bool,
+    /// How long its targets took.
+    pub elapsed: Duration,
+}
+
+pub struct DnsResult {
+    pub name: String,
+    pub answer: Result<dns::Answer, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Online,
+    /// Pings are answered but the HTTP probe fails, or loss or latency is
+    /// past the configured limits.
+    Degraded,
+    /// The network answers but names do not resolve.
+    DnsBroken,
+    /// HTTP is answered by a login page or redirect instead.
+    CaptivePortal,
+    Offline,
+}
+
+impl State {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            State::Online => "ONLINE",
+            State::Degraded => "DEGRADED",
+            State::DnsBroken => "DNS_BROKEN",
+            State::CaptivePortal => "CAPTIVE_PORTAL",
+            State::Offline => "OFFLINE",
+        }
+    }
+}
+
+/// The default gateway, checked before any external target.
+pub struct GatewayResult {
+    pub addr: Ipv4Addr,
+    pub iface: String,
+    /// The route's interface is up with carrier.
+    pub link: bool,
+    pub rtt: Option<Duration>,
+    /// Resolved by ARP; some routers do not answer pings.
+    pub arp: bool,
+    /// Its neighbour entry's state and MAC address, if it has one.
+    pub neigh: Option<String>,
+    pub mac: Option<String>,
+    /// The entry had failed, and was flushed before pinging again.
+    pub flushed: bool,
+    /// The entry is FAILED or INCOMPLETE.
+    pub arp_failed: bool,
+}
+
+impl GatewayResult {
+    /// An on-link default route (0.0.0.0, e.g. PPP) has nothing to ask.
+    pub fn reachable(&self) -> bool {
+        self.link && (self.addr.is_unspecified() || self.rtt.is_some() || self.arp)
+    }
+}
+
+/// Which stage an OFFLINE verdict failed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnosis {
+    LinkDown,
+    /// Carrier, but no default route to test a gateway through.
+    NoDefaultRoute,
+    GatewayUnreachable,
+    /// The gateway's neighbour entry failed and it does not answer ARP.
+    GatewayArpFailed,
+    UpstreamUnreachable,
+    /// With `[tunnel]`: the targets answer outside the tunnel, not through it.
+    TunnelDown,
+    /// ... nor outside it.
+    UplinkDown,
+}
+
+impl Diagnosis {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Diagnosis::LinkDown => "LINK_DOWN",
+            Diagnosis::NoDefaultRoute => "NO_DEFAULT_ROUTE",
+            Diagnosis::GatewayUnreachable => "GATEWAY_UNREACHABLE",
+            Diagnosis::GatewayArpFailed => "GATEWAY_ARP_FAILED",
+            Diagnosis::UpstreamUnreachable => "UPSTREAM_UNREACHABLE",
+            Diagnosis::TunnelDown => "TUNNEL_DOWN",
+            Diagnosis::UplinkDown => "UPLINK_DOWN",
+        }
+    }
+}
+
+/// What an OFFLINE check with `[tunnel]` found outside the tunnels.
+pub struct TunnelResult {
+    pub tunnels: Vec<String>,
+    /// The interface beneath them the outside targets were bound to.
+    pub uplink: String,
+    pub results: Vec<TargetResult>,
+    /// They met the quorum.
+    pub uplink_ok: bool,
+}
+
+/// Link quality over the latency window.
+pub struct Quality {
+    pub mean: Duration,
+    pub p95: Duration,
+    /// Percent of recent echoes lost.
+    pub loss: f64,
+    pub poor: bool,
+}
+
+/// A download of `throughput_url`.
+pub struct Throughput {
+    pub url: String,
+    pub bytes: usize,
+    pub elapsed: Duration,
+    pub error: Option<String>,
+    /// Under `min_throughput`, or failed with one set.
+    pub slow: bool,
+}
+
+impl Throughput {
+    pub fn kbps(&self) -> f64 {
+        self.bytes as f64 * 8.0 / self.elapsed.as_secs_f64().max(1e-6) / 1000.0
+    }
+}
+
+/// The path MTU an ONLINE check found, with `pmtu`.
+pub struct PathMtu {
+    pub iface: String,
+    pub target: IpAddr,
+    /// `None` when not even a small echo came back, or no ICMP socket
+    /// could be opened.
+    pub mtu: Option<u16>,
+    /// Below `pmtu_min`: large packets are lost, small ones get through.
+    pub low: bool,
+}
+
+pub struct Summary {
+    pub results: Vec<TargetResult>,
+    pub http: Option<HttpResult>,
+    pub dns: Option<DnsResult>,
+    pub quality: Option<Quality>,
+    /// Rounds that failed and were retried.
+    pub retries: Vec<Attempt>,
+    pub state: State,
+    /// Per-family verdicts; `None` when no target of that family was probed.
+    pub v4: Option<bool>,
+    pub v6: Option<bool>,
+    /// Per-interface verdicts; empty unless `per_interface` is set.
+    pub per_iface: Vec<IfaceVerdict>,
+    /// `None` without `gateway_check` or a default route.
+    pub gateway: Option<GatewayResult>,
+    /// Set when OFFLINE.
+    pub diagnosis: Option<Diagnosis>,
+    /// Reachable share of the targets by weight, with `quorum_threshold`.
+    pub weighted: Option<f64>,
+    /// Measured only once the rest is ONLINE.
+    pub throughput: Option<Throughput>,
+    /// Likewise.
+    pub pmtu: Option<PathMtu>,
+    /// The whole check, retries and throughput included.
+    pub elapsed: Duration,
+    /// Targets a relaxed check of the adaptive daemon left out.
+    pub skipped: Vec<ProbeMethod>,
+    /// Set when OFFLINE with `[tunnel]` and a tunnel there.
+    pub tunnel: Option<TunnelResult>,
+}
+
+impl Summary {
+    pub fn online(&self) -> bool {
+        self.state == State::Online
+    }
+
+    /// The fastest reply of an IP or TCP target.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.results
+            .iter()
+            .filter(|r| !r.target.is_dns())
+            .filter_map(|r| r.rtt)
+            .min()
+    }
+
+    /// The page to log in at when behind a captive portal: the redirect's
+    /// target, or the probe URL where the portal answered in its place.
+    pub fn portal_url(&self) -> Option<&str> {
+        let h = self
+            .http
+            .as_ref()
+            .filter(|_| self.state == State::CaptivePortal)?;
+        Some(h.location.as_deref().unwrap_or(&h.url))
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.state.as_str())?;
+        if !self.per_iface.is_empty() {
+            for (n, v) in self.per_iface.iter().enumerate() {
+                f.write_str(if n == 0 { ": " } else { ", " })?;
+                match v.rtt.filter(|_| v.online) {
+                    _ if v.gone => write!(f, "{}: GONE", v.iface)?,
+                    Some(d) => write!(f, "{}: ONLINE ({})", v.iface, fmt_rtt(d))?,
+                    None => write!(f, "{}: OFFLINE", v.iface)?,
+                }
+            }
+            for r in self.results.iter().filter(|r| r.target.is_dns()) {
+                if r.rtt.is_none() {
+                    write!(f, "; {} does not resolve", r.target)?;
+                }
+            }
+        } else if !self.results.is_empty() {
+            let all: Vec<&TargetResult> = self.results.iter().collect();
+            let reach = reach(&all).into_iter();
+            let up = reach.clone().filter(|r| r.rtt.is_some()).count();
+            match self.weighted {
+                Some(w) => write!(
+                    f,
+                    " ({}/{}, {:.0}% by weight)",
+                    up,
+                    reach.count(),
+                    w * 100.0
+                )?,
+                None => write!(f, " ({}/{})", up, reach.count())?,
+            }
+            for (n, r) in self.results.iter().enumerate() {
+                f.write_str(if n == 0 { ": " } else { ", " })?;
+                match (r.rtt, r.target.is_dns()) {
+                    (Some(d), false) => write!(f, "{} reachable {}", r.target, fmt_rtt(d))?,
+                    (None, false) => match &r.error {
+                        Some(e) => write!(f, "{} unreachable ({})", r.target, e)?,
+                        None => write!(f, "{} unreachable", r.target)?,
+                    },
+                    (Some(d), true) => write!(f, "{} resolves {}", r.target, fmt_rtt(d))?,
+                    (None, true) => write!(f, "{} does not resolve", r.target)?,
+                }
+                match (r.min, r.max) {
+                    (Some(lo), Some(hi)) if r.sent > 1 => write!(
+                        f,
+                        " [{}/{} replies, {:.0}% loss, min/max {:.2}/{:.2} ms]",
+                        r.received,
+                        r.sent,
+                        r.loss(),
+                        ms(lo),
+                        ms(hi)
+                    )?,
+                    _ if r.sent > 1 => write!(f, " [{}/{} replies]", r.received, r.sent)?,
+                    _ => {}
+                }
+                if r.weight != 1 {
+                    write!(f, " (weight {})", r.weight)?;
+                }
+                if r.degraded {
+                    f.write_str(" DEGRADED")?;
+                }
+            }
+        }
+        if let (Some(v4), Some(v6)) = (self.v4, self.v6) {
+            let st = |ok| if ok { "ok" } else { "down" };
+            write!(f, " (IPv4 {}, IPv6 {})", st(v4), st(v6))?;
+        }
+        if let Some(h) = &self.http {
+            f.write_str(if self.results.is_empty() { ": " } else { "; " })?;
+            match &h.verdict {
+                HttpVerdict::Ok => write!(f, "{} ok", h.url)?,
+                HttpVerdict::CaptivePortal => match (&h.location, h.server) {
+                    (Some(l), _) => write!(f, "{} captive portal at {}", h.url, l)?,
+                    (None, Some(a)) => write!(f, "{} captive portal, answered by {}", h.url, a)?,
+                    (None, None) => write!(f, "{} captive portal", h.url)?,
+                },
+                HttpVerdict::Failed(e) => write!(f, "{} failed: {}", h.url, e)?,
+            }
+            if let (Some(st), Some(d)) = (h.status, h.rtt) {
+                write!(f, " (HTTP {}, {})", st, fmt_rtt(d))?;
+            }
+        }
+        if let Some(d) = &self.dns {
+            let first = self.results.is_empty() && self.http.is_none();
+            f.write_str(if first { ": " } else { "; " })?;
+            match &d.answer {
+                Ok(a) => write!(
+                    f,
+                    "{} resolves to {} via {} ({})",
+                    d.name,
+                    a.addrs[0],
+                    a.server,
+                    fmt_rtt(a.rtt)
+                )?,
+                Err(e) => write!(f, "{} does not resolve: {}", d.name, e)?,
+            }
+        }
+        if let Some(d) = self.diagnosis {
+            let first = self.results.is_empty() && self.http.is_none() && self.dns.is_none();
+            f.write_str(if first { ": " } else { "; " })?;
+            f.write_str(d.as_str())?;
+            match (d, &self.gateway) {
+                (Diagnosis::LinkDown, Some(g)) => {
+                    write!(f, " ({} is down or has no carrier)", g.iface)?
+                }
+                (Diagnosis::LinkDown, None) => f.write_str(" (no interface has carrier)")?,
+                (Diagnosis::GatewayUnreachable, Some(g)) => write!(
+                    f,
+                    " ({} on {} answers neither ping nor ARP)",
+                    g.addr, g.iface
+                )?,
+                (Diagnosis::GatewayArpFailed, Some(g)) => write!(
+                    f,
+                    " ({} on {} does not answer ARP, neighbour entry {})",
+                    g.addr,
+                    g.iface,
+                    g.neigh.as_deref().unwrap_or("gone")
+                )?,
+                (Diagnosis::NoDefaultRoute, _)
+                | (Diagnosis::GatewayUnreachable | Diagnosis::GatewayArpFailed, None) => {}
+                (Diagnosis::UpstreamUnreachable, Some(g)) => match g.rtt {
+                    Some(d) => write!(f, " (gateway {} answers {})", g.addr, fmt_rtt(d))?,
+                    None => write!(f, " (gateway {} answers ARP)", g.addr)?,
+                },
+                (Diagnosis::UpstreamUnreachable, None) => {}
+                (Diagnosis::TunnelDown | Diagnosis::UplinkDown, _) => {
+                    if let Some(t) = &self.tunnel {
+                        let how = if t.uplink_ok { "" } else { " either" };
+                        let up = t.results.iter().filter(|r| r.rtt.is_some()).count();
+                        write!(
+                            f,
+                            " ({}/{} answer through {}{}; {})",
+                            up,
+                            t.results.len(),
+                            t.uplink,
+                            how,
+                            t.tunnels.join(", ")
+                        )?
+                    }
+                }
+            }
+            if first {
+                f.write_str(", targets skipped")?;
+            }
+        }
+        if let Some(p) = self.pmtu.as_ref().filter(|p| p.low) {
+            let m = p.mtu.unwrap_or_default();
+            write!(f, "; path MTU to {} via {} is {}", p.target, p.iface, m)?;
+        }
+        if let Some(t) = &self.throughput {
+            match &t.error {
+                Some(e) => write!(f, "; {} download failed: {}", t.url, e)?,
+                None => write!(
+                    f,
+                    "; throughput {:.0} kbit/s ({} bytes in {})",
+                    t.kbps(),
+                    t.bytes,
+                    fmt_rtt(t.elapsed)
+                )?,
+            }
+            if t.slow {
+                f.write_str(" DEGRADED")?;
+            }
+        }
+        if let Some(q) = self.quality.as_ref().filter(|q| q.poor) {
+            write!(
+                f,
+                "; poor link: {:.0}% loss, p95 {}",
+                q.loss,
+                fmt_rtt(q.p95)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Probes every configured target concurrently; each gets `count` echoes.
+/// The HTTP and DNS probes, if enabled, run alongside. A failed round is
+/// repeated up to `retries` times with back-off, within `deadline`; a
+/// captive portal is not, as waiting does not log in. Echo
+/// outcomes go into `win`, and an ONLINE verdict is downgraded to DEGRADED
+/// when the window shows too much loss or latency, or a throughput download
+/// is too slow.
+pub fn check(cfg: &Config, win: &mut LatencyWindow, deadline: Option<Instant>) -> Summary {
+    let start = Instant::now();
+    let b = Backoff::new(cfg.retries, cfg, deadline);
+    let mut log = Vec::new();
+    let mut sum = round(cfg);
+    record(cfg, &sum, win);
+    while !sum.online() && sum.state != State::CaptivePortal {
+        let Some(d) = b.delay(log.len() as u32 + 1) else {
+            break;
+        };
+        log.push(Attempt {
+            n: log.len() as u32 + 1,
+            of: b.attempts(),
+            delay: d,
+            error: sum.state.as_str().into(),
+        });
+        sys::sleep(d);
+        sum = round(cfg);
+        record(cfg, &sum, win);
+    }
+    sum.retries = log;
+    #[cfg(target_os = "linux")]
+    if cfg.tunnel && sum.state == State::Offline {
+        outside(cfg, &mut sum);
+    }
+    if let (Some(mean), Some(p95)) = (win.mean(), win.p95()) {
+        let loss = win.loss(cfg.loss_window);
+        let slow = cfg.max_p95_ms > 0 && p95 > Duration::from_millis(cfg.max_p95_ms);
+        let poor = loss > cfg.max_loss as f64 || slow;
+        if poor && sum.state == State::Online {
+            sum.state = State::Degraded;
+        }
+        sum.quality = Some(Quality {
+            mean,
+            p95,
+            loss,
+            poor,
+        });
+    }
+    if cfg.pmtu && sum.online() {
+        sum.pmtu = path_mtu(cfg);
+        if sum.pmtu.as_ref().is_some_and(|p| p.low) {
+            sum.state = State::Degraded;
+        }
+    }
+    if let Some(u) = cfg.throughput_url.as_ref().filter(|_| sum.online()) {
+        let t = throughput(cfg, u);
+        if t.slow {
+            sum.state = State::Degraded;
+        }
+        sum.throughput = Some(t);
+    }
+    sum.elapsed = start.elapsed();
+    sum
+}
+
+/// `cfg` for a relaxed check: one target, the first of the family that
+/// decides, or one of each with `family = "both"`; and the targets left
+/// out. Unchanged when none would be left.
+pub fn relaxed(cfg: &Config) -> (Config, Vec<ProbeMethod>) {
+    let ip = |v6: bool| {
+        cfg.targets
+            .iter()
+            .position(|t| matches!(t, ProbeMethod::Icmp(a) if a.is_ipv6() == v6))
+    };
+    let first = cfg.targets.iter().position(|t| !t.is_dns());
+    let keep: Vec<usize> = match cfg.family {
+        Family::Both => [ip(false), ip(true)].into_iter().flatten().collect(),
+        Family::V4 => ip(false).or(first).into_iter().collect(),
+        Family::V6 => ip(true).or(first).into_iter().collect(),
+        Family::Any => first.into_iter().collect(),
+    };
+    let mut c = cfg.clone();
+    if keep.is_empty() {
+        return (c, Vec::new());
+    }
+    let (kept, skipped) = cfg
+        .targets
+        .iter()
+        .cloned()
+        .enumerate()
+        .partition::<Vec<_>, _>(|(i, _)| keep.contains(i));
+    c.targets = kept.into_iter().map(|(_, t)| t).collect();
+    (c, skipped.into_iter().map(|(_, t)| t).collect())
+}
+
+/// Probes `tunnel_targets` again bound to the uplink beneath the tunnels,
+/// outside them: the tunnel is down when they answer, the uplink when they
+/// do not. A gateway stage that failed on the uplink has said as much
+/// already.
+#[cfg(target_os = "linux")]
+fn outside(cfg: &Config, sum: &mut Summary) {
+    let tunnels = tunnel::detect(cfg);
+    let below = match (&sum.gateway, sum.diagnosis) {
+        (Some(g), Some(d)) => d != Diagnosis::UpstreamUnreachable && !tunnels.contains(&g.iface),
+        _ => false,
+    };
+    if tunnels.is_empty() || below {
+        return;
+    }
+    let Some(uplink) = tunnel::uplink(cfg, &tunnels) else {
+        return;
+    };
+    let mut c = cfg.clone();
+    if !cfg.tunnel_targets.is_empty() {
+        c.targets = cfg.tunnel_targets.clone();
+    }
+    c.per_interface = false;
+    let results = targets(&c, Some(&uplink));
+    if results.is_empty() {
+        return;
+    }
+    let uplink_ok = reached(&c, &results.iter().collect::<Vec<_>>()).0;
+    sum.diagnosis = Some(match uplink_ok {
+        true => Diagnosis::TunnelDown,
+        false => Diagnosis::UplinkDown,
+    });
+    sum.tunnel = Some(TunnelResult {
+        tunnels,
+        uplink,
+        results,
+        uplink_ok,
+    });
+}
+
+/// The path MTU to the first IP target, through the interface the route
+/// to it takes; a blackhole there passes small pings and stalls the rest.
+fn path_mtu(cfg: &Config) -> Option<PathMtu> {
+    let target = cfg.targets.iter().find_map(|t| match t {
+        ProbeMethod::Icmp(a) => Some(*a),
+        _ => None,
+    })?;
+    let iface = path(sys::Bind::default(), target).0?;
+    let mtu = ping::measure_pmtu(&iface, target, cfg.pmtu_payload);
+    Some(PathMtu {
+        low: mtu.is_some_and(|m| m < cfg.pmtu_min),
+        iface,
+        target,
+        mtu,
+    })
+}
+
+fn throughput(cfg: &Config, u: &Url) -> Throughput {
+    let timeout = Duration::from_secs(cfg.throughput_timeout);
+    let (bytes, elapsed, error) = match http::request("GET", u, None, timeout) {
+        Ok(r) if (200..300).contains(&r.status) => (r.body.len(), r.elapsed, None),
+        Ok(r) => (0, r.elapsed, Some(format!("HTTP {}", r.status))),
+        Err(e) => (0, Duration::ZERO, Some(e)),
+    };
+    let mut t = Throughput {
+        url: u.to_string(),
+        bytes,
+        elapsed,
+        error,
+        slow: false,
+    };
+    t.slow = cfg.min_throughput > 0 && (t.error.is_some() || t.kbps() < cfg.min_throughput as f64);
+    t
+}
+
+/// Targets that did not answer at all are the quorum's business, not a
+/// sign of a lossy link, so only responsive targets contribute, and with
+/// `degraded_fail` only those the quorum counted. Lookups are not echoes
+/// and stay out.
+fn record(cfg: &Config, sum: &Summary, win: &mut LatencyWindow) {
+    for r in sum
+        .results
+        .iter()
+        .filter(|r| r.up(cfg) && !r.target.is_dns())
+    {
+        for _ in 0..r.received {
+            win.push(r.rtt);
+        }
+        for _ in r.received..r.sent {
+            win.push(None);
+        }
+    }
+}
+
+/// What of `rs` makes the quorum: all but the DNS targets, unless those
+/// are all there is.
+fn reach<'a>(rs: &[&'a TargetResult]) -> Vec<&'a TargetResult> {
+    let ips: Vec<&TargetResult> = rs.iter().copied().filter(|r| !r.target.is_dns()).collect();
+    match ips.is_empty() {
+        true => rs.to_vec(),
+        false => ips,
+    }
+}
+
+/// The reachable share of `rs` by weight; `None` when there are none.
+fn weighted(cfg: &Config, rs: &[&TargetResult]) -> Option<f64> {
+    let total: u32 = rs.iter().map(|r| r.weight).sum();
+    let up: u32 = rs.iter().filter(|r| r.up(cfg)).map(|r| r.weight).sum();
+    (total > 0).then(|| up as f64 / total as f64)
+}
+
+/// Whether `rs` meet the quorum for the families `cfg.family` asks for,
+/// with the IPv4 and IPv6 verdicts (`None` when no target of the family
+/// was probed). DNS targets count only when there are no others, and then
+/// for no family.
+fn reached(cfg: &Config, rs: &[&TargetResult]) -> (bool, Option<bool>, Option<bool>) {
+    let quorum = |rs: &[&TargetResult]| match cfg.quorum_threshold {
+        Some(t) => weighted(cfg, rs).is_some_and(|w| w > t),
+        None => {
+            let up = rs.iter().filter(|r| r.up(cfg)).count();
+            up >= cfg.quorum.min(rs.len()).max(1)
+        }
+    };
+    let rs = reach(rs);
+    if rs.iter().all(|r| r.target.is_dns()) {
+        return (!rs.is_empty() && quorum(&rs), None, None);
+    }
+    let rs = &rs[..];
+    let family = |v6: bool| {
+        let rs: Vec<&TargetResult> = rs.iter().copied().filter(|r| r.is_ipv6() == v6).collect();
+        (!rs.is_empty()).then(|| quorum(&rs))
+    };
+    let (v4, v6) = (family(false), family(true));
+    let ok = match cfg.family {
+        Family::V4 => v4 == Some(true),
+        Family::V6 => v6 == Some(true),
+        Family::Both => v4 == Some(true) && v6 == Some(true),
+        Family::Any if cfg.ipv6 => v4 == Some(true) || v6 == Some(true),
+        Family::Any => quorum(rs),
+    };
+    (ok, v4, v6)
+}
+
+/// Pings the gateway of the first default route and looks it up in the ARP
+/// cache, or asks by ARP itself; `None` without a default route. With
+/// `flush_neigh`, a failed neighbour entry is flushed and pinged again.
+fn gateway_probe(cfg: &Config) -> Option<GatewayResult> {
+    let r = net::default_routes().into_iter().next()?;
+    let mut g = GatewayResult {
+        link: net::admin_up(&r.iface) && net::carrier(&r.iface) != Some(false),
+        addr: r.gateway,
+        iface: r.iface,
+        rtt: None,
+        arp: false,
+        neigh: None,
+        mac: None,
+        flushed: false,
+        arp_failed: false,
+    };
+    if g.link && !g.addr.is_unspecified() {
+        let t = Duration::from_secs(cfg.ping_timeout);
+        let ping = |g: &GatewayResult| {
+            ping::probe_icmp(
+                IpAddr::V4(g.addr),
+                t,
+                cfg.ping_count,
+                sys::Bind::dev(Some(&g.iface)),
+            )
+            .rtt
+        };
+        #[cfg(not(target_os = "linux"))]
+        {
+            g.rtt = ping(&g);
+            g.arp = net::arp_resolved(g.addr, &g.iface);
+        }
+        #[cfg(target_os = "linux")]
+        neighbour(cfg, &mut g, t, ping);
+    }
+    Some(g)
+}
+
+/// Pings the gateway, with `ping`, and checks its neighbour entry, flushing
+/// a failed one as `flush_neigh` says; ARP asks when pings went unanswered.
+#[cfg(target_os = "linux")]
+fn neighbour(
+    cfg: &Config,
+    g: &mut GatewayResult,
+    t: Duration,
+    ping: impl Fn(&GatewayResult) -> Option<Duration>,
+) {
+    let index = netlink::index(&g.iface).ok();
+    let addr = g.addr;
+    let neigh = || index.and_then(|i| netlink::neighbour(i, addr).ok().flatten());
+    g.rtt = ping(g);
+    let mut n = neigh();
+    if g.rtt.is_none() && cfg.flush_neigh && n.as_ref().is_some_and(|n| n.failed()) {
+        // A stale entry answers for the gateway until it is asked again.
+        g.flushed = index.is_some_and(|i| netlink::delete_neighbour(i, addr).is_ok());
+        if g.flushed {
+            log::info!(iface = &g.iface; "{}: flushed the failed neighbour entry of {}", g.iface, addr);
+            g.rtt = ping(g);
+            n = neigh();
+        }
+    }
+    // The cache may have aged out while pings went unanswered.
+    g.arp = net::arp_resolved(addr, &g.iface)
+        || g.rtt.is_none() && arp::arp_ping(&g.iface, IpAddr::V4(addr), t) == Some(true);
+    if let Some(n) = n {
+        g.arp_failed = n.failed();
+        g.neigh = Some(n.state_str().into());
+        g.mac = n.mac();
+    }
+}
+
+/// Where an OFFLINE verdict failed, given what the gateway stage found.
+fn diagnose(cfg: &Config, gw: Option<&GatewayResult>) -> Diagnosis {
+    match gw {
+        Some(g) if !g.link => Diagnosis::LinkDown,
+        Some(g) if !g.reachable() && g.arp_failed => Diagnosis::GatewayArpFailed,
+        Some(g) if !g.reachable() => Diagnosis::GatewayUnreachable,
+        Some(_) => Diagnosis::UpstreamUnreachable,
+        None => {
+            let carrier = iface::discover(cfg)
+                .iter()
+                .any(|i| net::admin_up(i) && net::carrier(i) != Some(false));
+            if carrier {
+                Diagnosis::NoDefaultRoute
+            } else {
+                Diagnosis::LinkDown
+            }
+        }
+    }
+}
+
+fn round(cfg: &Config) -> Summary {
+    let staged = cfg.gateway_check && !cfg.per_interface && !cfg.ipv6;
+    let gateway = staged.then(|| gateway_probe(cfg)).flatten();
+    // Nothing beyond a dead link or gateway can answer.
+    if let Some(g) = gateway.as_ref().filter(|g| !g.reachable()) {
+        return Summary {
+            state: State::Offline,
+            weighted: None,
+            throughput: None,
+            pmtu: None,
+            elapsed: Duration::ZERO,
+            quality: None,
+            retries: Vec::new(),
+            v4: None,
+            v6: None,
+            per_iface: Vec::new(),
+            results: Vec::new(),
+            http: None,
+            dns: None,
+            diagnosis: Some(diagnose(cfg, Some(g))),
+            gateway,
+            skipped: Vec::new(),
+            tunnel: None,
+        };
+    }
+    let ifaces = if cfg.per_interface {
+        iface::discover(cfg)
+    } else {
+        Vec::new()
+    };
+    let mut took = Vec::new();
+    let (mut results, http, dns) = std::thread::scope(|s| {
+        let h = cfg.probe_mode.http().then(|| s.spawn(|| http_probe(cfg)));
+        let d = cfg.dns.then(|| s.spawn(|| dns_probe(cfg)));
+        let rs = match (cfg.probe_mode.targets(), cfg.per_interface) {
+            (false, _) => Vec::new(),
+            (true, false) => targets(cfg, None),
+            (true, true) => {
+                // Lookups go through the resolver, which cannot be bound to
+                // an interface; they run once.
+                let hs: Vec<_> = ifaces
+                    .iter()
+                    .map(|i| {
+                        s.spawn(move || {
+                            let t = Instant::now();
+                            (targets(cfg, Some(i)), t.elapsed())
+                        })
+                    })
+                    .collect();
+                let names = s.spawn(|| targets(cfg, None));
+                let mut rs = Vec::new();
+                for h in hs {
+                    let (r, t) = h.join().unwrap_or_default();
+                    rs.extend(r);
+                    took.push(t);
+                }
+                rs.extend(names.join().unwrap_or_default());
+                rs
+            }
+        };
+        (
+            rs,
+            h.and_then(|h| h.join().ok()),
+            d.and_then(|d| d.join().ok()),
+        )
+    });
+    // Unplugged mid-probe: its targets were not so much unreachable as
+    // out of reach for the moment.
+    let gone: Vec<&String> = ifaces.iter().filter(|i| !net::exists(i)).collect();
+    for r in results.iter_mut().filter(|r| r.rtt.is_none()) {
+        match r.iface.as_ref().filter(|i| gone.contains(i)) {
+            Some(i) if r.error.is_none() => r.error = Some(format!("{} is gone", i)),
+            _ => {}
+        }
+    }
+    let (all_ok, v4, v6) = reached(cfg, &results.iter().collect::<Vec<_>>());
+    let per_iface: Vec<IfaceVerdict> = ifaces
+        .iter()
+        .enumerate()
+        .map(|(n, i)| {
+            let rs: Vec<&TargetResult> = results
+                .iter()
+                .filter(|r| r.iface.as_ref() == Some(i))
+                .collect();
+            let gone = gone.contains(&i);
+            IfaceVerdict {
+                iface: i.clone(),
+                online: !gone && reached(cfg, &rs).0,
+                rtt: rs.iter().filter_map(|r| r.rtt).min(),
+                gone,
+                elapsed: took.get(n).copied().unwrap_or_default(),
+            }
+        })
+        .collect();
+    // One that is gone is not required.
+    let present = || per_iface.iter().filter(|v| !v.gone);
+    let pinged = match (cfg.per_interface, cfg.require_all) {
+        (false, _) => all_ok,
+        (true, false) => per_iface.iter().any(|v| v.online),
+        (true, true) => present().next().is_some() && present().all(|v| v.online),
+    };
+    let http_ok = http.as_ref().map(|h| h.verdict == HttpVerdict::Ok);
+    // Portals often drop pings too; what answers HTTP is the real story.
+    let portal = http
+        .as_ref()
+        .is_some_and(|h| h.verdict == HttpVerdict::CaptivePortal);
+    let mut state = match (cfg.probe_mode, http_ok) {
+        _ if portal => State::CaptivePortal,
+        (ProbeMode::Icmp | ProbeMode::Tcp, _) | (_, None) if pinged => State::Online,
+        (ProbeMode::Icmp | ProbeMode::Tcp, _) | (_, None) => State::Offline,
+        (_, Some(true)) => State::Online,
+        (ProbeMode::Both, Some(false)) if pinged => State::Degraded,
+        _ => State::Offline,
+    };
+    // Lossy or slow targets that still made up the quorum.
+    let poor = results.iter().any(|r| r.degraded && r.up(cfg));
+    if state == State::Online && poor && cfg.probe_mode.targets() {
+        state = State::Degraded;
+    }
+    // Broken DNS usually fails the HTTP probe too; name the real cause.
+    let names: Vec<&TargetResult> = results.iter().filter(|r| r.target.is_dns()).collect();
+    let names_ok = names.iter().all(|r| r.rtt.is_some());
+    let dns_ok = dns.as_ref().is_none_or(|d| d.answer.is_ok()) && names_ok;
+    if !dns_ok && !portal && (pinged || state != State::Offline) {
+        state = State::DnsBroken;
+    }
+    let all: Vec<&TargetResult> = results.iter().collect();
+    Summary {
+        state,
+        weighted: cfg.quorum_threshold.and(weighted(cfg, &reach(&all))),
+        throughput: None,
+        pmtu: None,
+        elapsed: Duration::ZERO,
+        quality: None,
+        retries: Vec::new(),
+        v4,
+        v6,
+        per_iface,
+        results,
+        http,
+        dns,
+        diagnosis: (staged && state == State::Offline).then(|| diagnose(cfg, gateway.as_ref())),
+        gateway,
+        skipped: Vec::new(),
+        tunnel: None,
+    }
+}
+
+fn dns_probe(cfg: &Config) -> DnsResult {
+    let servers = if cfg.dns_servers.is_empty() {
+        dns::system_servers()
+    } else {
+        cfg.dns_servers.clone()
+    };
+    DnsResult {
+        name: cfg.dns_name.clone(),
+        answer: dns::resolve(
+            &cfg.dns_name,
+            &servers,
+            Duration::from_secs(cfg.dns_timeout),
+        ),
+    }
+}
+
+fn http_probe(cfg: &Config) -> HttpResult {
+    let url = cfg.http_url.to_string();
+    let r = match http::request(
+        "GET",
+        &cfg.http_url,
+        None,
+        Duration::from_secs(cfg.http_timeout),
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            return HttpResult {
+                url,
+                status: None,
+                rtt: None,
+                verdict: HttpVerdict::Failed(e),
+                location: None,
+                server: None,
+            }
+        }
+    };
+    // Some generate_204 mirrors answer an empty 200; a portal never does.
+    let empty_200 = cfg.http_status == 204 && r.status == 200 && r.body.is_empty();
+    let verdict = if r.peer.is_some_and(|a| !expected_server(cfg, a)) {
+        // DNS sent us elsewhere; whatever answered is not the check host.
+        HttpVerdict::CaptivePortal
+    } else if r.status == cfg.http_status || empty_200 {
+        HttpVerdict::Ok
+    } else if (300..400).contains(&r.status) || (cfg.http_status == 204 && r.status == 200) {
+        HttpVerdict::CaptivePortal
+    } else {
+        HttpVerdict::Failed(format!("unexpected status {}", r.status))
+    };
+    HttpResult {
+        url,
+        status: Some(r.status),
+        rtt: Some(r.elapsed),
+        location: r.location.filter(|_| verdict == HttpVerdict::CaptivePortal),
+        server: r.peer,
+        verdict,
+    }
+}
+
+/// Whether `a` may answer for the HTTP probe's host: inside
+/// `expected_ranges`, or without them any public address, and any address
+/// at all when the URL names one.
+fn expected_server(cfg: &Config, a: IpAddr) -> bool {
+    if !cfg.http_expected_ranges.is_empty() {
+        return cfg.http_expected_ranges.iter().any(|r| r.contains(a));
+    }
+    let host = cfg.http_url.host.trim_matches(['[', ']']);
+    host.parse::<IpAddr>().is_ok() || !net::is_private(a)
+}
+
+/// Whether fetching `probe_url` runs into a captive portal: a redirect, a
+/// page where a 204 was expected, or an answer from outside the public
+/// addresses. An unparsable URL or a failed fetch is no portal.
+pub fn detect_captive_portal(probe_url: &str) -> bool {
+    let Ok(url) = probe_url.parse() else {
+        return false;
+    };
+    let cfg = Config {
+        http_url: url,
+        ..Config::default()
+    };
+    http_probe(&cfg).verdict == HttpVerdict::CaptivePortal
+}
+
+/// Checks one target: an echo, a handshake or a lookup.
+pub trait Probe: Send + Sync {
+    fn check(&self, timeout: Duration) -> ProbeResult;
+}
+
+/// How `t` is checked under `cfg`; in `tcp` mode IP targets get a
+/// handshake on port 443 instead of an echo.
+fn prober<'a>(
+    cfg: &Config,
+    t: &'a ProbeMethod,
+    count: u8,
+    bind: sys::Bind<'a>,
+) -> Box<dyn Probe + 'a> {
+    let tcp = |host: String, port| tcp::Tcp {
+        host,
+        port,
+        count,
+        bind,
+        refused_ok: cfg.tcp_refused_ok,
+    };
+    match t {
+        ProbeMethod::Icmp(a) if cfg.probe_mode == ProbeMode::Tcp => {
+            Box::new(tcp(a.to_string(), 443))
+        }
+        ProbeMethod::Icmp(addr) => Box::new(ping::Icmp {
+            addr: *addr,
+            count,
+            bind,
+        }),
+        ProbeMethod::Tcp { host, port } => Box::new(tcp(host.clone(), *port)),
+        ProbeMethod::Dns { hostname, server } => Box::new(dns::Lookup {
+            name: hostname,
+            server: *server,
+            count,
+        }),
+    }
+}
+
+/// The interface and source address a probe of `a` bound as `bind` left
+/// through, as the kernel routes it.
+#[cfg(target_os = "linux")]
+fn path(bind: sys::Bind, a: IpAddr) -> (Option<String>, Option<IpAddr>) {
+    let oif = match bind.dev.map(netlink::index) {
+        None => None,
+        Some(Ok(i)) => Some(i),
+        // Gone since: a route that avoids it is not the one taken.
+        Some(Err(_)) => return (bind.dev.map(String::from), bind.source),
+    };
+    match netlink::route_get(a, oif, bind.source, bind.mark) {
+        Ok((i, src)) => (netlink::name(i).ok(), bind.source.or(src)),
+        Err(_) => (bind.dev.map(String::from), bind.source),
+    }
+}
+
+/// The same off Linux: a UDP socket connected to `a` has the source the
+/// routing table picks, and the link holding that address is the one.
+#[cfg(not(target_os = "linux"))]
+fn path(bind: sys::Bind, a: IpAddr) -> (Option<String>, Option<IpAddr>) {
+    if bind.dev.is_some() {
+        return (bind.dev.map(String::from), bind.source);
+    }
+    let src = bind.source.or_else(|| {
+        let any = match a {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED),
+        };
+        let s = std::net::UdpSocket::bind((any, 0)).ok()?;
+        s.connect((a, 9)).ok()?;
+        Some(s.local_addr().ok()?.ip())
+    });
+    let iface = src.and_then(|src| {
+        crate::ifaddrs::links()
+            .ok()?
+            .into_iter()
+            .find(|l| l.addrs.iter().any(|&(x, _)| x == src))
+            .map(|l| l.name)
+    });
+    (iface, src)
+}
+
+/// What a binding that could not be made says about it.
+fn bind_error(bind: sys::Bind, e: std::io::Error) -> String {
+    let how: Vec<String> = [
+        bind.dev.map(|d| format!("via {}", d)),
+        bind.source.map(|a| format!("from {}", a)),
+        bind.mark.map(|m| format!("fwmark {:#x}", m)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    format!("{}: {}", how.join(" "), e)
+}
+
+/// Probes every target, through `dev` only when given. With `dev`, DNS
+/// targets are left out; without it, only they are if `per_interface`. A
+/// target with an interface of its own is only probed through that one. A
+/// binding that cannot be made fails its target alone.
+fn targets(cfg: &Config, dev: Option<&str>) -> Vec<TargetResult> {
+    let mut targets = cfg.probe_targets();
+    targets.retain(|t| match (dev, cfg.opts(t).interface.as_deref()) {
+        (Some(d), Some(i)) => d == i,
+        (Some(_), None) => !t.is_dns(),
+        (None, _) => t.is_dns() || !cfg.per_interface,
+    });
+    std::thread::scope(|s| {
+        let hs: Vec<_> = targets
+            .iter()
+            .map(|t| {
+                let o = cfg.opts(t);
+                let n = o.count.unwrap_or(cfg.ping_count);
+                let timeout = Duration::from_secs(o.timeout.unwrap_or(cfg.ping_timeout));
+                let bind = sys::Bind {
+                    dev: o.interface.as_deref().or(dev),
+                    source: o.source,
+                    mark: o.fwmark,
+                };
+                let h = bind.check().map(|()| {
+                    let p = prober(cfg, t, n, bind);
+                    s.spawn(move || p.check(timeout))
+                });
+                (o, n, bind, h)
+            })
+            .collect();
+        targets
+            .iter()
+            .zip(hs)
+            .map(|(t, (o, n, bind, h))| {
+                let (r, error) = match h {
+                    Ok(h) => (h.join().unwrap_or(ProbeResult::none(None, n)), None),
+                    Err(e) => (ProbeResult::none(None, 0), Some(bind_error(bind, e))),
+                };
+                let (iface, source) = match r.addr {
+                    Some(a) if !t.is_dns() => path(bind, a),
+                    _ => (bind.dev.map(String::from), bind.source),
+                };
+                let mut tr = TargetResult {
+                    target: t.clone(),
+                    addr: r.addr,
+                    rtt: r.rtt,
+                    min: r.min,
+                    max: r.max,
+                    sent: r.sent,
+                    received: r.received,
+                    degraded: false,
+                    weight: o.weight,
+                    iface,
+                    source,
+                    error,
+                };
+                let slow =
+                    cfg.max_rtt_ms > 0 && tr.rtt > Some(Duration::from_millis(cfg.max_rtt_ms));
+                tr.degraded =
+                    !t.is_dns() && tr.received > 0 && (tr.loss() > cfg.max_loss as f64 || slow);
+                // Enough lost replies are an unreachable target, whatever
+                // came back; the replies still show under min/max.
+                let lost = tr.sent.saturating_sub(tr.received);
+                match o.failure_threshold.or(cfg.failure_threshold) {
+                    Some(th) if tr.sent > 0 && lost >= th.min(tr.sent) => {
+                        tr.rtt = None;
+                        tr.degraded = false;
+                    }
+                    _ => {}
+                }
+                tr
+            })
+            .collect()
+    })
+}
+
+pub fn fmt_rtt(d: Duration) -> String {
+    format!("{:.2} ms", d.as_secs_f64() * 1000.0)
+}
+
+/// Milliseconds with microsecond precision, for JSON.
+pub fn ms(d: Duration) -> f64 {
+    (d.as_secs_f64() * 1e6).round() / 1e3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(target: ProbeMethod, up: bool) -> TargetResult {
+        TargetResult {
+            target,
+            addr: None,
+            rtt: up.then(|| Duration::from_micros(106)),
+            min: None,
+            max: None,
+            sent: 1,
+            received: up as u8,
+            degraded: false,
+            weight: 1,
+            iface: None,
+            source: None,
+            error: None,
+        }
+    }
+
+    fn lookup(up: bool) -> TargetResult {
+        let target = ProbeMethod::Dns {
+            hostname: "localhost".into(),
+            server: None,
+        };
+        result(target, up)
+    }
+
+    #[test]
+    fn lookups_alone_make_the_quorum() {
+        let cfg = Config::default();
+        for family in [Family::V4, Family::V6, Family::Both] {
+            let cfg = Config {
+                family,
+                ..cfg.clone()
+            };
+            assert_eq!(reached(&cfg, &[&lookup(true)]), (true, None, None));
+            assert_eq!(reached(&cfg, &[&lookup(false)]), (false, None, None));
+        }
+        let cfg = Config {
+            quorum_threshold: Some(0.5),
+            ..cfg
+        };
+        assert!(reached(&cfg, &[&lookup(true), &lookup(true), &lookup(false)]).0);
+        assert!(!reached(&cfg, &[]).0);
+    }
+
+    #[test]
+    fn lookups_beside_addresses_do_not_count() {
+        let cfg = Config::default();
+        let ping = |up| result(ProbeMethod::Icmp("192.0.2.1".parse().unwrap()), up);
+        assert_eq!(
+            reached(&cfg, &[&ping(false), &lookup(true)]),
+            (false, Some(false), None)
+        );
+        assert_eq!(
+            reached(&cfg, &[&ping(true), &lookup(false)]),
+            (true, Some(true), None)
+        );
+    }
+}
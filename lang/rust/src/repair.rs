@@ -0,0 +1,331 @@
+// `repair`: works up from the link to DNS on the interfaces given, or the
+// managed ones, and fixes a layer only once the one below it works; what
+// already works is left alone, so connections that are up stay up.
+use cw_netup::config::Config;
+use cw_netup::exec::Executor;
+use cw_netup::iface::{self, Dhcp, IfaceReport, LinkStatus, RouteProblem};
+use cw_netup::json::Json;
+use cw_netup::latency::LatencyWindow;
+use cw_netup::net::{self, Route};
+use cw_netup::output::OutputSink;
+use cw_netup::probe::{self, State};
+use cw_netup::{backoff, dns, manager, ping, sys};
+use std::fmt::Write as _;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+struct Step {
+    layer: &'static str,
+    iface: Option<String>,
+    /// What was found.
+    found: String,
+    /// What was done about it, and how that went.
+    action: Option<String>,
+    /// Working, in the end.
+    ok: bool,
+}
+
+struct Repair<'a> {
+    cfg: &'a Config,
+    ex: &'a dyn Executor,
+    deadline: Option<Instant>,
+    /// Who owns the links when it is not us; nothing is fixed then.
+    manager: Option<manager::NetworkBackend>,
+    steps: Vec<Step>,
+}
+
+impl Repair<'_> {
+    fn add(&mut self, layer: &'static str, iface: Option<&str>, found: String, ok: bool) {
+        self.steps.push(Step {
+            layer,
+            iface: iface.map(String::from),
+            found,
+            action: None,
+            ok,
+        });
+    }
+
+    /// Records something broken, and what `fix` did about it unless the
+    /// links are another's to fix.
+    fn fix(
+        &mut self,
+        layer: &'static str,
+        iface: Option<&str>,
+        found: String,
+        fix: impl FnOnce(&Repair) -> (String, bool),
+    ) -> bool {
+        let (action, ok) = match self.manager {
+            Some(b) => (format!("left to {}", b), false),
+            None => fix(self),
+        };
+        self.steps.push(Step {
+            layer,
+            iface: iface.map(String::from),
+            found,
+            action: Some(action),
+            ok,
+        });
+        ok
+    }
+
+    fn bring_up(&self, i: &str, dhcp: Dhcp) -> IfaceReport {
+        iface::bring_up(self.cfg, &[i.to_string()], dhcp, self.deadline, self.ex)
+            .pop()
+            .expect("a report per interface")
+    }
+
+    /// Up with carrier; a link that is not gets set up, its members and
+    /// association included.
+    fn link(&mut self, i: &str) -> bool {
+        if !net::exists(i) {
+            self.add("link", Some(i), "no such interface".into(), false);
+            return false;
+        }
+        if net::already_up(i) && net::carrier(i) != Some(false) {
+            self.add("link", Some(i), "up".into(), true);
+            return true;
+        }
+        let found = match (net::admin_up(i), net::carrier(i)) {
+            (false, _) => "down",
+            _ => "no carrier",
+        };
+        self.fix("link", Some(i), found.into(), |r| {
+            let up = r.bring_up(i, Dhcp::Off);
+            match up.link {
+                LinkStatus::Up => ("set it up".into(), true),
+                l if up.errors.is_empty() => (format!("set it up, still {}", l.as_str()), false),
+                _ => (format!("set it up: {}", up.errors.join("; ")), false),
+            }
+        })
+    }
+
+    fn address(&mut self, i: &str) -> bool {
+        if let Some(a) = net::ipv4_addr(i) {
+            self.add("address", Some(i), a.to_string(), true);
+            return true;
+        }
+        self.fix("address", Some(i), "no IPv4 address".into(), |r| {
+            let up = r.bring_up(i, Dhcp::Always);
+            match (up.ipv4, up.fallback) {
+                (Some(a), Some(true)) => (format!("static fallback {}", a), true),
+                (Some(a), _) => (format!("requested a lease, now {}", a), true),
+                (None, _) => (
+                    format!("requested a lease: {}", up.errors.join("; ")),
+                    false,
+                ),
+            }
+        })
+    }
+
+    /// A default route through one of `ifaces`; a missing one is added via
+    /// the gateway a lease or `[fallback_static]` names.
+    fn route(&mut self, ifaces: &[String]) -> Option<Route> {
+        let ours = || {
+            net::default_routes()
+                .into_iter()
+                .find(|r| ifaces.contains(&r.iface))
+        };
+        if let Some(rt) = ours() {
+            let found = format!("default via {}", rt.gateway);
+            self.add("route", Some(&rt.iface), found, true);
+            return Some(rt);
+        }
+        let ok = self.fix("route", None, "no default route".into(), |r| {
+            let mut c = r.cfg.clone();
+            c.repair_routes = true;
+            let rs = iface::bring_up(&c, ifaces, Dhcp::Off, r.deadline, r.ex);
+            let added = rs.iter().find_map(|i| {
+                i.routes.iter().find_map(|p| match p {
+                    RouteProblem::Missing {
+                        expected,
+                        repaired: true,
+                    } => Some(format!("added default via {} dev {}", expected, i.name)),
+                    _ => None,
+                })
+            });
+            match added {
+                Some(a) => (a, true),
+                None => {
+                    let errs: Vec<&str> = rs
+                        .iter()
+                        .flat_map(|i| &i.errors)
+                        .map(|e| e.as_str())
+                        .collect();
+                    if errs.is_empty() {
+                        ("no gateway known to add one via".into(), false)
+                    } else {
+                        (format!("adding one: {}", errs.join("; ")), false)
+                    }
+                }
+            }
+        });
+        ok.then(ours).flatten()
+    }
+
+    fn answers(&self, rt: &Route) -> Option<Duration> {
+        let t = Duration::from_secs(self.cfg.ping_timeout);
+        ping::probe_icmp(
+            IpAddr::V4(rt.gateway),
+            t,
+            self.cfg.ping_count,
+            sys::Bind::dev(Some(&rt.iface)),
+        )
+        .rtt
+    }
+
+    /// The default gateway answers pings; when it does not, the lease is
+    /// renewed, which may name another.
+    fn gateway(&mut self, rt: &Route) -> bool {
+        let i = rt.iface.as_str();
+        if let Some(d) = self.answers(rt) {
+            let found = format!("{} answers in {} ms", rt.gateway, probe::ms(d));
+            self.add("gateway", Some(i), found, true);
+            return true;
+        }
+        let found = format!("{} does not answer", rt.gateway);
+        self.fix("gateway", Some(i), found, |r| {
+            let st = r.cfg.fallback_static.get(i);
+            if st.is_some_and(|st| net::ipv4_addr(i) == Some(st.address)) {
+                return ("on the static fallback, nothing to renew".into(), false);
+            }
+            let up = r.bring_up(i, Dhcp::Always);
+            let rt = net::default_routes().into_iter().find(|rt| rt.iface == i);
+            match rt.as_ref().and_then(|rt| Some((rt, r.answers(rt)?))) {
+                Some((rt, _)) => (format!("renewed the lease, {} answers", rt.gateway), true),
+                None if up.dhcp == Some(false) => (
+                    format!("renewing the lease: {}", up.errors.join("; ")),
+                    false,
+                ),
+                None => ("renewed the lease, still no answer".into(), false),
+            }
+        })
+    }
+
+    /// The probe targets answer; beyond the gateway there is nothing here
+    /// to fix.
+    fn internet(&mut self) -> bool {
+        let mut c = self.cfg.clone();
+        c.targets.retain(|t| !t.is_dns());
+        c.dns = false;
+        let s = probe::check(&c, &mut LatencyWindow::new(1), self.deadline);
+        let ok = matches!(s.state, State::Online | State::Degraded);
+        self.add("internet", None, s.to_string(), ok);
+        if !ok {
+            if let Some(st) = self.steps.last_mut() {
+                st.action = Some("nothing to fix beyond the gateway".into());
+            }
+        }
+        ok
+    }
+
+    fn dns(&mut self) -> bool {
+        let servers = if self.cfg.dns_servers.is_empty() {
+            dns::system_servers()
+        } else {
+            self.cfg.dns_servers.clone()
+        };
+        if servers.is_empty() {
+            self.add("dns", None, "no nameserver in resolv.conf".into(), false);
+            return false;
+        }
+        let t = Duration::from_secs(self.cfg.dns_timeout);
+        let (found, ok) = match dns::resolve(&self.cfg.dns_name, &servers, t) {
+            Ok(_) => (format!("{} resolves", self.cfg.dns_name), true),
+            Err(e) => (format!("{}: {}", self.cfg.dns_name, e), false),
+        };
+        self.add("dns", None, found, ok);
+        ok
+    }
+}
+
+/// A line per step, and what is still broken or was fixed.
+fn text(steps: &[Step], ok: bool) -> String {
+    let mut t = String::new();
+    for s in steps {
+        let mark = if s.ok && s.action.is_none() {
+            "✓"
+        } else {
+            "✗"
+        };
+        let what = match &s.iface {
+            Some(i) => format!("{}: {}", i, s.found),
+            None => s.found.clone(),
+        };
+        let _ = match &s.action {
+            Some(a) => writeln!(t, "{} {:<8}  {}; {}", mark, s.layer, what, a),
+            None => writeln!(t, "{} {:<8}  {}", mark, s.layer, what),
+        };
+    }
+    let layers = |f: &dyn Fn(&Step) -> bool| {
+        let mut l: Vec<&str> = steps.iter().filter(|s| f(s)).map(|s| s.layer).collect();
+        l.dedup();
+        l.join(", ")
+    };
+    let broken = layers(&|s| !s.ok);
+    let fixed = layers(&|s| s.ok && s.action.is_some());
+    let _ = match (broken.is_empty(), fixed.is_empty()) {
+        (false, _) => writeln!(t, "still broken: {}", broken),
+        // A dry run's fixes change nothing to check further up.
+        _ if !ok => writeln!(
+            t,
+            "fixed: {}; nothing above {} checked",
+            fixed,
+            steps.last().map_or("", |s| s.layer)
+        ),
+        (true, false) => writeln!(t, "fixed: {}", fixed),
+        (true, true) => writeln!(t, "all working"),
+    };
+    t
+}
+
+/// Checks and fixes layer by layer, on `only` or else the managed
+/// interfaces, and puts each step through `out`, as lines or with
+/// `--output json` an object. Exits 0 when everything works in the end,
+/// else 1.
+pub fn run(cfg: &Config, only: &[String], ex: &dyn Executor, out: &mut dyn OutputSink) -> ! {
+    let ifaces = if only.is_empty() {
+        iface::discover(cfg)
+    } else {
+        only.to_vec()
+    };
+    let mut r = Repair {
+        cfg,
+        ex,
+        deadline: backoff::deadline(cfg),
+        manager: manager::passive(cfg),
+        steps: Vec::new(),
+    };
+    if ifaces.is_empty() {
+        r.add("link", None, "no interfaces to manage".into(), false);
+    }
+    let addressed: Vec<String> = ifaces
+        .iter()
+        .filter(|i| r.link(i) && r.address(i))
+        .cloned()
+        .collect();
+    let ok = !addressed.is_empty()
+        && r.route(&addressed).is_some_and(|rt| r.gateway(&rt))
+        && r.internet()
+        && r.dns()
+        && r.steps.iter().all(|s| s.ok);
+    let steps: Vec<Json> = r
+        .steps
+        .iter()
+        .map(|s| {
+            Json::obj()
+                .set("step", s.layer)
+                .set("iface", s.iface.as_deref())
+                .set("found", s.found.as_str())
+                .set("action", s.action.as_deref())
+                .set("ok", s.ok)
+        })
+        .collect();
+    out.document(
+        &text(&r.steps, ok),
+        Json::obj()
+            .set("ok", ok)
+            .set("dry_run", ex.actions())
+            .set("steps", steps),
+    );
+    std::process::exit(if ok { 0 } else { 1 })
+}
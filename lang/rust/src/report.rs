@@ -0,0 +1,417 @@
+use crate::availability::WindowStats;
+use crate::config::ProbeMethod;
+use crate::error::WatchdogError;
+use crate::iface::{self, IfaceReport, Member, RouteProblem};
+use crate::json::Json;
+use crate::leases::Lease;
+use crate::manager::NetworkBackend;
+use crate::net::{Duplex, IfaceStats};
+use crate::ping::Trace;
+use crate::probe::{
+    self, Diagnosis, DnsResult, GatewayResult, HttpResult, HttpVerdict, PathMtu, Quality, State,
+    Summary, TargetResult, Throughput, TunnelResult,
+};
+use crate::{clock, events, history, output};
+use std::net::Ipv4Addr;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Everything a one-shot run found out; `--output json` ends with this as
+/// one document.
+pub struct Report {
+    pub timestamp: String,
+    pub interfaces: Vec<IfaceReport>,
+    pub probes: Summary,
+    /// The `priority` uplink in use; `None` without one.
+    pub uplink: Option<String>,
+    pub quorum: usize,
+    /// Commands a dry run would have run; `None` outside one.
+    pub dry_run: Option<Vec<String>>,
+    /// The path to the first IP target, when OFFLINE.
+    pub trace: Option<Trace>,
+    /// The manager left in charge of the interfaces, which were only
+    /// looked at; `None` when they were brought up.
+    pub manager: Option<NetworkBackend>,
+    /// The `[[namespace]]` runs.
+    pub namespaces: Vec<NamespaceReport>,
+    /// The probes `--wait` made; `None` for a single check.
+    pub window: Option<WindowStats>,
+    /// How long the run took.
+    pub elapsed: Duration,
+}
+
+/// A `[[namespace]]`'s run, or why it could not be entered.
+pub struct NamespaceReport {
+    pub name: String,
+    pub result: Result<Report, WatchdogError>,
+}
+
+impl NamespaceReport {
+    fn to_json(&self) -> Json {
+        match &self.result {
+            Ok(r) => r.to_json().set("name", self.name.as_str()),
+            Err(e) => Json::obj()
+                .set("name", self.name.as_str())
+                .set("error", e.to_string()),
+        }
+    }
+}
+
+impl Report {
+    pub fn state(&self) -> &'static str {
+        match self.probes.diagnosis {
+            Some(Diagnosis::NoDefaultRoute) => Diagnosis::NoDefaultRoute.as_str(),
+            _ => self.probes.state.as_str(),
+        }
+    }
+
+    /// What the exit status should say: when not ONLINE, the first of no
+    /// interfaces, no link set up for lack of privileges, and no lease
+    /// anywhere that explains it, else the probe verdict. When ONLINE, the
+    /// first namespace that is not, or could not be entered, has its say.
+    pub fn outcome(&self) -> Result<(), WatchdogError> {
+        self.own_outcome()?;
+        for n in &self.namespaces {
+            match &n.result {
+                Ok(r) => r.outcome()?,
+                Err(e) => return Err(e.clone()),
+            }
+        }
+        Ok(())
+    }
+
+    fn own_outcome(&self) -> Result<(), WatchdogError> {
+        match self.probes.state {
+            State::Online => return Ok(()),
+            State::DnsBroken => return Err(WatchdogError::DnsBroken),
+            State::Degraded => return Err(WatchdogError::Degraded),
+            State::CaptivePortal => return Err(WatchdogError::CaptivePortal),
+            State::Offline => {}
+        }
+        let ifs = &self.interfaces;
+        if ifs.is_empty() {
+            return Err(WatchdogError::NoInterfaces);
+        }
+        if ifs.iter().all(|r| !r.link_up)
+            && ifs
+                .iter()
+                .any(|r| r.errors.iter().any(|e| iface::denied(e)))
+        {
+            return Err(WatchdogError::Permission);
+        }
+        if ifs
+            .iter()
+            .all(|r| r.dhcp == Some(false) && r.fallback != Some(true))
+        {
+            return Err(WatchdogError::DhcpFailed);
+        }
+        Err(WatchdogError::Offline)
+    }
+
+    pub fn to_json(&self) -> Json {
+        let ifaces: Vec<Json> = self.interfaces.iter().map(iface_json).collect();
+        let probes: Vec<Json> = self
+            .probes
+            .results
+            .iter()
+            .map(|r| target_json(Json::obj(), r))
+            .chain(
+                self.probes
+                    .skipped
+                    .iter()
+                    .map(|t| skipped_json(Json::obj(), t)),
+            )
+            .collect();
+        let on_static = |i: &str| {
+            self.interfaces
+                .iter()
+                .any(|r| r.name == i && r.fallback == Some(true))
+        };
+        let timed_out = |i: &str| self.interfaces.iter().any(|r| r.name == i && r.timed_out());
+        let per_iface: Vec<Json> = self
+            .probes
+            .per_iface
+            .iter()
+            .map(|v| {
+                let st = match (v.online, on_static(&v.iface)) {
+                    _ if v.gone => "GONE",
+                    (true, true) => "STATIC_FALLBACK",
+                    (true, false) => "ONLINE",
+                    (false, _) if timed_out(&v.iface) => "TIMEOUT",
+                    (false, _) => "OFFLINE",
+                };
+                Json::obj()
+                    .set("iface", v.iface.as_str())
+                    .set("status", st)
+                    .set("rtt_ms", v.rtt.filter(|_| v.online).map(probe::ms))
+            })
+            .collect();
+        let latency = self.probes.rtt().map(probe::ms);
+        let j = Json::obj()
+            .set("status", self.state())
+            .set("diagnosis", self.probes.diagnosis.map(|d| d.as_str()))
+            .set("portal_url", self.probes.portal_url())
+            .set("latency_ms", latency)
+            .set("timestamp", self.timestamp.as_str())
+            .set("interfaces", ifaces)
+            .set("uplink", self.uplink.as_deref())
+            .set("network_manager", self.manager.map(|b| b.to_string()))
+            .set("probes", probes)
+            .set("per_interface", per_iface)
+            .set("gateway", self.probes.gateway.as_ref().map(gateway_json))
+            .set("tunnel", self.probes.tunnel.as_ref().map(tunnel_json))
+            .set("http", self.probes.http.as_ref().map(http_json))
+            .set("dns", self.probes.dns.as_ref().map(dns_json))
+            .set("latency", self.probes.quality.as_ref().map(quality_json))
+            .set(
+                "throughput",
+                self.probes.throughput.as_ref().map(throughput_json),
+            )
+            .set("pmtu", self.probes.pmtu.as_ref().map(pmtu_json))
+            .set("ipv4", self.probes.v4)
+            .set("ipv6", self.probes.v6)
+            .set("quorum", self.quorum)
+            .set("weighted", self.probes.weighted)
+            .set("dry_run", self.dry_run.clone())
+            .set("traceroute", self.trace.as_ref().map(trace_json))
+            .set("window", self.window.as_ref().map(WindowStats::to_json))
+            .set(
+                "namespaces",
+                self.namespaces
+                    .iter()
+                    .map(NamespaceReport::to_json)
+                    .collect::<Vec<_>>(),
+            );
+        if output::benchmarking() {
+            j.set("benchmark", self.benchmark_json())
+        } else {
+            j
+        }
+    }
+
+    /// How long `iface`'s probes took; only known with `per_interface`.
+    pub fn probe_time(&self, iface: &str) -> Option<Duration> {
+        self.probes
+            .per_iface
+            .iter()
+            .find(|v| v.iface == iface)
+            .map(|v| v.elapsed)
+    }
+
+    fn benchmark_json(&self) -> Json {
+        let ifaces: Vec<Json> = self
+            .interfaces
+            .iter()
+            .map(|r| {
+                Json::obj()
+                    .set("iface", r.name.as_str())
+                    .set("link_up_ms", probe::ms(r.timing.link_up))
+                    .set("dhcp_ms", probe::ms(r.timing.dhcp + r.timing.dhcp6))
+                    .set("probe_ms", self.probe_time(&r.name).map(probe::ms))
+                    .set("total_ms", probe::ms(r.timing.total))
+            })
+            .collect();
+        Json::obj()
+            .set("interfaces", ifaces)
+            .set("probe_ms", probe::ms(self.probes.elapsed))
+            .set("total_ms", probe::ms(self.elapsed))
+    }
+}
+
+fn trace_json(t: &Trace) -> Json {
+    let hops: Vec<Json> = t
+        .hops
+        .iter()
+        .map(|h| {
+            Json::obj()
+                .set("hop", h.hop)
+                .set("addr", h.addr.map(|a| a.to_string()))
+                .set("rtt_ms", h.rtt.map(probe::ms))
+        })
+        .collect();
+    Json::obj()
+        .set("target", t.target.to_string())
+        .set("hops", hops)
+}
+
+/// One target's outcome, added to `j`.
+pub fn target_json(j: Json, r: &TargetResult) -> Json {
+    events::Probe::from(r).on(j)
+}
+
+/// A target a relaxed check left out: not probed, rather than unreachable.
+pub fn skipped_json(j: Json, t: &ProbeMethod) -> Json {
+    events::Probe::skipped(t).on(j)
+}
+
+fn gateway_json(g: &GatewayResult) -> Json {
+    Json::obj()
+        .set("addr", g.addr.to_string())
+        .set("iface", g.iface.as_str())
+        .set("link", g.link)
+        .set("reachable", g.reachable())
+        .set("rtt_ms", g.rtt.map(probe::ms))
+        .set("arp", g.arp)
+        .set("neigh", g.neigh.as_deref())
+        .set("mac", g.mac.as_deref())
+        .set("neigh_flushed", g.flushed)
+}
+
+fn tunnel_json(t: &TunnelResult) -> Json {
+    let probes: Vec<Json> = t
+        .results
+        .iter()
+        .map(|r| target_json(Json::obj(), r))
+        .collect();
+    Json::obj()
+        .set("tunnels", t.tunnels.clone())
+        .set("uplink", t.uplink.as_str())
+        .set("uplink_ok", t.uplink_ok)
+        .set("probes", probes)
+}
+
+fn http_json(h: &HttpResult) -> Json {
+    let (ok, portal, err) = match &h.verdict {
+        HttpVerdict::Ok => (true, false, None),
+        HttpVerdict::CaptivePortal => (false, true, None),
+        HttpVerdict::Failed(e) => (false, false, Some(e.as_str())),
+    };
+    Json::obj()
+        .set("url", h.url.as_str())
+        .set("ok", ok)
+        .set("captive_portal", portal)
+        .set("location", h.location.as_deref())
+        .set("server", h.server.map(|a| a.to_string()))
+        .set("status_code", h.status)
+        .set("rtt_ms", h.rtt.map(probe::ms))
+        .set("error", err)
+}
+
+fn quality_json(q: &Quality) -> Json {
+    Json::obj()
+        .set("mean_ms", probe::ms(q.mean))
+        .set("p95_ms", probe::ms(q.p95))
+        .set("loss_pct", (q.loss * 10.0).round() / 10.0)
+        .set("poor", q.poor)
+}
+
+fn pmtu_json(p: &PathMtu) -> Json {
+    Json::obj()
+        .set("iface", p.iface.as_str())
+        .set("target", p.target.to_string())
+        .set("mtu", p.mtu.map(u64::from))
+        .set("low", p.low)
+}
+
+fn throughput_json(t: &Throughput) -> Json {
+    let ok = t.error.is_none();
+    Json::obj()
+        .set("url", t.url.as_str())
+        .set("bytes", t.bytes)
+        .set("duration_ms", ok.then(|| probe::ms(t.elapsed)))
+        .set("kbps", ok.then(|| t.kbps().round()))
+        .set("error", t.error.as_deref())
+        .set("slow", t.slow)
+}
+
+fn dns_json(d: &DnsResult) -> Json {
+    let j = Json::obj()
+        .set("name", d.name.as_str())
+        .set("ok", d.answer.is_ok());
+    match &d.answer {
+        Ok(a) => j
+            .set("server", a.server.to_string())
+            .set(
+                "addrs",
+                a.addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            )
+            .set("rtt_ms", probe::ms(a.rtt)),
+        Err(e) => j.set("error", e.as_str()),
+    }
+}
+
+fn iface_json(r: &IfaceReport) -> Json {
+    Json::obj()
+        .set("name", r.name.as_str())
+        .set("kind", r.kind.as_str())
+        .set("role", r.kind.role().as_str())
+        .set("link_up", r.link_up)
+        .set("link", r.link.as_str())
+        .set("mac", r.mac.as_deref())
+        .set("associated", r.associated)
+        .set("ssid", r.ssid.as_deref())
+        .set("signal_dbm", r.signal_dbm.map(i64::from))
+        .set("already_configured", r.configured)
+        .set("ipv4", r.ipv4.map(|a| a.to_string()))
+        .set("previous_ipv4", r.previous.map(|a| a.to_string()))
+        .set("gateway", r.gateway.map(|a| a.to_string()))
+        .set("dhcp", r.dhcp)
+        .set("static_fallback", r.fallback)
+        .set(
+            "ipv6_addrs",
+            r.v6_addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        )
+        .set("dhcp6", r.dhcp6)
+        .set("lease", r.lease.as_ref().map(|l| lease_json(l, r.renew_at)))
+        .set("stats", stats_json(&r.stats))
+        .set("pmtu", r.pmtu.map(u64::from))
+        .set("speed_mbps", r.speed_mbps.map(u64::from))
+        .set("duplex", r.duplex.map(Duplex::as_str))
+        .set("timed_out", r.timed_out())
+        .set("manager_state", r.manager_state.as_deref())
+        .set("members", members_json(&r.members))
+        .set(
+            "route_problems",
+            r.routes.iter().map(|&p| route_json(p)).collect::<Vec<_>>(),
+        )
+        .set("errors", r.errors.clone())
+}
+
+pub fn route_json(p: RouteProblem) -> Json {
+    let repaired = match p {
+        RouteProblem::Missing { repaired, .. } => Some(repaired),
+        _ => None,
+    };
+    Json::obj()
+        .set("problem", p.as_str())
+        .set("gateway", p.gateway().map(|g| g.to_string()))
+        .set("repaired", repaired)
+}
+
+pub fn members_json(ms: &[Member]) -> Json {
+    ms.iter()
+        .map(|m| {
+            Json::obj()
+                .set("name", m.name.as_str())
+                .set("link", m.link.as_str())
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+pub fn lease_json(l: &Lease, renew_at: Option<u64>) -> Json {
+    let ip = |a: Option<Ipv4Addr>| a.map(|a| a.to_string());
+    let ts = |t: Option<u64>| t.map(|t| clock::rfc3339(UNIX_EPOCH + Duration::from_secs(t)));
+    Json::obj()
+        .set("source", l.source.as_str())
+        .set("address", ip(l.address))
+        .set("prefix_len", l.prefix_len().map(|p| p as u64))
+        .set("gateway", ip(l.gateway))
+        .set(
+            "dns",
+            l.dns.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        )
+        .set("lease_time_secs", l.lease_time)
+        .set("renew_at", ts(renew_at))
+        .set("expires", ts(l.expire))
+        .set("expires_in_secs", l.remaining(history::unix_now()))
+}
+
+pub fn stats_json(s: &IfaceStats) -> Json {
+    Json::obj()
+        .set("rx_bytes", s.rx_bytes)
+        .set("tx_bytes", s.tx_bytes)
+        .set("rx_packets", s.rx_packets)
+        .set("tx_packets", s.tx_packets)
+        .set("rx_errors", s.rx_errors)
+        .set("tx_errors", s.tx_errors)
+}
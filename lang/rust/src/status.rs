@@ -0,0 +1,128 @@
+// `--status`: the daemon's recorded state, and a table of the managed
+// interfaces as the kernel has them now beside what the daemon last saw.
+use cw_netup::config::Config;
+use cw_netup::error::Status;
+use cw_netup::history::{self, History, IfaceState};
+use cw_netup::json::Json;
+use cw_netup::output::OutputSink;
+use cw_netup::{iface, net, pidfile, uplink};
+use std::fmt::Write as _;
+
+struct Row<'a> {
+    name: String,
+    mac: Option<String>,
+    ipv4: Option<String>,
+    carrier: Option<bool>,
+    oper: net::OperState,
+    seen: Option<&'a IfaceState>,
+}
+
+fn rows<'a>(cfg: &Config, h: Option<&'a History>) -> Vec<Row<'a>> {
+    let (mut names, rest) = uplink::split(cfg, &iface::discover(cfg));
+    names.extend(rest);
+    names.extend(h.iter().flat_map(|h| h.ifaces.keys().cloned()));
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| Row {
+            mac: net::mac_addr(&name),
+            ipv4: net::ipv4_addr(&name).map(|a| a.to_string()),
+            carrier: net::carrier(&name),
+            oper: net::read_oper_state(&name),
+            seen: h.and_then(|h| h.ifaces.get(&name)),
+            name,
+        })
+        .collect()
+}
+
+fn row_json(r: &Row, now: u64, window: u64) -> Json {
+    Json::obj()
+        .set("name", r.name.as_str())
+        .set("mac", r.mac.as_deref())
+        .set("ipv4", r.ipv4.as_deref())
+        .set("carrier", r.carrier)
+        .set("operstate", r.oper.as_str())
+        .set("probe", r.seen.and_then(|s| s.probe.as_deref()))
+        .set("failures", r.seen.map(|s| s.failures as u64))
+        .set("flap_count", r.seen.map(|s| s.flaps.count(now, window)))
+        .set("lease_expires", r.seen.and_then(|s| s.lease_expires))
+        .set("since", r.seen.map(|s| s.since))
+        .set("since_secs", r.seen.map(|s| now.saturating_sub(s.since)))
+}
+
+fn table(t: &mut String, rows: &[Row], now: u64, window: u64) {
+    let w = rows.iter().map(|r| r.name.len()).max().unwrap_or(0).max(5);
+    let _ = writeln!(
+        t,
+        "{:<w$}  {:<17}  {:<15}  {:<7}  {:<14}  {:<10}  {:<5}  SINCE",
+        "IFACE", "MAC", "IPV4", "CARRIER", "OPERSTATE", "PROBE", "FLAPS"
+    );
+    for r in rows {
+        let carrier = match r.carrier {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "-",
+        };
+        let _ = writeln!(
+            t,
+            "{:<w$}  {:<17}  {:<15}  {:<7}  {:<14}  {:<10}  {:<5}  {}",
+            r.name,
+            r.mac.as_deref().unwrap_or("-"),
+            r.ipv4.as_deref().unwrap_or("-"),
+            carrier,
+            r.oper.as_str(),
+            r.seen.and_then(|s| s.probe.as_deref()).unwrap_or("-"),
+            r.seen
+                .map_or("-".into(), |s| s.flaps.count(now, window).to_string()),
+            r.seen
+                .map_or("-".into(), |s| history::span(now.saturating_sub(s.since))),
+        );
+    }
+}
+
+/// Puts the status through `out` and exits with the code of the state the
+/// daemon last recorded, 0 for ONLINE. Without a running daemon or its
+/// state file only the kernel's view is shown, with exit 1.
+pub fn run(cfg: &Config, out: &mut dyn OutputSink) -> ! {
+    // Without a PID file there is no telling; the state file is trusted.
+    let running = cfg
+        .pid_file
+        .as_deref()
+        .map(|p| pidfile::running(p).is_some());
+    let h = cfg
+        .state_file
+        .as_deref()
+        .filter(|p| p.exists() && running != Some(false))
+        .map(History::load);
+    let now = history::unix_now();
+    let window = cfg.flap_window * 60;
+    let rows = rows(cfg, h.as_ref());
+    let j = match &h {
+        Some(h) => {
+            let flaps = h.flaps(now, window);
+            h.to_json()
+                .set("flaps", flaps)
+                .set("flapping", flaps > cfg.flap_threshold)
+        }
+        None => Json::obj().set("state", None::<&str>),
+    };
+    let ifaces: Vec<Json> = rows.iter().map(|r| row_json(r, now, window)).collect();
+    let mut t = match (&h, running) {
+        (Some(h), _) => h.describe(now, window),
+        (None, Some(false)) => "daemon not running".into(),
+        (None, _) => "no state recorded".into(),
+    };
+    t.push_str("\n\n");
+    table(&mut t, &rows, now, window);
+    out.document(
+        &t,
+        j.set("daemon_running", running).set("interfaces", ifaces),
+    );
+    // RECOVERING, or nothing recorded, is OFFLINE.
+    let code = h
+        .as_ref()
+        .and_then(|h| h.state.as_deref())
+        .map_or(1, |s| s.parse::<Status>().map_or(1, Status::exit_code));
+    std::process::exit(code)
+}
@@ -0,0 +1,768 @@
+// Thin libc bindings; std already links libc so no extra crates are needed.
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+#[cfg(target_os = "linux")]
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+#[cfg(target_os = "linux")]
+use std::os::raw::c_ulong;
+#[cfg(target_os = "macos")]
+use std::os::raw::{c_char, c_uint};
+use std::os::raw::{c_int, c_long, c_short, c_void};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+pub const SIGHUP: c_int = 1;
+pub const SIGINT: c_int = 2;
+pub const SIGTERM: c_int = 15;
+#[cfg(target_os = "linux")]
+pub const SIGUSR1: c_int = 10;
+#[cfg(target_os = "linux")]
+pub const SIGUSR2: c_int = 12;
+#[cfg(target_os = "macos")]
+pub const SIGUSR1: c_int = 30;
+#[cfg(target_os = "macos")]
+pub const SIGUSR2: c_int = 31;
+
+pub const AF_INET: c_int = 2;
+#[cfg(target_os = "linux")]
+pub const AF_INET6: c_int = 10;
+#[cfg(target_os = "macos")]
+pub const AF_INET6: c_int = 30;
+pub const SOCK_STREAM: c_int = 1;
+pub const SOCK_DGRAM: c_int = 2;
+pub const SOCK_RAW: c_int = 3;
+const IPPROTO_IP: c_int = 0;
+const IPPROTO_IPV6: c_int = 41;
+const EPERM: i32 = 1;
+const EINTR: i32 = 4;
+const LOCK_EX: c_int = 2;
+const LOCK_NB: c_int = 4;
+const POLLIN: c_short = 1;
+const POLLOUT: c_short = 4;
+
+#[cfg(target_os = "linux")]
+mod os {
+    use std::os::raw::c_int;
+
+    pub const SOCK_NONBLOCK: c_int = 0o4000;
+    pub const SOCK_CLOEXEC: c_int = 0o2000000;
+    pub const SOL_SOCKET: c_int = 1;
+    pub const SO_ERROR: c_int = 4;
+    pub const SO_RCVTIMEO: c_int = 20;
+    pub const SO_BINDTODEVICE: c_int = 25;
+    pub const SO_MARK: c_int = 36;
+    pub const IP_MTU_DISCOVER: c_int = 10;
+    pub const IPV6_MTU_DISCOVER: c_int = 23;
+    /// `IP_PMTUDISC_DO` and `IPV6_PMTUDISC_DO`.
+    pub const PMTUDISC_DO: c_int = 2;
+    pub const IP_TTL: c_int = 2;
+    pub const IP_RECVERR: c_int = 11;
+    pub const IPV6_UNICAST_HOPS: c_int = 16;
+    pub const IPV6_RECVERR: c_int = 25;
+    pub const MSG_DONTWAIT: c_int = 0x40;
+    pub const MSG_ERRQUEUE: c_int = 0x2000;
+    /// `SO_EE_ORIGIN_ICMP` and `SO_EE_ORIGIN_ICMP6`.
+    pub const EE_ORIGIN_ICMP: u8 = 2;
+    pub const EE_ORIGIN_ICMP6: u8 = 3;
+    pub const EINPROGRESS: i32 = 115;
+    pub const SIG_BLOCK: c_int = 0;
+    /// `tv_usec` of a `struct timeval`.
+    pub type Usec = std::os::raw::c_long;
+    pub type Nfds = std::os::raw::c_ulong;
+}
+
+#[cfg(target_os = "macos")]
+mod os {
+    use std::os::raw::c_int;
+
+    pub const SOL_SOCKET: c_int = 0xffff;
+    pub const SO_ERROR: c_int = 0x1007;
+    pub const SO_RCVTIMEO: c_int = 0x1006;
+    pub const IP_BOUND_IF: c_int = 25;
+    pub const IPV6_BOUND_IF: c_int = 125;
+    pub const IP_DONTFRAG: c_int = 28;
+    pub const IPV6_DONTFRAG: c_int = 62;
+    pub const IP_TTL: c_int = 4;
+    pub const IPV6_UNICAST_HOPS: c_int = 4;
+    pub const EINPROGRESS: i32 = 36;
+    pub const SIG_BLOCK: c_int = 1;
+    pub const F_SETFD: c_int = 2;
+    pub const FD_CLOEXEC: c_int = 1;
+    pub const F_GETFL: c_int = 3;
+    pub const F_SETFL: c_int = 4;
+    pub const O_NONBLOCK: c_int = 4;
+    pub type Usec = c_int;
+    pub type Nfds = std::os::raw::c_uint;
+}
+
+use os::*;
+
+extern "C" {
+    fn signal(sig: c_int, handler: extern "C" fn(c_int)) -> usize;
+    #[link_name = "socket"]
+    fn c_socket(domain: c_int, ty: c_int, proto: c_int) -> c_int;
+    #[cfg(target_os = "linux")]
+    fn ioctl(fd: c_int, req: c_ulong, ...) -> c_int;
+    #[cfg(target_os = "macos")]
+    fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+    #[cfg(target_os = "macos")]
+    fn if_nametoindex(name: *const c_char) -> c_uint;
+    fn setsockopt(fd: c_int, level: c_int, name: c_int, val: *const c_void, len: u32) -> c_int;
+    fn getsockopt(fd: c_int, level: c_int, name: c_int, val: *mut c_void, len: *mut u32) -> c_int;
+    #[link_name = "connect"]
+    fn c_connect(fd: c_int, addr: *const c_void, len: u32) -> c_int;
+    #[link_name = "bind"]
+    fn c_bind(fd: c_int, addr: *const c_void, len: u32) -> c_int;
+    fn poll(fds: *mut PollFd, n: Nfds, timeout: c_int) -> c_int;
+    fn flock(fd: c_int, op: c_int) -> c_int;
+    fn kill(pid: c_int, sig: c_int) -> c_int;
+    fn gethostname(name: *mut u8, len: usize) -> c_int;
+    #[cfg(target_os = "linux")]
+    fn recvmsg(fd: c_int, msg: *mut MsgHdr, flags: c_int) -> isize;
+    fn pthread_sigmask(how: c_int, set: *const SigSet, old: *mut SigSet) -> c_int;
+}
+
+/// `sigset_t`: bit `n - 1` for signal `n`; glibc's has room for 1024
+/// signals, macOS's for 32.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct SigSet([u64; 16]);
+#[cfg(target_os = "macos")]
+#[repr(C)]
+struct SigSet([u32; 1]);
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct IoVec {
+    base: *mut c_void,
+    len: usize,
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct MsgHdr {
+    name: *mut c_void,
+    namelen: u32,
+    iov: *mut IoVec,
+    iovlen: usize,
+    control: *mut c_void,
+    controllen: usize,
+    flags: c_int,
+}
+
+/// `struct timeval`, as the kernel takes it for `SO_RCVTIMEO`.
+#[repr(C)]
+struct TimeVal {
+    sec: c_long,
+    usec: Usec,
+}
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+#[cfg(target_os = "linux")]
+const SIOCGIFADDR: c_ulong = 0x8915;
+
+/// `struct ifreq`: interface name followed by a 24-byte union.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct IfReq {
+    name: [u8; 16],
+    data: [u8; 24],
+}
+
+#[cfg(target_os = "linux")]
+impl IfReq {
+    fn new(iface: &str) -> io::Result<IfReq> {
+        let b = iface.as_bytes();
+        if b.is_empty() || b.len() >= 16 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "bad interface name",
+            ));
+        }
+        let mut r = IfReq {
+            name: [0; 16],
+            data: [0; 24],
+        };
+        r.name[..b.len()].copy_from_slice(b);
+        Ok(r)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn if_ioctl(iface: &str, req: c_ulong) -> io::Result<IfReq> {
+    let sock = socket(AF_INET, SOCK_DGRAM, 0)?;
+    let mut r = IfReq::new(iface)?;
+    if unsafe { ioctl(sock.as_raw_fd(), req, &mut r as *mut IfReq) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(r)
+}
+
+/// Primary IPv4 address of `iface` (`EADDRNOTAVAIL` when it has none).
+#[cfg(target_os = "linux")]
+pub fn if_ipv4(iface: &str) -> io::Result<Ipv4Addr> {
+    let r = if_ioctl(iface, SIOCGIFADDR)?;
+    // sockaddr_in: family, port, then the address.
+    Ok(Ipv4Addr::new(r.data[4], r.data[5], r.data[6], r.data[7]))
+}
+
+#[cfg(target_os = "linux")]
+pub fn socket(domain: c_int, ty: c_int, proto: c_int) -> io::Result<OwnedFd> {
+    let fd = unsafe { c_socket(domain, ty | SOCK_CLOEXEC, proto) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// macOS has no SOCK_CLOEXEC; the flag is set right after.
+#[cfg(target_os = "macos")]
+pub fn socket(domain: c_int, ty: c_int, proto: c_int) -> io::Result<OwnedFd> {
+    let fd = unsafe { c_socket(domain, ty, proto) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    if unsafe { fcntl(fd.as_raw_fd(), F_SETFD, FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Restricts `fd`, an IPv6 socket with `v6`, to traffic through `iface`
+/// (SO_BINDTODEVICE; needs CAP_NET_RAW on older kernels).
+#[cfg(target_os = "linux")]
+pub fn bind_to_device(fd: &impl AsRawFd, iface: &str, _v6: bool) -> io::Result<()> {
+    let r = unsafe {
+        setsockopt(
+            fd.as_raw_fd(),
+            SOL_SOCKET,
+            SO_BINDTODEVICE,
+            iface.as_ptr().cast(),
+            iface.len() as u32,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Restricts `fd` to traffic through `iface` (IP_BOUND_IF and
+/// IPV6_BOUND_IF, which take its index).
+#[cfg(target_os = "macos")]
+pub fn bind_to_device(fd: &impl AsRawFd, iface: &str, v6: bool) -> io::Result<()> {
+    let name = std::ffi::CString::new(iface).map_err(|_| io::ErrorKind::InvalidInput)?;
+    let index = unsafe { if_nametoindex(name.as_ptr()) };
+    if index == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    match v6 {
+        true => set_int(fd, IPPROTO_IPV6, IPV6_BOUND_IF, index as c_int),
+        false => set_int(fd, IPPROTO_IP, IP_BOUND_IF, index as c_int),
+    }
+}
+
+/// Where a probe socket leaves from: through an interface
+/// (SO_BINDTODEVICE), from a source address, and with a firewall mark for
+/// policy routing (SO_MARK; needs CAP_NET_ADMIN, and Linux). The default
+/// is wherever the routing table says.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bind<'a> {
+    pub dev: Option<&'a str>,
+    pub source: Option<IpAddr>,
+    pub mark: Option<u32>,
+}
+
+impl<'a> Bind<'a> {
+    /// Only through `dev`, when given.
+    pub fn dev(dev: Option<&'a str>) -> Bind<'a> {
+        Bind {
+            dev,
+            ..Bind::default()
+        }
+    }
+
+    /// Applies it to `fd`, an IPv6 socket with `v6`, before anything is
+    /// sent or connected.
+    pub fn apply(&self, fd: &impl AsRawFd, v6: bool) -> io::Result<()> {
+        if let Some(d) = self.dev {
+            bind_to_device(fd, d, v6)?;
+        }
+        if let Some(m) = self.mark {
+            #[cfg(target_os = "linux")]
+            set_int(fd, SOL_SOCKET, SO_MARK, m as c_int)?;
+            #[cfg(not(target_os = "linux"))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("fwmark {}: needs Linux", m),
+            ));
+        }
+        if let Some(a) = self.source {
+            let sa = sockaddr(&SocketAddr::new(a, 0));
+            if unsafe { c_bind(fd.as_raw_fd(), sa.as_ptr().cast(), sa.len() as u32) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a socket can be bound so: the interface exists, the source
+    /// address is local and the mark may be set.
+    pub fn check(&self) -> io::Result<()> {
+        let dom = match self.source {
+            Some(IpAddr::V6(_)) => AF_INET6,
+            _ => AF_INET,
+        };
+        self.apply(&socket(dom, SOCK_DGRAM, 0)?, dom == AF_INET6)
+    }
+}
+
+/// Sets DF on what `fd` sends; larger than the known path MTU fails with
+/// EMSGSIZE instead of being fragmented.
+#[cfg(target_os = "linux")]
+pub fn dont_fragment(fd: &impl AsRawFd, v6: bool) -> io::Result<()> {
+    match v6 {
+        true => set_int(fd, IPPROTO_IPV6, IPV6_MTU_DISCOVER, PMTUDISC_DO),
+        false => set_int(fd, IPPROTO_IP, IP_MTU_DISCOVER, PMTUDISC_DO),
+    }
+}
+
+/// Sets DF on what `fd` sends (IP_DONTFRAG, IPV6_DONTFRAG).
+#[cfg(target_os = "macos")]
+pub fn dont_fragment(fd: &impl AsRawFd, v6: bool) -> io::Result<()> {
+    match v6 {
+        true => set_int(fd, IPPROTO_IPV6, IPV6_DONTFRAG, 1),
+        false => set_int(fd, IPPROTO_IP, IP_DONTFRAG, 1),
+    }
+}
+
+fn set_int(fd: &impl AsRawFd, level: c_int, name: c_int, v: c_int) -> io::Result<()> {
+    let r = unsafe {
+        setsockopt(
+            fd.as_raw_fd(),
+            level,
+            name,
+            (&v as *const c_int).cast(),
+            std::mem::size_of::<c_int>() as u32,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Sets the TTL (hop limit) of what `fd` sends, and on Linux has the ICMP
+/// errors that draws queued for [`recv_icmp_error`]; macOS hands them to
+/// an ICMP socket like any other message.
+pub fn set_ttl(fd: &impl AsRawFd, v6: bool, ttl: u8) -> io::Result<()> {
+    if v6 {
+        set_int(fd, IPPROTO_IPV6, IPV6_UNICAST_HOPS, ttl.into())?;
+        #[cfg(target_os = "linux")]
+        set_int(fd, IPPROTO_IPV6, IPV6_RECVERR, 1)?;
+    } else {
+        set_int(fd, IPPROTO_IP, IP_TTL, ttl.into())?;
+        #[cfg(target_os = "linux")]
+        set_int(fd, IPPROTO_IP, IP_RECVERR, 1)?;
+    }
+    Ok(())
+}
+
+/// An ICMP error a packet from a socket drew.
+pub struct IcmpError {
+    /// The router or host that sent it.
+    pub from: Option<IpAddr>,
+    pub ty: u8,
+}
+
+/// Takes the next ICMP error off the error queue of `fd` without blocking;
+/// `None` when there is none.
+#[cfg(target_os = "linux")]
+pub fn recv_icmp_error(fd: &impl AsRawFd) -> io::Result<Option<IcmpError>> {
+    let mut data = [0u8; 576];
+    let mut ctl = [0u64; 64];
+    let mut iov = IoVec {
+        base: data.as_mut_ptr().cast(),
+        len: data.len(),
+    };
+    let mut msg = MsgHdr {
+        name: std::ptr::null_mut(),
+        namelen: 0,
+        iov: &mut iov,
+        iovlen: 1,
+        control: ctl.as_mut_ptr().cast(),
+        controllen: std::mem::size_of_val(&ctl),
+        flags: 0,
+    };
+    if unsafe { recvmsg(fd.as_raw_fd(), &mut msg, MSG_ERRQUEUE | MSG_DONTWAIT) } < 0 {
+        let e = io::Error::last_os_error();
+        return match e.kind() {
+            io::ErrorKind::WouldBlock => Ok(None),
+            _ => Err(e),
+        };
+    }
+    let b: &[u8] =
+        unsafe { std::slice::from_raw_parts(ctl.as_ptr().cast(), msg.controllen.min(512)) };
+    Ok(icmp_error(b))
+}
+
+/// No error queue: ICMP errors come in as messages of their own.
+#[cfg(target_os = "macos")]
+pub fn recv_icmp_error(_fd: &impl AsRawFd) -> io::Result<Option<IcmpError>> {
+    Ok(None)
+}
+
+/// `CMSG_ALIGN`: control messages, and the data after each header, start
+/// on `size_t` boundaries.
+#[cfg(target_os = "linux")]
+fn cmsg_align(n: usize) -> usize {
+    let a = std::mem::size_of::<usize>();
+    (n + a - 1) & !(a - 1)
+}
+
+/// The ICMP error among the control messages `b` of an error-queue read.
+#[cfg(target_os = "linux")]
+fn icmp_error(b: &[u8]) -> Option<IcmpError> {
+    // struct cmsghdr { size_t len; int level; int type; }, then its data.
+    let w = std::mem::size_of::<usize>();
+    let hdr = cmsg_align(w + 2 * std::mem::size_of::<c_int>());
+    let mut off = 0;
+    while off + hdr <= b.len() {
+        let len = usize::from_ne_bytes(b[off..off + w].try_into().unwrap());
+        let level = c_int::from_ne_bytes(b[off + w..off + w + 4].try_into().unwrap());
+        let ty = c_int::from_ne_bytes(b[off + w + 4..off + w + 8].try_into().unwrap());
+        if len < hdr || off + len > b.len() {
+            break;
+        }
+        let d = &b[off + hdr..off + len];
+        let ours =
+            (level, ty) == (IPPROTO_IP, IP_RECVERR) || (level, ty) == (IPPROTO_IPV6, IPV6_RECVERR);
+        // struct sock_extended_err (16 bytes), then the offender's sockaddr.
+        if ours && d.len() >= 16 && matches!(d[4], EE_ORIGIN_ICMP | EE_ORIGIN_ICMP6) {
+            let sa = &d[16..];
+            let family = sa
+                .get(..2)
+                .map(|f| u16::from_ne_bytes([f[0], f[1]]) as c_int);
+            let from = match family {
+                Some(AF_INET) if sa.len() >= 8 => {
+                    Some(IpAddr::V4(Ipv4Addr::new(sa[4], sa[5], sa[6], sa[7])))
+                }
+                Some(AF_INET6) if sa.len() >= 24 => {
+                    let o: [u8; 16] = sa[8..24].try_into().unwrap();
+                    Some(IpAddr::V6(Ipv6Addr::from(o)))
+                }
+                _ => None,
+            };
+            return Some(IcmpError { from, ty: d[5] });
+        }
+        off += cmsg_align(len);
+    }
+    None
+}
+
+/// Makes blocking receives on `fd` fail with EAGAIN after `t`.
+pub fn recv_timeout(fd: &impl AsRawFd, t: Duration) -> io::Result<()> {
+    let tv = TimeVal {
+        sec: t.as_secs().try_into().unwrap_or(c_long::MAX),
+        usec: t.subsec_micros() as Usec,
+    };
+    let r = unsafe {
+        setsockopt(
+            fd.as_raw_fd(),
+            SOL_SOCKET,
+            SO_RCVTIMEO,
+            (&tv as *const TimeVal).cast(),
+            std::mem::size_of::<TimeVal>() as u32,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// `struct sockaddr_in` / `sockaddr_in6` bytes. BSD ones start with their
+/// length, and a one-byte family.
+fn sockaddr(a: &SocketAddr) -> Vec<u8> {
+    let mut b = Vec::with_capacity(28);
+    let family = |b: &mut Vec<u8>, f: c_int, len: u8| match cfg!(target_os = "macos") {
+        true => b.extend_from_slice(&[len, f as u8]),
+        false => b.extend_from_slice(&(f as u16).to_ne_bytes()),
+    };
+    match a {
+        SocketAddr::V4(v4) => {
+            family(&mut b, AF_INET, 16);
+            b.extend_from_slice(&v4.port().to_be_bytes());
+            b.extend_from_slice(&v4.ip().octets());
+            b.extend_from_slice(&[0; 8]);
+        }
+        SocketAddr::V6(v6) => {
+            family(&mut b, AF_INET6, 28);
+            b.extend_from_slice(&v6.port().to_be_bytes());
+            b.extend_from_slice(&v6.flowinfo().to_be_bytes());
+            b.extend_from_slice(&v6.ip().octets());
+            b.extend_from_slice(&v6.scope_id().to_ne_bytes());
+        }
+    }
+    b
+}
+
+/// `TcpStream::connect_timeout`, but leaving as `bind` says.
+pub fn connect_timeout(a: &SocketAddr, t: Duration, bind: Bind) -> io::Result<TcpStream> {
+    if bind == Bind::default() {
+        return TcpStream::connect_timeout(a, t);
+    }
+    let dom = if a.is_ipv4() { AF_INET } else { AF_INET6 };
+    #[cfg(target_os = "linux")]
+    let fd = socket(dom, SOCK_STREAM | SOCK_NONBLOCK, 0)?;
+    #[cfg(target_os = "macos")]
+    let fd = {
+        let fd = socket(dom, SOCK_STREAM, 0)?;
+        let fl = unsafe { fcntl(fd.as_raw_fd(), F_GETFL) };
+        if fl < 0 || unsafe { fcntl(fd.as_raw_fd(), F_SETFL, fl | O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        fd
+    };
+    bind.apply(&fd, a.is_ipv6())?;
+    let sa = sockaddr(a);
+    if unsafe { c_connect(fd.as_raw_fd(), sa.as_ptr().cast(), sa.len() as u32) } < 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(EINPROGRESS) {
+            return Err(e);
+        }
+        let mut p = PollFd {
+            fd: fd.as_raw_fd(),
+            events: POLLOUT,
+            revents: 0,
+        };
+        let ms = t.as_millis().min(i32::MAX as u128) as c_int;
+        match unsafe { poll(&mut p, 1, ms) } {
+            n if n < 0 => return Err(io::Error::last_os_error()),
+            0 => return Err(io::ErrorKind::TimedOut.into()),
+            _ => {}
+        }
+        let mut err: c_int = 0;
+        let mut len = std::mem::size_of::<c_int>() as u32;
+        unsafe {
+            getsockopt(
+                fd.as_raw_fd(),
+                SOL_SOCKET,
+                SO_ERROR,
+                (&mut err as *mut c_int).cast(),
+                &mut len,
+            )
+        };
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(err));
+        }
+    }
+    let s = TcpStream::from(fd);
+    s.set_nonblocking(false)?;
+    Ok(s)
+}
+
+/// Bit `n` is set once signal `n` has been delivered.
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// Deliveries of each signal so far.
+static DELIVERED: [AtomicU32; 64] = [const { AtomicU32::new(0) }; 64];
+
+extern "C" fn on_signal(sig: c_int) {
+    PENDING.fetch_or(1 << sig, Ordering::SeqCst);
+    DELIVERED[sig as usize].fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn trap(sigs: &[c_int]) {
+    for &s in sigs {
+        unsafe { signal(s, on_signal) };
+    }
+}
+
+/// Traps `sigs` and blocks them on this thread, and on the threads it
+/// starts from now on, so that they never cut a probe's system call short
+/// with EINTR; a thread of their own takes them instead. Called before
+/// any other thread starts.
+pub fn trap_aside(sigs: &[c_int]) {
+    trap(sigs);
+    let _ = std::thread::Builder::new()
+        .name("signals".into())
+        .spawn(|| loop {
+            std::thread::park();
+        });
+    let mut set = SigSet(Default::default());
+    for &s in sigs {
+        set.0[0] |= 1 << (s - 1);
+    }
+    unsafe { pthread_sigmask(SIG_BLOCK, &set, std::ptr::null_mut()) };
+}
+
+/// The deliveries of some trapped signals one reader has yet to act on;
+/// each reader sees every one, whichever thread took it.
+pub struct Signals(Vec<(c_int, u32)>);
+
+impl Signals {
+    /// Counting from now.
+    pub fn new(sigs: &[c_int]) -> Signals {
+        Signals(
+            sigs.iter()
+                .map(|&s| (s, DELIVERED[s as usize].load(Ordering::SeqCst)))
+                .collect(),
+        )
+    }
+
+    pub fn pending(&self) -> bool {
+        self.0
+            .iter()
+            .any(|&(s, n)| DELIVERED[s as usize].load(Ordering::SeqCst) != n)
+    }
+
+    /// Whether `sig` arrived since it was last taken.
+    pub fn take(&mut self, sig: c_int) -> bool {
+        let Some(e) = self.0.iter_mut().find(|e| e.0 == sig) else {
+            return false;
+        };
+        let n = DELIVERED[sig as usize].load(Ordering::SeqCst);
+        std::mem::replace(&mut e.1, n) != n
+    }
+}
+
+pub fn pending(sig: c_int) -> bool {
+    PENDING.load(Ordering::SeqCst) & (1 << sig) != 0
+}
+
+/// Takes an exclusive `flock` on `f` without blocking; `false` when another
+/// process holds it.
+pub fn try_lock(f: &impl AsRawFd) -> io::Result<bool> {
+    if unsafe { flock(f.as_raw_fd(), LOCK_EX | LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    match e.kind() {
+        io::ErrorKind::WouldBlock => Ok(false),
+        _ => Err(e),
+    }
+}
+
+/// The device and inode of `f`, which tell whether two opens are of the
+/// same file.
+pub fn file_id(f: &std::fs::File) -> io::Result<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let m = f.metadata()?;
+    Ok((m.dev(), m.ino()))
+}
+
+/// Waits up to `t` for any of `fds` to be readable; per fd, whether a read
+/// will not block (data, EOF or an error).
+pub fn poll_in(fds: &[c_int], t: Duration) -> io::Result<Vec<bool>> {
+    let mut p: Vec<PollFd> = fds
+        .iter()
+        .map(|&fd| PollFd {
+            fd,
+            events: POLLIN,
+            revents: 0,
+        })
+        .collect();
+    let ms = t.as_millis().min(c_int::MAX as u128) as c_int;
+    if unsafe { poll(p.as_mut_ptr(), p.len() as Nfds, ms) } < 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(EINTR) {
+            return Err(e);
+        }
+    }
+    // POLLIN, or POLLHUP/POLLERR, which reads report too.
+    Ok(p.iter().map(|p| p.revents != 0).collect())
+}
+
+/// Sends SIGTERM to `pid`.
+pub fn terminate(pid: u32) -> io::Result<()> {
+    if unsafe { kill(pid as c_int, SIGTERM) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// `kill(pid, 0)`: whether the process exists, ours or not.
+pub fn alive(pid: i32) -> bool {
+    pid > 0
+        && (unsafe { kill(pid, 0) } == 0
+            || io::Error::last_os_error().raw_os_error() == Some(EPERM))
+}
+
+/// gethostname(2); empty when it fails.
+pub fn hostname() -> String {
+    let mut b = [0u8; 256];
+    if unsafe { gethostname(b.as_mut_ptr(), b.len()) } != 0 {
+        return String::new();
+    }
+    let n = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+    String::from_utf8_lossy(&b[..n]).into_owned()
+}
+
+pub fn stop_requested() -> bool {
+    pending(SIGTERM) || pending(SIGINT)
+}
+
+/// Sleeps for `d`, returning early when a stop signal arrives.
+pub fn sleep(d: Duration) {
+    let end = Instant::now() + d;
+    while !stop_requested() {
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return;
+        }
+        std::thread::sleep(left.min(Duration::from_millis(200)));
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    /// A control message as the kernel lays it out on this target.
+    fn cmsg(level: c_int, ty: c_int, data: &[u8]) -> Vec<u8> {
+        let w = std::mem::size_of::<usize>();
+        let hdr = cmsg_align(w + 8);
+        let mut b = (hdr + data.len()).to_ne_bytes().to_vec();
+        b.extend_from_slice(&level.to_ne_bytes());
+        b.extend_from_slice(&ty.to_ne_bytes());
+        b.resize(hdr, 0);
+        b.extend_from_slice(data);
+        b.resize(cmsg_align(b.len()), 0);
+        b
+    }
+
+    #[test]
+    fn control_messages_align_to_size_t() {
+        let w = std::mem::size_of::<usize>();
+        assert_eq!(cmsg_align(w + 8), if w == 8 { 16 } else { 12 });
+        assert_eq!(cmsg_align(1), w);
+        assert_eq!(cmsg_align(w), w);
+    }
+
+    #[test]
+    fn the_icmp_error_is_found_after_other_messages() {
+        // sock_extended_err: errno, origin ICMP, type 11 (time exceeded),
+        // code, pad, info, data; then the router's sockaddr_in.
+        let mut err = vec![0u8; 16];
+        err[4] = EE_ORIGIN_ICMP;
+        err[5] = 11;
+        err.extend_from_slice(&(AF_INET as u16).to_ne_bytes());
+        err.extend_from_slice(&[0, 0, 192, 0, 2, 1]);
+        err.resize(32, 0);
+        let mut b = cmsg(SOL_SOCKET, 29, &[1, 2, 3]);
+        b.extend(cmsg(IPPROTO_IP, IP_RECVERR, &err));
+        let e = icmp_error(&b).expect("an ICMP error");
+        assert_eq!(e.ty, 11);
+        assert_eq!(e.from, Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))));
+        assert!(icmp_error(&b[..b.len() - 1]).is_none());
+    }
+}
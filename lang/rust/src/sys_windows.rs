@@ -0,0 +1,450 @@
+// The Windows side of sys: console control events in place of signals,
+// file locks, processes and ICMP echoes through kernel32 and iphlpapi.
+// Only what probing and the report need is here.
+use std::fs::File;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, TcpStream};
+use std::os::raw::{c_int, c_void};
+use std::os::windows::io::AsRawHandle;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Signal numbers as elsewhere; only SIGINT (Ctrl-C) and SIGTERM (any other
+/// console event: break, close, logoff, shutdown) are ever delivered.
+pub const SIGHUP: c_int = 1;
+pub const SIGINT: c_int = 2;
+pub const SIGUSR1: c_int = 10;
+pub const SIGUSR2: c_int = 12;
+pub const SIGTERM: c_int = 15;
+
+type Handle = *mut c_void;
+
+const CTRL_C_EVENT: u32 = 0;
+const LOCKFILE_FAIL_IMMEDIATELY: u32 = 1;
+const LOCKFILE_EXCLUSIVE_LOCK: u32 = 2;
+const ERROR_ACCESS_DENIED: i32 = 5;
+const ERROR_LOCK_VIOLATION: i32 = 33;
+const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+const STILL_ACTIVE: u32 = 259;
+const IP_FLAG_DF: u8 = 0x2;
+const IP_REQ_TIMED_OUT: u32 = 11010;
+const IP_PACKET_TOO_BIG: u32 = 11009;
+const IP_TTL_EXPIRED_TRANSIT: u32 = 11013;
+
+#[repr(C)]
+struct Overlapped {
+    internal: usize,
+    internal_high: usize,
+    offset: u32,
+    offset_high: u32,
+    event: Handle,
+}
+
+/// `IP_OPTION_INFORMATION`.
+#[repr(C)]
+struct IpOptions {
+    ttl: u8,
+    tos: u8,
+    flags: u8,
+    options_size: u8,
+    options: *mut u8,
+}
+
+/// `ICMP_ECHO_REPLY`, up to what is read of it.
+#[repr(C)]
+struct EchoReply {
+    address: u32,
+    status: u32,
+    rtt: u32,
+    data_size: u16,
+    reserved: u16,
+    data: *mut c_void,
+    options: IpOptions,
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn SetConsoleCtrlHandler(handler: Option<extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    fn LockFileEx(
+        file: Handle,
+        flags: u32,
+        reserved: u32,
+        low: u32,
+        high: u32,
+        o: *mut Overlapped,
+    ) -> i32;
+    fn OpenProcess(access: u32, inherit: i32, pid: u32) -> Handle;
+    fn GetExitCodeProcess(process: Handle, code: *mut u32) -> i32;
+    fn CloseHandle(h: Handle) -> i32;
+    fn GetFileInformationByHandle(file: Handle, info: *mut [u32; 13]) -> i32;
+}
+
+#[link(name = "iphlpapi")]
+extern "system" {
+    fn IcmpCreateFile() -> Handle;
+    fn Icmp6CreateFile() -> Handle;
+    fn IcmpCloseHandle(h: Handle) -> i32;
+    fn IcmpSendEcho2Ex(
+        h: Handle,
+        event: Handle,
+        apc: *mut c_void,
+        ctx: *mut c_void,
+        source: u32,
+        dest: u32,
+        data: *const c_void,
+        size: u16,
+        opts: *const IpOptions,
+        reply: *mut c_void,
+        reply_size: u32,
+        timeout: u32,
+    ) -> u32;
+    fn Icmp6SendEcho2(
+        h: Handle,
+        event: Handle,
+        apc: *mut c_void,
+        ctx: *mut c_void,
+        source: *const u8,
+        dest: *const u8,
+        data: *const c_void,
+        size: u16,
+        opts: *const IpOptions,
+        reply: *mut c_void,
+        reply_size: u32,
+        timeout: u32,
+    ) -> u32;
+}
+
+/// Where a probe socket leaves from. Only the default, wherever the
+/// routing table says, is supported here; ICMP takes a source address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bind<'a> {
+    pub dev: Option<&'a str>,
+    pub source: Option<IpAddr>,
+    pub mark: Option<u32>,
+}
+
+impl<'a> Bind<'a> {
+    /// Only through `dev`, when given.
+    pub fn dev(dev: Option<&'a str>) -> Bind<'a> {
+        Bind {
+            dev,
+            ..Bind::default()
+        }
+    }
+
+    /// Whether a socket can be bound so.
+    pub fn check(&self) -> io::Result<()> {
+        match *self == Bind::default() {
+            true => Ok(()),
+            false => Err(unsupported()),
+        }
+    }
+}
+
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "binding to an interface, a source address or a mark needs Linux or macOS",
+    )
+}
+
+/// `TcpStream::connect_timeout`; any other `bind` than the default fails.
+pub fn connect_timeout(a: &SocketAddr, t: Duration, bind: Bind) -> io::Result<TcpStream> {
+    bind.check()?;
+    TcpStream::connect_timeout(a, t)
+}
+
+/// What became of an echo request.
+pub enum Echo {
+    Reply(IpAddr),
+    /// Time exceeded, from the router it ran out at.
+    Expired(IpAddr),
+    /// Larger than the path takes with DF set.
+    TooBig,
+    /// Any other ICMP error, from where it came if known.
+    Unreachable(Option<IpAddr>),
+}
+
+/// Sends an echo request carrying `payload` bytes from `source` to `dest`
+/// and waits up to `t` for what comes back, with `ttl` and DF when given.
+/// `TimedOut` when nothing does.
+pub fn icmp_echo(
+    dest: IpAddr,
+    source: Option<IpAddr>,
+    payload: usize,
+    ttl: Option<u8>,
+    df: bool,
+    t: Duration,
+) -> io::Result<Echo> {
+    let data = vec![0u8; payload.max(16)];
+    let opts = IpOptions {
+        ttl: ttl.unwrap_or(128),
+        tos: 0,
+        flags: if df { IP_FLAG_DF } else { 0 },
+        options_size: 0,
+        options: std::ptr::null_mut(),
+    };
+    let ms = t.as_millis().clamp(1, u32::MAX as u128) as u32;
+    // The reply, the data echoed and room for an ICMP error.
+    let mut reply = vec![0u64; (data.len() + 256) / 8];
+    let size = (reply.len() * 8) as u32;
+    let h = match dest {
+        IpAddr::V4(_) => unsafe { IcmpCreateFile() },
+        IpAddr::V6(_) => unsafe { Icmp6CreateFile() },
+    };
+    if h.is_null() || h as isize == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let n = match (dest, source) {
+        (IpAddr::V4(d), s) => {
+            let s = match s {
+                Some(IpAddr::V4(s)) => u32::from_ne_bytes(s.octets()),
+                _ => 0,
+            };
+            unsafe {
+                IcmpSendEcho2Ex(
+                    h,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    s,
+                    u32::from_ne_bytes(d.octets()),
+                    data.as_ptr().cast(),
+                    data.len() as u16,
+                    &opts,
+                    reply.as_mut_ptr().cast(),
+                    size,
+                    ms,
+                )
+            }
+        }
+        (IpAddr::V6(d), s) => {
+            let s = match s {
+                Some(IpAddr::V6(s)) => s,
+                _ => Ipv6Addr::UNSPECIFIED,
+            };
+            unsafe {
+                Icmp6SendEcho2(
+                    h,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    sockaddr_in6(s).as_ptr(),
+                    sockaddr_in6(d).as_ptr(),
+                    data.as_ptr().cast(),
+                    data.len() as u16,
+                    &opts,
+                    reply.as_mut_ptr().cast(),
+                    size,
+                    ms,
+                )
+            }
+        }
+    };
+    let err = (n == 0).then(io::Error::last_os_error);
+    unsafe { IcmpCloseHandle(h) };
+    let b: &[u8] = unsafe { std::slice::from_raw_parts(reply.as_ptr().cast(), reply.len() * 8) };
+    // ICMPV6_ECHO_REPLY is packed: the address (port, flow info, address,
+    // scope) takes 26 bytes, the status follows.
+    let (status, from) = match dest {
+        IpAddr::V4(_) => {
+            let r = unsafe { &*reply.as_ptr().cast::<EchoReply>() };
+            let a = r.address.to_ne_bytes();
+            (r.status, IpAddr::from(a))
+        }
+        IpAddr::V6(_) => {
+            let a: [u8; 16] = b[6..22].try_into().expect("16 bytes");
+            (
+                u32::from_ne_bytes(b[26..30].try_into().expect("4 bytes")),
+                IpAddr::from(a),
+            )
+        }
+    };
+    // A failed send names why in the last error; the reply may still say
+    // where the error came from.
+    let status = match &err {
+        Some(e) => match e.raw_os_error().map(|c| c as u32) {
+            Some(s) if s == status || s == IP_TTL_EXPIRED_TRANSIT || s == IP_PACKET_TOO_BIG => s,
+            Some(IP_REQ_TIMED_OUT) => return Err(io::ErrorKind::TimedOut.into()),
+            _ => return Err(err.expect("an error")),
+        },
+        None => status,
+    };
+    let known = (!from.is_unspecified()).then_some(from);
+    Ok(match status {
+        0 => Echo::Reply(from),
+        IP_TTL_EXPIRED_TRANSIT => match known {
+            Some(a) => Echo::Expired(a),
+            None => Echo::Unreachable(None),
+        },
+        IP_PACKET_TOO_BIG => Echo::TooBig,
+        IP_REQ_TIMED_OUT => return Err(io::ErrorKind::TimedOut.into()),
+        _ => Echo::Unreachable(known),
+    })
+}
+
+/// `struct sockaddr_in6` bytes for `a`, port and scope 0.
+fn sockaddr_in6(a: Ipv6Addr) -> [u8; 28] {
+    const AF_INET6: u16 = 23;
+    let mut b = [0u8; 28];
+    b[..2].copy_from_slice(&AF_INET6.to_ne_bytes());
+    b[8..24].copy_from_slice(&a.octets());
+    b
+}
+
+/// Deliveries of each signal so far.
+static DELIVERED: [AtomicU32; 16] = [const { AtomicU32::new(0) }; 16];
+/// Bit `n` is set once signal `n` has been delivered.
+static PENDING: AtomicU64 = AtomicU64::new(0);
+static HANDLER: AtomicBool = AtomicBool::new(false);
+
+extern "system" fn on_ctrl(event: u32) -> i32 {
+    let sig = match event {
+        CTRL_C_EVENT => SIGINT,
+        _ => SIGTERM,
+    };
+    PENDING.fetch_or(1 << sig, Ordering::SeqCst);
+    DELIVERED[sig as usize].fetch_add(1, Ordering::SeqCst);
+    1
+}
+
+/// Takes console control events from now on, as SIGINT and SIGTERM; the
+/// other signals never arrive.
+pub fn trap(_sigs: &[c_int]) {
+    if !HANDLER.swap(true, Ordering::SeqCst) {
+        unsafe { SetConsoleCtrlHandler(Some(on_ctrl), 1) };
+    }
+}
+
+/// [`trap`]: the handler runs on a thread of its own anyway.
+pub fn trap_aside(sigs: &[c_int]) {
+    trap(sigs);
+}
+
+/// The deliveries of some trapped signals one reader has yet to act on;
+/// each reader sees every one, whichever thread took it.
+pub struct Signals(Vec<(c_int, u32)>);
+
+impl Signals {
+    /// Counting from now.
+    pub fn new(sigs: &[c_int]) -> Signals {
+        Signals(
+            sigs.iter()
+                .map(|&s| (s, DELIVERED[s as usize].load(Ordering::SeqCst)))
+                .collect(),
+        )
+    }
+
+    pub fn pending(&self) -> bool {
+        self.0
+            .iter()
+            .any(|&(s, n)| DELIVERED[s as usize].load(Ordering::SeqCst) != n)
+    }
+
+    /// Whether `sig` arrived since it was last taken.
+    pub fn take(&mut self, sig: c_int) -> bool {
+        let Some(e) = self.0.iter_mut().find(|e| e.0 == sig) else {
+            return false;
+        };
+        let n = DELIVERED[sig as usize].load(Ordering::SeqCst);
+        std::mem::replace(&mut e.1, n) != n
+    }
+}
+
+pub fn pending(sig: c_int) -> bool {
+    PENDING.load(Ordering::SeqCst) & (1 << sig) != 0
+}
+
+/// Takes an exclusive lock on `f` without blocking; `false` when another
+/// process holds it. The byte locked lies far past the end, so that the
+/// pid written stays readable: Windows locks keep others from reading.
+pub fn try_lock(f: &File) -> io::Result<bool> {
+    let mut o = Overlapped {
+        internal: 0,
+        internal_high: 0,
+        offset: u32::MAX,
+        offset_high: u32::MAX >> 1,
+        event: std::ptr::null_mut(),
+    };
+    let flags = LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY;
+    if unsafe { LockFileEx(f.as_raw_handle(), flags, 0, 1, 0, &mut o) } != 0 {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(ERROR_LOCK_VIOLATION) => Ok(false),
+        _ => Err(e),
+    }
+}
+
+/// The volume serial and file index of `f`, which tell whether two opens
+/// are of the same file.
+pub fn file_id(f: &File) -> io::Result<(u64, u64)> {
+    // BY_HANDLE_FILE_INFORMATION: the serial is the 8th word, the index
+    // the last two.
+    let mut i = [0u32; 13];
+    if unsafe { GetFileInformationByHandle(f.as_raw_handle(), &mut i) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((u64::from(i[7]), u64::from(i[11]) << 32 | u64::from(i[12])))
+}
+
+/// Whether the process exists, ours or not.
+pub fn alive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+    let h = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+    if h.is_null() {
+        return io::Error::last_os_error().raw_os_error() == Some(ERROR_ACCESS_DENIED);
+    }
+    let mut code = 0;
+    let ok = unsafe { GetExitCodeProcess(h, &mut code) } != 0;
+    unsafe { CloseHandle(h) };
+    ok && code == STILL_ACTIVE
+}
+
+/// `%COMPUTERNAME%`; empty when unset.
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+pub fn stop_requested() -> bool {
+    pending(SIGTERM) || pending(SIGINT)
+}
+
+/// Sleeps for `d`, returning early when a stop signal arrives.
+pub fn sleep(d: Duration) {
+    let end = Instant::now() + d;
+    while !stop_requested() {
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return;
+        }
+        std::thread::sleep(left.min(Duration::from_millis(200)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn own_process_is_alive() {
+        assert!(alive(std::process::id() as i32));
+        assert!(!alive(0));
+    }
+
+    #[test]
+    fn lock_leaves_the_file_readable() {
+        let p = std::env::temp_dir().join(format!("cw-lock-{}", std::process::id()));
+        std::fs::write(&p, "42\n").unwrap();
+        let f = File::options().read(true).write(true).open(&p).unwrap();
+        assert!(try_lock(&f).unwrap());
+        let g = File::options().read(true).write(true).open(&p).unwrap();
+        assert!(!try_lock(&g).unwrap());
+        assert_eq!(std::fs::read_to_string(&p).unwrap(), "42\n");
+        drop((f, g));
+        let _ = std::fs::remove_file(&p);
+    }
+}
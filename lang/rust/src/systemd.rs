@@ -0,0 +1,44 @@
+// sd_notify(3) without libsystemd: one datagram per message to
+// $NOTIFY_SOCKET, which may be an abstract socket (leading '@').
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+pub struct Systemd {
+    sock: UnixDatagram,
+    addr: SocketAddr,
+    /// Half of `WATCHDOG_USEC`, when the service manager expects keep-alives.
+    keepalive: Option<Duration>,
+}
+
+impl Systemd {
+    /// `None` when not started by systemd with `Type=notify`.
+    pub fn from_env() -> Option<Systemd> {
+        let path = std::env::var("NOTIFY_SOCKET").ok()?;
+        let addr = match path.strip_prefix('@') {
+            Some(n) => SocketAddr::from_abstract_name(n),
+            None => SocketAddr::from_pathname(&path),
+        }
+        .ok()?;
+        let ours =
+            std::env::var("WATCHDOG_PID").map_or(true, |p| p.parse() == Ok(std::process::id()));
+        let keepalive = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|u| u.parse::<u64>().ok())
+            .filter(|&u| u > 0 && ours)
+            .map(|u| Duration::from_micros(u / 2));
+        Some(Systemd {
+            sock: UnixDatagram::unbound().ok()?,
+            addr,
+            keepalive,
+        })
+    }
+
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.keepalive
+    }
+
+    pub fn notify(&self, msg: &str) {
+        let _ = self.sock.send_to_addr(msg.as_bytes(), &self.addr);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::ping::ProbeResult;
+use crate::probe::Probe;
+use crate::sys::{self, Bind};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// `count` TCP handshakes with `host:port`; the RTT is the connect time.
+/// For networks that drop ICMP. Only a completed handshake counts, or with
+/// `refused_ok` a refusal too. Connections leave as `bind` says.
+pub fn probe_tcp(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    count: u8,
+    bind: Bind,
+    refused_ok: bool,
+) -> ProbeResult {
+    let addrs: Vec<SocketAddr> = match (host, port).to_socket_addrs() {
+        Ok(a) => a.collect(),
+        Err(_) => return ProbeResult::none(None, count),
+    };
+    let mut addr = addrs.first().map(SocketAddr::ip);
+    let mut rtts = Vec::new();
+    for _ in 0..count {
+        for a in &addrs {
+            let t0 = Instant::now();
+            let answered = match sys::connect_timeout(a, timeout, bind) {
+                Ok(_) => true,
+                Err(e) => refused_ok && e.kind() == io::ErrorKind::ConnectionRefused,
+            };
+            if answered {
+                rtts.push(t0.elapsed());
+                addr = Some(a.ip());
+                break;
+            }
+        }
+    }
+    ProbeResult::from_rtts(addr, count, &rtts)
+}
+
+pub struct Tcp<'a> {
+    pub host: String,
+    pub port: u16,
+    pub count: u8,
+    pub bind: Bind<'a>,
+    pub refused_ok: bool,
+}
+
+impl Probe for Tcp<'_> {
+    fn check(&self, timeout: Duration) -> ProbeResult {
+        probe_tcp(
+            &self.host,
+            self.port,
+            timeout,
+            self.count,
+            self.bind,
+            self.refused_ok,
+        )
+    }
+}
@@ -0,0 +1,396 @@
+// The history file: a JSON line per state change of the daemon, kept apart
+// from the state file so it outlives reboots, and rotated to `<file>.1` by
+// size and age. `history` reads it back as outages and availability.
+use crate::config::Config;
+use crate::history::{rfc3339, unix_now};
+use crate::json::Json;
+use crate::leases;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// The last record of a daemon that exited; nothing is known of the time
+/// until the next one.
+pub const STOPPED: &str = "STOPPED";
+
+/// A moment by the wall clock and by the monotonic clock of the boot it
+/// was in, which wall clock steps do not move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamp {
+    /// Unix seconds.
+    pub at: u64,
+    /// Seconds since boot.
+    pub mono: u64,
+    pub boot: String,
+}
+
+impl Stamp {
+    pub fn now() -> Stamp {
+        Stamp {
+            at: unix_now(),
+            mono: leases::uptime().unwrap_or(0.0) as u64,
+            boot: fs::read_to_string("/proc/sys/kernel/random/boot_id")
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// One state change.
+pub struct Record {
+    pub stamp: Stamp,
+    /// `None` for the first check after the daemon started.
+    pub from: Option<String>,
+    pub to: String,
+    /// The diagnosis of an OFFLINE verdict, when the probes made one.
+    pub cause: Option<String>,
+}
+
+impl Record {
+    pub fn new(from: Option<String>, to: &str, cause: Option<&str>) -> Record {
+        Record {
+            stamp: Stamp::now(),
+            from,
+            to: to.into(),
+            cause: cause.map(String::from),
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        Json::obj()
+            .set("ts", rfc3339(self.stamp.at))
+            .set("at", self.stamp.at)
+            .set("mono", self.stamp.mono)
+            .set("boot", self.stamp.boot.as_str())
+            .set("from", self.from.as_deref())
+            .set("to", self.to.as_str())
+            .set("cause", self.cause.as_deref())
+    }
+
+    fn from_json(j: &Json) -> Option<Record> {
+        let s = |k: &str| j.get(k).and_then(Json::as_str).map(String::from);
+        Some(Record {
+            stamp: Stamp {
+                at: j.get("at")?.as_u64()?,
+                mono: j.get("mono")?.as_u64()?,
+                boot: s("boot")?,
+            },
+            from: s("from"),
+            to: s("to")?,
+            cause: s("cause"),
+        })
+    }
+}
+
+/// `<path>.1`.
+pub fn rotated(path: &Path) -> PathBuf {
+    let mut s = OsString::from(path);
+    s.push(".1");
+    PathBuf::from(s)
+}
+
+fn first(path: &Path) -> Option<Record> {
+    let mut line = String::new();
+    BufReader::new(File::open(path).ok()?)
+        .read_line(&mut line)
+        .ok()?;
+    Record::from_json(&Json::parse(&line).ok()?)
+}
+
+/// Appends `r` to `history_file`, first moving the file to `<file>.1`,
+/// over the one before, when it has grown past `history_max_size` bytes
+/// or holds records older than `history_max_days`.
+pub fn append(cfg: &Config, r: &Record) -> io::Result<()> {
+    let Some(path) = &cfg.history_file else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let big = fs::metadata(path).is_ok_and(|m| m.len() >= cfg.history_max_size);
+    let old = || {
+        first(path)
+            .is_some_and(|f| r.stamp.at.saturating_sub(f.stamp.at) > cfg.history_max_days * 86400)
+    };
+    if big || old() {
+        fs::rename(path, rotated(path))?;
+    }
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(f, "{}", r.to_json())
+}
+
+/// The records of `<path>.1`, then of `path`, oldest first. Lines that do
+/// not parse, such as one cut short by a crash, are skipped.
+pub fn read(path: &Path) -> Vec<Record> {
+    [rotated(path), path.to_path_buf()]
+        .iter()
+        .filter_map(|p| File::open(p).ok())
+        .flat_map(|f| BufReader::new(f).lines().map_while(Result::ok))
+        .filter_map(|l| Json::parse(&l).ok().as_ref().and_then(Record::from_json))
+        .collect()
+}
+
+/// States that count as down time: no working path to the internet.
+pub fn is_down(state: &str) -> bool {
+    matches!(
+        state,
+        "OFFLINE" | "RECOVERING" | "DNS_BROKEN" | "CAPTIVE_PORTAL"
+    )
+}
+
+/// Each record's time on today's wall clock. Within a boot, a record is
+/// placed by its monotonic distance from the boot's last record (from
+/// `now`, for the current boot), so a clock stepped since, by NTP on a
+/// board without an RTC, say, neither stretches nor shrinks what came
+/// before. A past boot's last record keeps its own wall time.
+fn placed(records: &[Record], now: &Stamp) -> Vec<u64> {
+    let mut at = vec![0; records.len()];
+    let mut anchor = now;
+    let mut anchor_at = now.at;
+    for (i, r) in records.iter().enumerate().rev() {
+        let s = &r.stamp;
+        if s.boot == anchor.boot && s.mono <= anchor.mono {
+            at[i] = anchor_at.saturating_sub(anchor.mono - s.mono);
+        } else {
+            at[i] = s.at;
+        }
+        anchor = s;
+        anchor_at = at[i];
+    }
+    at
+}
+
+pub struct Outage {
+    pub start: u64,
+    /// The last moment it is known to have lasted until.
+    pub end: u64,
+    /// Whether the state was seen to come back; not when the daemon
+    /// stopped, or the host went down, first.
+    pub recovered: bool,
+    /// Still going on now.
+    pub ongoing: bool,
+    pub cause: String,
+}
+
+impl Outage {
+    /// At least this long, unless [`Outage::recovered`].
+    pub fn duration(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Up and down time over a window.
+pub struct Stats {
+    pub since: u64,
+    pub now: u64,
+    /// Seconds of the window there is a record of the state for.
+    pub observed: u64,
+    pub downtime: u64,
+    /// Those overlapping the window, oldest first.
+    pub outages: Vec<Outage>,
+}
+
+impl Stats {
+    /// Percent of the observed time not down.
+    pub fn availability(&self) -> Option<f64> {
+        (self.observed > 0)
+            .then(|| 100.0 * (self.observed - self.downtime) as f64 / self.observed as f64)
+    }
+
+    pub fn longest(&self) -> Option<&Outage> {
+        self.outages.iter().max_by_key(|o| o.duration())
+    }
+
+    pub fn to_json(&self) -> Json {
+        let o = |o: &Outage| {
+            Json::obj()
+                .set("start", rfc3339(o.start))
+                .set("end", rfc3339(o.end))
+                .set("duration_secs", o.duration())
+                .set("cause", o.cause.as_str())
+                .set("recovered", o.recovered)
+                .set("ongoing", o.ongoing)
+        };
+        Json::obj()
+            .set("since", rfc3339(self.since))
+            .set("until", rfc3339(self.now))
+            .set("observed_secs", self.observed)
+            .set("downtime_secs", self.downtime)
+            .set("availability", self.availability())
+            .set("longest", self.longest().map(o))
+            .set("outages", self.outages.iter().map(o).collect::<Vec<_>>())
+    }
+}
+
+/// Outages and down time from `records` over the `window` seconds up to
+/// `now`. Each state lasts until the next record of the same boot; the last
+/// one until `now` while the daemon is `running`. Time after a STOPPED
+/// record or a boot's last one is not observed, and outages then have no
+/// known end.
+pub fn stats(records: &[Record], now: &Stamp, window: u64, running: bool) -> Stats {
+    let at = placed(records, now);
+    let since = now.at.saturating_sub(window);
+    let mut st = Stats {
+        since,
+        now: now.at,
+        observed: 0,
+        downtime: 0,
+        outages: Vec::new(),
+    };
+    let mut open: Option<Outage> = None;
+    for (i, r) in records.iter().enumerate() {
+        let end = match records.get(i + 1) {
+            Some(n) if n.stamp.boot == r.stamp.boot => Some(at[i + 1]),
+            Some(_) => None,
+            None if running && r.stamp.boot == now.boot => Some(now.at),
+            None => None,
+        };
+        let down = is_down(&r.to);
+        if let (Some(end), true) = (end, r.to != STOPPED) {
+            let span = end.min(now.at).saturating_sub(at[i].max(since));
+            st.observed += span;
+            if down {
+                st.downtime += span;
+            }
+        }
+        if !down {
+            if let Some(mut o) = open.take() {
+                o.recovered = r.to != STOPPED;
+                st.outages.push(o);
+            }
+            continue;
+        }
+        let cause = r.cause.clone().unwrap_or_else(|| r.to.clone());
+        let o = open.get_or_insert_with(|| Outage {
+            start: at[i],
+            end: at[i],
+            recovered: false,
+            ongoing: false,
+            cause: cause.clone(),
+        });
+        // A renewal that did not help says more once probed again.
+        if o.cause == "RECOVERING" {
+            o.cause = cause;
+        }
+        o.end = end.unwrap_or(at[i]);
+        o.ongoing = end.is_some() && i + 1 == records.len();
+        if end.is_none() {
+            st.outages.extend(open.take());
+        }
+    }
+    st.outages.extend(open);
+    st.outages.retain(|o| o.end >= since);
+    st
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(boot: &str, at: u64, mono: u64) -> Stamp {
+        Stamp {
+            at,
+            mono,
+            boot: boot.into(),
+        }
+    }
+
+    /// Records of `(boot, at, mono, to)`, each from the one before.
+    fn records(rs: &[(&str, u64, u64, &str)]) -> Vec<Record> {
+        let mut from = None;
+        rs.iter()
+            .map(|&(boot, at, mono, to)| Record {
+                stamp: stamp(boot, at, mono),
+                from: from.replace(to.to_string()),
+                to: to.into(),
+                cause: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_clock_step_within_a_boot_is_undone() {
+        // Started with the clock at 1970, stepped by NTP before OFFLINE.
+        let rs = records(&[
+            ("a", 1000, 100, "ONLINE"),
+            ("a", 2_000_000_000, 600, "OFFLINE"),
+        ]);
+        let now = stamp("a", 2_000_000_100, 700);
+        assert_eq!(placed(&rs, &now), [1_999_999_500, 2_000_000_000]);
+        let st = stats(&rs, &now, 86400, true);
+        assert_eq!((st.observed, st.downtime), (600, 100));
+        let [o] = &st.outages[..] else {
+            panic!("{} outages", st.outages.len());
+        };
+        assert_eq!((o.start, o.end), (2_000_000_000, now.at));
+        assert!(o.ongoing && !o.recovered);
+        assert_eq!(o.cause, "OFFLINE");
+        // Without a daemon running, the time since is not known.
+        let st = stats(&rs, &now, 86400, false);
+        assert_eq!((st.observed, st.downtime), (500, 0));
+        assert!(!st.outages[0].ongoing);
+        assert_eq!(st.outages[0].duration(), 0);
+    }
+
+    #[test]
+    fn a_reboot_ends_what_is_known() {
+        let rs = records(&[
+            ("a", 100, 10, "ONLINE"),
+            ("a", 200, 110, "OFFLINE"),
+            ("b", 500, 5, "ONLINE"),
+        ]);
+        let now = stamp("b", 600, 105);
+        // The past boot's last record keeps its own wall time.
+        assert_eq!(placed(&rs, &now), [100, 200, 500]);
+        let st = stats(&rs, &now, 86400, true);
+        assert_eq!((st.observed, st.downtime), (200, 0));
+        let [o] = &st.outages[..] else {
+            panic!("{} outages", st.outages.len());
+        };
+        assert_eq!((o.start, o.end), (200, 200));
+        assert!(!o.recovered && !o.ongoing);
+        assert_eq!(st.availability(), Some(100.0));
+    }
+
+    #[test]
+    fn nothing_is_known_after_stopped() {
+        let rs = records(&[
+            ("a", 100, 10, "ONLINE"),
+            ("a", 150, 60, "OFFLINE"),
+            ("a", 200, 110, STOPPED),
+            ("a", 300, 210, "ONLINE"),
+        ]);
+        let now = stamp("a", 400, 310);
+        let st = stats(&rs, &now, 86400, true);
+        assert_eq!((st.observed, st.downtime), (200, 50));
+        let [o] = &st.outages[..] else {
+            panic!("{} outages", st.outages.len());
+        };
+        assert_eq!((o.start, o.end), (150, 200));
+        assert!(!o.recovered);
+        assert_eq!(st.availability(), Some(75.0));
+        // Only what overlaps the window.
+        assert!(stats(&rs, &now, 150, true).outages.is_empty());
+    }
+
+    #[test]
+    fn recovering_takes_the_cause_found_next() {
+        let mut rs = records(&[
+            ("a", 100, 10, "ONLINE"),
+            ("a", 200, 110, "RECOVERING"),
+            ("a", 210, 120, "OFFLINE"),
+            ("a", 300, 210, "ONLINE"),
+        ]);
+        rs[2].cause = Some("UPSTREAM_UNREACHABLE".into());
+        let now = stamp("a", 400, 310);
+        let st = stats(&rs, &now, 86400, true);
+        assert_eq!((st.observed, st.downtime), (300, 100));
+        let [o] = &st.outages[..] else {
+            panic!("{} outages", st.outages.len());
+        };
+        assert_eq!((o.start, o.end, o.duration()), (200, 300, 100));
+        assert!(o.recovered && !o.ongoing);
+        assert_eq!(o.cause, "UPSTREAM_UNREACHABLE");
+    }
+}
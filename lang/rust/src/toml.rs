@@ -0,0 +1,490 @@
+// Minimal TOML reader: tables, arrays of tables, inline tables, strings,
+// integers, floats, booleans and (multi-line) arrays. Enough for config files.
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub type Table = BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Int(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+/// Renders `s` as a TOML basic string.
+pub fn quote(s: &str) -> String {
+    let mut o = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => o.push_str("\\\""),
+            '\\' => o.push_str("\\\\"),
+            '\n' => o.push_str("\\n"),
+            '\t' => o.push_str("\\t"),
+            '\r' => o.push_str("\\r"),
+            c if (c as u32) < 0x20 => o.push_str(&format!("\\u{:04x}", c as u32)),
+            c => o.push(c),
+        }
+    }
+    o.push('"');
+    o
+}
+
+pub fn quote_list<T: ToString>(v: &[T]) -> String {
+    let items: Vec<String> = v.iter().map(|s| quote(&s.to_string())).collect();
+    format!("[{}]", items.join(", "))
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+pub fn parse(src: &str) -> Result<Table, ParseError> {
+    let mut p = Parser {
+        s: src.as_bytes(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Table::new();
+    let mut cur: Vec<String> = Vec::new();
+    // Headers seen; those under an array of tables only until its next one.
+    let mut defined: Vec<Vec<String>> = Vec::new();
+    loop {
+        p.skip_ws_nl();
+        if p.eof() {
+            return Ok(root);
+        }
+        if p.peek() == Some(b'[') {
+            let aot = p.s.get(p.pos + 1) == Some(&b'[');
+            p.pos += if aot { 2 } else { 1 };
+            let path = p.key_path()?;
+            p.expect(b']')?;
+            if aot {
+                p.expect(b']')?;
+            }
+            p.end_line()?;
+            if aot {
+                defined.retain(|d| !d.starts_with(&path));
+            } else if defined.contains(&path) {
+                return Err(p.err(&format!("table `{}` defined twice", path.join("."))));
+            } else {
+                defined.push(path.clone());
+            }
+            open(&mut root, &path, aot).map_err(|m| p.err(&m))?;
+            cur = path;
+            continue;
+        }
+        let path = p.key_path()?;
+        p.skip_ws();
+        p.expect(b'=')?;
+        p.skip_ws();
+        let v = p.value()?;
+        p.end_line()?;
+        let t = current(&mut root, &cur);
+        insert(t, &path, v).map_err(|m| p.err(&m))?;
+    }
+}
+
+fn open(root: &mut Table, path: &[String], aot: bool) -> Result<(), String> {
+    let (last, head) = path.split_last().unwrap();
+    let t = walk(root, head)?;
+    if aot {
+        match t
+            .entry(last.clone())
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            Value::Array(a) => a.push(Value::Table(Table::new())),
+            _ => return Err(format!("`{}` is not an array of tables", last)),
+        }
+    } else {
+        match t
+            .entry(last.clone())
+            .or_insert_with(|| Value::Table(Table::new()))
+        {
+            Value::Table(_) => {}
+            _ => return Err(format!("`{}` is not a table", last)),
+        }
+    }
+    Ok(())
+}
+
+fn walk<'t>(mut t: &'t mut Table, path: &[String]) -> Result<&'t mut Table, String> {
+    for k in path {
+        let v = t
+            .entry(k.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        t = match v {
+            Value::Table(t) => t,
+            Value::Array(a) => match a.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => return Err(format!("`{}` is not a table", k)),
+            },
+            _ => return Err(format!("`{}` is not a table", k)),
+        };
+    }
+    Ok(t)
+}
+
+fn current<'t>(root: &'t mut Table, path: &[String]) -> &'t mut Table {
+    walk(root, path).expect("header already validated")
+}
+
+fn insert(t: &mut Table, path: &[String], v: Value) -> Result<(), String> {
+    let (last, head) = path.split_last().unwrap();
+    let t = walk(t, head)?;
+    if t.contains_key(last) {
+        return Err(format!("duplicate key `{}`", last));
+    }
+    t.insert(last.clone(), v);
+    Ok(())
+}
+
+impl Parser<'_> {
+    fn eof(&self) -> bool {
+        self.pos >= self.s.len()
+    }
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+    fn err(&self, m: &str) -> ParseError {
+        ParseError {
+            line: self.line,
+            msg: m.to_string(),
+        }
+    }
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+    fn skip_comment(&mut self) {
+        if self.peek() == Some(b'#') {
+            while !matches!(self.peek(), None | Some(b'\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+    fn skip_ws_nl(&mut self) {
+        loop {
+            self.skip_ws();
+            self.skip_comment();
+            match self.peek() {
+                Some(b'\n') => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                Some(b'\r') => self.pos += 1,
+                _ => return,
+            }
+        }
+    }
+    fn end_line(&mut self) -> Result<(), ParseError> {
+        self.skip_ws();
+        self.skip_comment();
+        match self.peek() {
+            None | Some(b'\n' | b'\r') => Ok(()),
+            Some(c) => Err(self.err(&format!("unexpected `{}` after value", c as char))),
+        }
+    }
+    fn expect(&mut self, c: u8) -> Result<(), ParseError> {
+        self.skip_ws();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.err(&format!("expected `{}`", c as char)))
+        }
+    }
+    fn key_path(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut path = vec![self.key()?];
+        loop {
+            self.skip_ws();
+            if self.peek() != Some(b'.') {
+                return Ok(path);
+            }
+            self.pos += 1;
+            path.push(self.key()?);
+        }
+    }
+    fn key(&mut self) -> Result<String, ParseError> {
+        self.skip_ws();
+        match self.peek() {
+            Some(b'"') => self.basic_string(),
+            Some(b'\'') => self.literal_string(),
+            _ => {
+                let st = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == b'_' || c == b'-')
+                {
+                    self.pos += 1;
+                }
+                if st == self.pos {
+                    return Err(self.err("expected a key"));
+                }
+                Ok(String::from_utf8_lossy(&self.s[st..self.pos]).into_owned())
+            }
+        }
+    }
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some(b'"') => self.basic_string().map(Value::Str),
+            Some(b'\'') => self.literal_string().map(Value::Str),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.inline_table(),
+            Some(b't') if self.s[self.pos..].starts_with(b"true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            }
+            Some(b'f') if self.s[self.pos..].starts_with(b"false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            }
+            Some(c) if c.is_ascii_digit() || c == b'-' || c == b'+' => self.number(),
+            _ => Err(self.err("expected a value")),
+        }
+    }
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let st = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || matches!(c, b'-' | b'+' | b'.' | b'_'))
+        {
+            self.pos += 1;
+        }
+        let raw: String = String::from_utf8_lossy(&self.s[st..self.pos]).replace('_', "");
+        if let Ok(i) = raw.parse::<i64>() {
+            return Ok(Value::Int(i));
+        }
+        if let Some(h) = raw.strip_prefix("0x") {
+            if let Ok(i) = i64::from_str_radix(h, 16) {
+                return Ok(Value::Int(i));
+            }
+        }
+        raw.parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| self.err(&format!("invalid number `{}`", raw)))
+    }
+    fn basic_string(&mut self) -> Result<String, ParseError> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(b'\n') => return Err(self.err("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(String::from_utf8_lossy(&out).into_owned());
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = self.peek().ok_or_else(|| self.err("unterminated string"))?;
+                    self.pos += 1;
+                    match c {
+                        b'n' => out.push(b'\n'),
+                        b't' => out.push(b'\t'),
+                        b'r' => out.push(b'\r'),
+                        b'"' => out.push(b'"'),
+                        b'\\' => out.push(b'\\'),
+                        b'u' => {
+                            let hex = self
+                                .s
+                                .get(self.pos..self.pos + 4)
+                                .ok_or_else(|| self.err("bad \\u escape"))?;
+                            let cp = u32::from_str_radix(&String::from_utf8_lossy(hex), 16)
+                                .map_err(|_| self.err("bad \\u escape"))?;
+                            let ch =
+                                char::from_u32(cp).ok_or_else(|| self.err("bad \\u escape"))?;
+                            out.extend_from_slice(ch.to_string().as_bytes());
+                            self.pos += 4;
+                        }
+                        _ => return Err(self.err(&format!("unknown escape `\\{}`", c as char))),
+                    }
+                }
+                Some(c) => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+    fn literal_string(&mut self) -> Result<String, ParseError> {
+        self.pos += 1;
+        let st = self.pos;
+        while !matches!(self.peek(), None | Some(b'\'' | b'\n')) {
+            self.pos += 1;
+        }
+        if self.peek() != Some(b'\'') {
+            return Err(self.err("unterminated string"));
+        }
+        let s = String::from_utf8_lossy(&self.s[st..self.pos]).into_owned();
+        self.pos += 1;
+        Ok(s)
+    }
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_ws_nl();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_ws_nl();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {}
+                _ => return Err(self.err("expected `,` or `]` in array")),
+            }
+        }
+    }
+    fn inline_table(&mut self) -> Result<Value, ParseError> {
+        self.pos += 1;
+        let mut t = Table::new();
+        loop {
+            self.skip_ws();
+            if self.peek() == Some(b'}') {
+                self.pos += 1;
+                return Ok(Value::Table(t));
+            }
+            let path = self.key_path()?;
+            self.expect(b'=')?;
+            self.skip_ws();
+            let v = self.value()?;
+            insert(&mut t, &path, v).map_err(|m| self.err(&m))?;
+            self.skip_ws();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {}
+                _ => return Err(self.err("expected `,` or `}` in inline table")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn err(src: &str) -> String {
+        parse(src).unwrap_err().to_string()
+    }
+
+    fn table(v: &Value) -> &Table {
+        match v {
+            Value::Table(t) => t,
+            v => panic!("{} is no table", v.type_name()),
+        }
+    }
+
+    #[test]
+    fn values_and_tables() {
+        let t = parse(
+            r#"
+top = 1
+[a]
+s = "x\ty\u00e9\"\\"   # comment
+lit = 'C:\dir'
+f = 0.5
+n = -3_000
+hex = 0x1F
+on = true
+list = [
+  "one", # first
+  "two",
+]
+[a.b]
+c.d = false
+"#,
+        )
+        .unwrap();
+        assert_eq!(t["top"], Value::Int(1));
+        let a = table(&t["a"]);
+        assert_eq!(a["s"], Value::Str("x\tyé\"\\".into()));
+        assert_eq!(a["lit"], Value::Str("C:\\dir".into()));
+        assert_eq!(a["f"], Value::Float(0.5));
+        assert_eq!((&a["n"], &a["hex"]), (&Value::Int(-3000), &Value::Int(31)));
+        assert_eq!(a["on"], Value::Bool(true));
+        let two = ["one", "two"].map(|s| Value::Str(s.into())).to_vec();
+        assert_eq!(a["list"], Value::Array(two));
+        assert_eq!(table(&table(&a["b"])["c"])["d"], Value::Bool(false));
+    }
+
+    #[test]
+    fn arrays_of_tables_and_inline_tables() {
+        let t = parse(
+            r#"
+[[probes]]
+target = "192.0.2.1"
+[probes.opts]
+weight = 2
+[[probes]]
+target = "192.0.2.2"
+[probes.opts]
+weight = 1
+[x]
+point = { x = 1, y.z = "deep" }
+"#,
+        )
+        .unwrap();
+        let Value::Array(ps) = &t["probes"] else {
+            panic!("{} is no array", t["probes"].type_name());
+        };
+        let weights: Vec<&Value> = ps
+            .iter()
+            .map(|p| &table(&table(p)["opts"])["weight"])
+            .collect();
+        assert_eq!(weights, [&Value::Int(2), &Value::Int(1)]);
+        let point = table(&table(&t["x"])["point"]);
+        assert_eq!(point["x"], Value::Int(1));
+        assert_eq!(table(&point["y"])["z"], Value::Str("deep".into()));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(err("a = 1\na = 2\n"), "line 2: duplicate key `a`");
+        assert_eq!(
+            err("[a]\nx = 1\n\n[b]\n[a]\n"),
+            "line 5: table `a` defined twice"
+        );
+        assert_eq!(
+            err("[a.b]\n[a]\n[a.b]\n"),
+            "line 3: table `a.b` defined twice"
+        );
+        assert_eq!(err("p = { q = 1, q = 2 }\n"), "line 1: duplicate key `q`");
+        assert_eq!(err("\n\ns = \"open\n"), "line 3: unterminated string");
+        assert_eq!(err("s = \"\\x\"\n"), "line 1: unknown escape `\\x`");
+        assert_eq!(err("a = 1 2\n"), "line 1: unexpected `2` after value");
+        assert_eq!(err("a = [1 2]\n"), "line 1: expected `,` or `]` in array");
+        assert_eq!(err("a = 1\n[a]\n"), "line 2: `a` is not a table");
+        assert_eq!(err("[a]\n[[a]]\n"), "line 2: `a` is not an array of tables");
+        // [a.b] implies [a], which may still come after.
+        assert!(parse("[a.b]\n[a]\n").is_ok());
+    }
+}
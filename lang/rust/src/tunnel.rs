@@ -0,0 +1,56 @@
+// Tunnels the probes go through (WireGuard, tun/tap VPNs, IP tunnels), and
+// the uplink beneath them that carries their packets: probing both tells a
+// VPN endpoint that is down from an ISP that is.
+use crate::config::Config;
+use crate::{glob, iface, net, netlink};
+
+/// IFLA_INFO_KIND of the links taken for tunnels.
+const KINDS: [&str; 10] = [
+    "wireguard",
+    "tun",
+    "ipip",
+    "sit",
+    "gre",
+    "gretap",
+    "ip6tnl",
+    "ip6gre",
+    "vti",
+    "vti6",
+];
+
+/// `tunnel_interfaces`, or else the links of a tunnel kind or named `wg*`,
+/// `tun*` or `tap*`; those that exist now, sorted.
+pub fn detect(cfg: &Config) -> Vec<String> {
+    let names = ["wg*", "tun*", "tap*"].map(String::from);
+    let Ok(links) = netlink::list_links() else {
+        return Vec::new();
+    };
+    let mut v: Vec<String> = links
+        .into_iter()
+        .filter(|l| match cfg.tunnel_interfaces.is_empty() {
+            false => glob::any(&cfg.tunnel_interfaces, &l.name),
+            true => {
+                l.kind.as_deref().is_some_and(|k| KINDS.contains(&k)) || glob::any(&names, &l.name)
+            }
+        })
+        .map(|l| l.name)
+        .collect();
+    v.sort();
+    v
+}
+
+/// The interface beneath `tunnels`: the first default route's not through
+/// one of them, else the first managed interface with carrier.
+pub fn uplink(cfg: &Config, tunnels: &[String]) -> Option<String> {
+    let outside = |i: &String| !tunnels.contains(i);
+    net::default_routes()
+        .into_iter()
+        .map(|r| r.iface)
+        .find(outside)
+        .or_else(|| {
+            iface::discover(cfg)
+                .into_iter()
+                .filter(outside)
+                .find(|i| net::has_carrier(i))
+        })
+}
@@ -0,0 +1,98 @@
+// Uplink failover: the `priority` interfaces are brought up one at a time
+// until one gets ONLINE; those after it are kept on standby.
+use crate::config::Config;
+use crate::exec::Executor;
+use crate::iface::{self, Dhcp, IfaceReport};
+use crate::latency::LatencyWindow;
+use crate::probe::{self, Summary};
+use crate::{log, net};
+use std::time::Instant;
+
+/// The uplinks that exist, in priority order, and the other managed
+/// interfaces.
+pub fn split(cfg: &Config, ifaces: &[String]) -> (Vec<String>, Vec<String>) {
+    let uplinks: Vec<String> = cfg
+        .priority
+        .iter()
+        .filter(|i| net::exists(i))
+        .cloned()
+        .collect();
+    let rest = ifaces
+        .iter()
+        .filter(|i| !uplinks.contains(i))
+        .cloned()
+        .collect();
+    (uplinks, rest)
+}
+
+/// Probes through `uplink` alone.
+pub fn check(
+    cfg: &Config,
+    uplink: &str,
+    win: &mut LatencyWindow,
+    deadline: Option<Instant>,
+) -> Summary {
+    let mut c = cfg.clone();
+    c.interfaces = vec![uplink.to_string()];
+    c.per_interface = true;
+    c.require_all = false;
+    probe::check(&c, win, deadline)
+}
+
+/// How [`select`] went.
+pub struct Selection {
+    /// The uplink that got ONLINE.
+    pub active: Option<String>,
+    /// Bring-up and probes of each uplink tried, in order.
+    pub tried: Vec<(Vec<IfaceReport>, Summary)>,
+    /// Put on standby after `active`.
+    pub standby: Vec<String>,
+}
+
+/// Brings up `uplinks` in order and probes through each until one is
+/// ONLINE; one skipped as already configured gets a lease after all before
+/// the next is tried. The remaining ones go on standby.
+pub fn select(
+    cfg: &Config,
+    uplinks: &[String],
+    dhcp: Dhcp,
+    deadline: Option<Instant>,
+    ex: &dyn Executor,
+    win: &mut LatencyWindow,
+) -> Selection {
+    let mut sel = Selection {
+        active: None,
+        tried: Vec::new(),
+        standby: Vec::new(),
+    };
+    for (n, u) in uplinks.iter().enumerate() {
+        let rs = iface::bring_up(cfg, std::slice::from_ref(u), dhcp, deadline, ex);
+        let skipped = rs.iter().any(|r| r.configured && r.dhcp.is_none());
+        let s = check(cfg, u, win, deadline);
+        let online = s.online();
+        sel.tried.push((rs, s));
+        if !online && skipped && !cfg.skip_dhcp {
+            let rs = iface::bring_up(cfg, std::slice::from_ref(u), Dhcp::Always, deadline, ex);
+            let s = check(cfg, u, win, deadline);
+            sel.tried.push((rs, s));
+        }
+        if sel.tried.last().is_some_and(|(_, s)| s.online()) {
+            sel.active = Some(u.clone());
+            sel.standby = uplinks[n + 1..].to_vec();
+            standby(cfg, &sel.standby, ex);
+            break;
+        }
+        log::warn!(iface = u; "uplink {} not ONLINE", u);
+    }
+    sel
+}
+
+/// Releases the leases of `ifaces` and, with `standby_down`, sets them down.
+pub fn standby(cfg: &Config, ifaces: &[String], ex: &dyn Executor) {
+    for i in ifaces {
+        log::info!(iface = i; "{}: standby", i);
+    }
+    for (i, e) in iface::tear_down(cfg, ifaces, cfg.standby_down, ex) {
+        log::warn!(iface = &i; "{}: {}", i, e);
+    }
+}
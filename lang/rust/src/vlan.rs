@@ -0,0 +1,100 @@
+// VLAN sub-interfaces from `[[vlans]]`: created on startup where missing,
+// so discovery, bring-up and DHCP treat them like any other link, and
+// deleted again on teardown when asked to.
+use crate::config::Config;
+use crate::exec::Executor;
+use crate::iface;
+use crate::{log, net, netlink};
+
+/// The longest name the kernel takes (IFNAMSIZ less the NUL).
+const IFNAMSIZ: usize = 15;
+
+/// `<parent>.<id>`, as `ip` and vconfig name them.
+pub fn name(parent: &str, id: u16) -> String {
+    format!("{}.{}", parent, id)
+}
+
+/// Makes sure VLAN `id` exists on `parent`, creating it if it does not,
+/// and that `parent` is up so the VLAN can get carrier. Returns the
+/// sub-interface's name.
+pub fn ensure_vlan(ex: &dyn Executor, parent: &str, id: u16) -> Result<String, String> {
+    let vlan = name(parent, id);
+    if vlan.len() > IFNAMSIZ {
+        return Err(format!("{}: name longer than {} bytes", vlan, IFNAMSIZ));
+    }
+    let links = netlink::list_links().map_err(|e| format!("listing links: {}", e))?;
+    let Some(p) = links.iter().find(|l| l.name == parent) else {
+        return Err(format!("{}: parent {} does not exist", vlan, parent));
+    };
+    match links.iter().find(|l| l.name == vlan) {
+        Some(l) if l.kind.as_deref() != Some("vlan") => {
+            return Err(format!("{}: exists but is not a VLAN", vlan));
+        }
+        Some(l) if l.parent != Some(p.index) => {
+            return Err(format!("{}: exists but not on {}", vlan, parent));
+        }
+        Some(_) => log::debug!(iface = &vlan; "{}: already exists", vlan),
+        None => {
+            let id_s = id.to_string();
+            iface::link_op(
+                ex,
+                parent,
+                "adding VLAN",
+                |i| netlink::add_vlan(i, &vlan, id),
+                &[
+                    "link", "add", "link", parent, "name", &vlan, "type", "vlan", "id", &id_s,
+                ],
+            )
+            .map_err(|e| format!("{}: {}", vlan, e))?;
+            log::info!(iface = &vlan; "{}: created, VLAN {} on {}", vlan, id, parent);
+        }
+    }
+    if !net::admin_up(parent) {
+        iface::link_op(
+            ex,
+            parent,
+            "setting parent link up",
+            netlink::set_link_up,
+            &["link", "set", parent, "up"],
+        )
+        .map_err(|e| format!("{}: {}", parent, e))?;
+    }
+    Ok(vlan)
+}
+
+/// [`ensure_vlan`] for each `[[vlans]]` entry, logging what fails. Returns
+/// whether any was created, so discovery is worth running again.
+pub fn setup(cfg: &Config, ex: &dyn Executor) -> bool {
+    let mut created = false;
+    for v in &cfg.vlans {
+        let existed = netlink::index(&name(&v.parent, v.id)).is_ok();
+        match ensure_vlan(ex, &v.parent, v.id) {
+            Ok(_) => created |= !existed,
+            Err(e) => log::error!("{}", e),
+        }
+    }
+    created
+}
+
+/// Deletes the `[[vlans]]` with `delete_on_exit`. Returns what failed, per
+/// interface, as [`iface::tear_down`] does.
+pub fn remove(cfg: &Config, ex: &dyn Executor) -> Vec<(String, String)> {
+    let mut failed = Vec::new();
+    for v in cfg.vlans.iter().filter(|v| v.delete_on_exit) {
+        let vlan = name(&v.parent, v.id);
+        if netlink::index(&vlan).is_err() && ex.live() {
+            continue;
+        }
+        match iface::link_op(
+            ex,
+            &vlan,
+            "deleting VLAN",
+            netlink::delete_link,
+            &["link", "delete", &vlan],
+        ) {
+            Ok(()) => log::info!(iface = &vlan; "{}: deleted", vlan),
+            Err(e) => failed.push((vlan, e)),
+        }
+    }
+    failed
+}
@@ -0,0 +1,172 @@
+// Wi-Fi association through wpa_supplicant/wpa_cli, and what `iw` says
+// about the current link.
+use crate::exec::{self, Executor};
+use crate::log;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// Whether an interface is associated, and to what.
+pub struct Assoc {
+    pub associated: bool,
+    pub ssid: Option<String>,
+    pub signal_dbm: Option<i32>,
+}
+
+/// `wpa_cli status` output; `None` when no supplicant answers.
+fn wpa_status(iface: &str) -> Option<String> {
+    let out = exec::sys()
+        .exec(
+            Command::new("wpa_cli").args(["-i", iface, "status"]),
+            exec::limits().wireless,
+        )
+        .map_err(|e| log::debug!("wpa_cli -i {} status: {}", iface, e))
+        .ok()
+        .filter(|o| o.success())?;
+    Some(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+fn field<'a>(status: &'a str, key: &str) -> Option<&'a str> {
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// `wpa_state` from `wpa_cli status`.
+pub fn wpa_state(iface: &str) -> Option<String> {
+    field(&wpa_status(iface)?, "wpa_state").map(String::from)
+}
+
+fn wpa_cli(ex: &dyn Executor, iface: &str, cmd: &str) -> Result<(), String> {
+    ex.run(
+        &exec::argv(&["wpa_cli", "-i", iface, cmd]),
+        exec::limits().wireless,
+    )
+}
+
+fn start_supplicant(ex: &dyn Executor, iface: &str, conf: &Path) -> Result<(), String> {
+    let conf = conf.to_string_lossy();
+    ex.run(
+        &exec::argv(&["wpa_supplicant", "-B", "-i", iface, "-c", &conf]),
+        Duration::from_secs(10),
+    )
+}
+
+/// Waits up to `timeout` for `COMPLETED`; not at all in a dry run.
+fn wait(ex: &dyn Executor, iface: &str, timeout: Duration) -> Result<(), String> {
+    if !ex.live() {
+        return Ok(());
+    }
+    let end = Instant::now() + timeout;
+    loop {
+        let state = wpa_state(iface);
+        if state.as_deref() == Some("COMPLETED") {
+            return Ok(());
+        }
+        if Instant::now() >= end {
+            return Err(format!(
+                "not associated after {}s (wpa_state {})",
+                timeout.as_secs(),
+                state.as_deref().unwrap_or("unknown")
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+}
+
+/// Gets `iface` associated: starts wpa_supplicant with `conf` unless one is
+/// already running there, otherwise (with `reconnect`) asks it to reconnect
+/// and, failing that, restarts it with `conf`. Waits up to `timeout` each
+/// time.
+pub fn associate(
+    ex: &dyn Executor,
+    iface: &str,
+    conf: Option<&Path>,
+    reconnect: bool,
+    timeout: Duration,
+) -> Result<(), String> {
+    match (wpa_state(iface).as_deref(), conf) {
+        (Some("COMPLETED"), _) => return Ok(()),
+        (None, Some(c)) => {
+            return start_supplicant(ex, iface, c).and_then(|()| wait(ex, iface, timeout))
+        }
+        (None, None) => return Err("no wpa_supplicant running".into()),
+        (Some(_), _) if reconnect => wpa_cli(ex, iface, "reconnect")?,
+        (Some(_), _) => {}
+    }
+    let err = match wait(ex, iface, timeout) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    match conf {
+        Some(c) if reconnect => {
+            wpa_cli(ex, iface, "terminate")?;
+            std::thread::sleep(Duration::from_millis(500));
+            start_supplicant(ex, iface, c)?;
+            wait(ex, iface, timeout)
+                .map_err(|e| format!("{}, also after restarting wpa_supplicant", e))
+        }
+        _ => Err(err),
+    }
+}
+
+/// Association per `iw dev <if> link`, or else wpa_supplicant; `None` when
+/// neither can tell.
+pub fn association(iface: &str) -> Option<Assoc> {
+    let iw = exec::sys()
+        .exec(
+            Command::new("iw").args(["dev", iface, "link"]),
+            exec::limits().wireless,
+        )
+        .map_err(|e| log::debug!("iw dev {} link: {}", iface, e))
+        .ok()
+        .filter(|o| o.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).into_owned());
+    match iw {
+        // "Connected to 00:11:22:33:44:55 (on wlan0)\n\tSSID: home\n\t...
+        // \tsignal: -40 dBm"
+        Some(s) if s.starts_with("Connected") => {
+            let line = |k: &str| s.lines().find_map(|l| l.trim().strip_prefix(k));
+            Some(Assoc {
+                associated: true,
+                ssid: line("SSID:").map(|v| v.trim().to_string()),
+                signal_dbm: line("signal:")
+                    .and_then(|v| v.split_whitespace().next()?.parse().ok())
+                    .or_else(|| proc_signal(iface)),
+            })
+        }
+        Some(s) if s.starts_with("Not connected") => Some(Assoc {
+            associated: false,
+            ssid: None,
+            signal_dbm: None,
+        }),
+        _ => {
+            let st = wpa_status(iface)?;
+            let associated = field(&st, "wpa_state") == Some("COMPLETED");
+            Some(Assoc {
+                associated,
+                ssid: field(&st, "ssid").filter(|_| associated).map(String::from),
+                signal_dbm: proc_signal(iface).filter(|_| associated),
+            })
+        }
+    }
+}
+
+/// Signal level in dBm from `/proc/net/wireless`.
+fn proc_signal(iface: &str) -> Option<i32> {
+    let s = fs::read_to_string("/proc/thread-self/net/wireless").ok()?;
+    // "wlan0: 0000   70.  -40.  -256  ..." : status, link, level, noise
+    s.lines().find_map(|l| {
+        let (name, rest) = l.split_once(':')?;
+        if name.trim() != iface {
+            return None;
+        }
+        let level = rest.split_whitespace().nth(2)?;
+        level
+            .trim_end_matches('.')
+            .parse::<f64>()
+            .ok()
+            .map(|l| l as i32)
+    })
+}
@@ -0,0 +1,1255 @@
+// cw-netup run against veth links in throwaway network namespaces, with no
+// real hardware involved. Built only with `--features integration-tests`;
+// needs root (CAP_SYS_ADMIN for the namespaces, CAP_NET_ADMIN for the links)
+// and iproute2's `ip`:
+//
+//     sudo -E cargo test --features integration-tests --test integration
+//
+// The scenarios also script some commands through `$CW_EXEC_SCRIPT`.
+// Linux only, like the namespaces.
+#![cfg(target_os = "linux")]
+
+mod sandbox;
+mod scenarios;
+
+use cw_netup::error::Status;
+use sandbox::{signal, wait_for, Sandbox};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Stdio;
+use std::time::Duration;
+
+#[test]
+fn dhcp_failure_falls_back_to_static() {
+    let mut sb = Sandbox::new("fallback");
+    sb.link("cw0", "10.200.0.1/24");
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["cw0"]
+
+[probe]
+targets = ["10.200.0.1"]
+timeout = 1
+
+[dhcp]
+command = "false"
+
+[fallback_static.cw0]
+address = "10.200.0.2"
+prefix_len = 24
+gateway = "10.200.0.1"
+"#,
+        "",
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["--output", "json"])
+        .output()
+        .unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    let report = out.lines().last().unwrap_or_default();
+    assert!(
+        o.status.success(),
+        "{}: {}",
+        o.status,
+        String::from_utf8_lossy(&o.stderr)
+    );
+    assert!(report.contains(r#""status":"ONLINE""#), "{}", report);
+    assert!(report.contains(r#""static_fallback":true"#), "{}", report);
+    // The report alone: events are for --output ndjson.
+    assert_eq!(out.lines().count(), 1, "{}", out);
+    assert!(sb
+        .ip(&["-4", "addr", "show", "dev", "cw0"])
+        .contains("10.200.0.2/24"));
+    assert!(sb
+        .ip(&["route", "show", "default"])
+        .contains("via 10.200.0.1 dev cw0"));
+}
+
+#[test]
+fn static_fallback_is_audited_and_rolled_back() {
+    let mut sb = Sandbox::new("audit");
+    sb.link("cw0", "10.200.21.1/24");
+    let audit = sb.path("audit.jsonl");
+    let cfg = sb.path("config.toml");
+    fs::write(
+        &cfg,
+        format!(
+            r#"
+[interfaces]
+manage = ["cw0"]
+
+[probe]
+targets = ["10.200.21.1"]
+timeout = 1
+
+[dhcp]
+command = "false"
+
+[fallback_static.cw0]
+address = "10.200.21.2"
+prefix_len = 24
+gateway = "10.200.21.1"
+
+[daemon]
+state_file = ""
+pid_file = ""
+history_file = ""
+audit_file = "{}"
+"#,
+            audit.display()
+        ),
+    )
+    .unwrap();
+    let o = sb.cw_netup(&cfg).output().unwrap();
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    let trail = fs::read_to_string(&audit).unwrap();
+    for want in [
+        r#""action":"ip link set cw0 up""#,
+        r#""kind":"command","action":"false"#,
+        r#""undo":{"op":"del_address","iface":"cw0","address":"10.200.21.2/24""#,
+        r#""undo":{"op":"del_route","iface":"cw0","gateway":"10.200.21.1""#,
+    ] {
+        assert!(trail.contains(want), "{} not in {}", want, trail);
+    }
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["audit", "--last", "2"])
+        .output()
+        .unwrap();
+    let table = String::from_utf8_lossy(&o.stdout);
+    assert!(o.status.success(), "{}", table);
+    assert_eq!(table.lines().count(), 3, "{}", table);
+    assert!(
+        table.contains("undo: ip route del default via 10.200.21.1 dev cw0"),
+        "{}",
+        table
+    );
+
+    // The route went in last, so it comes out first, then the address.
+    let rollback = || {
+        sb.cw_netup(&cfg)
+            .args(["audit", "--rollback-last"])
+            .output()
+            .unwrap()
+    };
+    let o = rollback();
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    assert!(!sb.ip(&["route", "show", "default"]).contains("dev cw0"));
+    assert!(sb
+        .ip(&["-4", "addr", "show", "dev", "cw0"])
+        .contains("10.200.21.2/24"));
+    let o = rollback();
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    assert!(!sb
+        .ip(&["-4", "addr", "show", "dev", "cw0"])
+        .contains("10.200.21.2/24"));
+    assert_eq!(rollback().status.code(), Some(1));
+    let trail = fs::read_to_string(&audit).unwrap();
+    assert_eq!(trail.matches(r#""undoes":""#).count(), 2, "{}", trail);
+    let o = sb.cw_netup(&cfg).args(["audit"]).output().unwrap();
+    let table = String::from_utf8_lossy(&o.stdout);
+    assert_eq!(table.matches("; taken back").count(), 2, "{}", table);
+    // With --output json the object alone, and with -q nothing.
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["audit", "--output", "json"])
+        .output()
+        .unwrap();
+    let out = String::from_utf8(o.stdout).unwrap();
+    assert_eq!(out.lines().count(), 1, "{}", out);
+    assert!(out.starts_with("{\"entries\":["), "{}", out);
+    let o = sb.cw_netup(&cfg).args(["audit", "-q"]).output().unwrap();
+    assert!(o.status.success());
+    assert!(
+        o.stdout.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&o.stdout)
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["audit", "--rollback-last", "--output", "json"])
+        .output()
+        .unwrap();
+    assert_eq!(o.status.code(), Some(1));
+    assert!(
+        o.stdout.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&o.stdout)
+    );
+}
+
+#[test]
+fn history_keeps_to_the_output_format() {
+    let sb = Sandbox::new("history");
+    let history = sb.path("history.jsonl");
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let rec = |at: u64, from: Option<&str>, to: &str| {
+        let from = from.map_or("null".into(), |f| format!("\"{}\"", f));
+        format!(
+            r#"{{"at":{},"mono":{},"boot":"b","from":{},"to":"{}","cause":null}}"#,
+            at,
+            at - (now - 1000),
+            from,
+            to
+        )
+    };
+    fs::write(
+        &history,
+        [
+            rec(now - 900, None, "ONLINE"),
+            rec(now - 600, Some("ONLINE"), "OFFLINE"),
+            rec(now - 300, Some("OFFLINE"), "ONLINE"),
+            rec(now - 200, Some("ONLINE"), "STOPPED"),
+        ]
+        .join("\n")
+            + "\n",
+    )
+    .unwrap();
+    let cfg = sb.path("config.toml");
+    fs::write(
+        &cfg,
+        format!(
+            "[daemon]\nstate_file = \"\"\npid_file = \"\"\naudit_file = \"\"\nhistory_file = \"{}\"\n",
+            history.display()
+        ),
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        let o = sb
+            .cw_netup(&cfg)
+            .arg("history")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+        String::from_utf8(o.stdout).unwrap()
+    };
+    let table = run(&[]);
+    assert!(table.contains("longest outage: 5m"), "{}", table);
+    let json = run(&["--output", "json"]);
+    assert_eq!(json.lines().count(), 1, "{}", json);
+    assert!(
+        json.starts_with('{') && json.contains(r#""window_secs":86400"#),
+        "{}",
+        json
+    );
+    assert_eq!(run(&["-q"]), "");
+}
+
+#[test]
+fn new_lease_address_is_reported_as_changed() {
+    let mut sb = Sandbox::new("ip-changed");
+    sb.link("cw0", "10.200.14.1/24");
+    sb.ip(&["addr", "add", "10.200.14.2/24", "dev", "cw0"]);
+    sb.ip(&["link", "set", "cw0", "up"]);
+    // A server that hands out another address this time.
+    let client = sb.path("dhcp.sh");
+    fs::write(
+        &client,
+        "#!/bin/sh\nip addr flush dev \"$1\"\nip addr add 10.200.14.3/24 dev \"$1\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&client, fs::Permissions::from_mode(0o755)).unwrap();
+    let cfg = sb.config(
+        &format!(
+            "[interfaces]\nmanage = [\"cw0\"]\n\n[probe]\ntargets = [\"10.200.14.1\"]\ntimeout = 1\n\n[dhcp]\ncommand = {:?}\n",
+            client.to_str().unwrap()
+        ),
+        "",
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["--force-dhcp", "--output", "ndjson"])
+        .output()
+        .unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    assert!(
+        out.contains(
+            r#"{"event":"ip_changed","iface":"cw0","old":"10.200.14.2","new":"10.200.14.3","#
+        ),
+        "{}",
+        out
+    );
+    assert!(out.contains(r#""previous_ipv4":"10.200.14.2""#), "{}", out);
+}
+
+#[test]
+fn ping_failure_changes_state() {
+    let mut sb = Sandbox::new("state");
+    sb.link("cw0", "10.200.1.1/24");
+    sb.ip(&["addr", "add", "10.200.1.2/24", "dev", "cw0"]);
+    sb.ip(&["link", "set", "cw0", "up"]);
+    let states = sb.path("states");
+    let cfg = sb.config(
+        &format!(
+            r#"
+[interfaces]
+manage = ["cw0"]
+
+[probe]
+targets = ["10.200.1.1"]
+timeout = 1
+gateway_check = false
+
+[dhcp]
+skip = true
+
+[hooks]
+on_change = "echo $CW_STATE >> {}"
+"#,
+            states.display()
+        ),
+        "interval = 1\n",
+    );
+    let mut d = sb
+        .cw_netup(&cfg)
+        .arg("--daemon")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let seen = |st: &str| fs::read_to_string(&states).is_ok_and(|s| s.lines().any(|l| l == st));
+    let online = wait_for(Duration::from_secs(10), || seen("ONLINE"));
+    if online {
+        sb.peer_ip(&["addr", "flush", "dev", "cw0p"]);
+    }
+    let offline = online && wait_for(Duration::from_secs(15), || seen("OFFLINE"));
+    let _ = d.kill();
+    let _ = d.wait();
+    let log = fs::read_to_string(&states).unwrap_or_default();
+    assert!(online, "never ONLINE: {:?}", log);
+    assert!(offline, "never OFFLINE after the peer went away: {:?}", log);
+}
+
+#[test]
+fn status_shows_the_rolling_window() {
+    let mut sb = Sandbox::new("window");
+    sb.link("aw0", "10.200.16.1/24");
+    sb.ip(&["addr", "add", "10.200.16.2/24", "dev", "aw0"]);
+    sb.ip(&["link", "set", "aw0", "up"]);
+    let cfg = sb.path("config.toml");
+    fs::write(
+        &cfg,
+        format!(
+            r#"
+[interfaces]
+manage = ["aw0"]
+
+[probe]
+targets = ["10.200.16.1"]
+timeout = 1
+gateway_check = false
+
+[dhcp]
+skip = true
+
+[daemon]
+interval = 1
+state_file = "{}"
+pid_file = ""
+history_file = ""
+audit_file = ""
+availability_window = 60
+"#,
+            sb.path("state.json").display()
+        ),
+    )
+    .unwrap();
+    let log = sb.path("log");
+    let mut d = sb
+        .cw_netup(&cfg)
+        .args(["--daemon", "--fail-threshold", "3"])
+        .stdout(Stdio::null())
+        .stderr(fs::File::create(&log).unwrap())
+        .spawn()
+        .unwrap();
+    let status = |sb: &Sandbox| {
+        let o = sb
+            .cw_netup(&cfg)
+            .args(["--status", "--output", "json"])
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&o.stdout).into_owned()
+    };
+    let up = wait_for(Duration::from_secs(10), || {
+        status(&sb).contains(r#""success_streak":3,"#)
+    });
+    if up {
+        sb.peer_ip(&["addr", "flush", "dev", "aw0p"]);
+    }
+    let down = up
+        && wait_for(Duration::from_secs(15), || {
+            status(&sb).contains(r#""state":"OFFLINE""#)
+        });
+    let st = status(&sb);
+    let _ = d.kill();
+    let _ = d.wait();
+    let err = fs::read_to_string(&log).unwrap_or_default();
+    assert!(up, "never 3 checks up in a row: {}", st);
+    assert!(down, "never OFFLINE after the peer went away: {}", st);
+    assert!(st.contains(r#""span_secs":60,"#), "{}", st);
+    assert!(
+        err.contains("check failed (2 of 3 before OFFLINE)"),
+        "{}",
+        err
+    );
+    assert!(!err.contains("check failed (3 of 3"), "{}", err);
+}
+
+#[test]
+fn doctor_keeps_to_the_output_format() {
+    let sb = Sandbox::new("doctor");
+    let cfg = sb.path("config.toml");
+    fs::write(&cfg, "[probe\n").unwrap();
+    let doctor = |args: &[&str]| {
+        let o = sb.cw_netup(&cfg).arg("doctor").args(args).output().unwrap();
+        (String::from_utf8(o.stdout).unwrap(), o.status.code())
+    };
+    let (table, code) = doctor(&[]);
+    assert_eq!(code, Some(1), "{}", table);
+    assert!(table.starts_with("CHECK "), "{}", table);
+    let config = table.lines().nth(1).unwrap_or_default();
+    assert!(config.starts_with("config "), "{}", table);
+    assert!(config.contains(" fail "), "{}", table);
+    // With --output json the object alone, and with -q nothing.
+    let (json, _) = doctor(&["--output", "json"]);
+    assert_eq!(json.lines().count(), 1, "{}", json);
+    assert!(json.starts_with(r#"{"ok":false,"checks":["#), "{}", json);
+    assert_eq!(doctor(&["-q"]), (String::new(), Some(1)));
+}
+
+#[test]
+fn list_ifaces_keeps_to_the_output_format() {
+    let mut sb = Sandbox::new("list");
+    sb.link("lsta0", "10.200.24.1/24");
+    sb.link("lstb0", "10.200.25.1/24");
+    let cfg = sb.config("[interfaces]\nexclude = [\"lstb*\"]\n", "");
+    let list = |args: &[&str]| {
+        let o = sb
+            .cw_netup(&cfg)
+            .arg("--list-ifaces")
+            .args(args)
+            .output()
+            .unwrap();
+        (String::from_utf8(o.stdout).unwrap(), o.status.code())
+    };
+    assert_eq!(list(&[]), ("lsta0\tvirtual\n".into(), Some(0)));
+    for format in ["json", "ndjson"] {
+        let (json, _) = list(&["--output", format]);
+        assert_eq!(
+            json,
+            "{\"interfaces\":[{\"name\":\"lsta0\",\"kind\":\"virtual\"}]}\n"
+        );
+    }
+    assert_eq!(list(&["-q"]), (String::new(), Some(0)));
+}
+
+#[test]
+fn status_exits_with_the_recorded_state() {
+    let sb = Sandbox::new("status-exit");
+    let state = sb.path("state.json");
+    let cfg = sb.path("config.toml");
+    fs::write(
+        &cfg,
+        format!(
+            "[interfaces]\nmanage = []\n\n[daemon]\nstate_file = \"{}\"\npid_file = \"\"\nhistory_file = \"\"\naudit_file = \"\"\n",
+            state.display()
+        ),
+    )
+    .unwrap();
+    let status = |args: &[&str]| {
+        let o = sb.cw_netup(&cfg).arg("status").args(args).output().unwrap();
+        (String::from_utf8(o.stdout).unwrap(), o.status.code())
+    };
+    for (s, code) in [("DNS_BROKEN", 6), ("DEGRADED", 7), ("RECOVERING", 1)] {
+        fs::write(&state, format!(r#"{{"state":"{}","since":1}}"#, s)).unwrap();
+        let (text, got) = status(&[]);
+        assert_eq!(got, Some(code), "{}", text);
+        assert!(text.starts_with(s), "{}", text);
+    }
+    // With --output json the object alone, and with -q nothing.
+    let (json, _) = status(&["--output", "json"]);
+    assert_eq!(json.lines().count(), 1, "{}", json);
+    assert!(json.starts_with(r#"{"state":"RECOVERING""#), "{}", json);
+    assert_eq!(status(&["-q"]), (String::new(), Some(1)));
+}
+
+#[test]
+fn signals_reload_check_and_dump() {
+    let mut sb = Sandbox::new("signals");
+    sb.link("sg0", "10.200.17.1/24");
+    sb.ip(&["addr", "add", "10.200.17.2/24", "dev", "sg0"]);
+    sb.ip(&["link", "set", "sg0", "up"]);
+    let config = |target: &str| {
+        format!(
+            r#"
+[interfaces]
+manage = ["sg0"]
+
+[probe]
+targets = ["{}"]
+timeout = 1
+gateway_check = false
+
+[dhcp]
+skip = true
+"#,
+            target
+        )
+    };
+    let daemon = "interval = 3600\nconsecutive_failures_before_offline = 1\n";
+    let cfg = sb.config(&config("10.200.17.1"), daemon);
+    let out = sb.path("out");
+    let mut d = sb
+        .cw_netup(&cfg)
+        .args(["--daemon", "--output", "ndjson"])
+        .stdout(fs::File::create(&out).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let lines = || fs::read_to_string(&out).unwrap_or_default();
+    let count = |ev: &str| lines().matches(ev).count();
+    let online = wait_for(Duration::from_secs(10), || {
+        lines().contains(r#""event":"state","status":"ONLINE""#)
+    });
+    signal(&d, "USR2");
+    let dumped = wait_for(Duration::from_secs(5), || {
+        count(r#""event":"dump","state":{"state":"ONLINE""#) == 1
+    });
+    signal(&d, "USR1");
+    let checked = wait_for(Duration::from_secs(5), || count(r#""event":"check""#) == 2);
+    // Kept: it does not load.
+    fs::write(&cfg, "[probe]\ntimeout = \"soon\"\n").unwrap();
+    signal(&d, "HUP");
+    let refused = wait_for(Duration::from_secs(5), || {
+        count(r#""event":"reload","ok":false"#) == 1
+    });
+    sb.config(&config("10.200.17.9"), daemon);
+    signal(&d, "HUP");
+    let offline = wait_for(Duration::from_secs(10), || {
+        lines().contains(r#""event":"state","status":"OFFLINE""#)
+    });
+    let _ = d.kill();
+    let _ = d.wait();
+    let log = lines();
+    assert!(online, "never ONLINE: {}", log);
+    assert!(dumped, "no dump on SIGUSR2: {}", log);
+    assert!(checked, "no check on SIGUSR1: {}", log);
+    assert!(refused, "a config that does not load taken: {}", log);
+    assert_eq!(count(r#""event":"reload","ok":true"#), 1, "{}", log);
+    assert!(offline, "the reloaded target not probed: {}", log);
+}
+
+#[test]
+fn adaptive_daemon_backs_off_to_one_target() {
+    let mut sb = Sandbox::new("adaptive");
+    sb.link("ad0", "10.200.18.1/24");
+    sb.peer_ip(&["addr", "add", "10.200.18.3/24", "dev", "ad0p"]);
+    sb.ip(&["addr", "add", "10.200.18.2/24", "dev", "ad0"]);
+    sb.ip(&["link", "set", "ad0", "up"]);
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["ad0"]
+
+[probe]
+targets = ["10.200.18.1", "10.200.18.3"]
+timeout = 1
+gateway_check = false
+
+[dhcp]
+skip = true
+"#,
+        "interval = 1\nadaptive_after = 1\n",
+    );
+    let (out, log) = (sb.path("out"), sb.path("log"));
+    let mut d = sb
+        .cw_netup(&cfg)
+        .args(["--max-interval", "2", "--output", "ndjson"])
+        .stdout(fs::File::create(&out).unwrap())
+        .stderr(fs::File::create(&log).unwrap())
+        .spawn()
+        .unwrap();
+    let lines = || fs::read_to_string(&out).unwrap_or_default();
+    let relaxed = wait_for(Duration::from_secs(15), || {
+        lines()
+            .contains(r#""target":"10.200.18.3","method":"icmp","reachable":null,"skipped":true"#)
+    });
+    if relaxed {
+        sb.peer_ip(&["addr", "flush", "dev", "ad0p"]);
+    }
+    let offline = relaxed
+        && wait_for(Duration::from_secs(15), || {
+            lines().contains(r#""event":"state","status":"OFFLINE""#)
+        });
+    let _ = d.kill();
+    let _ = d.wait();
+    let (o, err) = (lines(), fs::read_to_string(&log).unwrap_or_default());
+    assert!(relaxed, "never down to one target: {}\n{}", o, err);
+    assert!(offline, "never OFFLINE after the peer went away: {}", o);
+    assert!(err.contains("healthy: probing one target"), "{}", err);
+    assert!(
+        err.contains("OFFLINE with one target; probing them all"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn hotplugged_interface_is_taken_in_and_let_go() {
+    let mut sb = Sandbox::new("hotplug");
+    sb.link("cw0", "10.200.11.1/24");
+    sb.ip(&["addr", "add", "10.200.11.2/24", "dev", "cw0"]);
+    sb.ip(&["link", "set", "cw0", "up"]);
+    let events = sb.path("events");
+    // Checks far enough apart that only the hotplug explains a quick one.
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["cw0", "hp0"]
+
+[probe]
+targets = ["10.200.11.1"]
+timeout = 1
+gateway_check = false
+
+[dhcp]
+skip = true
+"#,
+        "interval = 60\n",
+    );
+    let mut d = sb
+        .cw_netup(&cfg)
+        .args(["--daemon", "--output", "ndjson"])
+        .stdout(fs::File::create(&events).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let seen = |e: &str| fs::read_to_string(&events).is_ok_and(|s| s.contains(e));
+    let started = wait_for(Duration::from_secs(10), || seen(r#""event":"state""#));
+    sb.link("hp0", "10.200.12.1/24");
+    let added = started
+        && wait_for(Duration::from_secs(10), || {
+            seen(r#""iface":"hp0","action":"added""#)
+                && seen(r#""event":"interface_up","iface":"hp0""#)
+        });
+    sb.ip(&["link", "del", "hp0"]);
+    let removed = added
+        && wait_for(Duration::from_secs(10), || {
+            seen(r#""iface":"hp0","action":"removed""#)
+        });
+    let _ = d.kill();
+    let _ = d.wait();
+    let log = fs::read_to_string(&events).unwrap_or_default();
+    assert!(started, "never checked: {}", log);
+    assert!(added, "hp0 not taken in: {}", log);
+    assert!(removed, "hp0 not let go: {}", log);
+}
+
+#[test]
+fn repair_fixes_each_layer_in_turn() {
+    let mut sb = Sandbox::new("repair");
+    sb.link("rp0", "10.200.11.1/24");
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["rp0"]
+
+[probe]
+targets = ["10.200.11.1"]
+timeout = 1
+
+[dhcp]
+command = "false"
+
+[dns]
+servers = ["10.200.11.1"]
+timeout = 1
+
+[fallback_static.rp0]
+address = "10.200.11.2"
+prefix_len = 24
+gateway = "10.200.11.1"
+"#,
+        "",
+    );
+    let o = sb.cw_netup(&cfg).arg("repair").output().unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    // Nothing answers DNS on the far end.
+    assert_eq!(o.status.code(), Some(1), "{}", out);
+    for l in [
+        "✗ link      rp0: down; set it up",
+        "✗ address   rp0: no IPv4 address; static fallback 10.200.11.2",
+        "✓ route     rp0: default via 10.200.11.1",
+        "✓ internet  ONLINE",
+        "✗ dns",
+        "still broken: dns",
+    ] {
+        assert!(out.contains(l), "{}", out);
+    }
+    // Working now, so left alone.
+    let o = sb.cw_netup(&cfg).arg("repair").output().unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    assert!(out.contains("✓ link      rp0: up"), "{}", out);
+    assert!(out.contains("✓ address   rp0: 10.200.11.2"), "{}", out);
+    // With --output json the object alone, and with -q nothing.
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["repair", "--output", "json"])
+        .output()
+        .unwrap();
+    let out = String::from_utf8(o.stdout).unwrap();
+    assert_eq!(out.lines().count(), 1, "{}", out);
+    assert!(out.starts_with("{\"ok\":false,"), "{}", out);
+    let o = sb.cw_netup(&cfg).args(["repair", "-q"]).output().unwrap();
+    assert_eq!(o.status.code(), Some(1));
+    assert!(
+        o.stdout.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&o.stdout)
+    );
+}
+
+#[test]
+fn path_mtu_blackhole_is_degraded() {
+    let mut sb = Sandbox::new("pmtu");
+    sb.link("mtu0", "10.200.12.1/24");
+    // The far end drops what it cannot take, without a word.
+    sb.peer_ip(&["link", "set", "mtu0p", "mtu", "1400"]);
+    sb.ip(&["addr", "add", "10.200.12.2/24", "dev", "mtu0"]);
+    sb.ip(&["link", "set", "mtu0", "up"]);
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["mtu0"]
+
+[probe]
+targets = ["10.200.12.1"]
+timeout = 1
+gateway_check = false
+
+[dhcp]
+skip = true
+"#,
+        "",
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["--min-mtu", "1492", "--output", "json"])
+        .output()
+        .unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    let report = out.lines().last().unwrap_or_default();
+    assert_eq!(o.status.code(), Some(7), "{}", report);
+    assert!(report.contains(r#""status":"DEGRADED""#), "{}", report);
+    // veth lets a VLAN tag's worth past the MTU.
+    assert!(
+        report.contains(r#""pmtu":{"iface":"mtu0","target":"10.200.12.1","mtu":1404,"low":true}"#),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn health_check_answers_with_the_state() {
+    let mut sb = Sandbox::new("health");
+    sb.link("hc0", "10.200.21.1/24");
+    sb.peer_ip(&["link", "set", "hc0p", "mtu", "1400"]);
+    sb.ip(&["addr", "add", "10.200.21.2/24", "dev", "hc0"]);
+    sb.ip(&["link", "set", "hc0", "up"]);
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["hc0"]
+
+[probe]
+targets = ["10.200.21.1"]
+timeout = 1
+gateway_check = false
+
+[dhcp]
+skip = true
+
+[health]
+enabled = true
+"#,
+        "interval = 1
+",
+    );
+    let mut d = sb
+        .cw_netup(&cfg)
+        .args(["--daemon", "--min-mtu", "1492"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let get = || {
+        let mut c = TcpStream::connect("127.0.0.1:9102").ok()?;
+        c.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+        c.write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n").ok()?;
+        let mut r = String::new();
+        c.read_to_string(&mut r).ok()?;
+        Some(r)
+    };
+    let degraded = wait_for(Duration::from_secs(10), || {
+        get().is_some_and(|r| r.ends_with(r#"{"status":"DEGRADED"}"#))
+    });
+    // A client that never sends its request holds up no one else.
+    let silent = TcpStream::connect("127.0.0.1:9102");
+    let answer = get();
+    drop(silent);
+    let _ = d.kill();
+    let _ = d.wait();
+    assert!(degraded, "never DEGRADED: {:?}", answer);
+    let answer = answer.unwrap_or_default();
+    assert!(
+        answer.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+        "{}",
+        answer
+    );
+}
+
+#[test]
+fn gateway_that_stops_answering_arp_is_told_apart() {
+    let mut sb = Sandbox::new("neigh");
+    sb.link("gw0", "10.200.13.1/24");
+    // A gateway still there, but deaf to ARP, as a wedged switch leaves it.
+    sb.peer_ip(&["link", "set", "gw0p", "arp", "off"]);
+    sb.ip(&["addr", "add", "10.200.13.2/24", "dev", "gw0"]);
+    sb.ip(&["link", "set", "gw0", "up"]);
+    sb.ip(&["route", "add", "default", "via", "10.200.13.1"]);
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["gw0"]
+
+[probe]
+targets = ["10.200.99.1"]
+timeout = 1
+
+[dhcp]
+skip = true
+"#,
+        "",
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["--flush-neigh", "--output", "json"])
+        .output()
+        .unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    let report = out.lines().last().unwrap_or_default();
+    assert_eq!(o.status.code(), Some(1), "{}", report);
+    assert!(
+        report.contains(r#""diagnosis":"GATEWAY_ARP_FAILED""#),
+        "{}",
+        report
+    );
+    assert!(report.contains(r#""neigh_flushed":true"#), "{}", report);
+    assert!(
+        report.contains(r#""neigh":"FAILED""#) || report.contains(r#""neigh":"INCOMPLETE""#),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn tunnel_down_is_told_from_uplink_down() {
+    let mut sb = Sandbox::new("tunnel");
+    sb.link("tu0", "10.200.19.1/24");
+    sb.peer_ip(&["addr", "add", "10.200.20.9/32", "dev", "lo"]);
+    sb.ip(&["addr", "add", "10.200.19.2/24", "dev", "tu0"]);
+    sb.ip(&["link", "set", "tu0", "up"]);
+    sb.ip(&["route", "add", "default", "via", "10.200.19.1"]);
+    // A VPN whose daemon is gone: the target is routed into it.
+    sb.ip(&["tuntap", "add", "tun0", "mode", "tun"]);
+    sb.ip(&["addr", "add", "10.77.0.2/24", "dev", "tun0"]);
+    sb.ip(&["link", "set", "tun0", "up"]);
+    sb.ip(&["route", "add", "10.200.20.9/32", "dev", "tun0"]);
+    let marks = sb.path("restarts");
+    let cfg = sb.config(
+        &format!(
+            r#"
+[interfaces]
+manage = ["tu0"]
+
+[probe]
+targets = ["10.200.20.9"]
+timeout = 1
+retries = 0
+
+[dhcp]
+skip = true
+
+[tunnel]
+enabled = true
+restart_command = "echo restart >> {}"
+"#,
+            marks.display()
+        ),
+        "interval = 1\nconsecutive_failures_before_offline = 1\n",
+    );
+    let once = |sb: &Sandbox| {
+        let o = sb
+            .cw_netup(&cfg)
+            .args(["--once", "--output", "json"])
+            .output()
+            .unwrap();
+        let out = String::from_utf8_lossy(&o.stdout);
+        (
+            o.status.code(),
+            out.lines().last().unwrap_or_default().to_string(),
+        )
+    };
+    // --once exits 1 when OFFLINE.
+    let offline = Some(1);
+    let (code, report) = once(&sb);
+    assert_eq!(code, offline, "{}", report);
+    assert!(
+        report.contains(r#""diagnosis":"TUNNEL_DOWN""#),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains(r#""tunnel":{"tunnels":["tun0"],"uplink":"tu0","uplink_ok":true"#),
+        "{}",
+        report
+    );
+    let log = sb.path("log");
+    let mut d = sb
+        .cw_netup(&cfg)
+        .arg("--daemon")
+        .stdout(Stdio::null())
+        .stderr(fs::File::create(&log).unwrap())
+        .spawn()
+        .unwrap();
+    let restarted = wait_for(Duration::from_secs(10), || marks.exists());
+    // Checks go on; the restart waits for the tunnel to come back first.
+    std::thread::sleep(Duration::from_secs(3));
+    let _ = d.kill();
+    let _ = d.wait();
+    let err = fs::read_to_string(&log).unwrap_or_default();
+    assert!(restarted, "no tunnel restart: {}", err);
+    assert_eq!(
+        fs::read_to_string(&marks).unwrap_or_default(),
+        "restart\n",
+        "{}",
+        err
+    );
+    // No lease renewal for the tunnel's sake.
+    assert!(!err.contains("RECOVERING"), "{}", err);
+    sb.peer_ip(&["addr", "del", "10.200.20.9/32", "dev", "lo"]);
+    let (code, report) = once(&sb);
+    assert_eq!(code, offline, "{}", report);
+    assert!(
+        report.contains(r#""diagnosis":"UPLINK_DOWN""#),
+        "{}",
+        report
+    );
+    assert!(report.contains(r#""uplink_ok":false"#), "{}", report);
+}
+
+#[test]
+fn missing_interface_is_reported() {
+    let sb = Sandbox::new("missing");
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["nosuch0"]
+
+[probe]
+targets = ["10.200.2.1"]
+timeout = 1
+
+[dhcp]
+skip = true
+"#,
+        "",
+    );
+    let o = sb.cw_netup(&cfg).output().unwrap();
+    let err = String::from_utf8_lossy(&o.stderr);
+    // OFFLINE, not a crash.
+    assert_eq!(o.status.code(), Some(1), "{}", err);
+    assert!(err.contains("nosuch0: setting link up"), "{}", err);
+    assert!(!err.contains("panicked"), "{}", err);
+}
+
+#[test]
+fn once_wait_blocks_until_online() {
+    let mut sb = Sandbox::new("wait");
+    sb.link("wt0", "10.200.15.1/24");
+    sb.ip(&["link", "set", "wt0", "up"]);
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["wt0"]
+
+[probe]
+targets = ["10.200.15.1"]
+timeout = 1
+"#,
+        "",
+    );
+    // A timeout exits with the code of the state it stands in.
+    let wait = ["--wait", "--timeout", "3s", "--wait-interval", "1"];
+    let o = sb
+        .cw_netup(&cfg)
+        .args(wait)
+        .args(["--output", "ndjson"])
+        .output()
+        .unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    assert_eq!(o.status.code(), Some(1), "{}", out);
+    assert!(
+        out.contains(r#"{"event":"wait","attempt":2,"status":"OFFLINE""#),
+        "{}",
+        out
+    );
+    // Up a moment after it starts waiting.
+    let child = sb
+        .cw_netup(&cfg)
+        .args(wait)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(1500));
+    sb.ip(&["addr", "add", "10.200.15.2/24", "dev", "wt0"]);
+    let o = child.wait_with_output().unwrap();
+    let err = String::from_utf8_lossy(&o.stderr);
+    assert_eq!(o.status.code(), Some(0), "{}", err);
+    assert!(err.starts_with(".."), "{}", err);
+    assert!(String::from_utf8_lossy(&o.stdout).contains("ONLINE"));
+}
+
+#[test]
+fn state_only_prints_the_exit_status_word() {
+    let sb = Sandbox::new("state-only");
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["nosuch0"]
+
+[probe]
+targets = ["10.200.2.1"]
+timeout = 1
+
+[dhcp]
+skip = true
+"#,
+        "",
+    );
+    let o = sb.cw_netup(&cfg).arg("--state-only").output().unwrap();
+    let err = String::from_utf8_lossy(&o.stderr);
+    assert_eq!(String::from_utf8_lossy(&o.stdout), "OFFLINE\n", "{}", err);
+    assert_eq!(o.status.code(), Some(1));
+    assert!(err.contains("OFFLINE (0/1)"), "{}", err);
+    let o = sb.cw_netup(&cfg).arg("--quiet").output().unwrap();
+    assert!(o.stdout.is_empty());
+    assert_eq!(o.status.code(), Some(1));
+}
+
+#[test]
+fn once_exits_with_the_state_it_prints() {
+    let mut sb = Sandbox::new("once-exit");
+    sb.link("oe0", "10.200.22.1/24");
+    sb.ip(&["addr", "add", "10.200.22.2/24", "dev", "oe0"]);
+    sb.ip(&["link", "set", "oe0", "up"]);
+    // The config for a probe of `target`, and the word and exit status.
+    let state = |target: &str, args: &[&str]| {
+        let cfg = sb.config(
+            &format!(
+                r#"
+[interfaces]
+manage = ["oe0"]
+
+[probe]
+targets = ["{}"]
+timeout = 1
+
+[dhcp]
+skip = true
+
+[dns]
+servers = ["10.200.22.1"]
+timeout = 1
+"#,
+                target
+            ),
+            "",
+        );
+        let o = sb
+            .cw_netup(&cfg)
+            .args(["--once", "--state-only"])
+            .args(args)
+            .output()
+            .unwrap();
+        let word = String::from_utf8(o.stdout).unwrap();
+        let status: Status = word.trim_end().parse().expect(&word);
+        assert_eq!(o.status.code(), Some(status.exit_code()), "{}", word);
+        status
+    };
+    // Nothing answers DNS on the far end.
+    let name = ["--dns-name", "example.com"];
+    assert_eq!(state("10.200.22.1", &name), Status::DnsBroken);
+    assert_eq!(state("10.200.23.1", &[]), Status::Offline);
+    // Not ONLINE in time is reported as it stands.
+    let wait = ["--wait", "--wait-timeout", "2", "--wait-interval", "1"];
+    assert_eq!(state("10.200.23.1", &wait), Status::Offline);
+}
+
+#[test]
+fn probe_fragments_add_targets() {
+    let sb = Sandbox::new("probes-d");
+    let dir = sb.path("probes.d");
+    fs::create_dir(&dir).unwrap();
+    fs::write(
+        dir.join("10-internal.toml"),
+        "[[probes]]\nmethod = \"tcp\"\nhost = \"10.200.3.1\"\nport = 53\nweight = 2\n",
+    )
+    .unwrap();
+    // Already a target of the main config, and not a fragment at all.
+    fs::write(
+        dir.join("20-dup.toml"),
+        "[[probes]]\ntarget = \"10.200.3.2\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("ntp.toml.disabled"), "not toml").unwrap();
+    let cfg = sb.config(
+        &format!(
+            "[probe]\ntargets = [\"10.200.3.2\"]\nprobes_dir = {:?}\n",
+            dir.to_str().unwrap()
+        ),
+        "",
+    );
+    let o = sb.cw_netup(&cfg).arg("--print-config").output().unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    assert!(o.status.success(), "{}", String::from_utf8_lossy(&o.stderr));
+    assert!(out.contains("targets = [\"10.200.3.2\"]\n"), "{}", out);
+    assert!(
+        out.contains("host = \"10.200.3.1\"\nport = 53\nweight = 2\n"),
+        "{}",
+        out
+    );
+    assert_eq!(out.matches("10.200.3.2").count(), 1, "{}", out);
+    // The printed config loads the same, fragments and all.
+    let printed = sb.path("printed.toml");
+    fs::write(&printed, out.as_bytes()).unwrap();
+    let o = sb
+        .cw_netup(&printed)
+        .arg("--print-config")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&o.stdout), out);
+    fs::write(dir.join("30-bad.toml"), "[[probes]]\nmethod = \"tcp\"\n").unwrap();
+    let o = sb.cw_netup(&cfg).arg("--print-config").output().unwrap();
+    let err = String::from_utf8_lossy(&o.stderr);
+    assert_eq!(
+        o.status.code(),
+        Some(Status::ConfigError.exit_code()),
+        "{}",
+        err
+    );
+    assert!(err.contains("30-bad.toml: probes[0].host"), "{}", err);
+}
+
+#[test]
+fn probe_bound_to_missing_interface_fails_alone() {
+    let mut sb = Sandbox::new("bound");
+    sb.link("bnd0", "10.200.10.1/24");
+    sb.peer_ip(&["addr", "add", "10.200.10.3/24", "dev", "bnd0p"]);
+    sb.ip(&["addr", "add", "10.200.10.2/24", "dev", "bnd0"]);
+    sb.ip(&["link", "set", "bnd0", "up"]);
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["bnd0"]
+
+[probe]
+targets = ["10.200.10.1"]
+quorum = 1
+timeout = 1
+gateway_check = false
+
+[[probes]]
+target = "10.200.10.3"
+interface = "nosuch0"
+
+[dhcp]
+skip = true
+"#,
+        "",
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["--output", "json"])
+        .output()
+        .unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    let err = String::from_utf8_lossy(&o.stderr);
+    let report = out.lines().last().unwrap_or_default();
+    assert!(o.status.success(), "{}: {}\n{}", o.status, out, err);
+    assert!(
+        report.contains(r#""iface":"bnd0","source":"10.200.10.2","error":null"#),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains(r#""iface":"nosuch0","source":null,"error":"via nosuch0: "#),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn namespaces_are_checked_alongside() {
+    let mut sb = Sandbox::new("netns");
+    sb.link("nsv0", "10.200.9.1/24");
+    let peer = sb.peer_path();
+    let cfg = sb.config(
+        &format!(
+            r#"
+[interfaces]
+manage = ["nsv0"]
+
+[probe]
+targets = ["10.200.9.1"]
+timeout = 1
+
+[dhcp]
+skip = true
+
+[[namespace]]
+name = "peer"
+path = "{}"
+manage = ["nsv0p"]
+"#,
+            peer
+        ),
+        "",
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .args(["--once", "--output", "json"])
+        .output()
+        .unwrap();
+    let out = String::from_utf8_lossy(&o.stdout);
+    // The peer answers itself; here nsv0 is down and has no address.
+    assert_eq!(o.status.code(), Some(1), "{}", out);
+    assert!(out.starts_with(r#"{"status":"OFFLINE""#), "{}", out);
+    assert!(
+        out.contains(r#""namespaces":[{"status":"ONLINE""#),
+        "{}",
+        out
+    );
+    assert!(out.contains(r#""name":"nsv0p""#), "{}", out);
+    assert!(out.contains(r#""name":"peer"}"#), "{}", out);
+}
@@ -0,0 +1,188 @@
+// A network namespace per test: the test's thread leaves the host's for a
+// fresh one, which the commands it runs inherit, and the far ends of its
+// links sit in a second one held open by a sleeping child.
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::raw::c_int;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CLONE_NEWNET: c_int = 0x4000_0000;
+
+extern "C" {
+    fn unshare(flags: c_int) -> c_int;
+    fn setns(fd: c_int, nstype: c_int) -> c_int;
+}
+
+pub struct Sandbox {
+    dir: PathBuf,
+    /// Holds the peer namespace; `None` until a link needs one.
+    peer: Option<(Child, File)>,
+}
+
+fn check(what: &str, o: io::Result<Output>) -> Output {
+    let o = o.unwrap_or_else(|e| panic!("{}: {}", what, e));
+    if !o.status.success() {
+        panic!(
+            "{}: {}: {}",
+            what,
+            o.status,
+            String::from_utf8_lossy(&o.stderr).trim()
+        );
+    }
+    o
+}
+
+impl Sandbox {
+    /// Moves the calling thread into a new network namespace with `lo` up.
+    /// Needs CAP_SYS_ADMIN.
+    pub fn new(name: &str) -> Sandbox {
+        if unsafe { unshare(CLONE_NEWNET) } < 0 {
+            panic!(
+                "unshare(CLONE_NEWNET): {} (integration tests need CAP_SYS_ADMIN)",
+                io::Error::last_os_error()
+            );
+        }
+        let dir = std::env::temp_dir().join(format!("cw-netup-it-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sb = Sandbox { dir, peer: None };
+        sb.ip(&["link", "set", "lo", "up"]);
+        sb
+    }
+
+    /// Runs `ip` here.
+    pub fn ip(&self, args: &[&str]) -> String {
+        let o = check(
+            &format!("ip {}", args.join(" ")),
+            Command::new("ip").args(args).output(),
+        );
+        String::from_utf8_lossy(&o.stdout).into_owned()
+    }
+
+    /// Runs `ip` in the peer namespace.
+    pub fn peer_ip(&mut self, args: &[&str]) -> String {
+        let ns = self.peer().as_raw_fd();
+        let mut cmd = Command::new("ip");
+        cmd.args(args);
+        // Only setns(2) between fork and exec.
+        unsafe {
+            cmd.pre_exec(move || match setns(ns, CLONE_NEWNET) {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            });
+        }
+        let o = check(&format!("peer: ip {}", args.join(" ")), cmd.output());
+        String::from_utf8_lossy(&o.stdout).into_owned()
+    }
+
+    fn peer(&mut self) -> &File {
+        if self.peer.is_none() {
+            let mut cmd = Command::new("sleep");
+            cmd.arg("3600").stdin(Stdio::null());
+            unsafe {
+                cmd.pre_exec(|| match unshare(CLONE_NEWNET) {
+                    0 => Ok(()),
+                    _ => Err(io::Error::last_os_error()),
+                });
+            }
+            let child = cmd.spawn().expect("spawning the peer namespace holder");
+            let ns = File::open(format!("/proc/{}/ns/net", child.id())).unwrap();
+            self.peer = Some((child, ns));
+            self.peer_ip(&["link", "set", "lo", "up"]);
+        }
+        &self.peer.as_ref().unwrap().1
+    }
+
+    /// A veth pair: `name` here, down and without an address, and
+    /// `<name>p` up in the peer namespace with `peer_addr` (CIDR).
+    pub fn link(&mut self, name: &str, peer_addr: &str) {
+        let far = format!("{}p", name);
+        self.ip(&["link", "add", name, "type", "veth", "peer", "name", &far]);
+        let holder = self.peer_pid().to_string();
+        self.ip(&["link", "set", &far, "netns", &holder]);
+        self.peer_ip(&["addr", "add", peer_addr, "dev", &far]);
+        self.peer_ip(&["link", "set", &far, "up"]);
+    }
+
+    /// The peer namespace, for `[[namespace]] path`.
+    pub fn peer_path(&mut self) -> String {
+        format!("/proc/{}/ns/net", self.peer_pid())
+    }
+
+    fn peer_pid(&mut self) -> u32 {
+        self.peer();
+        self.peer.as_ref().unwrap().0.id()
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Writes a config file: `body`, then a `[daemon]` section of `daemon`
+    /// that keeps the state, PID, history and audit files off the host.
+    pub fn config(&self, body: &str, daemon: &str) -> PathBuf {
+        let p = self.path("config.toml");
+        let daemon = format!(
+            "[daemon]\nstate_file = \"\"\npid_file = \"\"\nhistory_file = \"\"\naudit_file = \"\"\n{}",
+            daemon
+        );
+        fs::write(&p, format!("{}\n{}", body, daemon)).unwrap();
+        p
+    }
+
+    /// Writes an exec script of `[[exec]]` entries for `$CW_EXEC_SCRIPT`.
+    pub fn script(&self, body: &str) -> PathBuf {
+        let p = self.path("exec.toml");
+        fs::write(&p, body).unwrap();
+        p
+    }
+
+    /// The binary with `cfg`, working in this namespace; `--netns-path`
+    /// gets it a /sys that lists this namespace's links, and it manages
+    /// them even where the host runs NetworkManager.
+    pub fn cw_netup(&self, cfg: &Path) -> Command {
+        let mut c = Command::new(env!("CARGO_BIN_EXE_cw-netup"));
+        c.arg("--config")
+            .arg(cfg)
+            .args(["--netns-path", "/proc/thread-self/ns/net"])
+            .args(["--log-backend", "stderr"])
+            .args(["--network-manager", "none"]);
+        c
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        if let Some((mut c, _)) = self.peer.take() {
+            let _ = c.kill();
+            let _ = c.wait();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Waits up to `t` for `f` to hold.
+pub fn wait_for(t: Duration, mut f: impl FnMut() -> bool) -> bool {
+    let end = Instant::now() + t;
+    while Instant::now() < end {
+        if f() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    f()
+}
+
+/// Sends `child` signal `sig` (`HUP`, `USR1`, ...).
+pub fn signal(child: &Child, sig: &str) {
+    check(
+        &format!("kill -{}", sig),
+        Command::new("kill")
+            .args(["-s", sig, &child.id().to_string()])
+            .output(),
+    );
+}
@@ -0,0 +1,230 @@
+// Failures that are awkward to bring about for real: commands the binary
+// runs are scripted through `$CW_EXEC_SCRIPT` instead.
+use crate::sandbox::Sandbox;
+use std::process::Output;
+
+fn report(o: &Output) -> String {
+    let out = String::from_utf8_lossy(&o.stdout);
+    out.lines().last().unwrap_or_default().to_string()
+}
+
+#[test]
+fn dhclient_timeout_fails_dhcp() {
+    let mut sb = Sandbox::new("dhclient-timeout");
+    sb.link("cw0", "10.201.0.1/24");
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["cw0"]
+
+[probe]
+targets = ["10.201.0.1"]
+timeout = 1
+
+[dhcp]
+backend = "dhclient"
+timeout = 5
+"#,
+        "",
+    );
+    let script = sb.script("[[exec]]\nprogram = \"dhclient\"\ntimeout = true\n");
+    let o = sb
+        .cw_netup(&cfg)
+        .env("CW_EXEC_SCRIPT", &script)
+        .output()
+        .unwrap();
+    let err = String::from_utf8_lossy(&o.stderr);
+    assert_eq!(o.status.code(), Some(3), "{}", err);
+    assert!(err.contains("dhclient timed out"), "{}", err);
+    assert!(String::from_utf8_lossy(&o.stdout).contains("cw0: TIMEOUT"));
+}
+
+#[test]
+fn missing_ip_and_dhclient_still_fall_back() {
+    let mut sb = Sandbox::new("no-ip");
+    sb.link("cw0", "10.201.1.1/24");
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["cw0"]
+
+[probe]
+targets = ["10.201.1.1"]
+timeout = 1
+
+[dhcp]
+backend = "dhclient"
+
+[fallback_static.cw0]
+address = "10.201.1.2"
+prefix_len = 24
+"#,
+        "",
+    );
+    let script = sb.script(
+        r#"
+[[exec]]
+program = "ip"
+missing = true
+
+[[exec]]
+program = "dhclient"
+missing = true
+"#,
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .env("CW_EXEC_SCRIPT", &script)
+        .output()
+        .unwrap();
+    let err = String::from_utf8_lossy(&o.stderr);
+    // Links are set up over netlink; `ip` is never needed.
+    assert!(o.status.success(), "{}: {}", o.status, err);
+    assert!(err.contains("dhclient not found"), "{}", err);
+    assert!(sb
+        .ip(&["-4", "addr", "show", "dev", "cw0"])
+        .contains("10.201.1.2/24"));
+}
+
+#[test]
+fn ping_loss_is_degraded() {
+    let mut sb = Sandbox::new("ping-loss");
+    sb.link("cw0", "10.201.2.1/24");
+    sb.ip(&["addr", "add", "10.201.2.2/24", "dev", "cw0"]);
+    sb.ip(&["link", "set", "cw0", "up"]);
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["cw0"]
+
+[probe]
+targets = ["10.201.2.1"]
+count = 3
+timeout = 1
+gateway_check = false
+
+[dhcp]
+skip = true
+
+[latency]
+max_loss = 20
+"#,
+        "",
+    );
+    let script = sb.script(
+        r#"
+[[exec]]
+program = "ping"
+args = "* 10.201.2.1"
+stdout = "3 packets transmitted, 1 received, 66% packet loss, time 2003ms\nrtt min/avg/max/mdev = 0.100/0.200/0.300/0.050 ms\n"
+"#,
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .env("CW_EXEC_SCRIPT", &script)
+        .args(["--output", "json"])
+        .output()
+        .unwrap();
+    let report = report(&o);
+    let err = String::from_utf8_lossy(&o.stderr);
+    assert_eq!(o.status.code(), Some(7), "{}\n{}", report, err);
+    assert!(report.contains(r#""status":"DEGRADED""#), "{}", report);
+}
+
+#[test]
+fn ping_loss_past_failure_threshold_is_offline() {
+    let mut sb = Sandbox::new("ping-threshold");
+    sb.link("cw0", "10.201.3.1/24");
+    sb.ip(&["addr", "add", "10.201.3.2/24", "dev", "cw0"]);
+    sb.ip(&["link", "set", "cw0", "up"]);
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["cw0"]
+
+[probe]
+targets = ["10.201.3.1"]
+count = 3
+failure_threshold = 2
+timeout = 1
+gateway_check = false
+
+[dhcp]
+skip = true
+"#,
+        "",
+    );
+    let script = sb.script(
+        r#"
+[[exec]]
+program = "ping"
+args = "* 10.201.3.1"
+stdout = "3 packets transmitted, 1 received, 66% packet loss, time 2003ms\nrtt min/avg/max/mdev = 0.100/0.200/0.300/0.050 ms\n"
+"#,
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .env("CW_EXEC_SCRIPT", &script)
+        .args(["--output", "json"])
+        .output()
+        .unwrap();
+    let report = report(&o);
+    let err = String::from_utf8_lossy(&o.stderr);
+    assert_eq!(o.status.code(), Some(1), "{}\n{}", report, err);
+    assert!(report.contains(r#""status":"OFFLINE""#), "{}", report);
+    assert!(report.contains(r#""reachable":false"#), "{}", report);
+}
+
+#[test]
+fn mixed_results_across_interfaces() {
+    let mut sb = Sandbox::new("mixed");
+    sb.link("cw0", "10.201.3.1/24");
+    sb.link("cw1", "10.201.4.1/24");
+    let cfg = sb.config(
+        r#"
+[interfaces]
+manage = ["cw0", "cw1"]
+
+[probe]
+targets = ["10.201.4.1"]
+timeout = 1
+
+[dhcp]
+backend = "dhclient"
+
+[fallback_static.cw1]
+address = "10.201.4.2"
+prefix_len = 24
+"#,
+        "",
+    );
+    let script = sb.script(
+        r#"
+[[exec]]
+program = "dhclient"
+exit = 2
+stderr = "no DHCPOFFERS received"
+"#,
+    );
+    let o = sb
+        .cw_netup(&cfg)
+        .env("CW_EXEC_SCRIPT", &script)
+        .args(["--output", "json"])
+        .output()
+        .unwrap();
+    let report = report(&o);
+    let err = String::from_utf8_lossy(&o.stderr);
+    let out = String::from_utf8_lossy(&o.stdout);
+    assert!(o.status.success(), "{}: {}\n{}", o.status, out, err);
+    assert!(out.contains("no DHCPOFFERS received"), "{}", out);
+    let cw0 = report.find(r#""name":"cw0""#).expect("cw0 in the report");
+    let cw1 = report.find(r#""name":"cw1""#).expect("cw1 in the report");
+    let (first, second) = if cw0 < cw1 {
+        (&report[cw0..cw1], &report[cw1..])
+    } else {
+        (&report[cw0..], &report[cw1..cw0])
+    };
+    assert!(first.contains(r#""dhcp":false"#), "{}", report);
+    assert!(!first.contains(r#""static_fallback":true"#), "{}", report);
+    assert!(second.contains(r#""static_fallback":true"#), "{}", report);
+}